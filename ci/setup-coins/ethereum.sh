@@ -0,0 +1,32 @@
+#!/bin/bash
+set -euxo pipefail
+
+config_dir="$(pwd)/config"
+mkdir -p "$config_dir"
+
+curl -L https://foundry.paradigm.xyz | bash
+~/.foundry/bin/foundryup
+export PATH="$HOME/.foundry/bin:$PATH"
+
+# Anvil mines every transaction immediately, and funds ten accounts from a well-known mnemonic
+anvil --chain-id 1337 > /tmp/anvil.log 2>&1 &
+for i in {1..10}; do
+    if cast chain-id --rpc-url http://127.0.0.1:8545 >/dev/null 2>&1; then break; fi
+    sleep 1
+done
+
+# The first account deploys the contract and funds deposits, while the second pays for gas
+deployer="0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcaff1e4bb8a5c4f07"
+gas_key="0x59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d"
+contract="$(forge create contracts/Swap.sol:Swap --rpc-url http://127.0.0.1:8545 --private-key "$deployer" --broadcast | grep 'Deployed to:' | awk '{print $3}')"
+
+cat > "$config_dir/ethereum.json" << EOF2
+{
+  "chain_id": 1337,
+  "url": "http://127.0.0.1:8545",
+  "contract": "$contract",
+  "gas_key": "$gas_key",
+  "destination": "0x3C44CdDdB6a900fa2b585dd299e03d12FA4293BC",
+  "refund": "0x90F79bf6EB2c4f870365E785982E1f101E93b906"
+}
+EOF2
@@ -44,6 +44,7 @@ refund="$(curl -s '[::1]:45000' --data '{"action":"account_create","wallet":"'"$
 
 cat > "$config_dir/nano.json" << EOF
 {
+    "network": "dev",
     "rpc_url": "http://[::1]:45000/",
     "destination": "$destination",
     "refund": "$refund",
@@ -0,0 +1,137 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.0;
+
+interface IERC20 {
+  function transfer(address to, uint256 value) external returns (bool);
+  function transferFrom(address from, address to, uint256 value) external returns (bool);
+}
+
+/*
+  The Ethereum equivalent of the Bitcoin lock, refund, and their spends
+  Every swap is identified by the hash of its parameters, which are passed with every call, so only its status is stored
+  Signatures are ECDSA, checked via ecrecover, with the buy and spend revealing the decrypted adaptor signatures in their events
+*/
+contract Swap {
+  enum State {
+    None,
+    Locked,
+    Refunded,
+    Done
+  }
+
+  struct Params {
+    // The zero address for ether, or an ERC-20 token
+    address token;
+    uint256 value;
+    // The SHA-256 hash of the swap secret, which the client needs to buy
+    bytes32 hash;
+    // The host's key, which signs the buy
+    address host;
+    // The client's refund key, which signs the spend
+    address clientRefund;
+    address payable hostDestination;
+    address payable clientDestination;
+    // In blocks, after the lock for t0 and after the refund for t1
+    uint256 t0;
+    uint256 t1;
+  }
+
+  struct Status {
+    State state;
+    uint256 lockedAt;
+    uint256 refundedAt;
+  }
+
+  uint8 constant BUY = 1;
+  uint8 constant SPEND = 2;
+
+  mapping(bytes32 => Status) public swaps;
+
+  event Locked(bytes32 indexed id);
+  event Refunded(bytes32 indexed id);
+  event Bought(bytes32 indexed id, bytes32 r, bytes32 s);
+  event Spent(bytes32 indexed id, bytes32 r, bytes32 s);
+  event Claimed(bytes32 indexed id);
+
+  function id(Params calldata params) public pure returns (bytes32) {
+    return keccak256(abi.encode(params));
+  }
+
+  // The hash signed to authorize a buy or spend of this swap, on this chain, through this contract
+  function message(bytes32 swap, uint8 action) public view returns (bytes32) {
+    return keccak256(abi.encodePacked(block.chainid, address(this), swap, action));
+  }
+
+  function pay(Params calldata params, address payable to) internal {
+    if (params.token == address(0)) {
+      (bool success, ) = to.call{value: params.value}("");
+      require(success, "Ether transfer failed");
+    } else {
+      // Some tokens don't return a value, so only a returned false is treated as a failure
+      (bool success, bytes memory result) = params.token.call(abi.encodeWithSelector(IERC20.transfer.selector, to, params.value));
+      require(success && ((result.length == 0) || abi.decode(result, (bool))), "Token transfer failed");
+    }
+  }
+
+  function lock(Params calldata params) external payable {
+    bytes32 swap = id(params);
+    require(swaps[swap].state == State.None, "Swap already exists");
+    require((params.t0 != 0) && (params.t1 != 0), "Timelocks must be nonzero");
+    swaps[swap] = Status(State.Locked, block.number, 0);
+
+    if (params.token == address(0)) {
+      require(msg.value == params.value, "Incorrect value");
+    } else {
+      require(msg.value == 0, "Ether sent with a token lock");
+      (bool success, bytes memory result) = params.token.call(
+        abi.encodeWithSelector(IERC20.transferFrom.selector, msg.sender, address(this), params.value)
+      );
+      require(success && ((result.length == 0) || abi.decode(result, (bool))), "Token transfer failed");
+    }
+    emit Locked(swap);
+  }
+
+  // The client buys with the swap secret and the host's decrypted signature, which lets the host recover the client's key
+  function buy(Params calldata params, bytes32 secret, uint8 v, bytes32 r, bytes32 s) external {
+    bytes32 swap = id(params);
+    require(swaps[swap].state == State.Locked, "Swap isn't locked");
+    require(sha256(abi.encodePacked(secret)) == params.hash, "Invalid swap secret");
+    address signer = ecrecover(message(swap, BUY), v, r, s);
+    require((signer != address(0)) && (signer == params.host), "Invalid buy signature");
+    swaps[swap].state = State.Done;
+    pay(params, params.clientDestination);
+    emit Bought(swap, r, s);
+  }
+
+  // Anyone may refund after t0, as it only starts the window for the spend or claim
+  function refund(Params calldata params) external {
+    bytes32 swap = id(params);
+    require(swaps[swap].state == State.Locked, "Swap isn't locked");
+    require(block.number >= swaps[swap].lockedAt + params.t0, "T0 hasn't expired");
+    swaps[swap].state = State.Refunded;
+    swaps[swap].refundedAt = block.number;
+    emit Refunded(swap);
+  }
+
+  // The host spends the refund with the client's decrypted signature, which lets the client recover the host's key
+  function spend(Params calldata params, uint8 v, bytes32 r, bytes32 s) external {
+    bytes32 swap = id(params);
+    require(swaps[swap].state == State.Refunded, "Swap wasn't refunded");
+    require(block.number < swaps[swap].refundedAt + params.t1, "T1 has expired");
+    address signer = ecrecover(message(swap, SPEND), v, r, s);
+    require((signer != address(0)) && (signer == params.clientRefund), "Invalid spend signature");
+    swaps[swap].state = State.Done;
+    pay(params, params.hostDestination);
+    emit Spent(swap, r, s);
+  }
+
+  // If the host doesn't spend the refund within t1, the client claims it
+  function claim(Params calldata params) external {
+    bytes32 swap = id(params);
+    require(swaps[swap].state == State.Refunded, "Swap wasn't refunded");
+    require(block.number >= swaps[swap].refundedAt + params.t1, "T1 hasn't expired");
+    swaps[swap].state = State.Done;
+    pay(params, params.clientDestination);
+    emit Claimed(swap);
+  }
+}
@@ -0,0 +1,181 @@
+use std::{
+  fs,
+  io::Write,
+  path::Path
+};
+
+use log::info;
+
+use curve25519_dalek::{constants::X25519_BASEPOINT, scalar::Scalar};
+
+use snow::{Builder, HandshakeState, TransportState};
+use tokio::{
+  prelude::*,
+  net::TcpStream
+};
+
+use crate::db::{create_private_dir, create_private_file};
+
+const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+// Bound into the handshake, so only peers speaking this protocol can complete it
+const PROLOGUE: &[u8] = b"ASMR";
+// Noise messages can't exceed this, including the 16-byte authentication tag
+const MAX_NOISE_MESSAGE: usize = 65535;
+const TAG_LENGTH: usize = 16;
+pub const MAX_ITEM_LENGTH: u32 = 256 * 1024; // 256 KB. The largest transmitted data is the DL EQ Proof which is still less than this
+
+/*
+  A long-term static key identifying this instance
+  It's what the Noise handshake authenticates, and what a counterparty can pin with --peer-key
+  It isn't tied to any coin keys, which are still freshly generated per swap
+*/
+#[derive(Clone)]
+pub struct Identity {
+  private: Vec<u8>,
+  pub public: Vec<u8>
+}
+
+impl Identity {
+  pub fn load_or_generate(path: &Path) -> anyhow::Result<Identity> {
+    let private = if path.exists() {
+      let private = hex::decode(fs::read_to_string(path)?.trim())?;
+      anyhow::ensure!(private.len() == 32, "Identity key file didn't contain a 32-byte key");
+      private
+    } else {
+      let keypair = Builder::new(NOISE_PARAMS.parse()?).generate_keypair()?;
+      if let Some(parent) = path.parent() {
+        create_private_dir(parent)?;
+      }
+      create_private_file(path)?.write_all(hex::encode(&keypair.private).as_bytes())?;
+      info!("Generated a new identity key at {}", path.display());
+      keypair.private
+    };
+
+    // X25519 public key derivation, as snow doesn't expose it for an existing private key
+    let mut bits = [0; 32];
+    bits.copy_from_slice(&private);
+    bits[0] &= 248;
+    bits[31] &= 127;
+    bits[31] |= 64;
+    let public = (X25519_BASEPOINT * Scalar::from_bits(bits)).to_bytes().to_vec();
+
+    Ok(Identity { private, public })
+  }
+}
+
+async fn write_frame(stream: &mut TcpStream, frame: &[u8]) -> anyhow::Result<()> {
+  stream.write_all(&(frame.len() as u16).to_be_bytes()).await?;
+  stream.write_all(frame).await?;
+  Ok(())
+}
+
+async fn read_frame(stream: &mut TcpStream) -> anyhow::Result<Vec<u8>> {
+  let mut len = [0; 2];
+  stream.read_exact(&mut len).await?;
+  let mut frame = vec![0; u16::from_be_bytes(len) as usize];
+  stream.read_exact(&mut frame).await?;
+  Ok(frame)
+}
+
+/*
+  An encrypted and authenticated channel with the counterparty, established via a Noise XX handshake
+  XX transmits both static keys encrypted, so an observer can't link multiple swaps to the same party
+  Every item is prefixed with its length and split across as many Noise messages as needed
+  As every Noise message is authenticated and ordered by its nonce, any alteration, reordering, or truncation is detected
+*/
+pub struct Channel {
+  stream: TcpStream,
+  noise: TransportState
+}
+
+impl Channel {
+  async fn handshake(
+    mut stream: TcpStream,
+    mut noise: HandshakeState,
+    peer_key: Option<&[u8]>
+  ) -> anyhow::Result<Channel> {
+    let mut buf = vec![0; MAX_NOISE_MESSAGE];
+    while !noise.is_handshake_finished() {
+      if noise.is_my_turn() {
+        let len = noise.write_message(&[], &mut buf)?;
+        write_frame(&mut stream, &buf[..len]).await?;
+      } else {
+        let frame = read_frame(&mut stream).await?;
+        noise.read_message(&frame, &mut buf)
+          .map_err(|e| anyhow::anyhow!("Noise handshake failed (is the peer an ASMR instance?): {}", e))?;
+      }
+    }
+
+    let remote = noise.get_remote_static().expect("Finished an XX handshake without the remote static key").to_vec();
+    if let Some(peer_key) = peer_key {
+      if remote != peer_key {
+        anyhow::bail!("Peer's identity key {} doesn't match the expected key", hex::encode(&remote));
+      }
+    }
+    info!("Established an encrypted channel with peer {}", hex::encode(&remote));
+
+    Ok(Channel {
+      stream,
+      noise: noise.into_transport_mode()?
+    })
+  }
+
+  pub async fn initiate(stream: TcpStream, identity: &Identity, peer_key: Option<&[u8]>) -> anyhow::Result<Channel> {
+    let noise = Builder::new(NOISE_PARAMS.parse()?)
+      .local_private_key(&identity.private)
+      .prologue(PROLOGUE)
+      .build_initiator()?;
+    Channel::handshake(stream, noise, peer_key).await
+  }
+
+  pub async fn respond(stream: TcpStream, identity: &Identity, peer_key: Option<&[u8]>) -> anyhow::Result<Channel> {
+    let noise = Builder::new(NOISE_PARAMS.parse()?)
+      .local_private_key(&identity.private)
+      .prologue(PROLOGUE)
+      .build_responder()?;
+    Channel::handshake(stream, noise, peer_key).await
+  }
+
+  pub async fn write(&mut self, value: &[u8]) -> anyhow::Result<()> {
+    let len = value.len() as u32;
+    assert!(len <= MAX_ITEM_LENGTH);
+    let mut plaintext = len.to_le_bytes().to_vec();
+    plaintext.extend(value);
+
+    let mut buf = vec![0; MAX_NOISE_MESSAGE];
+    for chunk in plaintext.chunks(MAX_NOISE_MESSAGE - TAG_LENGTH) {
+      let len = self.noise.write_message(chunk, &mut buf)?;
+      write_frame(&mut self.stream, &buf[..len]).await?;
+    }
+    Ok(())
+  }
+
+  pub async fn read(&mut self) -> anyhow::Result<Vec<u8>> {
+    let mut buf = vec![0; MAX_NOISE_MESSAGE];
+    let mut plaintext = Vec::new();
+    let mut expected = None;
+    loop {
+      let frame = read_frame(&mut self.stream).await?;
+      let len = self.noise.read_message(&frame, &mut buf)
+        .map_err(|e| anyhow::anyhow!("Received a message which failed authentication: {}", e))?;
+      plaintext.extend(&buf[..len]);
+
+      if expected.is_none() && (plaintext.len() >= 4) {
+        let mut len = [0; 4];
+        len.copy_from_slice(&plaintext[..4]);
+        let len = u32::from_le_bytes(len);
+        if len > MAX_ITEM_LENGTH {
+          anyhow::bail!("Attempted to read {} byte item, longer than maximum", len);
+        }
+        expected = Some((len as usize) + 4);
+      }
+
+      if let Some(expected) = expected {
+        if plaintext.len() >= expected {
+          anyhow::ensure!(plaintext.len() == expected, "Received more data than the item's length");
+          return Ok(plaintext.split_off(4));
+        }
+      }
+    }
+  }
+}
@@ -51,6 +51,10 @@ fn host_or_client_from_str(s: &str) -> Result<HostOrClient, HostOrClientError> {
 pub enum ScriptedCoin {
   #[enumeration(alias = "btc")]
   Bitcoin,
+  #[enumeration(alias = "ltc")]
+  Litecoin,
+  #[enumeration(alias = "eth")]
+  Ethereum,
 }
 
 #[derive(FromStr, Debug, Clone)]
@@ -60,7 +64,9 @@ pub enum UnscriptedCoin {
   Meros,
   Nano,
   #[enumeration(alias = "xmr")]
-  Monero
+  Monero,
+  #[enumeration(alias = "wow")]
+  Wownero
 }
 
 enum AnyCoin {
@@ -128,16 +134,48 @@ impl FromStr for CoinPair {
 #[derive(StructOpt, Clone)]
 pub struct Cli {
   /// Host, trading a scripted coin for an unscripted coin, or client, the reverse.
-  #[structopt(parse(try_from_str = host_or_client_from_str))]
-  pub host_or_client: HostOrClient,
+  #[structopt(parse(try_from_str = host_or_client_from_str), required_unless_one = &["resume", "rpc"])]
+  pub host_or_client: Option<HostOrClient>,
   /// The TCP address to listen on as the host, or connect to as the client.
-  pub tcp_address: SocketAddr,
+  #[structopt(required_unless_one = &["resume", "rpc"])]
+  pub tcp_address: Option<SocketAddr>,
   /// The pair to trade, e.g. btc-mr.
-  pub pair: CoinPair,
+  #[structopt(required_unless_one = &["resume", "rpc"])]
+  pub pair: Option<CoinPair>,
+  /// The amount of the scripted coin to trade, in its smallest unit (satoshis for Bitcoin).
+  #[structopt(long, required_unless_one = &["resume", "rpc"])]
+  pub scripted_amount: Option<u64>,
+  /// The amount of the unscripted coin to trade, in its smallest unit.
+  #[structopt(long, required_unless_one = &["resume", "rpc"])]
+  pub unscripted_amount: Option<u128>,
+  /// How far, in basis points, on-chain amounts may deviate from the agreed amounts, accommodating fees.
+  #[structopt(long, default_value = "100")]
+  pub tolerance: u16,
   /// The path to a JSON config file for the scripted coin. Defaults to `config/{coin}.json`.
   #[structopt(long)]
   pub scripted_config: Option<PathBuf>,
   /// The path to a JSON config file for the unscripted coin. Defaults to `config/{coin}.json`.
   #[structopt(long)]
   pub unscripted_config: Option<PathBuf>,
+  /// As the host, keep accepting clients after the first, running their swaps concurrently.
+  #[structopt(long)]
+  pub daemon: bool,
+  /// The directory swap states are checkpointed to.
+  #[structopt(long, default_value = "swaps")]
+  pub data_dir: PathBuf,
+  /// The file storing this instance's long-term identity key, generated if it doesn't exist.
+  #[structopt(long, default_value = "config/identity.key")]
+  pub identity: PathBuf,
+  /// Only complete the handshake with a peer using this hex-encoded identity key.
+  #[structopt(long, parse(try_from_str = hex::decode))]
+  pub peer_key: Option<Vec<u8>>,
+  /// Instead of running a single swap, serve the JSON-RPC control API on this address.
+  #[structopt(long)]
+  pub rpc: Option<SocketAddr>,
+  /// Allow the unauthenticated control API to be served on a non-loopback address.
+  #[structopt(long)]
+  pub rpc_allow_remote: bool,
+  /// Resume the swap with this ID from its last checkpoint, refunding if it can't be continued.
+  #[structopt(long)]
+  pub resume: Option<String>,
 }
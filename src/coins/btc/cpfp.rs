@@ -0,0 +1,193 @@
+use log::{info, warn};
+
+use bitcoin::{
+  secp256k1,
+  hashes::hex::FromHex, hash_types::Txid,
+  blockdata::{script::Script, transaction::{OutPoint, TxIn, TxOut, Transaction}},
+  network::constants::Network,
+  util::{address::Address, base58, key::{PrivateKey, PublicKey}, bip143::SighashComponents},
+  consensus::serialize
+};
+
+use crate::coins::btc::{engine::*, rpc::BtcRpc};
+
+// The value of every anchor output, the dust limit of P2WSH outputs, which is above P2WPKH's
+pub const ANCHOR_VALUE: u64 = 330;
+
+// An output spendable by the holder of the specified key, so they can later pay for the transaction via a child
+pub fn anchor_output(key: &[u8]) -> anyhow::Result<TxOut> {
+  Ok(
+    TxOut {
+      // Scripts are the same on every network
+      script_pubkey: Address::p2wpkh(&PublicKey::from_slice(key)?, Network::Bitcoin).script_pubkey(),
+      value: ANCHOR_VALUE
+    }
+  )
+}
+
+/*
+  Pays for pre-signed transactions which are stuck, via children spending their anchor outputs
+  An anchor alone can't pay for much, so children are funded by a dedicated P2WPKH key, configured via cpfp_key
+  Without one, stuck transactions are only logged
+*/
+pub struct Bumper {
+  wallet: Option<PrivateKey>,
+  after_blocks: isize
+}
+
+impl Bumper {
+  pub fn new(config: &BtcConfig) -> anyhow::Result<Bumper> {
+    // Decoded by hand, as each chain has its own WIF prefix, which the key is only used on anyways
+    let wallet = config.cpfp_key.as_ref().map(|key| -> anyhow::Result<PrivateKey> {
+      let data = base58::from_check(key)?;
+      anyhow::ensure!((data.len() == 34) && (data[33] == 1), "CPFP key must be a WIF key for a compressed public key");
+      Ok(PrivateKey {
+        compressed: true,
+        network: Network::Bitcoin,
+        key: secp256k1::SecretKey::from_slice(&data[1 .. 33])?
+      })
+    }).transpose()?;
+    Ok(Bumper {
+      wallet,
+      after_blocks: config.cpfp_after_blocks
+    })
+  }
+
+  // Watch a just published transaction, which spent input_value, with an anchor output for our key
+  pub fn track(
+    &self,
+    parent: Transaction,
+    input_value: u64,
+    anchor_vout: u32,
+    anchor_key: secp256k1::SecretKey,
+    kind: TxKind,
+    height: isize
+  ) -> Cpfp {
+    Cpfp {
+      parent_fee: input_value.saturating_sub(parent.output.iter().map(|output| output.value).sum()),
+      parent,
+      anchor_vout,
+      anchor_key,
+      kind,
+      wallet: self.wallet,
+      after_blocks: self.after_blocks,
+      next_height: height + self.after_blocks,
+      funding: Vec::new(),
+      child_fee: 0
+    }
+  }
+}
+
+pub struct Cpfp {
+  parent: Transaction,
+  parent_fee: u64,
+  anchor_vout: u32,
+  anchor_key: secp256k1::SecretKey,
+  kind: TxKind,
+  wallet: Option<PrivateKey>,
+  after_blocks: isize,
+  next_height: isize,
+  // Replacement children must spend the same funding so they conflict with the child they replace
+  funding: Vec<(OutPoint, u64)>,
+  child_fee: u64
+}
+
+impl Cpfp {
+  /*
+    Called while waiting for the parent to confirm
+    Every after_blocks blocks it remains unconfirmed, a child, or a replacement for the last child, is published
+    Failures are only logged, as the parent may still confirm on its own
+  */
+  pub async fn poll(&mut self, rpc: &BtcRpc) {
+    if rpc.get_height().await < self.next_height {
+      return;
+    }
+    self.next_height += self.after_blocks;
+
+    if self.wallet.is_none() {
+      warn!("{} is still unconfirmed, yet no CPFP key is configured to pay for it", self.parent.txid());
+      return;
+    }
+    if let Err(err) = self.bump(rpc).await {
+      warn!("Couldn't pay for {} via CPFP: {:?}", self.parent.txid(), err);
+    }
+  }
+
+  async fn bump(&mut self, rpc: &BtcRpc) -> anyhow::Result<()> {
+    let wallet = self.wallet.expect("Bumping without a CPFP key");
+    let wallet_public = wallet.public_key(&SECP);
+    let wallet_script = Address::p2wpkh(&wallet_public, Network::Bitcoin).script_pubkey();
+    let wallet_address = rpc.params().encode_address(&wallet_script);
+
+    let fee_per_byte = rpc.get_fee_per_byte(self.kind).await?;
+    let parent_vsize = ((self.parent.get_weight() + 3) / 4) as u64;
+    if (self.child_fee == 0) && (self.parent_fee >= (parent_vsize * fee_per_byte)) {
+      info!("{} already pays the current fee rate of {}", self.parent.txid(), fee_per_byte);
+      return Ok(());
+    }
+
+    if self.funding.len() == 0 {
+      for utxo in rpc.get_spendable(&wallet_address).await? {
+        self.funding.push((OutPoint { txid: Txid::from_hex(&utxo.tx_hash)?, vout: utxo.tx_pos }, utxo.value));
+      }
+      anyhow::ensure!(self.funding.len() != 0, "CPFP address {} has no funds", wallet_address);
+    }
+
+    let mut inputs = vec![(OutPoint { txid: self.parent.txid(), vout: self.anchor_vout }, ANCHOR_VALUE, self.anchor_key)];
+    inputs.extend(self.funding.iter().map(|funding| (funding.0, funding.1, wallet.key)));
+    let input_value: u64 = inputs.iter().map(|input| input.1).sum();
+
+    let mut child = Transaction {
+      version: 2,
+      lock_time: 0,
+      input: inputs.iter().map(|input| TxIn {
+        previous_output: input.0,
+        script_sig: Script::new(),
+        // Signal replaceability so a later child can pay more
+        sequence: 0xFFFFFFFD,
+        witness: Vec::new()
+      }).collect(),
+      output: vec![
+        TxOut {
+          script_pubkey: wallet_script,
+          value: input_value
+        }
+      ]
+    };
+
+    // The child pays for the entire package to reach the current fee rate
+    let child_vsize = signed_vsize(&child, P2WPKH_WITNESS);
+    let mut fee = ((parent_vsize + child_vsize) * fee_per_byte).saturating_sub(self.parent_fee);
+    if self.child_fee != 0 {
+      // A replacement must pay for its own relay on top of the fee of the child it replaces
+      fee = fee.max(self.child_fee + child_vsize);
+    }
+    child.output[0].value = input_value.checked_sub(fee).filter(|value| *value >= ANCHOR_VALUE)
+      .ok_or_else(|| anyhow::anyhow!("CPFP address {} doesn't have enough to pay {} sats of fees", wallet_address, fee))?;
+
+    let components = SighashComponents::new(&child);
+    let mut witnesses = Vec::new();
+    for (i, input) in inputs.iter().enumerate() {
+      let public = PublicKey {
+        compressed: true,
+        key: secp256k1::PublicKey::from_secret_key(&SECP, &input.2)
+      };
+      let mut signature = SECP.sign(
+        &secp256k1::Message::from_slice(
+          &components.sighash_all(&child.input[i], &Address::p2pkh(&public, Network::Bitcoin).script_pubkey(), input.1)
+        )?,
+        &input.2
+      ).serialize_der().to_vec();
+      signature.push(1);
+      witnesses.push(vec![signature, public.to_bytes()]);
+    }
+    for (input, witness) in child.input.iter_mut().zip(witnesses) {
+      input.witness = witness;
+    }
+
+    rpc.publish(&serialize(&child)).await?;
+    info!("Published {} paying {} sats for {} via CPFP", child.txid(), fee, self.parent.txid());
+    self.child_fee = fee;
+    Ok(())
+  }
+}
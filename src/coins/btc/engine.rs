@@ -7,36 +7,342 @@ use serde::{Serialize, Deserialize};
 
 use bitcoin::{
   secp256k1::{self, Secp256k1},
+  bech32::{self, u5, ToBase32, FromBase32},
   hashes::Hash, hash_types::{Txid, WPubkeyHash},
   blockdata::{script::Script, transaction::{OutPoint, TxIn, TxOut, Transaction}},
   network::constants::Network,
-  util::{address::Address, bip143::SighashComponents}
+  util::{address::Address, base58, bip143::SighashComponents}
 };
 
-use crate::crypt_engines::{CryptEngine, secp256k1_engine::Secp256k1Engine};
-
-pub const T0: u8 = 6;
-pub const T1: u8 = 6;
-pub const SWAP_CUTOFF_BLOCKS: isize = 4;
+use crate::{
+  crypt_engines::{CryptEngine, secp256k1_engine::Secp256k1Engine},
+  coins::{Timelocks, btc::{cpfp::{ANCHOR_VALUE, anchor_output}, taproot::TaprootLock}}
+};
 
 #[cfg(not(feature = "no_confs"))]
 pub const CONFIRMATIONS: isize = 1;
 #[cfg(feature = "no_confs")]
 pub const CONFIRMATIONS: isize = -1;
 
-#[cfg(not(test))]
-pub const NETWORK: Network = Network::Testnet;
-#[cfg(test)]
-pub const NETWORK: Network = Network::Regtest;
-
 lazy_static! {
   pub static ref SECP: Secp256k1<secp256k1::All> = Secp256k1::new();
 }
 
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum BtcNetwork {
+  Mainnet,
+  Testnet,
+  Signet,
+  Regtest
+}
+
+impl Default for BtcNetwork {
+  fn default() -> BtcNetwork {
+    BtcNetwork::Testnet
+  }
+}
+
+impl BtcNetwork {
+  // The name bitcoind, and Litecoin Core, report for this network
+  pub fn name(&self) -> &'static str {
+    match self {
+      BtcNetwork::Mainnet => "main",
+      BtcNetwork::Testnet => "test",
+      BtcNetwork::Signet => "signet",
+      BtcNetwork::Regtest => "regtest"
+    }
+  }
+}
+
+/*
+  The chains supported as the scripted coin, which all share Bitcoin's scripts and transaction format
+  They only differ in their address encodings, genesis blocks, and block times
+*/
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BtcChain {
+  Bitcoin,
+  Litecoin
+}
+
+impl BtcChain {
+  // The average time between blocks, used to check the unscripted coin can settle before the swap's cutoff
+  pub fn block_seconds(&self) -> u64 {
+    match self {
+      BtcChain::Bitcoin => 600,
+      BtcChain::Litecoin => 150
+    }
+  }
+}
+
+// A chain and the network of it in use, which decide how addresses are encoded
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BtcParams {
+  pub chain: BtcChain,
+  pub network: BtcNetwork
+}
+
+impl BtcParams {
+  pub fn new(chain: BtcChain, network: BtcNetwork) -> anyhow::Result<BtcParams> {
+    if (chain == BtcChain::Litecoin) && (network == BtcNetwork::Signet) {
+      anyhow::bail!("Litecoin doesn't have a signet");
+    }
+    Ok(BtcParams { chain, network })
+  }
+
+  // Signet shares testnet's encoding
+  fn hrp(&self) -> &'static str {
+    match (self.chain, self.network) {
+      (BtcChain::Bitcoin, BtcNetwork::Mainnet) => "bc",
+      (BtcChain::Bitcoin, BtcNetwork::Testnet) | (BtcChain::Bitcoin, BtcNetwork::Signet) => "tb",
+      (BtcChain::Bitcoin, BtcNetwork::Regtest) => "bcrt",
+      (BtcChain::Litecoin, BtcNetwork::Mainnet) => "ltc",
+      (BtcChain::Litecoin, BtcNetwork::Regtest) => "rltc",
+      (BtcChain::Litecoin, _) => "tltc"
+    }
+  }
+
+  // The base58 prefixes for P2PKH and P2SH addresses, where regtest shares testnet's
+  fn base58_prefixes(&self) -> (u8, u8) {
+    match (self.chain, self.network) {
+      (BtcChain::Bitcoin, BtcNetwork::Mainnet) => (0x00, 0x05),
+      (BtcChain::Bitcoin, _) => (0x6f, 0xc4),
+      // Litecoin moved P2SH addresses from Bitcoin's 3 prefix to M, and only the latter is accepted
+      (BtcChain::Litecoin, BtcNetwork::Mainnet) => (0x30, 0x32),
+      (BtcChain::Litecoin, _) => (0x6f, 0x3a)
+    }
+  }
+
+  pub fn genesis_hash(&self) -> &'static str {
+    match (self.chain, self.network) {
+      (BtcChain::Bitcoin, BtcNetwork::Mainnet) => "000000000019d6689c085ae165831e934ff763aae46a2a6c172b3f1b60a8ce26",
+      (BtcChain::Bitcoin, BtcNetwork::Testnet) => "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943",
+      (BtcChain::Bitcoin, BtcNetwork::Signet) => "00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6",
+      (BtcChain::Bitcoin, BtcNetwork::Regtest) => "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206",
+      (BtcChain::Litecoin, BtcNetwork::Mainnet) => "12a765e31ffd4059bada1e25190f6e98c99d9714d334efa41a195a7e7e04bfe2",
+      (BtcChain::Litecoin, BtcNetwork::Regtest) => "530827f38f93b43ed12af0b3ad25a288dc02ed74d6d7857862df51fc56c416f9",
+      (BtcChain::Litecoin, _) => "4966625a4b2851d9fdee139e56211a0d88575f59ed816ff5e6a63deb4e3e29a0"
+    }
+  }
+
+  /*
+    Segwit v1 addresses use bech32m, which bech32 0.7 predates
+    It only differs from bech32 in the constant its checksum is offset by
+  */
+  fn encode_bech32m(&self, data: &[u5]) -> String {
+    const CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+
+    let hrp = self.hrp();
+    let mut values: Vec<u8> = hrp.bytes().map(|c| c >> 5).collect();
+    values.push(0);
+    values.extend(hrp.bytes().map(|c| c & 31));
+    values.extend(data.iter().map(|value| value.to_u8()));
+    values.extend(&[0; 6]);
+    let mut checksum: u32 = 1;
+    for value in values {
+      let top = checksum >> 25;
+      checksum = ((checksum & 0x1ffffff) << 5) ^ (value as u32);
+      for (i, generator) in GENERATOR.iter().enumerate() {
+        if ((top >> i) & 1) == 1 {
+          checksum ^= generator;
+        }
+      }
+    }
+    checksum ^= 0x2bc830a3;
+
+    let mut address = format!("{}1", hrp);
+    for value in data {
+      address.push(CHARSET[value.to_u8() as usize] as char);
+    }
+    for i in 0 .. 6 {
+      address.push(CHARSET[((checksum >> (5 * (5 - i))) & 31) as usize] as char);
+    }
+    address
+  }
+
+  // Only called with the output scripts this library creates or decoded from an address
+  pub fn encode_address(&self, script: &Script) -> String {
+    let bytes = script.as_bytes();
+    let (p2pkh, p2sh) = self.base58_prefixes();
+    if script.is_v0_p2wpkh() || script.is_v0_p2wsh() {
+      let mut data = vec![u5::try_from_u8(0).unwrap()];
+      data.extend(bytes[2 ..].to_base32());
+      bech32::encode(self.hrp(), data).expect("Couldn't encode a bech32 address")
+    } else if (bytes.len() == 34) && (bytes[0] == 0x51) && (bytes[1] == 0x20) {
+      // A Taproot output
+      let mut data = vec![u5::try_from_u8(1).unwrap()];
+      data.extend(bytes[2 ..].to_base32());
+      self.encode_bech32m(&data)
+    } else if script.is_p2pkh() {
+      let mut data = vec![p2pkh];
+      data.extend(&bytes[3 .. 23]);
+      base58::check_encode_slice(&data)
+    } else if script.is_p2sh() {
+      let mut data = vec![p2sh];
+      data.extend(&bytes[2 .. 22]);
+      base58::check_encode_slice(&data)
+    } else {
+      panic!("Tried to encode an address for an unsupported script");
+    }
+  }
+
+  pub fn p2wsh_address(&self, script: &Script) -> String {
+    self.encode_address(&Address::p2wsh(script, Network::Bitcoin).script_pubkey())
+  }
+
+  pub fn decode_address(&self, address: &str) -> anyhow::Result<Script> {
+    if let Ok((hrp, data)) = bech32::decode(address) {
+      if hrp != self.hrp() {
+        anyhow::bail!("{} isn't an address for the configured network", address);
+      }
+      if (data.len() == 0) || (data[0].to_u8() != 0) {
+        anyhow::bail!("{} isn't a SegWit v0 address", address);
+      }
+      let program = Vec::<u8>::from_base32(&data[1 ..])?;
+      if (program.len() != 20) && (program.len() != 32) {
+        anyhow::bail!("{} has an invalid witness program length", address);
+      }
+      let mut script = vec![0, program.len() as u8];
+      script.extend(program);
+      return Ok(Script::from(script));
+    }
+
+    let data = base58::from_check(address)?;
+    if data.len() != 21 {
+      anyhow::bail!("{} has an invalid length", address);
+    }
+    let (p2pkh, p2sh) = self.base58_prefixes();
+    let mut script;
+    if data[0] == p2pkh {
+      script = hex!("76a914").to_vec();
+      script.extend(&data[1 ..]);
+      script.extend(&hex!("88ac"));
+    } else if data[0] == p2sh {
+      script = hex!("a914").to_vec();
+      script.extend(&data[1 ..]);
+      script.push(0x87);
+    } else {
+      anyhow::bail!("{} isn't an address for the configured network", address);
+    }
+    Ok(Script::from(script))
+  }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum BtcBackendKind {
+  Electrum,
+  Bitcoind,
+  Esplora
+}
+
+impl Default for BtcBackendKind {
+  fn default() -> BtcBackendKind {
+    BtcBackendKind::Electrum
+  }
+}
+
+// The transactions this swap creates, each with its own confirmation target
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TxKind {
+  Lock,
+  Refund,
+  Spend,
+  Buy,
+  Claim
+}
+
+// Confirmation targets, in blocks, used when estimating each transaction's fee rate
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct FeeTargets {
+  pub lock: usize,
+  pub refund: usize,
+  pub spend: usize,
+  pub buy: usize,
+  pub claim: usize
+}
+
+impl Default for FeeTargets {
+  fn default() -> FeeTargets {
+    FeeTargets {
+      lock: 3,
+      refund: 6,
+      spend: 6,
+      buy: 3,
+      claim: 6
+    }
+  }
+}
+
+impl FeeTargets {
+  pub fn target(&self, kind: TxKind) -> usize {
+    match kind {
+      TxKind::Lock => self.lock,
+      TxKind::Refund => self.refund,
+      TxKind::Spend => self.spend,
+      TxKind::Buy => self.buy,
+      TxKind::Claim => self.claim
+    }
+  }
+}
+
+fn default_min_fee_per_byte() -> u64 {
+  1
+}
+
+fn default_max_fee_per_byte() -> u64 {
+  500
+}
+
+fn default_cpfp_after_blocks() -> isize {
+  2
+}
+
+// The lock the host creates
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LockKind {
+  P2wsh,
+  Taproot
+}
+
+impl Default for LockKind {
+  fn default() -> LockKind {
+    LockKind::P2wsh
+  }
+}
+
 #[derive(Deserialize)]
 pub struct BtcConfig {
+  #[serde(default)]
+  pub network: BtcNetwork,
+  #[serde(default)]
+  pub backend: BtcBackendKind,
+  // The Electrum daemon's URL, bitcoind's when using the bitcoind backend, or the base of the Esplora API
   pub url: String,
   pub btc_url: Option<String>,
+  // The bitcoind wallet to watch addresses with, if bitcoind has multiple loaded
+  pub wallet: Option<String>,
+  // Estimates are clamped to this range, which is also what's offered to the counterparty
+  #[serde(default = "default_min_fee_per_byte")]
+  pub min_fee_per_byte: u64,
+  #[serde(default = "default_max_fee_per_byte")]
+  pub max_fee_per_byte: u64,
+  #[serde(default)]
+  pub fee_targets: FeeTargets,
+  // Offered when hosting, and the minimum window for the buy to confirm in when joining
+  #[serde(default)]
+  pub timelocks: Timelocks,
+  // A WIF key for a compressed P2WPKH address funding CPFP children of stuck transactions
+  pub cpfp_key: Option<String>,
+  // How many blocks a transaction may remain unconfirmed before it's bumped
+  #[serde(default = "default_cpfp_after_blocks")]
+  pub cpfp_after_blocks: isize,
+  // Only used when hosting, as the client follows whichever lock the host chose
+  #[serde(default)]
+  pub lock: LockKind,
   pub destination: String,
   pub refund: String
 }
@@ -44,12 +350,23 @@ pub struct BtcConfig {
 #[derive(Serialize, Deserialize)]
 pub struct BuyInfo {
   pub value: u64,
-  pub encrypted_signature: Vec<u8>
+  // Empty for a Taproot lock, where the buy is instead signed via MuSig with these nonces
+  pub encrypted_signature: Vec<u8>,
+  pub nonces: Vec<u8>
+}
+
+// The client's half of a Taproot lock's buy, replying to the host's BuyInfo
+#[derive(Serialize, Deserialize)]
+pub struct ClientBuySignature {
+  pub nonces: Vec<u8>,
+  pub partial_signature: Vec<u8>
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct LockAndRefundInfo {
+  // Empty for a Taproot lock, which has no hash lock
   pub swap_hash: Vec<u8>,
+  pub taproot: bool,
   pub lock_id: Vec<u8>,
   pub host_refund_signature: Vec<u8>,
   pub value: u64,
@@ -62,6 +379,7 @@ pub struct ClientRefundAndSpendSignatures {
   pub encrypted_spend_signature: Vec<u8>
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct BtcEngine {
   pub b: <Secp256k1Engine as CryptEngine>::PrivateKey,
   pub br: <Secp256k1Engine as CryptEngine>::PrivateKey,
@@ -69,6 +387,8 @@ pub struct BtcEngine {
 
   pub lock_script_bytes: Option<Vec<u8>>,
   lock_script: Option<Script>,
+  // Replaces the lock script, if the host chose a Taproot lock
+  pub taproot: Option<TaprootLock>,
 
   pub refund_script_bytes: Option<Vec<u8>>
 }
@@ -82,17 +402,13 @@ impl BtcEngine {
 
       lock_script_bytes: None,
       lock_script: None,
+      taproot: None,
 
       refund_script_bytes: None
     }
   }
 
-  pub fn decode_address(address: &str) -> anyhow::Result<Script> {
-    let address: Address = address.parse()?;
-    Ok(address.script_pubkey())
-  }
-
-  pub fn generate_deposit_address() -> (<Secp256k1Engine as CryptEngine>::PrivateKey, String, [u8; 20]) {
+  pub fn generate_deposit_address(params: BtcParams) -> (<Secp256k1Engine as CryptEngine>::PrivateKey, String, [u8; 20]) {
     let key = Secp256k1Engine::new_private_key();
     let public_key = &bitcoin::util::key::PublicKey {
       compressed: true,
@@ -108,7 +424,7 @@ impl BtcEngine {
 
     (
       key,
-      Address::p2wpkh(public_key, NETWORK).to_string(),
+      params.encode_address(&Address::p2wpkh(public_key, Network::Bitcoin).script_pubkey()),
       // This is the same payload used in the Address
       // The field unfortunately isn't public though
       WPubkeyHash::from_engine(hash_engine).as_ref().try_into().expect("Couldn't convert a twenty-byte hash to a twenty-byte array")
@@ -116,13 +432,96 @@ impl BtcEngine {
   }
 }
 
+/*
+  The largest DER-encoded signature, including its sighash type
+  Signatures are usually a byte or two shorter, so this slightly overestimates fees instead of risking underpaying
+*/
+const MAX_SIGNATURE_LENGTH: usize = 73;
+// BIP340 signatures, which are a fixed length, and omit the sighash type when it's SIGHASH_DEFAULT
+const SCHNORR_SIGNATURE_LENGTH: usize = 64;
+const COMPRESSED_KEY_LENGTH: usize = 33;
+// The control block for a tree with a single leaf, which is just the leaf version and the internal key
+const CONTROL_BLOCK_LENGTH: usize = 33;
+pub const P2WPKH_WITNESS: &[usize] = &[MAX_SIGNATURE_LENGTH, COMPRESSED_KEY_LENGTH];
+
+/*
+  The virtual size of a transaction once every input has a witness with items of the specified lengths
+  Fees must be calculated before signing, when the witness is still empty, yet the witness is most of these transactions' weight
+*/
+pub fn signed_vsize(tx: &Transaction, witness: &[usize]) -> u64 {
+  let mut tx = tx.clone();
+  for input in tx.input.iter_mut() {
+    input.witness = witness.iter().map(|len| vec![0; *len]).collect();
+  }
+  ((tx.get_weight() + 3) / 4) as u64
+}
+
+/*
+  Pushes a positive number, minimally encoded as CHECKSEQUENCEVERIFY requires
+  Numbers are little endian with the top bit of the last byte as the sign, so 128 and up may need a trailing 0
+*/
+pub fn push_number(script: &mut Vec<u8>, number: u16) {
+  match number {
+    0 => script.push(0),
+    1 ..= 16 => script.push(0x50 + (number as u8)),
+    _ => {
+      let mut bytes = number.to_le_bytes().to_vec();
+      if bytes[1] == 0 {
+        bytes.pop();
+      }
+      if (bytes[bytes.len() - 1] & 0x80) != 0 {
+        bytes.push(0);
+      }
+      script.push(bytes.len() as u8);
+      script.extend(bytes);
+    }
+  }
+}
+
 impl BtcEngine {
+  // Both signatures, the false branch selector, and the lock script, or both signatures, the refund leaf, and its control block
+  pub fn refund_witness(&self) -> Vec<usize> {
+    if let Some(taproot) = self.taproot.as_ref() {
+      return vec![SCHNORR_SIGNATURE_LENGTH, SCHNORR_SIGNATURE_LENGTH, taproot.refund_leaf.len(), CONTROL_BLOCK_LENGTH];
+    }
+    vec![0, MAX_SIGNATURE_LENGTH, MAX_SIGNATURE_LENGTH, 0, self.lock_script_bytes().len()]
+  }
+
+  // Both signatures, the swap secret, the true branch selector, and the lock script, or the key path's signature
+  pub fn buy_witness(&self) -> Vec<usize> {
+    if self.taproot.is_some() {
+      return vec![SCHNORR_SIGNATURE_LENGTH];
+    }
+    vec![0, MAX_SIGNATURE_LENGTH, MAX_SIGNATURE_LENGTH, 32, 1, self.lock_script_bytes().len()]
+  }
+
+  // Both signatures, the true branch selector, and the refund script
+  pub fn spend_witness(&self) -> Vec<usize> {
+    vec![
+      0,
+      MAX_SIGNATURE_LENGTH,
+      MAX_SIGNATURE_LENGTH,
+      1,
+      self.refund_script_bytes.as_ref().expect("Retrieving the spend's witness before creating the refund script").len()
+    ]
+  }
+
+  // The client's signature, the false branch selector, and the refund script
+  pub fn claim_witness(&self) -> Vec<usize> {
+    vec![
+      MAX_SIGNATURE_LENGTH,
+      0,
+      self.refund_script_bytes.as_ref().expect("Retrieving the claim's witness before creating the refund script").len()
+    ]
+  }
+
   pub fn create_lock_script(
     &mut self,
     swap_hash: &[u8],
     is_host: bool,
     other: &[u8],
-    other_refund: &[u8]
+    other_refund: &[u8],
+    t0: u16
   ) -> Vec<u8> {
     let b = Secp256k1Engine::public_key_to_bytes(&Secp256k1Engine::to_public_key(&self.b));
     let br = Secp256k1Engine::public_key_to_bytes(&Secp256k1Engine::to_public_key(&self.br));
@@ -141,11 +540,7 @@ impl BtcEngine {
     lock_script.extend(bs[1]);
 
     lock_script.extend(&hex!("52ae67"));
-    match T0 {
-      0 => lock_script.push(0),
-      1 ..= 16 => lock_script.push(80 + T0),
-      _ => lock_script.extend(&[1, T0])
-    };
+    push_number(&mut lock_script, t0);
     lock_script.extend(&hex!("b2755221"));
     lock_script.extend(bs[2]);
     lock_script.extend(&hex!("21"));
@@ -174,6 +569,72 @@ impl BtcEngine {
     self.lock_script.as_ref().expect("Retrieving the lock script before creating it")
   }
 
+  // Creates the Taproot lock in place of the lock script, with the host's keys first
+  pub fn create_taproot_lock(
+    &mut self,
+    is_host: bool,
+    other: &[u8],
+    other_refund: &[u8],
+    t0: u16
+  ) -> anyhow::Result<()> {
+    let mut keys = vec![Secp256k1Engine::bytes_to_public_key(other)?, Secp256k1Engine::to_public_key(&self.b)];
+    let mut refund_keys = vec![Secp256k1Engine::bytes_to_public_key(other_refund)?, Secp256k1Engine::to_public_key(&self.br)];
+    if is_host {
+      keys.swap(0, 1);
+      refund_keys.swap(0, 1);
+    }
+    self.taproot = Some(TaprootLock::new(&keys, t0, &refund_keys[0], &refund_keys[1])?);
+    Ok(())
+  }
+
+  // The lock's output script, whose address is watched for the lock and whatever spends it
+  pub fn lock_script_pubkey(&self) -> Script {
+    if let Some(taproot) = self.taproot.as_ref() {
+      return Script::from(taproot.script_pubkey());
+    }
+    // Scripts are the same on every network
+    Address::p2wsh(self.lock_script(), Network::Bitcoin).script_pubkey()
+  }
+
+  // The message the buy's signature is for, where the buy's only input is the lock
+  pub fn buy_message(&self, buy: &Transaction, lock_value: u64) -> Vec<u8> {
+    if let Some(taproot) = self.taproot.as_ref() {
+      return taproot.sighash(buy, lock_value, false).to_vec();
+    }
+    SighashComponents::new(buy).sighash_all(&buy.input[0], self.lock_script(), lock_value).to_vec()
+  }
+
+  pub fn verify_refund_signature(&self, message: &secp256k1::Message, signature: &[u8], key: &[u8]) -> anyhow::Result<()> {
+    if self.taproot.is_some() {
+      return Secp256k1Engine::schnorr_verify(
+        &Secp256k1Engine::x_only(&Secp256k1Engine::bytes_to_public_key(key)?),
+        &Secp256k1Engine::bytes_to_schnorr_signature(signature)?,
+        &message[..]
+      );
+    }
+    SECP.verify(message, &secp256k1::Signature::from_der(signature)?, &secp256k1::PublicKey::from_slice(key)?)?;
+    Ok(())
+  }
+
+  // The refund's witness, given both parties' signatures for it
+  pub fn refund_witness_with_signatures(&self, host_signature: Vec<u8>, client_signature: Vec<u8>) -> Vec<Vec<u8>> {
+    // The refund leaf checks the host's signature first, so it's on the top of the stack
+    if let Some(taproot) = self.taproot.as_ref() {
+      return vec![client_signature, host_signature, taproot.refund_leaf.clone(), taproot.control_block()];
+    }
+
+    let mut witness = vec![
+      Vec::new(),
+      client_signature,
+      host_signature,
+      Vec::new(),
+      self.lock_script_bytes().to_vec()
+    ];
+    witness[1].push(1);
+    witness[2].push(1);
+    witness
+  }
+
   pub fn prepare_and_sign_refund(
     &mut self,
     lock_id: Txid,
@@ -181,7 +642,8 @@ impl BtcEngine {
     other_refund: &[u8],
     client: &[u8],
     value: u64,
-    fee_per_byte: u64
+    fee_per_byte: u64,
+    timelocks: &Timelocks
   ) -> anyhow::Result<(Script, Transaction, secp256k1::Message, Vec<u8>)> {
     #[allow(non_snake_case)]
     let BR = Secp256k1Engine::public_key_to_bytes(&Secp256k1Engine::to_public_key(&self.br));
@@ -196,11 +658,7 @@ impl BtcEngine {
     refund_script.push(refund_keys[1].len() as u8);
     refund_script.extend(refund_keys[1]);
     refund_script.extend(&[0x52, 0xae, 0x67]);
-    match T1 {
-      0 => refund_script.push(0),
-      1 ..= 16 => refund_script.push(80 + T1),
-      _ => refund_script.extend(&[1, T1])
-    };
+    push_number(&mut refund_script, timelocks.t1);
     refund_script.extend(&[0xb2, 0x75, client.len() as u8]);
     refund_script.extend(client);
     refund_script.extend(&[0xac, 0x68]);
@@ -216,21 +674,32 @@ impl BtcEngine {
             vout: 0
           },
           script_sig: Script::new(),
-          sequence: T0 as u32,
+          sequence: timelocks.t0 as u32,
           witness: Vec::new()
         }
       ],
       output: vec![
         TxOut {
-          script_pubkey: Address::p2wsh(&refund_script, NETWORK).script_pubkey(),
+          // Scripts are the same on every network
+          script_pubkey: Address::p2wsh(&refund_script, Network::Bitcoin).script_pubkey(),
           value: value
-        }
+        },
+        // Anchors letting either party pay for the refund if it's stuck, host's first
+        anchor_output(refund_keys[1])?,
+        anchor_output(refund_keys[0])?
       ]
     };
-    let fee = ((refund.get_weight() as u64) / 4) * fee_per_byte;
-    refund.output[0].value = refund.output[0].value.checked_sub(fee)
+    let fee = signed_vsize(&refund, &self.refund_witness()) * fee_per_byte;
+    refund.output[0].value = refund.output[0].value.checked_sub(fee + (2 * ANCHOR_VALUE))
       .ok_or_else(|| anyhow::anyhow!("Not enough Bitcoin to pay for {} sats of fees", fee))?;
 
+    // The Taproot refund is signed via the refund leaf, with BIP340 signatures
+    if let Some(taproot) = self.taproot.as_ref() {
+      let message = secp256k1::Message::from_slice(&taproot.sighash(&refund, value, true))?;
+      let signature = Secp256k1Engine::schnorr_signature_to_bytes(&Secp256k1Engine::schnorr_sign(&self.br, &message[..])?);
+      return Ok((refund_script, refund, message, signature));
+    }
+
     let components = SighashComponents::new(&refund);
     let message = secp256k1::Message::from_slice(
       &components.sighash_all(
@@ -258,8 +727,10 @@ impl BtcEngine {
   }
 
   pub fn prepare_spend(
+    &self,
     refund_id: Txid,
     output: Script,
+    anchor: &[u8],
     value: u64,
     fee_per_byte: u64
   ) -> anyhow::Result<Transaction> {
@@ -281,11 +752,13 @@ impl BtcEngine {
         TxOut {
           script_pubkey: output,
           value: value
-        }
+        },
+        // Lets the host pay for the spend if it's stuck
+        anchor_output(anchor)?
       ]
     };
-    let fee = ((spend.get_weight() as u64) / 4) * fee_per_byte;
-    spend.output[0].value = spend.output[0].value.checked_sub(fee)
+    let fee = signed_vsize(&spend, &self.spend_witness()) * fee_per_byte;
+    spend.output[0].value = spend.output[0].value.checked_sub(fee + ANCHOR_VALUE)
       .ok_or_else(|| anyhow::anyhow!("Not enough Bitcoin to pay for {} sats of fees", fee))?;
     Ok(spend)
   }
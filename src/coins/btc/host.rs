@@ -10,27 +10,69 @@ use hex_literal::hex;
 use rand::{rngs::OsRng, RngCore};
 use digest::Digest;
 
-use serde::Deserialize;
+use secp256kfun::{marker::*, Scalar};
+
+use serde::{Serialize, Deserialize};
 
 use bitcoin::{
   secp256k1,
   hashes::hex::FromHex, hash_types::Txid,
   blockdata::{script::Script, transaction::{OutPoint, TxIn, TxOut, Transaction}},
+  network::constants::Network,
   util::{address::Address, bip143::SighashComponents},
   consensus::serialize
 };
 
 use crate::{
-  crypt_engines::{KeyBundle, CryptEngine, secp256k1_engine::Secp256k1Engine},
+  crypt_engines::{KeyBundle, CryptEngine, secp256k1_engine::{Secp256k1Engine, schnorr::*}},
   coins::{
-    ScriptedHost, UnscriptedVerifier,
-    btc::{engine::*, rpc::*}
+    FeeBounds, Timelocks, SwapTerms, ScriptedHost, UnscriptedVerifier,
+    btc::{engine::*, rpc::*, cpfp::*, taproot::*}
   }
 };
 
+#[derive(Serialize, Deserialize)]
+struct BtcHostState {
+  engine: BtcEngine,
+  terms: Option<SwapTerms>,
+  address: Option<(<Secp256k1Engine as CryptEngine>::PrivateKey, String, [u8; 20])>,
+
+  swap_secret: [u8; 32],
+
+  lock: Option<Transaction>,
+  lock_height: Option<isize>,
+
+  refund: Option<Transaction>,
+  spend: Option<Transaction>,
+  refund_script: Option<Script>,
+  refund_message: Option<Vec<u8>>,
+  refund_signature: Option<Vec<u8>>,
+  spend_message: Option<Vec<u8>>,
+  encrypted_spend_signature: Option<<Secp256k1Engine as CryptEngine>::EncryptedSignature>,
+
+  client: Option<Vec<u8>>,
+  client_refund: Option<Vec<u8>>,
+  client_destination_script: Option<Script>,
+
+  encryption_key: Option<<Secp256k1Engine as CryptEngine>::PublicKey>,
+  encrypted_signature: Option<<Secp256k1Engine as CryptEngine>::EncryptedSignature>,
+  buy: Option<Txid>,
+
+  // Only used with a Taproot lock, where the buy is signed via MuSig
+  buy_message: Option<Vec<u8>>,
+  buy_nonces: Option<SecretNonces>,
+  buy_session: Option<MusigSession>,
+  // Ours, then the client's
+  buy_partial_signatures: Option<Vec<Scalar::<Public, Zero>>>
+}
+
 pub struct BtcHost {
   engine: BtcEngine,
+  terms: Option<SwapTerms>,
   rpc: BtcRpc,
+  bumper: Bumper,
+  timelocks: Timelocks,
+  lock_kind: LockKind,
   #[cfg(test)]
   refund_pubkey: Option<bitcoin::util::key::PublicKey>,
   refund_pubkey_script: Script,
@@ -56,22 +98,35 @@ pub struct BtcHost {
 
   encryption_key: Option<<Secp256k1Engine as CryptEngine>::PublicKey>,
   encrypted_signature: Option<<Secp256k1Engine as CryptEngine>::EncryptedSignature>,
-  buy: Option<Txid>
+  buy: Option<Txid>,
+
+  // Only used with a Taproot lock, where the buy is signed via MuSig
+  buy_message: Option<Vec<u8>>,
+  buy_nonces: Option<SecretNonces>,
+  buy_session: Option<MusigSession>,
+  // Ours, then the client's
+  buy_partial_signatures: Option<Vec<Scalar::<Public, Zero>>>
 }
 
 impl BtcHost {
-  pub fn new(config_path: &Path) -> anyhow::Result<BtcHost> {
-    let config = serde_json::from_reader(File::open(config_path)?)?;
+  pub async fn new(config_path: &Path, chain: BtcChain) -> anyhow::Result<BtcHost> {
+    let config: BtcConfig = serde_json::from_reader(File::open(config_path)?)?;
+
+    let rpc = BtcRpc::new(&config, chain).await?;
 
     let mut swap_secret = [0; 32];
     OsRng.fill_bytes(&mut swap_secret);
     Ok(BtcHost {
       engine: BtcEngine::new(),
-      rpc: BtcRpc::new(&config)?,
+      terms: None,
+      bumper: Bumper::new(&config)?,
+      timelocks: config.timelocks,
+      lock_kind: config.lock,
       #[cfg(test)]
       refund_pubkey: None,
-      refund_pubkey_script: BtcEngine::decode_address(&config.refund)?,
+      refund_pubkey_script: rpc.params().decode_address(&config.refund)?,
       address: None,
+      rpc,
 
       swap_secret,
       swap_hash: sha2::Sha256::digest(&swap_secret).to_vec(),
@@ -93,24 +148,35 @@ impl BtcHost {
 
       encryption_key: None,
       encrypted_signature: None,
-      buy: None
+      buy: None,
+
+      buy_message: None,
+      buy_nonces: None,
+      buy_session: None,
+      buy_partial_signatures: None
     })
   }
 
   async fn prepare_refund_and_spend(&mut self, lock_id: Txid, lock_value: u64) -> anyhow::Result<(u64, Vec<u8>)> {
-    let fee_per_byte = self.rpc.get_fee_per_byte().await?;
+    // Both are signed now with a single rate, so use whichever of their targets is more demanding
+    let terms = self.terms.expect("Creating refund before agreeing on terms");
+    let fee_per_byte = terms.scripted_fee_bounds.clamp(
+      self.rpc.get_fee_per_byte(TxKind::Refund).await?.max(self.rpc.get_fee_per_byte(TxKind::Spend).await?)
+    );
     let (refund_script, refund, refund_message, sig) = self.engine.prepare_and_sign_refund(
       lock_id,
       true,
       self.client_refund.as_ref().expect("Creating refund before verifying keys"),
       self.client.as_ref().expect("Creating refund before verifying keys"),
       lock_value,
-      fee_per_byte
+      fee_per_byte,
+      &terms.timelocks
     )?;
 
-    let spend = BtcEngine::prepare_spend(
+    let spend = self.engine.prepare_spend(
       refund.txid(),
       self.refund_pubkey_script.clone(),
+      &Secp256k1Engine::public_key_to_bytes(&Secp256k1Engine::to_public_key(&self.engine.br)),
       refund.output[0].value,
       fee_per_byte
     )?;
@@ -135,6 +201,93 @@ impl BtcHost {
 
 #[async_trait]
 impl ScriptedHost for BtcHost {
+  fn serialize_state(&self) -> Vec<u8> {
+    bincode::serialize(
+      &BtcHostState {
+        engine: self.engine.clone(),
+        terms: self.terms,
+        address: self.address.clone(),
+
+        swap_secret: self.swap_secret,
+
+        lock: self.lock.clone(),
+        lock_height: self.lock_height,
+
+        refund: self.refund.clone(),
+        spend: self.spend.clone(),
+        refund_script: self.refund_script.clone(),
+        refund_message: self.refund_message.map(|message| message[..].to_vec()),
+        refund_signature: self.refund_signature.clone(),
+        spend_message: self.spend_message.clone(),
+        encrypted_spend_signature: self.encrypted_spend_signature.clone(),
+
+        client: self.client.clone(),
+        client_refund: self.client_refund.clone(),
+        client_destination_script: self.client_destination_script.clone(),
+
+        encryption_key: self.encryption_key.clone(),
+        encrypted_signature: self.encrypted_signature.clone(),
+        buy: self.buy,
+
+        buy_message: self.buy_message.clone(),
+        buy_nonces: self.buy_nonces.clone(),
+        buy_session: self.buy_session.clone(),
+        buy_partial_signatures: self.buy_partial_signatures.clone()
+      }
+    ).expect("Couldn't serialize the BTC host's state")
+  }
+
+  fn restore_state(&mut self, state: &[u8]) -> anyhow::Result<()> {
+    let state: BtcHostState = bincode::deserialize(state)?;
+    self.engine = state.engine;
+    self.terms = state.terms;
+    self.address = state.address;
+
+    self.swap_secret = state.swap_secret;
+    self.swap_hash = sha2::Sha256::digest(&self.swap_secret).to_vec();
+
+    self.lock = state.lock;
+    self.lock_height = state.lock_height;
+
+    self.refund = state.refund;
+    self.spend = state.spend;
+    self.refund_script = state.refund_script;
+    self.refund_message = state.refund_message.map(|message| secp256k1::Message::from_slice(&message)).transpose()?;
+    self.refund_signature = state.refund_signature;
+    self.spend_message = state.spend_message;
+    self.encrypted_spend_signature = state.encrypted_spend_signature;
+
+    self.client = state.client;
+    self.client_refund = state.client_refund;
+    self.client_destination_script = state.client_destination_script;
+
+    self.encryption_key = state.encryption_key;
+    self.encrypted_signature = state.encrypted_signature;
+    self.buy = state.buy;
+
+    self.buy_message = state.buy_message;
+    self.buy_nonces = state.buy_nonces;
+    self.buy_session = state.buy_session;
+    self.buy_partial_signatures = state.buy_partial_signatures;
+    Ok(())
+  }
+
+  fn set_terms(&mut self, terms: SwapTerms) {
+    self.terms = Some(terms);
+  }
+
+  fn fee_bounds(&self) -> FeeBounds {
+    self.rpc.fee_bounds()
+  }
+
+  fn timelocks(&self) -> Timelocks {
+    self.timelocks
+  }
+
+  fn block_seconds(&self) -> u64 {
+    self.rpc.params().chain.block_seconds()
+  }
+
   fn generate_keys<Verifier: UnscriptedVerifier>(&mut self, verifier: &mut Verifier) -> Vec<u8> {
     let (dl_eq, key) = verifier.generate_keys_for_engine::<Secp256k1Engine>(PhantomData);
     self.engine.bs = Some(key);
@@ -159,12 +312,33 @@ impl ScriptedHost for BtcHost {
     Ok(())
   }
 
+  // With a Taproot lock, our partial signature for the buy takes the place of the swap secret
   fn swap_secret(&self) -> [u8; 32] {
+    if let Some(partial_signatures) = self.buy_partial_signatures.as_ref() {
+      return partial_signatures[0].to_bytes();
+    }
     self.swap_secret
   }
 
+  fn txids(&self) -> Vec<(&'static str, String)> {
+    let mut txids = Vec::new();
+    if let Some(lock) = self.lock.as_ref() {
+      txids.push(("lock", lock.txid().to_string()));
+    }
+    if let Some(refund) = self.refund.as_ref() {
+      txids.push(("refund", refund.txid().to_string()));
+    }
+    if let Some(spend) = self.spend.as_ref() {
+      txids.push(("spend", spend.txid().to_string()));
+    }
+    if let Some(buy) = self.buy {
+      txids.push(("buy", buy.to_string()));
+    }
+    txids
+  }
+
   fn generate_deposit_address(&mut self) -> String {
-    let address = BtcEngine::generate_deposit_address();
+    let address = BtcEngine::generate_deposit_address(self.rpc.params());
     self.address = Some(address.clone());
     address.1.to_string()
   }
@@ -201,9 +375,13 @@ impl ScriptedHost for BtcHost {
       })
     }).collect::<anyhow::Result<_>>()?;
 
-    self.engine.create_lock_script(&self.swap_hash, true, client, client_refund);
-    let mut lock_script_hash = hex!("0020").to_vec();
-    lock_script_hash.extend(sha2::Sha256::digest(self.engine.lock_script_bytes()));
+    let t0 = self.terms.expect("Creating lock before agreeing on terms").timelocks.t0;
+    let taproot = self.lock_kind == LockKind::Taproot;
+    if taproot {
+      self.engine.create_taproot_lock(true, client, client_refund, t0)?;
+    } else {
+      self.engine.create_lock_script(&self.swap_hash, true, client, client_refund, t0);
+    }
 
     let mut lock = Transaction {
       version: 2,
@@ -211,12 +389,14 @@ impl ScriptedHost for BtcHost {
       input: inputs,
       output: vec![TxOut {
         value,
-        script_pubkey: Script::from(lock_script_hash)
+        script_pubkey: self.engine.lock_script_pubkey()
       }]
     };
-    let fee = ((lock.get_weight() / 4) as u64) * self.rpc.get_fee_per_byte().await?;
+    let fee = signed_vsize(&lock, P2WPKH_WITNESS) * self.rpc.get_fee_per_byte(TxKind::Lock).await?;
     lock.output[0].value = lock.output[0].value.checked_sub(fee)
       .ok_or_else(|| anyhow::anyhow!("Not enough Bitcoin to pay for {} sats of fees", fee))?;
+    // Don't offer a lock the client will reject anyways
+    self.terms.expect("Creating lock before agreeing on terms").verify_scripted(lock.output[0].value)?;
 
     let private_key = secp256k1::SecretKey::from_slice(
       &Secp256k1Engine::private_key_to_bytes(&address.0)
@@ -247,7 +427,8 @@ impl ScriptedHost for BtcHost {
     let result = Ok(
       bincode::serialize(
         &LockAndRefundInfo {
-          swap_hash: self.swap_hash.clone(),
+          swap_hash: if taproot { Vec::new() } else { self.swap_hash.clone() },
+          taproot,
           lock_id: serialize(&lock.txid()),
           host_refund_signature: fee_per_byte_and_sig.1,
           value: lock.output[0].value,
@@ -265,10 +446,10 @@ impl ScriptedHost for BtcHost {
     let refund_signature = sigs.refund_signature;
     let encrypted_spend_signature = Secp256k1Engine::bytes_to_encrypted_signature(&sigs.encrypted_spend_signature)?;
 
-    SECP.verify(
+    self.engine.verify_refund_signature(
       self.refund_message.as_ref().expect("Couldn't grab the refund's message despite attempting to verify the refund"),
-      &secp256k1::Signature::from_der(&refund_signature)?,
-      &secp256k1::PublicKey::from_slice(self.client_refund.as_ref().expect("Couldn't grab the client's refund public key despite attempting to verify the refund"))?
+      &refund_signature,
+      self.client_refund.as_ref().expect("Couldn't grab the client's refund public key despite attempting to verify the refund")
     )?;
 
     Secp256k1Engine::encrypted_verify(
@@ -280,15 +461,10 @@ impl ScriptedHost for BtcHost {
 
     // Complete the refund transaction
     let mut refund = self.refund.take().expect("Verifying and completing the refund before creating it");
-    refund.input[0].witness = vec![
-      Vec::new(),
-      refund_signature,
+    refund.input[0].witness = self.engine.refund_witness_with_signatures(
       self.refund_signature.clone().expect("Verifying the refund yet we never signed it"),
-      Vec::new(),
-      self.engine.lock_script_bytes.clone().expect("Finishing despite not knowing the lock script")
-    ];
-    refund.input[0].witness[1].push(1);
-    refund.input[0].witness[2].push(1);
+      refund_signature
+    );
     self.refund = Some(refund);
 
     self.encrypted_spend_signature = Some(encrypted_spend_signature);
@@ -302,7 +478,7 @@ impl ScriptedHost for BtcHost {
     let lock = self.lock.as_ref().expect("Trying to publish a lock transaction before creating it");
     self.rpc.publish(&serialize(lock)).await?;
 
-    let address = Address::p2wsh(self.engine.lock_script(), NETWORK).to_string();
+    let address = self.rpc.params().encode_address(&self.engine.lock_script_pubkey());
     let mut history = self.rpc.get_address_history(&address).await;
     while (history.len() == 0) || (history[0].confirmations < CONFIRMATIONS) {
       #[cfg(test)]
@@ -315,6 +491,29 @@ impl ScriptedHost for BtcHost {
     Ok(())
   }
 
+  /*
+    The lock height is only set once publish_lock sees the lock confirm
+    If we crashed before then, it's recovered from the lock address's history, waiting for the lock to confirm if necessary
+  */
+  async fn find_lock_height(&self) -> anyhow::Result<isize> {
+    if let Some(height) = self.lock_height {
+      return Ok(height);
+    }
+
+    let lock_id = self.lock.as_ref().expect("Finding the lock height before creating the lock").txid();
+    let address = self.rpc.params().encode_address(&self.engine.lock_script_pubkey());
+    loop {
+      let history = self.rpc.get_address_history(&address).await;
+      if let Some(lock) = history.iter().find(|tx| (tx.tx.txid() == lock_id) && (tx.height > 0)) {
+        return Ok(lock.height);
+      }
+
+      #[cfg(test)]
+      self.rpc.mine_block().await?;
+      tokio::time::delay_for(std::time::Duration::from_secs(20)).await;
+    }
+  }
+
   async fn refund<Verifier: UnscriptedVerifier>(mut self, mut verifier: Verifier) -> anyhow::Result<()> {
     /*
       There are four states to be aware of:
@@ -369,7 +568,7 @@ impl ScriptedHost for BtcHost {
               }
             ]
           };
-          let fee = ((return_tx.get_weight() / 4) as u64) * self.rpc.get_fee_per_byte().await?;
+          let fee = signed_vsize(&return_tx, P2WPKH_WITNESS) * self.rpc.get_fee_per_byte(TxKind::Lock).await?;
           return_tx.output[0].value = return_tx.output[0].value.checked_sub(fee)
             .ok_or_else(|| anyhow::anyhow!("Not enough Bitcoin to pay for {} sats of fees", fee))?;
 
@@ -405,9 +604,11 @@ impl ScriptedHost for BtcHost {
         // If we published the lock, we need to publish the refund transaction
         // First, we need to wait for T0 to expire
 
-        while self.rpc.get_height().await < (self.lock_height.expect("Never set lock height despite published lock") + (T0 as isize)) {
+        let t0 = self.terms.expect("Published the lock before agreeing on terms").timelocks.t0;
+        let lock_height = self.find_lock_height().await?;
+        while self.rpc.get_height().await < (lock_height + (t0 as isize)) {
           #[cfg(test)]
-          for _ in 0 .. T0 {
+          for _ in 0 .. t0 {
             self.rpc.mine_block().await?;
           }
           tokio::time::delay_for(std::time::Duration::from_secs(20)).await;
@@ -416,10 +617,17 @@ impl ScriptedHost for BtcHost {
         let refund = self.refund.clone().expect("Refund transaction doesn't exist despite having published the lock");
         let refund_id = refund.txid();
         let _ = self.rpc.publish(&serialize(&refund)).await;
-        let refund_address = Address::p2wsh(
-          self.refund_script.as_ref().expect("Calling refund after publishing the lock but before knowinng the refund script"),
-          NETWORK
-        ).to_string();
+        let mut cpfp = self.bumper.track(
+          refund,
+          self.lock.as_ref().expect("Publishing the refund despite not having created the lock").output[0].value,
+          1,
+          secp256k1::SecretKey::from_slice(&Secp256k1Engine::private_key_to_bytes(&self.engine.br))?,
+          TxKind::Refund,
+          self.rpc.get_height().await
+        );
+        let refund_address = self.rpc.params().p2wsh_address(
+          self.refund_script.as_ref().expect("Calling refund after publishing the lock but before knowinng the refund script")
+        );
 
         // Wait for the refund to confirm
         'outer: loop {
@@ -443,6 +651,7 @@ impl ScriptedHost for BtcHost {
             return verifier.finish(&mut self).await;
           }
 
+          cpfp.poll(&self.rpc).await;
           tokio::time::delay_for(std::time::Duration::from_secs(20)).await;
         }
 
@@ -471,7 +680,28 @@ impl ScriptedHost for BtcHost {
         spend.input[0].witness[2].push(1);
         self.rpc.publish(&serialize(&spend)).await?;
 
-        Ok(())
+        // Wait for the spend to confirm, bumping it if needed, as the client can claim the refund after T1
+        let spend_id = spend.txid();
+        let mut cpfp = self.bumper.track(
+          spend,
+          self.refund.expect("Refund transaction doesn't exist despite having published the lock").output[0].value,
+          1,
+          secp256k1::SecretKey::from_slice(&Secp256k1Engine::private_key_to_bytes(&self.engine.br))?,
+          TxKind::Spend,
+          self.rpc.get_height().await
+        );
+        loop {
+          #[cfg(test)]
+          self.rpc.mine_block().await?;
+
+          let history = self.rpc.get_address_history(&refund_address).await;
+          if history.iter().any(|tx| (tx.tx.txid() == spend_id) && (tx.confirmations >= CONFIRMATIONS)) {
+            return Ok(());
+          }
+
+          cpfp.poll(&self.rpc).await;
+          tokio::time::delay_for(std::time::Duration::from_secs(20)).await;
+        }
       }
     }
   }
@@ -497,39 +727,89 @@ impl ScriptedHost for BtcHost {
         TxOut {
           script_pubkey: self.client_destination_script.clone().expect("Preparing buy for client before knowing their destination"),
           value: lock.output[0].value
-        }
+        },
+        // Lets the client pay for the buy if it's stuck, as they're the one who publishes it
+        anchor_output(self.client.as_ref().expect("Preparing buy for client before knowing their key"))?
       ]
     };
-    let fee = ((buy.get_weight() as u64) / 4) * self.rpc.get_fee_per_byte().await?;
-    buy.output[0].value = buy.output[0].value.checked_sub(fee)
+    let fee_per_byte = self.terms.expect("Preparing buy before agreeing on terms").scripted_fee_bounds.clamp(
+      self.rpc.get_fee_per_byte(TxKind::Buy).await?
+    );
+    let fee = signed_vsize(&buy, &self.engine.buy_witness()) * fee_per_byte;
+    buy.output[0].value = buy.output[0].value.checked_sub(fee + ANCHOR_VALUE)
       .ok_or_else(|| anyhow::anyhow!("Not enough Bitcoin to pay for {} sats of fees", fee))?;
 
-    let components = SighashComponents::new(&buy);
+    self.buy = Some(buy.txid());
+    let message = self.engine.buy_message(&buy, lock.output[0].value);
+
+    // With a Taproot lock, we can't sign until we have the client's nonces, so we only send our own
+    if self.engine.taproot.is_some() {
+      let nonces = SecretNonces::new();
+      let result = Ok(
+        bincode::serialize(&BuyInfo {
+          value: buy.output[0].value,
+          encrypted_signature: Vec::new(),
+          nonces: nonces_to_bytes(&nonces.public())
+        })?
+      );
+      self.buy_message = Some(message);
+      self.buy_nonces = Some(nonces);
+      return result;
+    }
+
     let encrypted_signature = Secp256k1Engine::encrypted_sign(
       &self.engine.b,
       self.encryption_key.as_ref().expect("Attempted to generate encrypted sign before verifying dleq proof"),
-      &components.sighash_all(
-        &buy.input[0],
-        self.engine.lock_script(),
-        lock.output[0].value
-      )
+      &message
     )?;
 
-    self.buy = Some(buy.txid());
-
     let result = Ok(
       bincode::serialize(&BuyInfo {
         value: buy.output[0].value,
-        encrypted_signature: Secp256k1Engine::encrypted_signature_to_bytes(&encrypted_signature)
+        encrypted_signature: Secp256k1Engine::encrypted_signature_to_bytes(&encrypted_signature),
+        nonces: Vec::new()
       })?
     );
     self.encrypted_signature = Some(encrypted_signature);
     result
   }
 
-  async fn recover_final_key(&self) -> anyhow::Result<[u8; 32]> {
-    let encrypted_signature = self.encrypted_signature.as_ref().expect("Trying to recover the final key before preparing the encrypted signature");
+  fn verify_buy_signature(&mut self, buy_signature: &[u8]) -> anyhow::Result<()> {
+    let taproot = if let Some(taproot) = self.engine.taproot.as_ref() {
+      taproot
+    } else {
+      // The P2WSH buy was already fully signed by us
+      if buy_signature.len() != 0 {
+        anyhow::bail!("Client sent a signature for a buy which doesn't need one");
+      }
+      return Ok(());
+    };
 
+    let client_signature: ClientBuySignature = bincode::deserialize(buy_signature)?;
+    let client_nonces = bytes_to_nonces(&client_signature.nonces)?;
+    let client_partial_signature = bytes_to_partial_signature(&client_signature.partial_signature)?;
+
+    let nonces = self.buy_nonces.take().expect("Verifying the client's buy signature before sending our nonces");
+    let message = schnorr_message(self.buy_message.as_ref().expect("Verifying the client's buy signature before creating the buy"))?;
+    let encryption_key = self.encryption_key.as_ref().expect("Verifying the client's buy signature before verifying dleq proof");
+    let session = MusigSession::new(taproot, &[nonces.public(), client_nonces], encryption_key, &message)?;
+    let partial_signature = session.partial_sign(&taproot.tweak_key_share(&self.engine.b, true)?, nonces);
+
+    // If the combined signature is valid, and ours is, so is theirs
+    let partial_signatures = vec![partial_signature, client_partial_signature];
+    Secp256k1Engine::schnorr_encrypted_verify(
+      &taproot.output_key,
+      encryption_key,
+      &session.encrypted_signature(&partial_signatures),
+      &message
+    ).map_err(|_| anyhow::anyhow!("Client's partial signature for the buy was invalid"))?;
+
+    self.buy_session = Some(session);
+    self.buy_partial_signatures = Some(partial_signatures);
+    Ok(())
+  }
+
+  async fn recover_final_key(&self) -> anyhow::Result<[u8; 32]> {
     let mut buy_hash = self.buy.expect("Trying to recover the final key before creating the buy").to_vec();
     buy_hash.reverse();
     let buy_hash = hex::encode(buy_hash);
@@ -541,7 +821,22 @@ impl ScriptedHost for BtcHost {
       }
     }
 
-    let their_signature = &buy?.input[0].witness[2];
+    let buy = buy?;
+    if let Some(session) = self.buy_session.as_ref() {
+      return Ok(Secp256k1Engine::private_key_to_little_endian_bytes(
+        &Secp256k1Engine::schnorr_recover_key(
+          self.encryption_key.as_ref().expect("Attempted to recover final key before verifying dleq proof"),
+          &session.encrypted_signature(
+            self.buy_partial_signatures.as_ref().expect("Trying to recover the final key before signing the buy")
+          ),
+          &Secp256k1Engine::bytes_to_schnorr_signature(&buy.input[0].witness[0])
+            .expect("Signature included in the buy transaction wasn't valid despite getting on chain")
+        ).expect("Failed to recover key from decrypted signature")
+      ));
+    }
+
+    let encrypted_signature = self.encrypted_signature.as_ref().expect("Trying to recover the final key before preparing the encrypted signature");
+    let their_signature = &buy.input[0].witness[2];
     let signature = secp256k1::Signature::from_der(
       &their_signature[.. their_signature.len() - 1]
     ).expect("Signature included in the buy transaction wasn't valid despite getting on chain").serialize_compact();
@@ -561,7 +856,7 @@ impl ScriptedHost for BtcHost {
       &SECP,
       &bitcoin::util::key::PrivateKey {
         compressed: true,
-        network: NETWORK,
+        network: Network::Bitcoin,
         key: secp256k1::SecretKey::from_slice(
           &Secp256k1Engine::private_key_to_bytes(
             &Secp256k1Engine::new_private_key()
@@ -570,18 +865,13 @@ impl ScriptedHost for BtcHost {
       }
     );
 
-    self.refund_pubkey_script = BtcEngine::decode_address(
-      &Address::p2pkh(
-        &pubkey,
-        NETWORK
-      ).to_string()
-    ).expect("Generated an invalid random address");
+    self.refund_pubkey_script = Address::p2pkh(&pubkey, Network::Bitcoin).script_pubkey();
 
     self.refund_pubkey = Some(pubkey);
   }
   #[cfg(test)]
   async fn send_from_node(&self) -> anyhow::Result<()> {
-    self.rpc.send_from_electrum(&self.address.as_ref().unwrap().1.to_string()).await
+    self.rpc.send_from_wallet(&self.address.as_ref().unwrap().1.to_string()).await
   }
   #[cfg(test)]
   async fn advance_consensus(&self) -> anyhow::Result<()> {
@@ -592,7 +882,9 @@ impl ScriptedHost for BtcHost {
   }
   #[cfg(test)]
   fn get_refund_address(&self) -> String {
-    Address::p2pkh(&self.refund_pubkey.expect("Calling test get_refund_address despite not overriding it"), NETWORK).to_string()
+    self.rpc.params().encode_address(
+      &Address::p2pkh(&self.refund_pubkey.expect("Calling test get_refund_address despite not overriding it"), Network::Bitcoin).script_pubkey()
+    )
   }
   #[cfg(test)]
   async fn get_if_funded(self, address: &str) -> bool {
@@ -1,5 +1,18 @@
+#[cfg(not(test))]
 mod engine;
+#[cfg(test)]
+pub mod engine;
+#[cfg(not(test))]
 mod rpc;
+#[cfg(test)]
+pub mod rpc;
+mod cpfp;
+#[cfg(not(test))]
+mod taproot;
+#[cfg(test)]
+pub mod taproot;
+
+pub use engine::BtcChain;
 
 pub mod host;
 pub mod verifier;
@@ -0,0 +1,303 @@
+use std::{
+  sync::Mutex,
+  collections::{HashSet, HashMap},
+  fmt::Debug
+};
+
+use async_trait::async_trait;
+use log::debug;
+
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use serde_json::{json, Value};
+
+use bitcoin::{blockdata::transaction::Transaction, consensus::deserialize, util::amount::Amount};
+
+use crate::coins::btc::{engine::{BtcConfig, BtcParams}, rpc::{UnspentInputResponse, BtcBackend}};
+
+// Label applied to every address we watch, keeping them distinguishable within the wallet
+const LABEL: &str = "asmr";
+
+#[derive(Serialize)]
+struct FullParams<'a, T> {
+  jsonrpc: &'a str,
+  id: &'a str,
+  method: &'a str,
+  params: T
+}
+
+/*
+  Bitcoin Core itself, without any indexer
+  UTXOs are found via scantxoutset, which only covers the confirmed UTXO set
+  Address histories require a wallet, so every address queried is imported into it as watch-only
+  Descriptor wallets use importdescriptors, while legacy wallets fall back to importaddress
+  The outpoints each wallet transaction spends are cached, as they never change, so polling only fetches new transactions
+*/
+pub struct BitcoindBackend {
+  url: String,
+  wallet_url: String,
+  watched: Mutex<HashSet<String>>,
+  spent: Mutex<HashMap<String, Vec<(String, u32)>>>
+}
+
+impl BitcoindBackend {
+  pub fn new(config: &BtcConfig) -> BitcoindBackend {
+    let url = config.url.trim_end_matches('/').to_string();
+    BitcoindBackend {
+      wallet_url: match config.wallet.as_ref() {
+        Some(wallet) => format!("{}/wallet/{}", url, wallet),
+        None => url.clone()
+      },
+      url,
+      watched: Mutex::new(HashSet::new()),
+      spent: Mutex::new(HashMap::new())
+    }
+  }
+
+  async fn rpc_call<
+    Params: Serialize + Debug,
+    Response: DeserializeOwned + Debug
+  >(&self, url: &str, method: &str, params: &Params) -> anyhow::Result<Response> {
+    #[derive(Deserialize, Debug)]
+    struct RpcError {
+      code: i64,
+      message: String
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct FullResponse {
+      result: Value,
+      error: Option<RpcError>
+    }
+
+    let client = reqwest::Client::new();
+    let res =
+      client.post(url)
+      .json(
+        &FullParams {
+          jsonrpc: "1.0",
+          id: LABEL,
+          method,
+          params
+        }
+      )
+      .send()
+      .await?
+      .text()
+      .await?;
+    debug!("bitcoind RPC call to {} with {:?} returned {}", method, params, &res);
+    let parsed_res: FullResponse = serde_json::from_str(&res)
+      .map_err(|_| anyhow::anyhow!("bitcoind returned an invalid response to {}: {}", method, res))?;
+    if let Some(error) = parsed_res.error {
+      anyhow::bail!("bitcoind RPC returned an error for {}: {} ({})", method, error.message, error.code);
+    }
+    Ok(serde_json::from_value(parsed_res.result)?)
+  }
+
+  /*
+    Swap addresses are freshly generated, so there's no need to rescan for prior history
+    The only exception is the user's own refund address, where any prior history is irrelevant
+  */
+  async fn watch(&self, address: &str) -> anyhow::Result<()> {
+    #[derive(Deserialize, Debug)]
+    struct DescriptorInfo {
+      descriptor: String
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct ImportResult {
+      success: bool
+    }
+
+    if self.watched.lock().unwrap().contains(address) {
+      return Ok(());
+    }
+
+    let info: DescriptorInfo = self.rpc_call(&self.url, "getdescriptorinfo", &[format!("addr({})", address)]).await?;
+    let imported: anyhow::Result<Vec<ImportResult>> = self.rpc_call(
+      &self.wallet_url,
+      "importdescriptors",
+      &json!([[{ "desc": info.descriptor, "timestamp": "now", "label": LABEL }]])
+    ).await;
+    match imported {
+      Ok(results) => anyhow::ensure!(results.iter().all(|result| result.success), "bitcoind refused to watch {}", address),
+      Err(_) => {
+        let _: Value = self.rpc_call(&self.wallet_url, "importaddress", &json!([address, LABEL, false])).await?;
+      }
+    }
+
+    self.watched.lock().unwrap().insert(address.to_string());
+    Ok(())
+  }
+
+  async fn get_spent_outpoints(&self, txid: &str) -> anyhow::Result<Vec<(String, u32)>> {
+    #[derive(Deserialize, Debug)]
+    struct FullTransaction {
+      hex: String
+    }
+
+    if let Some(spent) = self.spent.lock().unwrap().get(txid) {
+      return Ok(spent.clone());
+    }
+
+    let full: FullTransaction = self.rpc_call(&self.wallet_url, "gettransaction", &json!([txid, true])).await?;
+    let full: Transaction = deserialize(&hex::decode(full.hex)?)?;
+    let spent: Vec<(String, u32)> = full.input.iter()
+      .map(|input| (input.previous_output.txid.to_string(), input.previous_output.vout))
+      .collect();
+    self.spent.lock().unwrap().insert(txid.to_string(), spent.clone());
+    Ok(spent)
+  }
+}
+
+#[async_trait]
+impl BtcBackend for BitcoindBackend {
+  async fn get_spendable(&self, address: &str) -> anyhow::Result<Vec<UnspentInputResponse>> {
+    #[derive(Deserialize, Debug)]
+    struct ScanUnspent {
+      txid: String,
+      vout: u32,
+      amount: f64,
+      height: u32
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct ScanResponse {
+      unspents: Vec<ScanUnspent>
+    }
+
+    let scan: ScanResponse = self.rpc_call(&self.url, "scantxoutset", &json!(["start", [format!("addr({})", address)]])).await?;
+    let mut result = Vec::new();
+    for utxo in scan.unspents {
+      result.push(
+        UnspentInputResponse {
+          height: utxo.height,
+          tx_hash: utxo.txid,
+          tx_pos: utxo.vout,
+          value: Amount::from_btc(utxo.amount)?.as_sat()
+        }
+      );
+    }
+    Ok(result)
+  }
+
+  async fn get_transaction(&self, hash_hex: &str) -> anyhow::Result<Transaction> {
+    #[derive(Deserialize, Debug)]
+    struct WalletTransaction {
+      hex: String
+    }
+
+    let tx = match self.rpc_call::<_, String>(&self.url, "getrawtransaction", &[hash_hex]).await {
+      Ok(tx) => tx,
+      // Without -txindex, only mempool transactions are available via getrawtransaction, yet the wallet has any we watched
+      Err(_) => self.rpc_call::<_, WalletTransaction>(&self.wallet_url, "gettransaction", &json!([hash_hex, true])).await?.hex
+    };
+    Ok(deserialize(&hex::decode(tx)?)?)
+  }
+
+  async fn get_height(&self) -> anyhow::Result<isize> {
+    self.rpc_call(&self.url, "getblockcount", &json!([])).await
+  }
+
+  async fn verify_network(&self, params: BtcParams) -> anyhow::Result<()> {
+    #[derive(Deserialize, Debug)]
+    struct BlockchainInfo {
+      chain: String
+    }
+
+    let info: BlockchainInfo = self.rpc_call(&self.url, "getblockchaininfo", &json!([])).await?;
+    if info.chain != params.network.name() {
+      anyhow::bail!("The node is on the {} network, yet the {} network is configured", info.chain, params.network.name());
+    }
+    Ok(())
+  }
+
+  /*
+    Transactions paying the address are listed as received by it
+    Transactions spending from it are instead listed as sent to their recipients, so they're found by their inputs
+    This requires listing the entire wallet, as listing by label omits sends
+  */
+  async fn get_address_history(&self, address: &str) -> anyhow::Result<Vec<(String, isize)>> {
+    #[derive(Deserialize, Debug)]
+    struct WalletTransaction {
+      address: Option<String>,
+      category: String,
+      vout: u32,
+      txid: String,
+      confirmations: isize,
+      blockheight: Option<isize>
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct WalletTransactions {
+      transactions: Vec<WalletTransaction>
+    }
+
+    self.watch(address).await?;
+    let listed: WalletTransactions = self.rpc_call(&self.wallet_url, "listsinceblock", &json!(["", 1, true])).await?;
+    // Negative confirmations mean the transaction conflicts with the chain
+    let transactions: Vec<WalletTransaction> = listed.transactions.into_iter().filter(|tx| tx.confirmations >= 0).collect();
+    let height = |tx: &WalletTransaction| if tx.confirmations == 0 { 0 } else { tx.blockheight.unwrap_or(0) };
+
+    let mut history: Vec<(String, isize)> = Vec::new();
+    let mut received = HashSet::new();
+    for tx in &transactions {
+      if (tx.category != "receive") || (tx.address.as_deref() != Some(address)) {
+        continue;
+      }
+      received.insert((tx.txid.clone(), tx.vout));
+      if !history.iter().any(|(hash, _)| hash == &tx.txid) {
+        history.push((tx.txid.clone(), height(tx)));
+      }
+    }
+
+    let mut checked = HashSet::new();
+    for tx in &transactions {
+      // Coinbases never spend anything
+      if ["generate", "immature", "orphan"].contains(&tx.category.as_ref()) ||
+        history.iter().any(|(hash, _)| hash == &tx.txid) ||
+        !checked.insert(tx.txid.clone()) {
+        continue;
+      }
+
+      if self.get_spent_outpoints(&tx.txid).await?.iter().any(|outpoint| received.contains(outpoint)) {
+        history.push((tx.txid.clone(), height(tx)));
+      }
+    }
+
+    // listsinceblock isn't ordered, so order by height, with unconfirmed transactions last
+    // The sort is stable, leaving transactions paying the address before those spending from it within a block
+    history.sort_by_key(|(_, height)| if *height < 1 { isize::MAX } else { *height });
+    Ok(history)
+  }
+
+  async fn publish(&self, tx: &[u8]) -> anyhow::Result<String> {
+    self.rpc_call(&self.url, "sendrawtransaction", &[hex::encode(tx)]).await
+  }
+
+  // bitcoind returns BTC/kvB, and omits the rate entirely when it doesn't have enough data
+  async fn estimate_fee_per_byte(&self, target: usize) -> anyhow::Result<Option<u64>> {
+    #[derive(Deserialize, Debug)]
+    struct FeeEstimate {
+      feerate: Option<f64>
+    }
+
+    let estimate: FeeEstimate = self.rpc_call(&self.url, "estimatesmartfee", &json!([target])).await?;
+    match estimate.feerate {
+      Some(feerate) => Ok(Some((Amount::from_btc(feerate)?.as_sat() + 999) / 1000)),
+      None => Ok(None)
+    }
+  }
+
+  #[cfg(test)]
+  async fn send_from_wallet(&self, address: &str) -> anyhow::Result<()> {
+    let _: String = self.rpc_call(&self.wallet_url, "sendtoaddress", &json!([address, 0.01])).await?;
+    self.mine_block().await
+  }
+
+  #[cfg(test)]
+  async fn mine_block(&self) -> anyhow::Result<()> {
+    let address: String = self.rpc_call(&self.wallet_url, "getnewaddress", &json!([])).await?;
+    let _: Value = self.rpc_call(&self.url, "generatetoaddress", &json!([1, address])).await?;
+    Ok(())
+  }
+}
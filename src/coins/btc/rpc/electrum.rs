@@ -1,13 +1,19 @@
 use std::fmt::Debug;
 
+use async_trait::async_trait;
 use log::debug;
 
 use serde::{Serialize, Deserialize, de::DeserializeOwned};
 use serde_json::json;
 
-use bitcoin::{blockdata::transaction::Transaction, consensus::deserialize};
+use bitcoin::{
+  blockdata::transaction::Transaction,
+  network::constants::Network,
+  util::{address::Address, key::PublicKey},
+  consensus::deserialize
+};
 
-use crate::coins::btc::engine::BtcConfig;
+use crate::coins::btc::{engine::{BtcConfig, BtcParams}, rpc::{UnspentInputResponse, BtcBackend}};
 
 #[derive(Serialize)]
 struct FullParams<'a, T> {
@@ -22,33 +28,19 @@ struct InfoResponse {
   blockchain_height: isize
 }
 
-#[derive(Deserialize, Debug)]
-pub struct UnspentInputResponse {
-  pub height: u32,
-  pub tx_hash: String,
-  pub tx_pos: u32,
-  pub value: u64
-}
-
-#[derive(Debug)]
-pub struct AddressHistoryItem {
-  pub tx: Transaction,
-  pub height: isize,
-  pub confirmations: isize,
-}
-
-pub struct BtcRpc {
+// An Electrum daemon, connected to an Electrum server such as electrs
+pub struct ElectrumBackend {
   url: String,
   #[allow(dead_code)]
   btc_url: Option<String>
 }
 
-impl BtcRpc {
-  pub fn new(config: &BtcConfig) -> anyhow::Result<BtcRpc> {
-    Ok(BtcRpc {
+impl ElectrumBackend {
+  pub fn new(config: &BtcConfig) -> ElectrumBackend {
+    ElectrumBackend {
       url: config.url.clone(),
       btc_url: config.btc_url.clone()
-    })
+    }
   }
 
   async fn rpc_call<
@@ -89,28 +81,38 @@ impl BtcRpc {
       FullResponse::Ok { result } => Ok(result),
     }
   }
+}
 
-  pub async fn get_spendable(&self, address: &str) -> anyhow::Result<Vec<UnspentInputResponse>> {
+#[async_trait]
+impl BtcBackend for ElectrumBackend {
+  async fn get_spendable(&self, address: &str) -> anyhow::Result<Vec<UnspentInputResponse>> {
     self.rpc_call("getaddressunspent", &[address]).await
   }
 
-  pub async fn get_fee_per_byte(&self) -> anyhow::Result<u64> {
-    // TODO: Revisit. Electrum returns fees in sats/kvByte and our size calculations are off
-    Ok(100)
-  }
-
-  pub async fn get_transaction(&self, hash_hex: &str) -> anyhow::Result<Transaction> {
+  async fn get_transaction(&self, hash_hex: &str) -> anyhow::Result<Transaction> {
     let tx: String = self.rpc_call("gettransaction", &[hash_hex]).await?;
     let decoded_hex = hex::decode(tx).expect("Electrum returned a transaction that wasn't stored as hex");
     Ok(deserialize(&decoded_hex).expect("Electrum returned a transaction we couldn't deserialize"))
   }
 
-  pub async fn get_height(&self) -> isize {
-    let info: InfoResponse = self.rpc_call("getinfo", &json!([])).await.expect("Couldn't get the network info");
-    info.blockchain_height
+  async fn get_height(&self) -> anyhow::Result<isize> {
+    let info: InfoResponse = self.rpc_call("getinfo", &json!([])).await?;
+    Ok(info.blockchain_height)
   }
 
-  pub async fn get_address_history(&self, address: &str) -> Vec<AddressHistoryItem> {
+  // Electrum doesn't expose its network, yet it only considers addresses for its own network valid
+  async fn verify_network(&self, params: BtcParams) -> anyhow::Result<()> {
+    // The secp256k1 generator, used as an arbitrary valid key
+    let key = PublicKey::from_slice(&hex::decode("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")?)?;
+    let address = params.encode_address(&Address::p2wpkh(&key, Network::Bitcoin).script_pubkey());
+    let valid: bool = self.rpc_call("validateaddress", &[&address]).await?;
+    if !valid {
+      anyhow::bail!("Electrum isn't on the configured {:?} {} network", params.chain, params.network.name());
+    }
+    Ok(())
+  }
+
+  async fn get_address_history(&self, address: &str) -> anyhow::Result<Vec<(String, isize)>> {
     #[derive(Deserialize, Debug)]
     struct AddressHistoryResponse {
       fee: Option<u64>,
@@ -118,31 +120,22 @@ impl BtcRpc {
       tx_hash: String
     };
 
-    let history: Vec<AddressHistoryResponse> = self.rpc_call(
-      "getaddresshistory",
-      &[address]
-    ).await.expect("Couldn't get the history of an address; this should only occur if we generated an invalid address");
-
-    let mut result = Vec::new();
-    let chain_height = self.get_height().await;
-    for tx in history {
-      result.push(
-        AddressHistoryItem {
-          tx: self.get_transaction(&tx.tx_hash).await.expect("Couldn't get a transaction part of an address's history"),
-          confirmations: if tx.height < 1 {tx.height} else {chain_height - tx.height + 1},
-          height: tx.height
-        }
-      );
-    }
-    result
+    let history: Vec<AddressHistoryResponse> = self.rpc_call("getaddresshistory", &[address]).await?;
+    Ok(history.into_iter().map(|tx| (tx.tx_hash, tx.height)).collect())
   }
 
-  pub async fn publish(&self, tx: &[u8]) -> anyhow::Result<String> {
+  async fn publish(&self, tx: &[u8]) -> anyhow::Result<String> {
     self.rpc_call("broadcast", &[hex::encode(tx)]).await
   }
 
+  // Electrum only offers its own configured fee policy, which it returns in sat/kvB
+  async fn estimate_fee_per_byte(&self, _: usize) -> anyhow::Result<Option<u64>> {
+    let fee: u64 = self.rpc_call("getfeerate", &json!([])).await?;
+    Ok(Some((fee + 999) / 1000))
+  }
+
   #[cfg(test)]
-  pub async fn send_from_electrum(&self, address: &str) -> anyhow::Result<()> {
+  async fn send_from_wallet(&self, address: &str) -> anyhow::Result<()> {
     let tx: String = self
       .rpc_call("payto", &json!([address, 0.01]))
       .await?;
@@ -151,7 +144,7 @@ impl BtcRpc {
   }
 
   #[cfg(test)]
-  pub async fn mine_block(&self) -> anyhow::Result<()> {
+  async fn mine_block(&self) -> anyhow::Result<()> {
     let address: String = self.rpc_call("getunusedaddress", &json!([])).await?;
     let client = reqwest::Client::new();
     let res: serde_json::Value =
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use log::debug;
+
+use serde::{Deserialize, de::DeserializeOwned};
+
+use bitcoin::{blockdata::transaction::Transaction, consensus::deserialize};
+
+use crate::coins::btc::{engine::BtcParams, rpc::{UnspentInputResponse, BtcBackend}};
+
+// Esplora returns confirmed address transactions in pages of this size
+const CHAIN_PAGE_SIZE: usize = 25;
+
+#[derive(Deserialize, Debug)]
+struct TxStatus {
+  confirmed: bool,
+  block_height: Option<isize>
+}
+
+impl TxStatus {
+  fn height(&self) -> isize {
+    if self.confirmed {
+      self.block_height.unwrap_or(0)
+    } else {
+      0
+    }
+  }
+}
+
+// An Esplora HTTP API, as served by Blockstream's electrs fork
+pub struct EsploraBackend {
+  url: String
+}
+
+impl EsploraBackend {
+  pub fn new(url: &str) -> EsploraBackend {
+    EsploraBackend {
+      url: url.trim_end_matches('/').to_string()
+    }
+  }
+
+  async fn get_text(&self, path: &str) -> anyhow::Result<String> {
+    let res = reqwest::get(&format!("{}{}", self.url, path)).await?;
+    let status = res.status();
+    let text = res.text().await?;
+    debug!("Esplora request for {} returned {}", path, &text);
+    if !status.is_success() {
+      anyhow::bail!("Esplora request for {} failed with {}: {}", path, status, text);
+    }
+    Ok(text)
+  }
+
+  async fn get<Response: DeserializeOwned>(&self, path: &str) -> anyhow::Result<Response> {
+    Ok(serde_json::from_str(&self.get_text(path).await?)?)
+  }
+}
+
+#[async_trait]
+impl BtcBackend for EsploraBackend {
+  async fn get_spendable(&self, address: &str) -> anyhow::Result<Vec<UnspentInputResponse>> {
+    #[derive(Deserialize, Debug)]
+    struct Utxo {
+      txid: String,
+      vout: u32,
+      status: TxStatus,
+      value: u64
+    }
+
+    let utxos: Vec<Utxo> = self.get(&format!("/address/{}/utxo", address)).await?;
+    Ok(
+      utxos.into_iter().map(|utxo| UnspentInputResponse {
+        height: utxo.status.height() as u32,
+        tx_hash: utxo.txid,
+        tx_pos: utxo.vout,
+        value: utxo.value
+      }).collect()
+    )
+  }
+
+  async fn get_transaction(&self, hash_hex: &str) -> anyhow::Result<Transaction> {
+    let tx = self.get_text(&format!("/tx/{}/hex", hash_hex)).await?;
+    Ok(deserialize(&hex::decode(tx.trim())?)?)
+  }
+
+  async fn get_height(&self) -> anyhow::Result<isize> {
+    Ok(self.get_text("/blocks/tip/height").await?.trim().parse()?)
+  }
+
+  // Esplora doesn't name its network, yet every network has a distinct genesis block
+  async fn verify_network(&self, params: BtcParams) -> anyhow::Result<()> {
+    let genesis = self.get_text("/block-height/0").await?;
+    if genesis.trim() != params.genesis_hash() {
+      anyhow::bail!("Esplora's genesis block is {}, which isn't the {:?} {} network's", genesis.trim(), params.chain, params.network.name());
+    }
+    Ok(())
+  }
+
+  /*
+    The first page contains every mempool transaction and the most recent confirmed transactions
+    Older confirmed transactions are paged through using the last transaction seen
+  */
+  async fn get_address_history(&self, address: &str) -> anyhow::Result<Vec<(String, isize)>> {
+    #[derive(Deserialize, Debug)]
+    struct AddressTx {
+      txid: String,
+      status: TxStatus
+    }
+
+    let mut page: Vec<AddressTx> = self.get(&format!("/address/{}/txs", address)).await?;
+    let mut history = Vec::new();
+    loop {
+      let confirmed = page.iter().filter(|tx| tx.status.confirmed).count();
+      let last = page.last().map(|tx| tx.txid.clone());
+      history.extend(page.into_iter().map(|tx| (tx.txid, tx.status.height())));
+
+      match last {
+        Some(last) if confirmed == CHAIN_PAGE_SIZE => {
+          page = self.get(&format!("/address/{}/txs/chain/{}", address, last)).await?;
+        },
+        _ => break
+      }
+    }
+    // Esplora returns the newest transactions first, whereas we expect the oldest first
+    history.reverse();
+    Ok(history)
+  }
+
+  async fn publish(&self, tx: &[u8]) -> anyhow::Result<String> {
+    let res = reqwest::Client::new().post(&format!("{}/tx", self.url)).body(hex::encode(tx)).send().await?;
+    let status = res.status();
+    let text = res.text().await?;
+    if !status.is_success() {
+      anyhow::bail!("Esplora rejected the transaction: {}", text);
+    }
+    Ok(text.trim().to_string())
+  }
+
+  // Esplora reports sat/vB estimates for a fixed set of targets, so use the closest one which is at least as fast
+  async fn estimate_fee_per_byte(&self, target: usize) -> anyhow::Result<Option<u64>> {
+    let estimates: HashMap<String, f64> = self.get("/fee-estimates").await?;
+    let mut best: Option<(usize, f64)> = None;
+    for (blocks, fee) in estimates {
+      let blocks: usize = blocks.parse()?;
+      if (blocks <= target) && best.map(|best| blocks > best.0).unwrap_or(true) {
+        best = Some((blocks, fee));
+      }
+    }
+    Ok(best.map(|best| best.1.ceil() as u64))
+  }
+
+  #[cfg(test)]
+  async fn send_from_wallet(&self, _: &str) -> anyhow::Result<()> {
+    anyhow::bail!("Esplora doesn't offer a wallet to send from")
+  }
+
+  #[cfg(test)]
+  async fn mine_block(&self) -> anyhow::Result<()> {
+    anyhow::bail!("Esplora can't mine blocks")
+  }
+}
@@ -0,0 +1,168 @@
+use async_trait::async_trait;
+use enum_dispatch::enum_dispatch;
+use log::warn;
+
+use serde::Deserialize;
+
+use bitcoin::blockdata::transaction::Transaction;
+
+use crate::coins::{FeeBounds, btc::engine::{BtcConfig, BtcChain, BtcParams, BtcBackendKind, FeeTargets, TxKind}};
+
+mod electrum;
+#[cfg(not(test))]
+mod bitcoind;
+#[cfg(test)]
+pub mod bitcoind;
+#[cfg(not(test))]
+mod esplora;
+#[cfg(test)]
+pub mod esplora;
+
+use electrum::ElectrumBackend;
+use bitcoind::BitcoindBackend;
+use esplora::EsploraBackend;
+
+#[derive(Deserialize, Debug)]
+pub struct UnspentInputResponse {
+  pub height: u32,
+  pub tx_hash: String,
+  pub tx_pos: u32,
+  pub value: u64
+}
+
+#[derive(Debug)]
+pub struct AddressHistoryItem {
+  pub tx: Transaction,
+  pub height: isize,
+  pub confirmations: isize,
+}
+
+// A source of chain data and a way to publish transactions
+#[async_trait]
+#[enum_dispatch(AnyBtcBackend)]
+pub trait BtcBackend: Send + Sync {
+  async fn get_spendable(&self, address: &str) -> anyhow::Result<Vec<UnspentInputResponse>>;
+  async fn get_transaction(&self, hash_hex: &str) -> anyhow::Result<Transaction>;
+  async fn get_height(&self) -> anyhow::Result<isize>;
+  // The hash and height of every transaction involving this address, where unconfirmed transactions have a height less than 1
+  async fn get_address_history(&self, address: &str) -> anyhow::Result<Vec<(String, isize)>>;
+  async fn publish(&self, tx: &[u8]) -> anyhow::Result<String>;
+  // The fee rate, in sat/vB, expected to confirm within the target amount of blocks, if the backend has an estimate
+  async fn estimate_fee_per_byte(&self, target: usize) -> anyhow::Result<Option<u64>>;
+  // Errors if the backend is on a different network
+  async fn verify_network(&self, params: BtcParams) -> anyhow::Result<()>;
+
+  #[cfg(test)]
+  async fn send_from_wallet(&self, address: &str) -> anyhow::Result<()>;
+  #[cfg(test)]
+  async fn mine_block(&self) -> anyhow::Result<()>;
+}
+
+#[enum_dispatch]
+pub enum AnyBtcBackend {
+  Electrum(ElectrumBackend),
+  Bitcoind(BitcoindBackend),
+  Esplora(EsploraBackend)
+}
+
+pub struct BtcRpc {
+  params: BtcParams,
+  backend: AnyBtcBackend,
+  fee_bounds: FeeBounds,
+  fee_targets: FeeTargets
+}
+
+impl BtcRpc {
+  // Connects to the configured backend, verifying it's on the configured network
+  pub async fn new(config: &BtcConfig, chain: BtcChain) -> anyhow::Result<BtcRpc> {
+    anyhow::ensure!(
+      (config.min_fee_per_byte != 0) && (config.min_fee_per_byte <= config.max_fee_per_byte),
+      "Invalid fee rate range of {}-{}", config.min_fee_per_byte, config.max_fee_per_byte
+    );
+    let rpc = BtcRpc {
+      params: BtcParams::new(chain, config.network)?,
+      fee_bounds: FeeBounds {
+        min: config.min_fee_per_byte,
+        max: config.max_fee_per_byte
+      },
+      fee_targets: config.fee_targets,
+      backend: match config.backend {
+        BtcBackendKind::Electrum => ElectrumBackend::new(config).into(),
+        BtcBackendKind::Bitcoind => BitcoindBackend::new(config).into(),
+        BtcBackendKind::Esplora => EsploraBackend::new(&config.url).into()
+      }
+    };
+    rpc.backend.verify_network(rpc.params).await?;
+    Ok(rpc)
+  }
+
+  // The chain and network addresses should be encoded for
+  pub fn params(&self) -> BtcParams {
+    self.params
+  }
+
+  pub async fn get_spendable(&self, address: &str) -> anyhow::Result<Vec<UnspentInputResponse>> {
+    self.backend.get_spendable(address).await
+  }
+
+  pub fn fee_bounds(&self) -> FeeBounds {
+    self.fee_bounds
+  }
+
+  /*
+    Estimates the fee rate for the specified transaction, clamped to the configured range
+    Without an estimate, such as on a fresh regtest chain, the configured minimum is used
+    Transactions the counterparty verifies must additionally be clamped to the agreed range
+  */
+  pub async fn get_fee_per_byte(&self, kind: TxKind) -> anyhow::Result<u64> {
+    let target = self.fee_targets.target(kind);
+    let fee_per_byte = match self.backend.estimate_fee_per_byte(target).await? {
+      Some(fee_per_byte) => fee_per_byte,
+      None => {
+        warn!("No fee estimate is available for a {} block target, so the minimum fee rate will be used", target);
+        self.fee_bounds.min
+      }
+    };
+    Ok(self.fee_bounds.clamp(fee_per_byte))
+  }
+
+  pub async fn get_transaction(&self, hash_hex: &str) -> anyhow::Result<Transaction> {
+    self.backend.get_transaction(hash_hex).await
+  }
+
+  pub async fn get_height(&self) -> isize {
+    self.backend.get_height().await.expect("Couldn't get the network info")
+  }
+
+  pub async fn get_address_history(&self, address: &str) -> Vec<AddressHistoryItem> {
+    let history = self.backend.get_address_history(address).await
+      .expect("Couldn't get the history of an address; this should only occur if we generated an invalid address");
+
+    let mut result = Vec::new();
+    let chain_height = self.get_height().await;
+    for (hash, height) in history {
+      result.push(
+        AddressHistoryItem {
+          tx: self.get_transaction(&hash).await.expect("Couldn't get a transaction part of an address's history"),
+          confirmations: if height < 1 {height} else {chain_height - height + 1},
+          height
+        }
+      );
+    }
+    result
+  }
+
+  pub async fn publish(&self, tx: &[u8]) -> anyhow::Result<String> {
+    self.backend.publish(tx).await
+  }
+
+  #[cfg(test)]
+  pub async fn send_from_wallet(&self, address: &str) -> anyhow::Result<()> {
+    self.backend.send_from_wallet(address).await
+  }
+
+  #[cfg(test)]
+  pub async fn mine_block(&self) -> anyhow::Result<()> {
+    self.backend.mine_block().await
+  }
+}
@@ -0,0 +1,298 @@
+use hex_literal::hex;
+
+use rand::rngs::OsRng;
+use digest::Digest;
+use sha2::Sha256;
+use secp256kfun::{marker::*, Scalar, Point, G, g, s};
+
+use serde::{Serialize, Deserialize};
+
+use bitcoin::{blockdata::{script::Script, transaction::Transaction}, consensus::serialize};
+
+use crate::{
+  crypt_engines::{
+    CryptEngine,
+    secp256k1_engine::{Secp256k1Engine, schnorr::{SchnorrEncryptedSignature, tagged_hash, has_even_y, lift_x, schnorr_challenge}}
+  },
+  coins::btc::engine::push_number
+};
+
+/*
+  An alternative lock which looks like an ordinary single key output when both parties cooperate
+  The key path is a 2-of-2 MuSig key, with the buy signed via MuSig2 and encrypted to the client's key, as the P2WSH buy's signature is
+  The only script path is the refund, timelocked and signed by both refund keys
+  The hash lock is replaced by the host's partial signature, which is what the host sends instead of the swap secret
+*/
+
+const LEAF_VERSION: u8 = 0xc0;
+
+fn key_list_hash(keys: &[Point]) -> [u8; 32] {
+  let mut list = tagged_hash("KeyAgg list");
+  for key in keys {
+    list = list.chain(&key.to_bytes()[..]);
+  }
+  list.finalize().into()
+}
+
+// Every key is weighted by a coefficient to prevent key cancellation
+fn key_coefficient(keys: &[Point], key: &Point) -> Scalar::<Public, Zero> {
+  // The second distinct key has a coefficient of 1, saving a multiplication when signing
+  if keys.iter().find(|other| *other != &keys[0]) == Some(key) {
+    return Scalar::one().mark::<Zero>().mark::<Public>();
+  }
+  let coefficient: [u8; 32] = tagged_hash("KeyAgg coefficient")
+    .chain(&key_list_hash(keys))
+    .chain(&key.to_bytes()[..])
+    .finalize()
+    .into();
+  Scalar::from_bytes_mod_order(coefficient).mark::<Public>()
+}
+
+// MuSig key aggregation, as specified by BIP327
+pub fn aggregate_keys(keys: &[Point]) -> anyhow::Result<Point> {
+  let mut aggregate = Point::zero().mark::<Jacobian>();
+  for key in keys {
+    let coefficient = key_coefficient(keys, key);
+    aggregate = g!(aggregate + coefficient * key);
+  }
+  aggregate.mark::<Normal>().mark::<NonZero>().ok_or_else(|| anyhow::anyhow!("Aggregated keys to zero"))
+}
+
+// After t0 blocks, both refund keys can spend the lock, as the refund branch of the P2WSH lock allows
+pub fn refund_leaf(t0: u16, host_refund: &Point, client_refund: &Point) -> Vec<u8> {
+  let mut script = Vec::new();
+  push_number(&mut script, t0);
+  script.extend(&hex!("b27520"));
+  script.extend(&Secp256k1Engine::x_only(host_refund));
+  script.extend(&hex!("ad20"));
+  script.extend(&Secp256k1Engine::x_only(client_refund));
+  script.extend(&hex!("ac"));
+  script
+}
+
+// With a single leaf, this is also the merkle root
+pub fn leaf_hash(script: &[u8]) -> [u8; 32] {
+  let mut hash = tagged_hash("TapLeaf").chain(&[LEAF_VERSION]);
+  // Compact size length prefix
+  if script.len() < 0xfd {
+    hash = hash.chain(&[script.len() as u8]);
+  } else {
+    hash = hash.chain(&[0xfd]).chain(&(script.len() as u16).to_le_bytes());
+  }
+  hash.chain(script).finalize().into()
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TaprootLock {
+  // The host's key, then the client's
+  pub keys: Vec<Point>,
+  pub internal_key: Point,
+  pub output_key: Point,
+  pub refund_leaf: Vec<u8>,
+  // The tweak, applied to the internal key after it's negated to have an even y coordinate, when signing via the key path
+  pub tweak: Scalar::<Public, Zero>
+}
+
+impl TaprootLock {
+  pub fn new(keys: &[Point], t0: u16, host_refund: &Point, client_refund: &Point) -> anyhow::Result<TaprootLock> {
+    let internal_key = aggregate_keys(keys)?;
+    let refund_leaf = refund_leaf(t0, host_refund, client_refund);
+    let internal_x = Secp256k1Engine::x_only(&internal_key);
+
+    let tweak: [u8; 32] = tagged_hash("TapTweak")
+      .chain(&internal_x)
+      .chain(&leaf_hash(&refund_leaf))
+      .finalize()
+      .into();
+    let tweak = Scalar::from_bytes_mod_order(tweak).mark::<Public>();
+    let even_internal_key = lift_x(internal_x)?;
+    let output_key = g!(even_internal_key + tweak * G).mark::<Normal>().mark::<NonZero>()
+      .ok_or_else(|| anyhow::anyhow!("Tweaked the Taproot key to zero"))?;
+
+    Ok(TaprootLock {
+      keys: keys.to_vec(),
+      internal_key,
+      output_key,
+      refund_leaf,
+      tweak
+    })
+  }
+
+  // A segwit v1 output to the x-only output key
+  pub fn script_pubkey(&self) -> Vec<u8> {
+    let mut script = hex!("5120").to_vec();
+    script.extend(&Secp256k1Engine::x_only(&self.output_key));
+    script
+  }
+
+  // Proves the refund leaf is committed to by the output key, for spending via the script path
+  pub fn control_block(&self) -> Vec<u8> {
+    let mut control = vec![LEAF_VERSION | (if has_even_y(&self.output_key) { 0 } else { 1 })];
+    control.extend(&Secp256k1Engine::x_only(&self.internal_key));
+    control
+  }
+
+  // Our share of the key path's signing key, given our key was one of those aggregated
+  pub fn tweak_key_share(&self, ours: &Scalar, first: bool) -> anyhow::Result<Scalar> {
+    let coefficient = key_coefficient(&self.keys, &Secp256k1Engine::to_public_key(ours));
+    let mut share = s!(coefficient * ours);
+    // BIP340 signs for the internal key with an even y coordinate
+    share.conditional_negate(!has_even_y(&self.internal_key));
+    // Only one party adds the tweak, so the shares still sum to the output key's discrete log
+    if first {
+      share = s!(share + self.tweak);
+    }
+    share.conditional_negate(!has_even_y(&self.output_key));
+    share.mark::<Secret>().mark::<NonZero>().ok_or_else(|| anyhow::anyhow!("Key share is zero"))
+  }
+
+  /*
+    The BIP341 signature hash for a transaction whose only input is this lock, with SIGHASH_DEFAULT
+    The refund is signed via the script path, committing to the refund leaf, and the buy via the key path
+    bitcoin 0.23 only implements the BIP143 signature hash, hence this
+  */
+  pub fn sighash(&self, tx: &Transaction, value: u64, script_path: bool) -> [u8; 32] {
+    assert_eq!(tx.input.len(), 1, "Taproot sighash only supports spending the lock alone");
+    let outputs = tx.output.iter().fold(Vec::new(), |mut outputs, output| {
+      outputs.extend(serialize(output));
+      outputs
+    });
+
+    let mut hash = tagged_hash("TapSighash")
+      // Epoch and hash type
+      .chain(&[0, 0])
+      .chain(&tx.version.to_le_bytes())
+      .chain(&tx.lock_time.to_le_bytes())
+      // BIP341 commits to every input's outpoint, value, script, and sequence, which are all just the lock's
+      .chain(&Sha256::digest(&serialize(&tx.input[0].previous_output)))
+      .chain(&Sha256::digest(&value.to_le_bytes()))
+      .chain(&Sha256::digest(&serialize(&Script::from(self.script_pubkey()))))
+      .chain(&Sha256::digest(&tx.input[0].sequence.to_le_bytes()))
+      .chain(&Sha256::digest(&outputs))
+      // Spend type, which is 2 for the script path and 0 for the key path as neither has an annex, and the input's index
+      .chain(&[if script_path { 2 } else { 0 }])
+      .chain(&0u32.to_le_bytes());
+    if script_path {
+      // The leaf, the key version, and the position of the last executed OP_CODESEPARATOR, of which there are none
+      hash = hash
+        .chain(&leaf_hash(&self.refund_leaf))
+        .chain(&[0])
+        .chain(&0xFFFFFFFFu32.to_le_bytes());
+    }
+    hash.finalize().into()
+  }
+}
+
+/*
+  MuSig2 nonces, as specified by BIP327, yet without its deterministic derivation, as they're randomly generated
+  Each party shares two nonces, which lets MuSig2 sign securely once they're exchanged, without a round committing to them first
+  They're consumed when signing, as reusing them with a different nonce from the other party would reveal our key
+*/
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SecretNonces(Scalar, Scalar);
+
+impl SecretNonces {
+  pub fn new() -> SecretNonces {
+    SecretNonces(Scalar::random(&mut OsRng), Scalar::random(&mut OsRng))
+  }
+
+  pub fn public(&self) -> (Point, Point) {
+    (Secp256k1Engine::to_public_key(&self.0), Secp256k1Engine::to_public_key(&self.1))
+  }
+}
+
+pub fn nonces_to_bytes(nonces: &(Point, Point)) -> Vec<u8> {
+  let mut bytes = nonces.0.to_bytes().to_vec();
+  bytes.extend(&nonces.1.to_bytes());
+  bytes
+}
+
+pub fn bytes_to_nonces(bytes: &[u8]) -> anyhow::Result<(Point, Point)> {
+  if bytes.len() != 66 {
+    anyhow::bail!("Expected MuSig nonces to be 66 bytes long");
+  }
+  Ok((Secp256k1Engine::bytes_to_public_key(&bytes[.. 33])?, Secp256k1Engine::bytes_to_public_key(&bytes[33 ..])?))
+}
+
+/*
+  A signing session for the lock's key path, encrypted to the client's key
+  The partial signatures sum to an encrypted signature, so whoever has both learns nothing until the client decrypts it
+*/
+#[derive(Clone, Serialize, Deserialize)]
+#[allow(non_snake_case)]
+pub struct MusigSession {
+  R: Point,
+  nonce_coefficient: Scalar::<Public, Zero>,
+  negate_nonce: bool,
+  challenge: Scalar::<Public, Zero>
+}
+
+impl MusigSession {
+  #[allow(non_snake_case)]
+  pub fn new(
+    lock: &TaprootLock,
+    nonces: &[(Point, Point)],
+    encryption_key: &Point,
+    message: &[u8; 32]
+  ) -> anyhow::Result<MusigSession> {
+    let mut R1 = Point::zero().mark::<Jacobian>();
+    let mut R2 = Point::zero().mark::<Jacobian>();
+    for (first, second) in nonces {
+      R1 = g!(R1 + first);
+      R2 = g!(R2 + second);
+    }
+    let R1 = R1.mark::<Normal>().mark::<NonZero>().ok_or_else(|| anyhow::anyhow!("Aggregated nonces to zero"))?;
+    let R2 = R2.mark::<Normal>().mark::<NonZero>().ok_or_else(|| anyhow::anyhow!("Aggregated nonces to zero"))?;
+
+    let output_x = Secp256k1Engine::x_only(&lock.output_key);
+    let nonce_coefficient: [u8; 32] = tagged_hash("MuSig/noncecoef")
+      .chain(&R1.to_bytes()[..])
+      .chain(&R2.to_bytes()[..])
+      .chain(&output_x)
+      .chain(message)
+      .finalize()
+      .into();
+    let nonce_coefficient = Scalar::from_bytes_mod_order(nonce_coefficient).mark::<Public>();
+    let R = g!(R1 + nonce_coefficient * R2).mark::<Normal>().mark::<NonZero>()
+      .ok_or_else(|| anyhow::anyhow!("Aggregated nonces to zero"))?;
+
+    // The signature's nonce is offset by the encryption key, which decides if every nonce is negated
+    let encrypted_R = g!(R + encryption_key).mark::<Normal>().mark::<NonZero>()
+      .ok_or_else(|| anyhow::anyhow!("Encrypted nonce is zero"))?;
+    Ok(MusigSession {
+      R,
+      nonce_coefficient,
+      negate_nonce: !has_even_y(&encrypted_R),
+      challenge: schnorr_challenge(&Secp256k1Engine::x_only(&encrypted_R), &output_x, message)
+    })
+  }
+
+  pub fn partial_sign(&self, share: &Scalar, nonces: SecretNonces) -> Scalar::<Public, Zero> {
+    let SecretNonces(first, second) = nonces;
+    let MusigSession { nonce_coefficient, challenge, .. } = self;
+    let mut nonce = s!(first + nonce_coefficient * second);
+    nonce.conditional_negate(self.negate_nonce);
+    s!(nonce + challenge * share).mark::<Public>()
+  }
+
+  // The encrypted signature for the lock's output key, verifiable via schnorr_encrypted_verify
+  pub fn encrypted_signature(&self, partial_signatures: &[Scalar::<Public, Zero>]) -> SchnorrEncryptedSignature {
+    let mut s_offset = Scalar::zero().mark::<Public>();
+    for partial in partial_signatures {
+      s_offset = s!(s_offset + partial).mark::<Public>();
+    }
+    SchnorrEncryptedSignature {
+      R_offset: self.R.clone(),
+      s_offset
+    }
+  }
+}
+
+pub fn bytes_to_partial_signature(bytes: &[u8]) -> anyhow::Result<Scalar::<Public, Zero>> {
+  if bytes.len() != 32 {
+    anyhow::bail!("Expected MuSig partial signature to be 32 bytes long");
+  }
+  let mut scalar = [0; 32];
+  scalar.copy_from_slice(bytes);
+  Ok(Scalar::from_bytes(scalar).ok_or_else(|| anyhow::anyhow!("Invalid partial signature"))?.mark::<Public>())
+}
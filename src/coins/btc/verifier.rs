@@ -1,7 +1,5 @@
 use std::{
   marker::PhantomData,
-  io::Write,
-  convert::{TryInto, TryFrom},
   path::Path,
   fs::File
 };
@@ -10,26 +8,61 @@ use async_trait::async_trait;
 
 use sha2::Digest;
 
+use secp256kfun::{marker::*, Scalar};
+
+use serde::{Serialize, Deserialize};
+
 use bitcoin::{
   secp256k1,
   hash_types::Txid,
   blockdata::{script::Script, transaction::{OutPoint, TxIn, TxOut, Transaction}},
-  util::{address::Address, bip143::SighashComponents},
+  util::bip143::SighashComponents,
   consensus::{serialize, deserialize}
 };
 
 use crate::{
-  crypt_engines::{KeyBundle, CryptEngine, secp256k1_engine::Secp256k1Engine},
+  crypt_engines::{KeyBundle, CryptEngine, secp256k1_engine::{Secp256k1Engine, schnorr::*}},
   dl_eq::DlEqProof,
   coins::{
-    ScriptedVerifier,
-    btc::{engine::*, rpc::BtcRpc}
+    FeeBounds, Timelocks, SwapTerms, ScriptedVerifier,
+    btc::{engine::*, rpc::BtcRpc, cpfp::*, taproot::*}
   }
 };
 
+#[derive(Serialize, Deserialize)]
+struct BtcVerifierState {
+  engine: BtcEngine,
+  terms: Option<SwapTerms>,
+
+  host: Option<Vec<u8>>,
+  host_refund: Option<Vec<u8>>,
+  host_refund_script: Option<Script>,
+
+  swap_hash: Option<Vec<u8>>,
+
+  decryption_key: Option<<Secp256k1Engine as CryptEngine>::PrivateKey>,
+  encryption_key: Option<<Secp256k1Engine as CryptEngine>::PublicKey>,
+  encrypted_spend_sig: Option<<Secp256k1Engine as CryptEngine>::EncryptedSignature>,
+
+  lock_id: Option<Txid>,
+  lock_value: Option<u64>,
+  lock_height: Option<isize>,
+
+  refund_script: Option<Script>,
+  refund: Option<Transaction>,
+  buy: Option<Transaction>,
+
+  // Only used with a Taproot lock, where the buy is signed via MuSig
+  buy_session: Option<MusigSession>,
+  buy_partial_signature: Option<Scalar::<Public, Zero>>
+}
+
 pub struct BtcVerifier {
   engine: BtcEngine,
+  terms: Option<SwapTerms>,
   rpc: BtcRpc,
+  bumper: Bumper,
+  timelocks: Timelocks,
   destination: String,
   destination_script: Script,
 
@@ -49,18 +82,26 @@ pub struct BtcVerifier {
 
   refund_script: Option<Script>,
   refund: Option<Transaction>,
-  buy: Option<Transaction>
+  buy: Option<Transaction>,
+
+  // Only used with a Taproot lock, where the buy is signed via MuSig
+  buy_session: Option<MusigSession>,
+  buy_partial_signature: Option<Scalar::<Public, Zero>>
 }
 
 impl BtcVerifier {
-  pub fn new(config_path: &Path) -> anyhow::Result<BtcVerifier> {
-    let config = serde_json::from_reader(File::open(config_path)?)?;
+  pub async fn new(config_path: &Path, chain: BtcChain) -> anyhow::Result<BtcVerifier> {
+    let config: BtcConfig = serde_json::from_reader(File::open(config_path)?)?;
+    let rpc = BtcRpc::new(&config, chain).await?;
 
     Ok(BtcVerifier {
       engine: BtcEngine::new(),
-      rpc: BtcRpc::new(&config)?,
+      terms: None,
+      bumper: Bumper::new(&config)?,
+      timelocks: config.timelocks,
       destination: config.destination.clone(),
-      destination_script: BtcEngine::decode_address(&config.destination)?,
+      destination_script: rpc.params().decode_address(&config.destination)?,
+      rpc,
 
       host: None,
       host_refund: None,
@@ -78,7 +119,10 @@ impl BtcVerifier {
 
       refund_script: None,
       refund: None,
-      buy: None
+      buy: None,
+
+      buy_session: None,
+      buy_partial_signature: None
     })
   }
 
@@ -108,14 +152,111 @@ impl BtcVerifier {
       None
     }
   }
+
+  // The transaction spending the lock, if one has been seen, along with its confirmations
+  async fn lock_spend(&self) -> Option<(Txid, isize)> {
+    let lock_id = self.lock_id.expect("Checking for the lock's spend despite not knowing the lock");
+    let lock_address = self.rpc.params().encode_address(&self.engine.lock_script_pubkey());
+    self.rpc.get_address_history(&lock_address).await.into_iter()
+      .find(|tx| tx.tx.txid() != lock_id)
+      .map(|tx| (tx.tx.txid(), tx.confirmations))
+  }
 }
 
 #[async_trait]
 impl ScriptedVerifier for BtcVerifier {
+  fn serialize_state(&self) -> Vec<u8> {
+    bincode::serialize(
+      &BtcVerifierState {
+        engine: self.engine.clone(),
+        terms: self.terms,
+
+        host: self.host.clone(),
+        host_refund: self.host_refund.clone(),
+        host_refund_script: self.host_refund_script.clone(),
+
+        swap_hash: self.swap_hash.clone(),
+
+        decryption_key: self.decryption_key.clone(),
+        encryption_key: self.encryption_key.clone(),
+        encrypted_spend_sig: self.encrypted_spend_sig.clone(),
+
+        lock_id: self.lock_id,
+        lock_value: self.lock_value,
+        lock_height: self.lock_height,
+
+        refund_script: self.refund_script.clone(),
+        refund: self.refund.clone(),
+        buy: self.buy.clone(),
+
+        buy_session: self.buy_session.clone(),
+        buy_partial_signature: self.buy_partial_signature.clone()
+      }
+    ).expect("Couldn't serialize the BTC verifier's state")
+  }
+
+  fn restore_state(&mut self, state: &[u8]) -> anyhow::Result<()> {
+    let state: BtcVerifierState = bincode::deserialize(state)?;
+    self.engine = state.engine;
+    self.terms = state.terms;
+
+    self.host = state.host;
+    self.host_refund = state.host_refund;
+    self.host_refund_script = state.host_refund_script;
+
+    self.swap_hash = state.swap_hash;
+
+    self.decryption_key = state.decryption_key;
+    self.encryption_key = state.encryption_key;
+    self.encrypted_spend_sig = state.encrypted_spend_sig;
+
+    self.lock_id = state.lock_id;
+    self.lock_value = state.lock_value;
+    self.lock_height = state.lock_height;
+
+    self.refund_script = state.refund_script;
+    self.refund = state.refund;
+    self.buy = state.buy;
+
+    self.buy_session = state.buy_session;
+    self.buy_partial_signature = state.buy_partial_signature;
+    Ok(())
+  }
+
+  fn set_terms(&mut self, terms: SwapTerms) {
+    self.terms = Some(terms);
+  }
+
+  fn fee_bounds(&self) -> FeeBounds {
+    self.rpc.fee_bounds()
+  }
+
+  fn timelocks(&self) -> Timelocks {
+    self.timelocks
+  }
+
+  fn block_seconds(&self) -> u64 {
+    self.rpc.params().chain.block_seconds()
+  }
+
   fn destination_script(&self) -> Vec<u8> {
     self.destination_script.to_bytes()
   }
 
+  fn txids(&self) -> Vec<(&'static str, String)> {
+    let mut txids = Vec::new();
+    if let Some(lock_id) = self.lock_id {
+      txids.push(("lock", lock_id.to_string()));
+    }
+    if let Some(refund) = self.refund.as_ref() {
+      txids.push(("refund", refund.txid().to_string()));
+    }
+    if let Some(buy) = self.buy.as_ref() {
+      txids.push(("buy", buy.txid().to_string()));
+    }
+    txids
+  }
+
   fn generate_keys_for_engine<OtherCrypt: CryptEngine>(&mut self, _: PhantomData<&OtherCrypt>) -> (Vec<u8>, OtherCrypt::PrivateKey) {
     let (proof, key1, key2) = DlEqProof::<Secp256k1Engine, OtherCrypt>::new();
     self.decryption_key = Some(key1);
@@ -148,19 +289,18 @@ impl ScriptedVerifier for BtcVerifier {
     let host_refund = self.host_refund.as_ref().expect("Completing refund before verifying keys");
     let lock_and_refund: LockAndRefundInfo = bincode::deserialize(lock_and_host_signed_refund)?;
 
-    let our_fee_per_byte: i64 = self.rpc.get_fee_per_byte().await?.try_into().expect("Fee per byte is greater than 2^63");
-    let variance = our_fee_per_byte / 10; // 10% variance
-    if (our_fee_per_byte - i64::try_from(lock_and_refund.fee_per_byte)?).abs() > variance {
-      anyhow::bail!("Used fee per byte outside of acceptable variance.");
-    }
+    let terms = self.terms.expect("Completing refund before agreeing on terms");
+    terms.scripted_fee_bounds.verify(lock_and_refund.fee_per_byte)?;
+    terms.verify_scripted(lock_and_refund.value)?;
 
-    self.engine.create_lock_script(
-      &lock_and_refund.swap_hash,
-      false,
-      self.host.as_ref().expect("Completing refund before verifying keys"),
-      host_refund
-    );
-    self.swap_hash = Some(lock_and_refund.swap_hash);
+    // The host chooses the lock, which we then recreate from our keys
+    let host = self.host.as_ref().expect("Completing refund before verifying keys");
+    if lock_and_refund.taproot {
+      self.engine.create_taproot_lock(false, host, host_refund, terms.timelocks.t0)?;
+    } else {
+      self.engine.create_lock_script(&lock_and_refund.swap_hash, false, host, host_refund, terms.timelocks.t0);
+      self.swap_hash = Some(lock_and_refund.swap_hash);
+    }
 
     let lock_id = deserialize(&lock_and_refund.lock_id)?;
     let (refund_script, mut refund, refund_msg, refund_sig) = self.engine.prepare_and_sign_refund(
@@ -169,21 +309,23 @@ impl ScriptedVerifier for BtcVerifier {
       host_refund,
       &self.B(),
       lock_and_refund.value,
-      lock_and_refund.fee_per_byte
+      lock_and_refund.fee_per_byte,
+      &terms.timelocks
     )?;
     self.lock_id = Some(lock_id);
     self.lock_value = Some(lock_and_refund.value);
     self.refund_script = Some(refund_script);
 
-    SECP.verify(
+    self.engine.verify_refund_signature(
       &refund_msg,
-      &secp256k1::Signature::from_der(&lock_and_refund.host_refund_signature)?,
-      &secp256k1::PublicKey::from_slice(self.host_refund.as_ref().expect("Completing refund despite not knowing the host's refund key"))?
+      &lock_and_refund.host_refund_signature,
+      self.host_refund.as_ref().expect("Completing refund despite not knowing the host's refund key")
     )?;
 
-    let spend = BtcEngine::prepare_spend(
+    let spend = self.engine.prepare_spend(
       refund.txid(),
       self.host_refund_script.clone().expect("Preparing spend despite not knowing the host's refund script"),
+      host_refund,
       refund.output[0].value,
       lock_and_refund.fee_per_byte
     )?;
@@ -201,15 +343,7 @@ impl ScriptedVerifier for BtcVerifier {
     let encrypted_spend_sig = Secp256k1Engine::encrypted_signature_to_bytes(&encrypted_spend_sig_typed);
     self.encrypted_spend_sig = Some(encrypted_spend_sig_typed);
 
-    refund.input[0].witness = vec![
-      Vec::new(),
-      refund_sig.clone(),
-      lock_and_refund.host_refund_signature,
-      Vec::new(),
-      self.engine.lock_script_bytes.clone().expect("Completing refund despite not knowing the lock script")
-    ];
-    refund.input[0].witness[1].push(1);
-    refund.input[0].witness[2].push(1);
+    refund.input[0].witness = self.engine.refund_witness_with_signatures(lock_and_refund.host_refund_signature, refund_sig.clone());
     self.refund = Some(refund);
 
     Ok(bincode::serialize(&ClientRefundAndSpendSignatures {
@@ -218,20 +352,13 @@ impl ScriptedVerifier for BtcVerifier {
     }).expect("Couldn't serialize the client's refund and spend signatures"))
   }
 
-  fn verify_prepared_buy(&mut self, buy_info: &[u8]) -> anyhow::Result<()> {
+  fn verify_prepared_buy(&mut self, buy_info: &[u8]) -> anyhow::Result<Vec<u8>> {
     let host_bytes = self.host.as_ref().expect("Verifying and waiting for lock before verifying their keys");
     let lock_id = self.lock_id.expect("Finishing our buy before knowing the lock's ID");
 
+    let lock_value = self.lock_value.expect("Finishing our buy before knowing the lock's value");
+
     let buy_info: BuyInfo = bincode::deserialize(buy_info)?;
-    if !cfg!(test) {
-      print!("You will receive {} satoshis. Continue (yes/no)? ", buy_info.value);
-      std::io::stdout().flush().expect("Failed to flush stdout");
-      let mut line = String::new();
-      std::io::stdin().read_line(&mut line).expect("Couldn't read from stdin");
-      if !line.to_lowercase().starts_with("y") {
-        anyhow::bail!("User didn't confirm BTC amount");
-      }
-    }
 
     let mut buy: Transaction = Transaction {
       version: 2,
@@ -251,19 +378,55 @@ impl ScriptedVerifier for BtcVerifier {
         TxOut {
           script_pubkey: self.destination_script.clone(),
           value: buy_info.value
-        }
+        },
+        anchor_output(&self.B())?
       ]
     };
 
-    let components = SighashComponents::new(&buy);
-    let buy_message = secp256k1::Message::from_slice(
-      &components.sighash_all(
-        &buy.input[0],
-        self.engine.lock_script(),
-        self.lock_value.expect("Finishing our buy before knowing the lock's value")
-      )
-    )?;
+    // The lock's value was already checked against the terms, so only the buy's fee needs to be checked
+    // It must be within what the agreed fee rates imply, as too low a fee could leave the buy unconfirmed past T0
+    let fee_bounds = self.terms.expect("Verifying buy before agreeing on terms").scripted_fee_bounds;
+    let vsize = signed_vsize(&buy, &self.engine.buy_witness());
+    let min_fee = vsize.saturating_mul(fee_bounds.min);
+    let max_fee = vsize.saturating_mul(fee_bounds.max);
+    if (buy_info.value + ANCHOR_VALUE) > lock_value {
+      anyhow::bail!("Buy's value of {} exceeds the lock's value of {}", buy_info.value, lock_value);
+    }
+    let fee = lock_value - buy_info.value - ANCHOR_VALUE;
+    if (fee < min_fee) || (fee > max_fee) {
+      anyhow::bail!("Buy pays {} sats in fees, outside the agreed range of {}-{}", fee, min_fee, max_fee);
+    }
 
+    let buy_message = self.engine.buy_message(&buy, lock_value);
+
+    /*
+      With a Taproot lock, the buy is signed by both of us via MuSig, with the signature encrypted to our decryption key
+      We sign first, as the host's partial signature is what lets us complete the buy, taking the place of the swap secret
+      The host can't publish the buy without our decryption key, which is revealed by publishing it
+    */
+    if let Some(taproot) = self.engine.taproot.as_ref() {
+      let host_nonces = bytes_to_nonces(&buy_info.nonces)?;
+      let nonces = SecretNonces::new();
+      let public_nonces = nonces.public();
+      let session = MusigSession::new(
+        taproot,
+        &[host_nonces, public_nonces.clone()],
+        &Secp256k1Engine::to_public_key(self.decryption_key.as_ref().expect("Attempted to sign the buy before generate_keys called")),
+        &schnorr_message(&buy_message)?
+      )?;
+      let partial_signature = session.partial_sign(&taproot.tweak_key_share(&self.engine.b, false)?, nonces);
+
+      let result = bincode::serialize(&ClientBuySignature {
+        nonces: nonces_to_bytes(&public_nonces),
+        partial_signature: partial_signature.to_bytes().to_vec()
+      })?;
+      self.buy_session = Some(session);
+      self.buy_partial_signature = Some(partial_signature);
+      self.buy = Some(buy);
+      return Ok(result);
+    }
+
+    let buy_message = secp256k1::Message::from_slice(&buy_message)?;
     let decrypted_signature = secp256k1::Signature::from_compact(
       &Secp256k1Engine::signature_to_bytes(
         &Secp256k1Engine::decrypt_signature(
@@ -297,13 +460,14 @@ impl ScriptedVerifier for BtcVerifier {
     buy.input[0].witness[2].push(1);
     self.buy = Some(buy);
 
-    Ok(())
+    // The host already signed, so there's nothing to reply with
+    Ok(Vec::new())
   }
 
   async fn verify_and_wait_for_lock(
     &mut self
   ) -> anyhow::Result<()> {
-    let lock_address = Address::p2wsh(self.engine.lock_script(), NETWORK).to_string();
+    let lock_address = self.rpc.params().encode_address(&self.engine.lock_script_pubkey());
 
     let mut history = self.rpc.get_address_history(&lock_address).await;
     while history.len() == 0 {
@@ -327,6 +491,9 @@ impl ScriptedVerifier for BtcVerifier {
     if lock.output.len() != 1 {
       anyhow::bail!("Lock didn't have the expected output set.");
     }
+    if lock.output[0].value != self.lock_value.expect("Waiting for lock despite not knowing its value") {
+      anyhow::bail!("Lock's value differs from the one we signed the refund for.");
+    }
 
     // Wait for the lock to confirm
     if cfg!(feature = "no_confs") {
@@ -352,7 +519,8 @@ impl ScriptedVerifier for BtcVerifier {
 
   async fn claim_refund_or_recover_key(mut self) -> anyhow::Result<Option<[u8; 32]>> {
     let lock_height = self.lock_height.expect("Trying to publish refund despite no lock on chain");
-    while self.rpc.get_height().await < (lock_height + (T0 as isize)) {
+    let timelocks = self.terms.expect("Trying to publish refund before agreeing on terms").timelocks;
+    while self.rpc.get_height().await < (lock_height + (timelocks.t0 as isize)) {
       tokio::time::delay_for(std::time::Duration::from_secs(20)).await;
     }
 
@@ -361,12 +529,20 @@ impl ScriptedVerifier for BtcVerifier {
     // Ignore this result
     // Publishing an existing transaction can cause an error
     let _ = self.rpc.publish(&serialize(refund)).await;
+    let mut cpfp = self.bumper.track(
+      refund.clone(),
+      self.lock_value.expect("Trying to publish refund despite not knowing the lock's value"),
+      2,
+      secp256k1::SecretKey::from_slice(&Secp256k1Engine::private_key_to_bytes(&self.engine.br))?,
+      TxKind::Refund,
+      self.rpc.get_height().await
+    );
 
     let refund_script = self.refund_script.as_ref().expect("Trying to recover key despite not having a refund transaction");
-    let refund_address = Address::p2wsh(refund_script, NETWORK).to_string();
+    let refund_address = self.rpc.params().p2wsh_address(refund_script);
     loop {
       #[cfg(test)]
-      for _ in 0 .. T0 {
+      for _ in 0 .. timelocks.t0 {
         self.rpc.mine_block().await?;
       }
 
@@ -374,13 +550,23 @@ impl ScriptedVerifier for BtcVerifier {
       if (history.len() > 0) && ((history[0].confirmations) >= CONFIRMATIONS) {
         break;
       }
+
+      // If our buy was published before we gave up on it, it may confirm instead, in which case we already have the BTC
+      if let Some(buy) = self.buy.as_ref() {
+        if let Some((spend, confirmations)) = self.lock_spend().await {
+          if (spend == buy.txid()) && (confirmations >= CONFIRMATIONS) {
+            return Ok(None);
+          }
+        }
+      }
+      cpfp.poll(&self.rpc).await;
       tokio::time::delay_for(std::time::Duration::from_secs(20)).await;
     }
     let refund_height = self.rpc.get_height().await;
 
-    while self.rpc.get_height().await < (refund_height + (T1 as isize)) {
+    while self.rpc.get_height().await < (refund_height + (timelocks.t1 as isize)) {
       #[cfg(test)]
-      for _ in 0 .. T1 {
+      for _ in 0 .. timelocks.t1 {
         self.rpc.mine_block().await?;
       }
 
@@ -400,7 +586,7 @@ impl ScriptedVerifier for BtcVerifier {
             vout: 0
           },
           script_sig: Script::new(),
-          sequence: T1 as u32,
+          sequence: timelocks.t1 as u32,
           witness: vec![
             vec![0; 65],
             Vec::new(),
@@ -415,7 +601,7 @@ impl ScriptedVerifier for BtcVerifier {
         }
       ]
     };
-    let fee = ((claim.get_weight() as u64) / 4) * self.rpc.get_fee_per_byte().await?;
+    let fee = signed_vsize(&claim, &self.engine.claim_witness()) * self.rpc.get_fee_per_byte(TxKind::Claim).await?;
     claim.output[0].value = claim.output[0].value.checked_sub(fee)
       .ok_or_else(|| anyhow::anyhow!("Not enough Bitcoin to pay for {} sats of fees", fee))?;
 
@@ -465,20 +651,77 @@ impl ScriptedVerifier for BtcVerifier {
   }
 
   async fn finish(&self, swap_secret: &[u8]) -> anyhow::Result<()> {
-    // Verify the received swap secret
-    if sha2::Sha256::digest(swap_secret).to_vec() != self.swap_hash.clone().expect("Trying to finish our buy before knowing the swap hash") {
-      anyhow::bail!("Received an invalid swap secret");
+    let mut buy = self.buy.clone().expect("Finishing before verifying buy");
+
+    // With a Taproot lock, the swap secret is the host's partial signature, completing the buy's signature
+    if let Some(session) = self.buy_session.as_ref() {
+      let taproot = self.engine.taproot.as_ref().expect("Signed a Taproot buy without a Taproot lock");
+      let encrypted_signature = session.encrypted_signature(&[
+        bytes_to_partial_signature(swap_secret)?,
+        self.buy_partial_signature.clone().expect("Trying to finish our buy before signing it")
+      ]);
+      let decryption_key = self.decryption_key.as_ref().expect("Attempted to finish verifier before generate_keys called");
+      let message = self.engine.buy_message(&buy, self.lock_value.expect("Finishing our buy before knowing the lock's value"));
+      Secp256k1Engine::schnorr_encrypted_verify(
+        &taproot.output_key,
+        &Secp256k1Engine::to_public_key(decryption_key),
+        &encrypted_signature,
+        &message
+      ).map_err(|_| anyhow::anyhow!("Received an invalid partial signature for the buy"))?;
+      buy.input[0].witness = vec![
+        Secp256k1Engine::schnorr_signature_to_bytes(&Secp256k1Engine::schnorr_decrypt_signature(&encrypted_signature, decryption_key)?)
+      ];
+    } else {
+      // Verify the received swap secret
+      if sha2::Sha256::digest(swap_secret).to_vec() != self.swap_hash.clone().expect("Trying to finish our buy before knowing the swap hash") {
+        anyhow::bail!("Received an invalid swap secret");
+      }
+      buy.input[0].witness[3] = swap_secret.to_vec();
     }
+
     // Check that we aren't nearing the end of the timelock
     let lock_height = self.lock_height.expect("Attempted to finish swap before verifying lock confirmation");
-    if self.rpc.get_height().await - lock_height >= SWAP_CUTOFF_BLOCKS {
+    let timelocks = self.terms.expect("Trying to finish our buy before agreeing on terms").timelocks;
+    let cutoff = timelocks.cutoff;
+    if self.rpc.get_height().await - lock_height >= (cutoff as isize) {
       anyhow::bail!("Attempted to finish swap, but we're nearing the end of the timelock");
     }
 
-    let mut buy = self.buy.clone().expect("Finishing before verifying buy");
-    buy.input[0].witness[3] = swap_secret.to_vec();
     self.rpc.publish(&serialize(&buy)).await?;
 
-    Ok(())
+    // Wait for the buy to confirm, bumping it if needed, as the host can publish the refund after T0
+    let buy_id = buy.txid();
+    let mut cpfp = self.bumper.track(
+      buy,
+      self.lock_value.expect("Finishing our buy before knowing the lock's value"),
+      1,
+      secp256k1::SecretKey::from_slice(&Secp256k1Engine::private_key_to_bytes(&self.engine.b))?,
+      TxKind::Buy,
+      self.rpc.get_height().await
+    );
+    /*
+      If the lock is spent by anything other than our buy, it was refunded
+      If T0 expires first, the host can refund at any time, so we stop waiting and fall back to the refund path
+      The refund path still completes if the buy confirms after all
+    */
+    loop {
+      #[cfg(test)]
+      self.rpc.mine_block().await?;
+
+      if let Some((spend, confirmations)) = self.lock_spend().await {
+        if spend != buy_id {
+          anyhow::bail!("The lock was refunded before our buy confirmed");
+        }
+        if confirmations >= CONFIRMATIONS {
+          return Ok(());
+        }
+      }
+      if self.rpc.get_height().await >= (lock_height + (timelocks.t0 as isize)) {
+        anyhow::bail!("Our buy didn't confirm before T0 expired");
+      }
+
+      cpfp.poll(&self.rpc).await;
+      tokio::time::delay_for(std::time::Duration::from_secs(20)).await;
+    }
   }
 }
@@ -0,0 +1,465 @@
+use std::convert::TryInto;
+
+use lazy_static::lazy_static;
+
+use serde::{Serialize, Deserialize};
+
+use sha3::{Digest, Keccak256};
+use secp256k1::{Secp256k1, SecretKey, PublicKey, Message, recovery::{RecoverableSignature, RecoveryId}};
+
+use crate::{
+  crypt_engines::{CryptEngine, secp256k1_engine::Secp256k1Engine},
+  coins::Timelocks
+};
+
+// Blocks a transaction must be in, including the one it was mined in
+pub const CONFIRMATIONS: u64 = 1;
+
+// Gas limits for every transaction we send, generous as unused gas is refunded
+pub const TRANSFER_GAS: u64 = 21000;
+pub const TOKEN_GAS: u64 = 100000;
+pub const LOCK_GAS: u64 = 150000;
+pub const REFUND_GAS: u64 = 80000;
+pub const BUY_GAS: u64 = 150000;
+pub const SPEND_GAS: u64 = 150000;
+pub const CLAIM_GAS: u64 = 150000;
+
+// The actions the contract accepts signatures for
+pub const BUY: u8 = 1;
+pub const SPEND: u8 = 2;
+
+// The tuple type of the contract's Params struct, as used in function signatures
+const PARAMS_TYPE: &str = "(address,uint256,bytes32,address,address,address,address,uint256,uint256)";
+
+lazy_static! {
+  pub static ref SECP: Secp256k1<secp256k1::All> = Secp256k1::new();
+}
+
+pub type Address = [u8; 20];
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+  Keccak256::digest(data).into()
+}
+
+pub fn selector(signature: &str) -> [u8; 4] {
+  keccak256(signature.as_bytes())[.. 4].try_into().unwrap()
+}
+
+pub fn address_from_public_key(key: &PublicKey) -> Address {
+  keccak256(&key.serialize_uncompressed()[1 ..])[12 ..].try_into().unwrap()
+}
+
+// Takes the compressed keys exchanged during the protocol
+pub fn address_from_key_bytes(key: &[u8]) -> anyhow::Result<Address> {
+  Ok(address_from_public_key(&PublicKey::from_slice(key)?))
+}
+
+pub fn secret_key(key: &<Secp256k1Engine as CryptEngine>::PrivateKey) -> SecretKey {
+  SecretKey::from_slice(&Secp256k1Engine::private_key_to_bytes(key)).expect("Secp256k1Engine generated an invalid secp256k1 key")
+}
+
+// Addresses are encoded with the EIP-55 checksum, which is only checked when decoding mixed case addresses
+pub fn encode_address(address: &Address) -> String {
+  let hex = hex::encode(address);
+  let hash = keccak256(hex.as_bytes());
+  let mut result = "0x".to_string();
+  for (i, c) in hex.chars().enumerate() {
+    if ((hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0xf) >= 8 {
+      result.push(c.to_ascii_uppercase());
+    } else {
+      result.push(c);
+    }
+  }
+  result
+}
+
+pub fn decode_address(address: &str) -> anyhow::Result<Address> {
+  let hex = address.strip_prefix("0x").ok_or_else(|| anyhow::anyhow!("Ethereum address isn't 0x prefixed"))?;
+  let decoded: Address = hex::decode(hex)?.as_slice().try_into()
+    .map_err(|_| anyhow::anyhow!("Ethereum address isn't 20 bytes"))?;
+  if (hex.to_lowercase() != hex) && (hex.to_uppercase() != hex) && (encode_address(&decoded) != address) {
+    anyhow::bail!("Ethereum address has an invalid checksum");
+  }
+  Ok(decoded)
+}
+
+pub fn decode_quantity(quantity: &str) -> anyhow::Result<u128> {
+  let hex = quantity.strip_prefix("0x").ok_or_else(|| anyhow::anyhow!("Quantity isn't 0x prefixed"))?;
+  if hex.len() == 0 {
+    return Ok(0);
+  }
+  Ok(u128::from_str_radix(hex, 16)?)
+}
+
+fn rlp_length(length: usize, offset: u8) -> Vec<u8> {
+  if length < 56 {
+    vec![offset + (length as u8)]
+  } else {
+    let bytes = length.to_be_bytes();
+    let length_bytes = &bytes[bytes.iter().position(|byte| *byte != 0).unwrap() ..];
+    let mut result = vec![offset + 55 + (length_bytes.len() as u8)];
+    result.extend(length_bytes);
+    result
+  }
+}
+
+fn rlp_bytes(bytes: &[u8]) -> Vec<u8> {
+  if (bytes.len() == 1) && (bytes[0] < 0x80) {
+    return bytes.to_vec();
+  }
+  let mut result = rlp_length(bytes.len(), 0x80);
+  result.extend(bytes);
+  result
+}
+
+// Integers are encoded as big endian without leading zeroes, with zero being the empty string
+fn rlp_integer(integer: u128) -> Vec<u8> {
+  let bytes = integer.to_be_bytes();
+  rlp_bytes(&bytes[bytes.iter().position(|byte| *byte != 0).unwrap_or(bytes.len()) ..])
+}
+
+fn rlp_list(items: &[Vec<u8>]) -> Vec<u8> {
+  let body = items.concat();
+  let mut result = rlp_length(body.len(), 0xc0);
+  result.extend(body);
+  result
+}
+
+// A legacy transaction, signed with EIP-155 replay protection
+#[derive(Clone, Debug)]
+pub struct EthTransaction {
+  pub nonce: u64,
+  pub gas_price: u128,
+  pub gas: u64,
+  pub to: Address,
+  pub value: u128,
+  pub data: Vec<u8>
+}
+
+impl EthTransaction {
+  fn fields(&self) -> Vec<Vec<u8>> {
+    vec![
+      rlp_integer(self.nonce as u128),
+      rlp_integer(self.gas_price),
+      rlp_integer(self.gas as u128),
+      rlp_bytes(&self.to),
+      rlp_integer(self.value),
+      rlp_bytes(&self.data)
+    ]
+  }
+
+  // Returns the serialized transaction and its hash
+  pub fn sign(&self, chain_id: u64, key: &SecretKey) -> (Vec<u8>, [u8; 32]) {
+    let mut unsigned = self.fields();
+    unsigned.extend(vec![rlp_integer(chain_id as u128), rlp_integer(0), rlp_integer(0)]);
+    let message = Message::from_slice(&keccak256(&rlp_list(&unsigned))).expect("Keccak256 hash wasn't 32 bytes");
+
+    let (recovery_id, signature) = SECP.sign_recoverable(&message, key).serialize_compact();
+    // r and s are 256-bit integers, too large for rlp_integer, yet encoded the same way
+    let strip = |bytes: &[u8]| rlp_bytes(&bytes[bytes.iter().position(|byte| *byte != 0).unwrap_or(bytes.len()) ..]);
+    let mut signed = self.fields();
+    signed.extend(vec![
+      rlp_integer((recovery_id.to_i32() as u128) + ((chain_id as u128) * 2) + 35),
+      strip(&signature[.. 32]),
+      strip(&signature[32 ..])
+    ]);
+
+    let serialized = rlp_list(&signed);
+    let hash = keccak256(&serialized);
+    (serialized, hash)
+  }
+}
+
+fn word(bytes: &[u8]) -> [u8; 32] {
+  let mut word = [0; 32];
+  word[32 - bytes.len() ..].copy_from_slice(bytes);
+  word
+}
+
+fn integer_word(integer: u128) -> [u8; 32] {
+  word(&integer.to_be_bytes())
+}
+
+/*
+  Every swap's parameters, as the contract's Params struct
+  The swap's ID is the hash of these, so both parties agreeing on them is agreeing on the swap
+*/
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SwapParams {
+  pub token: Address,
+  pub value: u128,
+  pub hash: [u8; 32],
+  pub host: Address,
+  pub client_refund: Address,
+  pub host_destination: Address,
+  pub client_destination: Address,
+  pub t0: u16,
+  pub t1: u16
+}
+
+impl SwapParams {
+  fn encode(&self) -> Vec<u8> {
+    [
+      word(&self.token),
+      integer_word(self.value),
+      self.hash,
+      word(&self.host),
+      word(&self.client_refund),
+      word(&self.host_destination),
+      word(&self.client_destination),
+      integer_word(self.t0 as u128),
+      integer_word(self.t1 as u128)
+    ].concat()
+  }
+
+  pub fn id(&self) -> [u8; 32] {
+    keccak256(&self.encode())
+  }
+
+  // The hash signed for the buy or spend, matching the contract's message function
+  pub fn message(&self, chain_id: u64, contract: &Address, action: u8) -> [u8; 32] {
+    let mut preimage = integer_word(chain_id as u128).to_vec();
+    preimage.extend(contract);
+    preimage.extend(&self.id());
+    preimage.push(action);
+    keccak256(&preimage)
+  }
+
+  fn call(&self, function: &str, extra: &[[u8; 32]]) -> Vec<u8> {
+    let mut types = PARAMS_TYPE.to_string();
+    match function {
+      "buy" => types += ",bytes32,uint8,bytes32,bytes32",
+      "spend" => types += ",uint8,bytes32,bytes32",
+      _ => ()
+    }
+    let mut data = selector(&format!("{}({})", function, types)).to_vec();
+    data.extend(self.encode());
+    data.extend(extra.concat());
+    data
+  }
+
+  pub fn lock_call(&self) -> Vec<u8> {
+    self.call("lock", &[])
+  }
+
+  pub fn buy_call(&self, secret: &[u8; 32], signature: &EthSignature) -> Vec<u8> {
+    self.call("buy", &[*secret, integer_word(signature.v as u128), signature.r, signature.s])
+  }
+
+  pub fn refund_call(&self) -> Vec<u8> {
+    self.call("refund", &[])
+  }
+
+  pub fn spend_call(&self, signature: &EthSignature) -> Vec<u8> {
+    self.call("spend", &[integer_word(signature.v as u128), signature.r, signature.s])
+  }
+
+  pub fn claim_call(&self) -> Vec<u8> {
+    self.call("claim", &[])
+  }
+}
+
+pub fn swaps_call(id: &[u8; 32]) -> Vec<u8> {
+  let mut data = selector("swaps(bytes32)").to_vec();
+  data.extend(id);
+  data
+}
+
+pub fn approve_call(spender: &Address, value: u128) -> Vec<u8> {
+  let mut data = selector("approve(address,uint256)").to_vec();
+  data.extend(&word(spender));
+  data.extend(&integer_word(value));
+  data
+}
+
+pub fn transfer_call(to: &Address, value: u128) -> Vec<u8> {
+  let mut data = selector("transfer(address,uint256)").to_vec();
+  data.extend(&word(to));
+  data.extend(&integer_word(value));
+  data
+}
+
+pub fn balance_of_call(owner: &Address) -> Vec<u8> {
+  let mut data = selector("balanceOf(address)").to_vec();
+  data.extend(&word(owner));
+  data
+}
+
+// The topic of the Bought or Spent event, which log the swap's ID and a signature's r and s values
+pub fn event_topic(event: &str) -> [u8; 32] {
+  keccak256(format!("{}(bytes32,bytes32,bytes32)", event).as_bytes())
+}
+
+// Decodes the nth 32-byte word of returned data as an integer
+pub fn decode_word(data: &[u8], n: usize) -> anyhow::Result<u128> {
+  let word = data.get((n * 32) .. ((n + 1) * 32)).ok_or_else(|| anyhow::anyhow!("Returned data was too short"))?;
+  if word[.. 16].iter().any(|byte| *byte != 0) {
+    anyhow::bail!("Returned integer doesn't fit in 128 bits");
+  }
+  Ok(u128::from_be_bytes(word[16 ..].try_into().unwrap()))
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SwapState {
+  None,
+  Locked,
+  Refunded,
+  Done
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SwapStatus {
+  pub state: SwapState,
+  pub locked_at: u64,
+  pub refunded_at: u64
+}
+
+impl SwapStatus {
+  pub fn decode(data: &[u8]) -> anyhow::Result<SwapStatus> {
+    Ok(SwapStatus {
+      state: match decode_word(data, 0)? {
+        0 => SwapState::None,
+        1 => SwapState::Locked,
+        2 => SwapState::Refunded,
+        3 => SwapState::Done,
+        _ => anyhow::bail!("Contract returned an unknown swap state")
+      },
+      locked_at: decode_word(data, 1)? as u64,
+      refunded_at: decode_word(data, 2)? as u64
+    })
+  }
+}
+
+// A signature as ecrecover takes it, with v being 27 or 28
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct EthSignature {
+  pub v: u8,
+  pub r: [u8; 32],
+  pub s: [u8; 32]
+}
+
+impl EthSignature {
+  /*
+    Adaptor signatures don't carry a recovery ID, so the one ecrecover needs is found by trying both
+    This also verifies the signature is from the expected signer
+  */
+  pub fn from_compact(signature: &[u8], message: &[u8; 32], signer: &Address) -> anyhow::Result<EthSignature> {
+    let message = Message::from_slice(message)?;
+    for id in 0 .. 2 {
+      let recoverable = RecoverableSignature::from_compact(signature, RecoveryId::from_i32(id)?)?;
+      if let Ok(key) = SECP.recover(&message, &recoverable) {
+        if &address_from_public_key(&key) == signer {
+          return Ok(EthSignature {
+            v: 27 + (id as u8),
+            r: signature[.. 32].try_into().unwrap(),
+            s: signature[32 ..].try_into().unwrap()
+          });
+        }
+      }
+    }
+    anyhow::bail!("Signature isn't from the expected signer")
+  }
+
+  // Reads the r and s values an event logged
+  pub fn from_log(data: &[u8]) -> anyhow::Result<<Secp256k1Engine as CryptEngine>::Signature> {
+    if data.len() != 64 {
+      anyhow::bail!("Logged signature wasn't 64 bytes");
+    }
+    Secp256k1Engine::bytes_to_signature(data)
+  }
+}
+
+fn default_unit_exponent() -> u32 {
+  9
+}
+
+fn default_block_seconds() -> u64 {
+  12
+}
+
+fn default_min_gas_price() -> u64 {
+  1_000_000_000
+}
+
+fn default_max_gas_price() -> u64 {
+  500_000_000_000
+}
+
+#[derive(Deserialize)]
+pub struct EthConfig {
+  // The chain ID, which selects the network and is checked against the node's
+  pub chain_id: u64,
+  pub url: String,
+  // The deployed swap contract
+  pub contract: String,
+  // An ERC-20 token to trade instead of ether
+  pub token: Option<String>,
+  // Amounts are in units of 10^unit_exponent of the smallest unit, as wei overflows a u64 past 18 ether
+  #[serde(default = "default_unit_exponent")]
+  pub unit_exponent: u32,
+  #[serde(default = "default_block_seconds")]
+  pub block_seconds: u64,
+  // In wei, with the node's gas price clamped to this range
+  #[serde(default = "default_min_gas_price")]
+  pub min_gas_price: u64,
+  #[serde(default = "default_max_gas_price")]
+  pub max_gas_price: u64,
+  #[serde(default)]
+  pub timelocks: Timelocks,
+  // A hex-encoded private key paying for the gas of every contract call besides the lock
+  pub gas_key: String,
+  pub destination: String,
+  pub refund: String
+}
+
+impl EthConfig {
+  // Rounds down, saturating if the value doesn't fit
+  pub fn from_base_units(&self, value: u128) -> u64 {
+    let unit = 10u128.checked_pow(self.unit_exponent).unwrap_or(u128::MAX);
+    let amount = value / unit;
+    if amount > (u64::MAX as u128) {
+      u64::MAX
+    } else {
+      amount as u64
+    }
+  }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EthEngine {
+  pub b: <Secp256k1Engine as CryptEngine>::PrivateKey,
+  pub br: <Secp256k1Engine as CryptEngine>::PrivateKey,
+  pub bs: Option<<Secp256k1Engine as CryptEngine>::PrivateKey>
+}
+
+impl EthEngine {
+  pub fn new() -> EthEngine {
+    EthEngine {
+      b: Secp256k1Engine::new_private_key(),
+      br: Secp256k1Engine::new_private_key(),
+      bs: None
+    }
+  }
+
+  pub fn generate_deposit_address() -> (<Secp256k1Engine as CryptEngine>::PrivateKey, Address) {
+    let key = Secp256k1Engine::new_private_key();
+    let address = address_from_public_key(&PublicKey::from_secret_key(&SECP, &secret_key(&key)));
+    (key, address)
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LockInfo {
+  pub contract: Address,
+  pub params: SwapParams
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ClientSpendSignature {
+  pub encrypted_spend_signature: Vec<u8>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BuyInfo {
+  pub encrypted_signature: Vec<u8>
+}
@@ -0,0 +1,474 @@
+use std::{
+  marker::PhantomData,
+  path::Path,
+  fs::File
+};
+
+use async_trait::async_trait;
+use rand::{rngs::OsRng, RngCore};
+use digest::Digest;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{
+  crypt_engines::{KeyBundle, CryptEngine, secp256k1_engine::Secp256k1Engine},
+  coins::{
+    FeeBounds, Timelocks, SwapTerms, ScriptedHost, UnscriptedVerifier,
+    eth::{engine::*, rpc::EthRpc}
+  }
+};
+
+#[derive(Serialize, Deserialize)]
+struct EthHostState {
+  engine: EthEngine,
+  terms: Option<SwapTerms>,
+  address: Option<(<Secp256k1Engine as CryptEngine>::PrivateKey, Address)>,
+
+  swap_secret: [u8; 32],
+
+  params: Option<SwapParams>,
+  lock_gas_price: Option<u128>,
+  lock: Option<[u8; 32]>,
+  lock_height: Option<u64>,
+  encrypted_spend_signature: Option<<Secp256k1Engine as CryptEngine>::EncryptedSignature>,
+
+  client_refund: Option<Vec<u8>>,
+  client_destination: Option<Address>,
+
+  encryption_key: Option<<Secp256k1Engine as CryptEngine>::PublicKey>,
+  encrypted_signature: Option<<Secp256k1Engine as CryptEngine>::EncryptedSignature>
+}
+
+pub struct EthHost {
+  engine: EthEngine,
+  terms: Option<SwapTerms>,
+  rpc: EthRpc,
+  config: EthConfig,
+  refund_address: Address,
+  address: Option<(<Secp256k1Engine as CryptEngine>::PrivateKey, Address)>,
+
+  swap_secret: [u8; 32],
+  swap_hash: [u8; 32],
+
+  params: Option<SwapParams>,
+  lock_gas_price: Option<u128>,
+  lock: Option<[u8; 32]>,
+  lock_height: Option<u64>,
+  encrypted_spend_signature: Option<<Secp256k1Engine as CryptEngine>::EncryptedSignature>,
+
+  client_refund: Option<Vec<u8>>,
+  client_destination: Option<Address>,
+
+  encryption_key: Option<<Secp256k1Engine as CryptEngine>::PublicKey>,
+  encrypted_signature: Option<<Secp256k1Engine as CryptEngine>::EncryptedSignature>
+}
+
+impl EthHost {
+  pub async fn new(config_path: &Path) -> anyhow::Result<EthHost> {
+    let config: EthConfig = serde_json::from_reader(File::open(config_path)?)?;
+    let rpc = EthRpc::new(&config).await?;
+
+    let mut swap_secret = [0; 32];
+    OsRng.fill_bytes(&mut swap_secret);
+    Ok(EthHost {
+      engine: EthEngine::new(),
+      terms: None,
+      rpc,
+      refund_address: decode_address(&config.refund)?,
+      config,
+      address: None,
+
+      swap_secret,
+      swap_hash: sha2::Sha256::digest(&swap_secret).into(),
+
+      params: None,
+      lock_gas_price: None,
+      lock: None,
+      lock_height: None,
+      encrypted_spend_signature: None,
+
+      client_refund: None,
+      client_destination: None,
+
+      encryption_key: None,
+      encrypted_signature: None
+    })
+  }
+
+  fn params(&self) -> &SwapParams {
+    self.params.as_ref().expect("Using the swap's parameters before creating the lock")
+  }
+
+  // Returns whatever the deposit address holds to the refund address
+  async fn return_deposit(&self) -> anyhow::Result<()> {
+    let address = self.address.as_ref().expect("Returning the deposit despite not having created an address");
+    let key = secret_key(&address.0);
+    let gas_price = self.rpc.get_gas_price().await?;
+
+    if self.rpc.is_token() {
+      let balance = self.rpc.get_traded_balance(&address.1).await?;
+      if balance == 0 {
+        return Ok(());
+      }
+      self.rpc.fund_gas(&address.1, TOKEN_GAS, gas_price).await?;
+      let hash = self.rpc.send(&key, &self.rpc.token(), 0, transfer_call(&self.refund_address, balance), TOKEN_GAS, gas_price).await?;
+      self.rpc.wait_for_transaction(&hash).await?;
+    } else {
+      let balance = self.rpc.get_balance(&address.1).await?;
+      let fee = (TRANSFER_GAS as u128) * gas_price;
+      if balance <= fee {
+        return Ok(());
+      }
+      let hash = self.rpc.send(&key, &self.refund_address, balance - fee, Vec::new(), TRANSFER_GAS, gas_price).await?;
+      self.rpc.wait_for_transaction(&hash).await?;
+    }
+    Ok(())
+  }
+}
+
+#[async_trait]
+impl ScriptedHost for EthHost {
+  fn serialize_state(&self) -> Vec<u8> {
+    bincode::serialize(
+      &EthHostState {
+        engine: self.engine.clone(),
+        terms: self.terms,
+        address: self.address.clone(),
+
+        swap_secret: self.swap_secret,
+
+        params: self.params,
+        lock_gas_price: self.lock_gas_price,
+        lock: self.lock,
+        lock_height: self.lock_height,
+        encrypted_spend_signature: self.encrypted_spend_signature.clone(),
+
+        client_refund: self.client_refund.clone(),
+        client_destination: self.client_destination,
+
+        encryption_key: self.encryption_key.clone(),
+        encrypted_signature: self.encrypted_signature.clone()
+      }
+    ).expect("Couldn't serialize the ETH host's state")
+  }
+
+  fn restore_state(&mut self, state: &[u8]) -> anyhow::Result<()> {
+    let state: EthHostState = bincode::deserialize(state)?;
+    self.engine = state.engine;
+    self.terms = state.terms;
+    self.address = state.address;
+
+    self.swap_secret = state.swap_secret;
+    self.swap_hash = sha2::Sha256::digest(&self.swap_secret).into();
+
+    self.params = state.params;
+    self.lock_gas_price = state.lock_gas_price;
+    self.lock = state.lock;
+    self.lock_height = state.lock_height;
+    self.encrypted_spend_signature = state.encrypted_spend_signature;
+
+    self.client_refund = state.client_refund;
+    self.client_destination = state.client_destination;
+
+    self.encryption_key = state.encryption_key;
+    self.encrypted_signature = state.encrypted_signature;
+    Ok(())
+  }
+
+  fn set_terms(&mut self, terms: SwapTerms) {
+    self.terms = Some(terms);
+  }
+
+  // Nothing is signed with a gas price ahead of time, so these only bound what we pay ourselves
+  fn fee_bounds(&self) -> FeeBounds {
+    self.rpc.fee_bounds()
+  }
+
+  fn timelocks(&self) -> Timelocks {
+    self.config.timelocks
+  }
+
+  fn block_seconds(&self) -> u64 {
+    self.config.block_seconds
+  }
+
+  fn generate_keys<Verifier: UnscriptedVerifier>(&mut self, verifier: &mut Verifier) -> Vec<u8> {
+    let (dl_eq, key) = verifier.generate_keys_for_engine::<Secp256k1Engine>(PhantomData);
+    self.engine.bs = Some(key);
+    KeyBundle {
+      dl_eq,
+      B: Secp256k1Engine::public_key_to_bytes(&Secp256k1Engine::to_public_key(&self.engine.b)),
+      BR: Secp256k1Engine::public_key_to_bytes(&Secp256k1Engine::to_public_key(&self.engine.br)),
+      scripted_destination: self.refund_address.to_vec()
+    }.serialize()
+  }
+
+  fn verify_keys<Verifier: UnscriptedVerifier>(&mut self, keys: &[u8], verifier: &mut Verifier) -> anyhow::Result<()> {
+    let keys = KeyBundle::deserialize(keys)?;
+    let key = verifier.verify_dleq_for_engine::<Secp256k1Engine>(&keys.dl_eq, PhantomData)?;
+    if (keys.B.len() != 33) || (keys.BR.len() != 33) {
+      anyhow::bail!("Keys have an invalid length");
+    }
+    if keys.scripted_destination.len() != 20 {
+      anyhow::bail!("Destination isn't an Ethereum address");
+    }
+    self.client_refund = Some(keys.BR);
+    self.encryption_key = Some(key);
+    let mut destination = [0; 20];
+    destination.copy_from_slice(&keys.scripted_destination);
+    self.client_destination = Some(destination);
+    Ok(())
+  }
+
+  fn swap_secret(&self) -> [u8; 32] {
+    self.swap_secret
+  }
+
+  fn txids(&self) -> Vec<(&'static str, String)> {
+    let mut txids = Vec::new();
+    if let Some(params) = self.params.as_ref() {
+      txids.push(("swap", format!("0x{}", hex::encode(params.id()))));
+    }
+    if let Some(lock) = self.lock {
+      txids.push(("lock", format!("0x{}", hex::encode(lock))));
+    }
+    txids
+  }
+
+  fn generate_deposit_address(&mut self) -> String {
+    let address = EthEngine::generate_deposit_address();
+    self.address = Some(address.clone());
+    encode_address(&address.1)
+  }
+
+  async fn create_lock_and_prepare_refund(
+    &mut self
+  ) -> anyhow::Result<Vec<u8>> {
+    let address = self.address.clone().expect("Creating lock before creating address");
+    let mut balance = self.rpc.get_traded_balance(&address.1).await?;
+    while balance == 0 {
+      tokio::time::delay_for(std::time::Duration::from_secs(10)).await;
+      balance = self.rpc.get_traded_balance(&address.1).await?;
+    }
+
+    // When locking ether, the deposit address also pays for the lock's gas
+    let gas_price = self.rpc.get_gas_price().await?;
+    let value = if self.rpc.is_token() {
+      balance
+    } else {
+      balance.checked_sub((LOCK_GAS as u128) * gas_price)
+        .ok_or_else(|| anyhow::anyhow!("Not enough ether to pay for the lock's gas"))?
+    };
+    let terms = self.terms.expect("Creating lock before agreeing on terms");
+    // Don't offer a lock the client will reject anyways
+    terms.verify_scripted(self.config.from_base_units(value))?;
+
+    let params = SwapParams {
+      token: self.rpc.token(),
+      value,
+      hash: self.swap_hash,
+      host: address_from_key_bytes(
+        &Secp256k1Engine::public_key_to_bytes(&Secp256k1Engine::to_public_key(&self.engine.b))
+      )?,
+      client_refund: address_from_key_bytes(self.client_refund.as_ref().expect("Creating lock before verifying keys"))?,
+      host_destination: self.refund_address,
+      client_destination: self.client_destination.expect("Creating lock before verifying keys"),
+      t0: terms.timelocks.t0,
+      t1: terms.timelocks.t1
+    };
+    self.params = Some(params);
+    self.lock_gas_price = Some(gas_price);
+
+    Ok(
+      bincode::serialize(
+        &LockInfo {
+          contract: self.rpc.contract(),
+          params
+        }
+      ).expect("Couldn't serialize the lock info")
+    )
+  }
+
+  fn verify_refund_and_spend(&mut self, refund_and_spend_sigs: &[u8]) -> anyhow::Result<()> {
+    let sig: ClientSpendSignature = bincode::deserialize(refund_and_spend_sigs)?;
+    let encrypted_spend_signature = Secp256k1Engine::bytes_to_encrypted_signature(&sig.encrypted_spend_signature)?;
+
+    Secp256k1Engine::encrypted_verify(
+      &Secp256k1Engine::bytes_to_public_key(self.client_refund.as_ref().expect("Trying to verify the spend signature before exchanging keys"))?,
+      &Secp256k1Engine::to_public_key(self.engine.bs.as_ref().expect("Verifying spend before generating keys")),
+      &encrypted_spend_signature,
+      &self.params().message(self.rpc.chain_id(), &self.rpc.contract(), SPEND)
+    )?;
+
+    self.encrypted_spend_signature = Some(encrypted_spend_signature);
+    Ok(())
+  }
+
+  async fn publish_lock(
+    &mut self
+  ) -> anyhow::Result<()> {
+    let params = *self.params();
+    let address = self.address.clone().expect("Publishing the lock before creating an address");
+    let key = secret_key(&address.0);
+    let gas_price = self.lock_gas_price.expect("Publishing the lock before creating it");
+
+    let lock = if self.rpc.is_token() {
+      // The deposit address only holds the token, so the gas key pays for it to approve the contract and lock
+      self.rpc.fund_gas(&address.1, TOKEN_GAS + LOCK_GAS, gas_price).await?;
+      let approval = self.rpc.send(&key, &params.token, 0, approve_call(&self.rpc.contract(), params.value), TOKEN_GAS, gas_price).await?;
+      self.rpc.wait_for_transaction(&approval).await?;
+      self.rpc.send(&key, &self.rpc.contract(), 0, params.lock_call(), LOCK_GAS, gas_price).await?
+    } else {
+      self.rpc.send(&key, &self.rpc.contract(), params.value, params.lock_call(), LOCK_GAS, gas_price).await?
+    };
+    self.lock = Some(lock);
+
+    self.lock_height = Some(self.rpc.wait_for_transaction(&lock).await?);
+    Ok(())
+  }
+
+  async fn refund<Verifier: UnscriptedVerifier>(self, mut verifier: Verifier) -> anyhow::Result<()> {
+    /*
+      There are four states to be aware of:
+      A) Never even created an address
+      B) Created address but didn't fund
+      C) Created address and did fund but didn't publish lock
+      D) Published lock
+      In the last case, the client may have already bought the ether, or may buy it before the refund confirms
+      In that case, all we can do is finish purchasing the unscripted coin
+    */
+
+    // Path A
+    if self.address.is_none() {
+      return Ok(());
+    }
+
+    // If the lock exists, confirm it was published
+    let lock_exists = match self.params.as_ref() {
+      Some(params) => self.rpc.get_status(params).await?.state != SwapState::None,
+      None => false
+    };
+
+    // Path B or C
+    if !lock_exists {
+      return self.return_deposit().await;
+    }
+
+    // Path D
+    // If we published the lock, we need to refund it once T0 expires
+    let params = *self.params();
+    let mut status = self.rpc.get_status(&params).await?;
+    while (status.state == SwapState::Locked) && (self.rpc.get_height().await? < (status.locked_at + (params.t0 as u64))) {
+      #[cfg(test)]
+      for _ in 0 .. params.t0 {
+        self.rpc.mine_block().await?;
+      }
+      tokio::time::delay_for(std::time::Duration::from_secs(20)).await;
+      status = self.rpc.get_status(&params).await?;
+    }
+
+    if status.state == SwapState::Locked {
+      let refund = self.rpc.send_from_gas_key(params.refund_call(), REFUND_GAS).await?;
+      // This only fails if the client's buy beat it, which is handled below
+      let _ = self.rpc.wait_for_transaction(&refund).await;
+      status = self.rpc.get_status(&params).await?;
+    }
+
+    // Path D/forced success
+    if self.rpc.get_revealed_signature(&params, "Bought").await?.is_some() {
+      return verifier.finish(&self).await;
+    }
+    // Already spent, as happens when resuming after the spend was published
+    if self.rpc.get_revealed_signature(&params, "Spent").await?.is_some() {
+      return Ok(());
+    }
+    if status.state != SwapState::Refunded {
+      anyhow::bail!("The client claimed the refund before we could spend it");
+    }
+
+    // Spend the refund, revealing our key to the client
+    let message = params.message(self.rpc.chain_id(), &self.rpc.contract(), SPEND);
+    let signature = EthSignature::from_compact(
+      &Secp256k1Engine::signature_to_bytes(
+        &Secp256k1Engine::decrypt_signature(
+          self.encrypted_spend_signature.as_ref().expect("Spend signature doesn't exist despite having published the lock"),
+          self.engine.bs.as_ref().expect("Never generated keys despite having published the lock")
+        )?
+      ),
+      &message,
+      &params.client_refund
+    )?;
+    let spend = self.rpc.send_from_gas_key(params.spend_call(&signature), SPEND_GAS).await?;
+    self.rpc.wait_for_transaction(&spend).await?;
+    Ok(())
+  }
+
+  async fn prepare_buy_for_client(&mut self) -> anyhow::Result<Vec<u8>> {
+    let params = *self.params();
+    let encrypted_signature = Secp256k1Engine::encrypted_sign(
+      &self.engine.b,
+      self.encryption_key.as_ref().expect("Attempted to generate encrypted sign before verifying dleq proof"),
+      &params.message(self.rpc.chain_id(), &self.rpc.contract(), BUY)
+    )?;
+
+    let result = Ok(
+      bincode::serialize(&BuyInfo {
+        encrypted_signature: Secp256k1Engine::encrypted_signature_to_bytes(&encrypted_signature)
+      })?
+    );
+    self.encrypted_signature = Some(encrypted_signature);
+    result
+  }
+
+  fn verify_buy_signature(&mut self, buy_signature: &[u8]) -> anyhow::Result<()> {
+    // The contract only checks our signature, which the client already has
+    if buy_signature.len() != 0 {
+      anyhow::bail!("Client sent a signature for a buy which doesn't need one");
+    }
+    Ok(())
+  }
+
+  async fn recover_final_key(&self) -> anyhow::Result<[u8; 32]> {
+    let encrypted_signature = self.encrypted_signature.as_ref().expect("Trying to recover the final key before preparing the encrypted signature");
+
+    let mut signature = self.rpc.get_revealed_signature(self.params(), "Bought").await?;
+    while signature.is_none() {
+      tokio::time::delay_for(std::time::Duration::from_secs(3)).await;
+      signature = self.rpc.get_revealed_signature(self.params(), "Bought").await?;
+    }
+
+    Ok(Secp256k1Engine::private_key_to_little_endian_bytes(
+      &Secp256k1Engine::recover_key(
+        self.encryption_key.as_ref().expect("Attempted to recover final key before verifying dleq proof"),
+        encrypted_signature,
+        &EthSignature::from_log(&signature.unwrap()).expect("Signature logged by the buy wasn't valid despite getting on chain")
+      ).expect("Failed to recover key from decrypted signature")
+    ))
+  }
+
+  #[cfg(test)]
+  fn override_refund_with_random_address(&mut self) {
+    self.refund_address = EthEngine::generate_deposit_address().1;
+  }
+  #[cfg(test)]
+  async fn send_from_node(&self) -> anyhow::Result<()> {
+    let value = 10u128.pow(self.config.unit_exponent) * (self.terms.expect("Sending from the node before agreeing on terms").scripted_amount as u128);
+    self.rpc.send_from_node(&self.address.as_ref().unwrap().1, value + ((LOCK_GAS as u128) * self.rpc.get_gas_price().await?)).await
+  }
+  #[cfg(test)]
+  async fn advance_consensus(&self) -> anyhow::Result<()> {
+    for _ in 0 .. CONFIRMATIONS {
+      self.rpc.mine_block().await?
+    }
+    Ok(())
+  }
+  #[cfg(test)]
+  fn get_refund_address(&self) -> String {
+    encode_address(&self.refund_address)
+  }
+  #[cfg(test)]
+  async fn get_if_funded(self, address: &str) -> bool {
+    self.rpc.get_traded_balance(&decode_address(address).expect("Test refund address was invalid")).await
+      .expect("Couldn't get the balance of an address") != 0
+  }
+}
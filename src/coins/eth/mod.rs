@@ -0,0 +1,8 @@
+#[cfg(not(test))]
+mod engine;
+#[cfg(test)]
+pub mod engine;
+mod rpc;
+
+pub mod host;
+pub mod verifier;
@@ -0,0 +1,278 @@
+use std::fmt::Debug;
+
+use log::{debug, warn};
+
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use serde_json::{json, Value};
+
+use secp256k1::{SecretKey, PublicKey};
+
+use crate::coins::{
+  FeeBounds,
+  eth::engine::*
+};
+
+#[derive(Serialize)]
+struct FullParams<'a, T> {
+  jsonrpc: &'a str,
+  id: u8,
+  method: &'a str,
+  params: T
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Receipt {
+  #[serde(rename = "blockNumber")]
+  pub block_number: String,
+  pub status: String
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Log {
+  pub data: String,
+  #[serde(rename = "blockNumber")]
+  pub block_number: Option<String>
+}
+
+pub struct EthRpc {
+  url: String,
+  chain_id: u64,
+  contract: Address,
+  token: Option<Address>,
+  fee_bounds: FeeBounds,
+  gas_key: SecretKey
+}
+
+impl EthRpc {
+  // Connects to the node, verifying it's on the configured chain
+  pub async fn new(config: &EthConfig) -> anyhow::Result<EthRpc> {
+    anyhow::ensure!(
+      (config.min_gas_price != 0) && (config.min_gas_price <= config.max_gas_price),
+      "Invalid gas price range of {}-{}", config.min_gas_price, config.max_gas_price
+    );
+    let gas_key = SecretKey::from_slice(&hex::decode(config.gas_key.trim_start_matches("0x"))?)?;
+    let rpc = EthRpc {
+      url: config.url.clone(),
+      chain_id: config.chain_id,
+      contract: decode_address(&config.contract)?,
+      token: config.token.as_ref().map(|token| decode_address(token)).transpose()?,
+      fee_bounds: FeeBounds {
+        min: config.min_gas_price,
+        max: config.max_gas_price
+      },
+      gas_key
+    };
+
+    let chain_id = decode_quantity(&rpc.rpc_call::<_, String>("eth_chainId", json!([])).await?)? as u64;
+    if chain_id != rpc.chain_id {
+      anyhow::bail!("The node is on chain {}, yet chain {} is configured", chain_id, rpc.chain_id);
+    }
+    if rpc.rpc_call::<_, String>("eth_getCode", json!([encode_address(&rpc.contract), "latest"])).await? == "0x" {
+      anyhow::bail!("No contract is deployed at {}", encode_address(&rpc.contract));
+    }
+    Ok(rpc)
+  }
+
+  async fn rpc_call<
+    Params: Serialize + Debug,
+    Response: DeserializeOwned + Debug
+  >(&self, method: &str, params: Params) -> anyhow::Result<Response> {
+    #[derive(Deserialize, Debug)]
+    struct RpcError {
+      code: i64,
+      message: String
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct FullResponse {
+      #[serde(default)]
+      result: Value,
+      error: Option<RpcError>
+    }
+
+    let res = reqwest::Client::new()
+      .post(&self.url)
+      .json(
+        &FullParams {
+          jsonrpc: "2.0",
+          id: 0,
+          method,
+          params: &params
+        }
+      )
+      .send()
+      .await?
+      .text()
+      .await?;
+    debug!("Ethereum RPC call to {} with {:?} returned {}", method, params, &res);
+    let parsed_res: FullResponse = serde_json::from_str(&res)
+      .map_err(|_| anyhow::anyhow!("Ethereum node returned an invalid response to {}: {}", method, res))?;
+    if let Some(error) = parsed_res.error {
+      anyhow::bail!("Ethereum RPC returned an error for {}: {} ({})", method, error.message, error.code);
+    }
+    Ok(serde_json::from_value(parsed_res.result)?)
+  }
+
+  pub fn chain_id(&self) -> u64 {
+    self.chain_id
+  }
+
+  pub fn contract(&self) -> Address {
+    self.contract
+  }
+
+  // The zero address when trading ether, as the contract expects
+  pub fn token(&self) -> Address {
+    self.token.unwrap_or([0; 20])
+  }
+
+  pub fn is_token(&self) -> bool {
+    self.token.is_some()
+  }
+
+  pub fn fee_bounds(&self) -> FeeBounds {
+    self.fee_bounds
+  }
+
+  pub async fn get_height(&self) -> anyhow::Result<u64> {
+    Ok(decode_quantity(&self.rpc_call::<_, String>("eth_blockNumber", json!([])).await?)? as u64)
+  }
+
+  // The node's gas price, clamped to the configured range
+  pub async fn get_gas_price(&self) -> anyhow::Result<u128> {
+    let gas_price = decode_quantity(&self.rpc_call::<_, String>("eth_gasPrice", json!([])).await?)?;
+    let clamped = self.fee_bounds.clamp(gas_price.min(u64::MAX as u128) as u64) as u128;
+    if clamped != gas_price {
+      warn!("Node's gas price of {} is outside the configured range, so {} will be used", gas_price, clamped);
+    }
+    Ok(clamped)
+  }
+
+  pub async fn get_balance(&self, address: &Address) -> anyhow::Result<u128> {
+    decode_quantity(&self.rpc_call::<_, String>("eth_getBalance", json!([encode_address(address), "latest"])).await?)
+  }
+
+  // The balance of whatever's being traded, being either ether or the token
+  pub async fn get_traded_balance(&self, address: &Address) -> anyhow::Result<u128> {
+    match self.token {
+      Some(token) => decode_word(&self.call(&token, &balance_of_call(address)).await?, 0),
+      None => self.get_balance(address).await
+    }
+  }
+
+  pub async fn call(&self, to: &Address, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let result: String = self.rpc_call(
+      "eth_call",
+      json!([{ "to": encode_address(to), "data": format!("0x{}", hex::encode(data)) }, "latest"])
+    ).await?;
+    Ok(hex::decode(result.trim_start_matches("0x"))?)
+  }
+
+  pub async fn get_status(&self, params: &SwapParams) -> anyhow::Result<SwapStatus> {
+    SwapStatus::decode(&self.call(&self.contract, &swaps_call(&params.id())).await?)
+  }
+
+  // Signs and publishes a transaction, returning its hash
+  pub async fn send(&self, key: &SecretKey, to: &Address, value: u128, data: Vec<u8>, gas: u64, gas_price: u128) -> anyhow::Result<[u8; 32]> {
+    let from = address_from_public_key(&PublicKey::from_secret_key(&SECP, key));
+    let nonce = decode_quantity(
+      &self.rpc_call::<_, String>("eth_getTransactionCount", json!([encode_address(&from), "pending"])).await?
+    )? as u64;
+
+    let (tx, hash) = EthTransaction {
+      nonce,
+      gas_price,
+      gas,
+      to: *to,
+      value,
+      data
+    }.sign(self.chain_id, key);
+    let _: String = self.rpc_call("eth_sendRawTransaction", json!([format!("0x{}", hex::encode(tx))])).await?;
+    Ok(hash)
+  }
+
+  // Calls the contract from the gas key, which pays for every call besides the lock
+  pub async fn send_from_gas_key(&self, data: Vec<u8>, gas: u64) -> anyhow::Result<[u8; 32]> {
+    let gas_price = self.get_gas_price().await?;
+    self.send(&self.gas_key, &self.contract, 0, data, gas, gas_price).await
+  }
+
+  // Sends an address enough ether from the gas key to pay for the specified gas
+  pub async fn fund_gas(&self, address: &Address, gas: u64, gas_price: u128) -> anyhow::Result<()> {
+    let needed = (gas as u128) * gas_price;
+    let balance = self.get_balance(address).await?;
+    if balance < needed {
+      let hash = self.send(&self.gas_key, address, needed - balance, Vec::new(), TRANSFER_GAS, gas_price).await?;
+      self.wait_for_transaction(&hash).await?;
+    }
+    Ok(())
+  }
+
+  pub async fn get_receipt(&self, hash: &[u8; 32]) -> anyhow::Result<Option<Receipt>> {
+    self.rpc_call("eth_getTransactionReceipt", json!([format!("0x{}", hex::encode(hash))])).await
+  }
+
+  // Waits for a transaction to confirm, erroring if it reverted, and returns the block it was included in
+  pub async fn wait_for_transaction(&self, hash: &[u8; 32]) -> anyhow::Result<u64> {
+    loop {
+      #[cfg(test)]
+      self.mine_block().await?;
+
+      if let Some(receipt) = self.get_receipt(hash).await? {
+        if decode_quantity(&receipt.status)? != 1 {
+          anyhow::bail!("Transaction 0x{} reverted", hex::encode(hash));
+        }
+        let block = decode_quantity(&receipt.block_number)? as u64;
+        if (self.get_height().await? + 1) >= (block + CONFIRMATIONS) {
+          return Ok(block);
+        }
+      }
+      tokio::time::delay_for(std::time::Duration::from_secs(5)).await;
+    }
+  }
+
+  // The logs of an event the contract emitted for this swap
+  pub async fn get_logs(&self, params: &SwapParams, event: &str) -> anyhow::Result<Vec<Log>> {
+    self.rpc_call(
+      "eth_getLogs",
+      json!([{
+        "fromBlock": "earliest",
+        "toBlock": "latest",
+        "address": encode_address(&self.contract),
+        "topics": [
+          format!("0x{}", hex::encode(event_topic(event))),
+          format!("0x{}", hex::encode(params.id()))
+        ]
+      }])
+    ).await
+  }
+
+  // The signature revealed by a buy or spend, if one was made
+  pub async fn get_revealed_signature(&self, params: &SwapParams, event: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    match self.get_logs(params, event).await?.into_iter().find(|log| log.block_number.is_some()) {
+      Some(log) => Ok(Some(hex::decode(log.data.trim_start_matches("0x"))?)),
+      None => Ok(None)
+    }
+  }
+
+  // Uses the node's first unlocked account, which local dev chains fund
+  #[cfg(test)]
+  pub async fn send_from_node(&self, address: &Address, value: u128) -> anyhow::Result<()> {
+    use std::convert::TryInto;
+
+    let accounts: Vec<String> = self.rpc_call("eth_accounts", json!([])).await?;
+    let hash: String = self.rpc_call(
+      "eth_sendTransaction",
+      json!([{ "from": accounts[0], "to": encode_address(address), "value": format!("0x{:x}", value) }])
+    ).await?;
+    let hash: [u8; 32] = hex::decode(hash.trim_start_matches("0x"))?.as_slice().try_into()?;
+    self.wait_for_transaction(&hash).await?;
+    Ok(())
+  }
+
+  #[cfg(test)]
+  pub async fn mine_block(&self) -> anyhow::Result<()> {
+    let _: Value = self.rpc_call("evm_mine", json!([])).await?;
+    Ok(())
+  }
+}
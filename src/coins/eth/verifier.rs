@@ -0,0 +1,361 @@
+use std::{
+  marker::PhantomData,
+  path::Path,
+  fs::File
+};
+
+use async_trait::async_trait;
+use digest::Digest;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{
+  crypt_engines::{CryptEngine, KeyBundle, secp256k1_engine::Secp256k1Engine},
+  dl_eq::DlEqProof,
+  coins::{
+    FeeBounds, Timelocks, SwapTerms, ScriptedVerifier,
+    eth::{engine::*, rpc::EthRpc}
+  }
+};
+
+#[derive(Serialize, Deserialize)]
+struct EthVerifierState {
+  engine: EthEngine,
+  terms: Option<SwapTerms>,
+
+  host: Option<Vec<u8>>,
+  host_destination: Option<Address>,
+
+  decryption_key: Option<<Secp256k1Engine as CryptEngine>::PrivateKey>,
+  encryption_key: Option<<Secp256k1Engine as CryptEngine>::PublicKey>,
+  encrypted_spend_sig: Option<<Secp256k1Engine as CryptEngine>::EncryptedSignature>,
+
+  params: Option<SwapParams>,
+  lock_height: Option<u64>,
+  buy_signature: Option<EthSignature>
+}
+
+pub struct EthVerifier {
+  engine: EthEngine,
+  terms: Option<SwapTerms>,
+  rpc: EthRpc,
+  config: EthConfig,
+  destination: Address,
+
+  host: Option<Vec<u8>>,
+  host_destination: Option<Address>,
+
+  decryption_key: Option<<Secp256k1Engine as CryptEngine>::PrivateKey>,
+  encryption_key: Option<<Secp256k1Engine as CryptEngine>::PublicKey>,
+  encrypted_spend_sig: Option<<Secp256k1Engine as CryptEngine>::EncryptedSignature>,
+
+  params: Option<SwapParams>,
+  lock_height: Option<u64>,
+  buy_signature: Option<EthSignature>
+}
+
+impl EthVerifier {
+  pub async fn new(config_path: &Path) -> anyhow::Result<EthVerifier> {
+    let config: EthConfig = serde_json::from_reader(File::open(config_path)?)?;
+    Ok(EthVerifier {
+      engine: EthEngine::new(),
+      terms: None,
+      rpc: EthRpc::new(&config).await?,
+      destination: decode_address(&config.destination)?,
+      config,
+
+      host: None,
+      host_destination: None,
+
+      decryption_key: None,
+      encryption_key: None,
+      encrypted_spend_sig: None,
+
+      params: None,
+      lock_height: None,
+      buy_signature: None
+    })
+  }
+
+  fn params(&self) -> &SwapParams {
+    self.params.as_ref().expect("Using the swap's parameters before verifying the lock")
+  }
+
+  async fn attempt_key_recovery(&self) -> anyhow::Result<Option<[u8; 32]>> {
+    let signature = match self.rpc.get_revealed_signature(self.params(), "Spent").await? {
+      Some(signature) => signature,
+      None => return Ok(None)
+    };
+
+    Ok(Some(
+      Secp256k1Engine::private_key_to_little_endian_bytes(
+        &Secp256k1Engine::recover_key(
+          self.encryption_key.as_ref().expect("Recovering key despite not having stored the encryption key"),
+          self.encrypted_spend_sig.as_ref().expect("Recovering key despite not having encrypted a signature"),
+          &EthSignature::from_log(&signature).expect("Signature logged by the spend wasn't valid despite getting on chain")
+        ).expect("Couldn't recover the private key")
+      )
+    ))
+  }
+}
+
+#[async_trait]
+impl ScriptedVerifier for EthVerifier {
+  fn serialize_state(&self) -> Vec<u8> {
+    bincode::serialize(
+      &EthVerifierState {
+        engine: self.engine.clone(),
+        terms: self.terms,
+
+        host: self.host.clone(),
+        host_destination: self.host_destination,
+
+        decryption_key: self.decryption_key.clone(),
+        encryption_key: self.encryption_key.clone(),
+        encrypted_spend_sig: self.encrypted_spend_sig.clone(),
+
+        params: self.params,
+        lock_height: self.lock_height,
+        buy_signature: self.buy_signature
+      }
+    ).expect("Couldn't serialize the ETH verifier's state")
+  }
+
+  fn restore_state(&mut self, state: &[u8]) -> anyhow::Result<()> {
+    let state: EthVerifierState = bincode::deserialize(state)?;
+    self.engine = state.engine;
+    self.terms = state.terms;
+
+    self.host = state.host;
+    self.host_destination = state.host_destination;
+
+    self.decryption_key = state.decryption_key;
+    self.encryption_key = state.encryption_key;
+    self.encrypted_spend_sig = state.encrypted_spend_sig;
+
+    self.params = state.params;
+    self.lock_height = state.lock_height;
+    self.buy_signature = state.buy_signature;
+    Ok(())
+  }
+
+  fn set_terms(&mut self, terms: SwapTerms) {
+    self.terms = Some(terms);
+  }
+
+  fn fee_bounds(&self) -> FeeBounds {
+    self.rpc.fee_bounds()
+  }
+
+  fn timelocks(&self) -> Timelocks {
+    self.config.timelocks
+  }
+
+  fn block_seconds(&self) -> u64 {
+    self.config.block_seconds
+  }
+
+  fn destination_script(&self) -> Vec<u8> {
+    self.destination.to_vec()
+  }
+
+  fn txids(&self) -> Vec<(&'static str, String)> {
+    let mut txids = Vec::new();
+    if let Some(params) = self.params.as_ref() {
+      txids.push(("swap", format!("0x{}", hex::encode(params.id()))));
+    }
+    txids
+  }
+
+  fn generate_keys_for_engine<OtherCrypt: CryptEngine>(&mut self, _: PhantomData<&OtherCrypt>) -> (Vec<u8>, OtherCrypt::PrivateKey) {
+    let (proof, key1, key2) = DlEqProof::<Secp256k1Engine, OtherCrypt>::new();
+    self.decryption_key = Some(key1);
+    (proof.serialize(), key2)
+  }
+
+  fn verify_keys_for_engine<OtherCrypt: CryptEngine>(&mut self, keys: &[u8], _: PhantomData<&OtherCrypt>) -> anyhow::Result<OtherCrypt::PublicKey> {
+    let bundle = KeyBundle::deserialize(keys)?;
+    let dleq = DlEqProof::<OtherCrypt, Secp256k1Engine>::deserialize(&bundle.dl_eq)?;
+    let (key1, key2) = dleq.verify()?;
+    if bundle.scripted_destination.len() != 20 {
+      anyhow::bail!("Destination isn't an Ethereum address");
+    }
+    let mut host_destination = [0; 20];
+    host_destination.copy_from_slice(&bundle.scripted_destination);
+    self.host = Some(bundle.B);
+    self.host_destination = Some(host_destination);
+    self.encryption_key = Some(key2);
+    Ok(key1)
+  }
+
+  fn B(&self) -> Vec<u8> {
+    Secp256k1Engine::public_key_to_bytes(&Secp256k1Engine::to_public_key(&self.engine.b))
+  }
+
+  fn BR(&self) -> Vec<u8> {
+    Secp256k1Engine::public_key_to_bytes(&Secp256k1Engine::to_public_key(&self.engine.br))
+  }
+
+  async fn complete_refund_and_prepare_spend(
+    &mut self,
+    lock_and_host_signed_refund: &[u8]
+  ) -> anyhow::Result<Vec<u8>> {
+    let lock: LockInfo = bincode::deserialize(lock_and_host_signed_refund)?;
+    let params = lock.params;
+    let terms = self.terms.expect("Completing refund before agreeing on terms");
+
+    // The contract enforces everything else, so the parameters only have to match what we expect
+    if lock.contract != self.rpc.contract() {
+      anyhow::bail!("Host is using a different swap contract");
+    }
+    if params.token != self.rpc.token() {
+      anyhow::bail!("Host is locking a different token");
+    }
+    terms.verify_scripted(self.config.from_base_units(params.value))?;
+    if params.host != address_from_key_bytes(self.host.as_ref().expect("Completing refund before verifying keys"))? {
+      anyhow::bail!("Lock doesn't use the host's key");
+    }
+    if params.client_refund != address_from_key_bytes(&self.BR())? {
+      anyhow::bail!("Lock doesn't use our refund key");
+    }
+    if (Some(params.host_destination) != self.host_destination) || (params.client_destination != self.destination) {
+      anyhow::bail!("Lock doesn't pay to the agreed destinations");
+    }
+    if (params.t0 != terms.timelocks.t0) || (params.t1 != terms.timelocks.t1) {
+      anyhow::bail!("Lock doesn't use the agreed timelocks");
+    }
+    self.params = Some(params);
+
+    let encrypted_spend_sig = Secp256k1Engine::encrypted_sign(
+      &self.engine.br,
+      self.encryption_key.as_ref().expect("Attempted to generate encrypted sign before verifying dleq proof"),
+      &params.message(self.rpc.chain_id(), &self.rpc.contract(), SPEND)
+    )?;
+    let result = bincode::serialize(&ClientSpendSignature {
+      encrypted_spend_signature: Secp256k1Engine::encrypted_signature_to_bytes(&encrypted_spend_sig)
+    }).expect("Couldn't serialize the client's spend signature");
+    self.encrypted_spend_sig = Some(encrypted_spend_sig);
+    Ok(result)
+  }
+
+  fn verify_prepared_buy(&mut self, buy_info: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let params = *self.params();
+    let buy_info: BuyInfo = bincode::deserialize(buy_info)?;
+
+    let decrypted_signature = Secp256k1Engine::decrypt_signature(
+      &Secp256k1Engine::bytes_to_encrypted_signature(&buy_info.encrypted_signature)?,
+      self.decryption_key.as_ref().expect("Attempted to finish verifier before generate_keys called")
+    )?;
+    // Verifies the decrypted signature is the host's, as the contract will check
+    self.buy_signature = Some(
+      EthSignature::from_compact(
+        &Secp256k1Engine::signature_to_bytes(&decrypted_signature),
+        &params.message(self.rpc.chain_id(), &self.rpc.contract(), BUY),
+        &params.host
+      )?
+    );
+    Ok(Vec::new())
+  }
+
+  async fn verify_and_wait_for_lock(
+    &mut self
+  ) -> anyhow::Result<()> {
+    let params = *self.params();
+
+    // As the swap's ID is the hash of its parameters, it being locked means it was locked as agreed
+    let mut status = self.rpc.get_status(&params).await?;
+    while status.state == SwapState::None {
+      tokio::time::delay_for(std::time::Duration::from_secs(20)).await;
+      status = self.rpc.get_status(&params).await?;
+    }
+    if status.state != SwapState::Locked {
+      anyhow::bail!("Lock was already refunded");
+    }
+
+    // Wait for the lock to confirm
+    while (self.rpc.get_height().await? + 1) < (status.locked_at + CONFIRMATIONS) {
+      tokio::time::delay_for(std::time::Duration::from_secs(20)).await;
+      status = self.rpc.get_status(&params).await?;
+      if status.state != SwapState::Locked {
+        anyhow::bail!("Lock was refunded");
+      }
+    }
+    self.lock_height = Some(status.locked_at);
+
+    Ok(())
+  }
+
+  async fn claim_refund_or_recover_key(self) -> anyhow::Result<Option<[u8; 32]>> {
+    let params = *self.params();
+    let lock_height = self.lock_height.expect("Trying to publish refund despite no lock on chain");
+    while self.rpc.get_height().await? < (lock_height + (params.t0 as u64)) {
+      #[cfg(test)]
+      for _ in 0 .. params.t0 {
+        self.rpc.mine_block().await?;
+      }
+      tokio::time::delay_for(std::time::Duration::from_secs(20)).await;
+    }
+
+    // The host may have already refunded it
+    if self.rpc.get_status(&params).await?.state == SwapState::Locked {
+      let refund = self.rpc.send_from_gas_key(params.refund_call(), REFUND_GAS).await?;
+      let _ = self.rpc.wait_for_transaction(&refund).await;
+    }
+    let status = self.rpc.get_status(&params).await?;
+    if status.state == SwapState::Locked {
+      anyhow::bail!("Couldn't refund the lock");
+    }
+
+    // Wait for the host to spend the refund, or for T1 to expire
+    while self.rpc.get_height().await? < (status.refunded_at + (params.t1 as u64)) {
+      #[cfg(test)]
+      for _ in 0 .. params.t1 {
+        self.rpc.mine_block().await?;
+      }
+
+      // Check if host spent refund
+      if let Some(recovered_key) = self.attempt_key_recovery().await? {
+        return Ok(Some(recovered_key));
+      }
+      tokio::time::delay_for(std::time::Duration::from_secs(20)).await;
+    }
+
+    if self.rpc.get_status(&params).await?.state == SwapState::Refunded {
+      let claim = self.rpc.send_from_gas_key(params.claim_call(), CLAIM_GAS).await?;
+      if self.rpc.wait_for_transaction(&claim).await.is_ok() {
+        return Ok(None);
+      }
+    }
+
+    // Transaction was beat
+    if let Some(recovered_key) = self.attempt_key_recovery().await? {
+      Ok(Some(recovered_key))
+    } else {
+      anyhow::bail!("Claim was beat/disappeared and we failed to recover the key");
+    }
+  }
+
+  async fn finish(&self, swap_secret: &[u8]) -> anyhow::Result<()> {
+    let params = *self.params();
+    // Verify the received swap secret
+    if sha2::Sha256::digest(swap_secret)[..] != params.hash[..] {
+      anyhow::bail!("Received an invalid swap secret");
+    }
+    let mut secret = [0; 32];
+    secret.copy_from_slice(swap_secret);
+
+    // Check that we aren't nearing the end of the timelock
+    let lock_height = self.lock_height.expect("Attempted to finish swap before verifying lock confirmation");
+    let cutoff = self.terms.expect("Trying to finish our buy before agreeing on terms").timelocks.cutoff;
+    if self.rpc.get_height().await?.saturating_sub(lock_height) >= (cutoff as u64) {
+      anyhow::bail!("Attempted to finish swap, but we're nearing the end of the timelock");
+    }
+
+    let buy = self.rpc.send_from_gas_key(
+      params.buy_call(&secret, &self.buy_signature.expect("Finishing before verifying buy")),
+      BUY_GAS
+    ).await?;
+    self.rpc.wait_for_transaction(&buy).await?;
+    Ok(())
+  }
+}
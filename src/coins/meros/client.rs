@@ -6,21 +6,33 @@ use std::{
 
 use async_trait::async_trait;
 
+use serde::{Serialize, Deserialize};
+
 use crate::{
   crypt_engines::{KeyBundle, CryptEngine, ed25519_engine::Ed25519Sha},
   coins::{
     UnscriptedClient, ScriptedVerifier,
     meros::{
-      transaction::Transaction,
-      engine::MerosEngine,
+      transaction::{Output, Transaction},
+      engine::{MerosNetwork, MerosConfig, MerosEngine, SETTLEMENT_SECONDS},
       rpc::MerosRpc
     }
   }
 };
 
+#[derive(Serialize, Deserialize)]
+struct MerosClientState {
+  key_share: Option<<Ed25519Sha as CryptEngine>::PrivateKey>,
+  shared_key: Option<<Ed25519Sha as CryptEngine>::PublicKey>,
+  address: Option<String>,
+  deposited: bool
+}
+
 pub struct MerosClient {
+  network: MerosNetwork,
   rpc: MerosRpc,
   refund: Vec<u8>,
+  payments: Vec<Output>,
   key_share: Option<<Ed25519Sha as CryptEngine>::PrivateKey>,
   shared_key: Option<<Ed25519Sha as CryptEngine>::PublicKey>,
   address: Option<String>,
@@ -29,10 +41,12 @@ pub struct MerosClient {
 
 impl MerosClient {
   pub fn new(config_path: &Path) -> anyhow::Result<MerosClient> {
-    let config = serde_json::from_reader(File::open(config_path)?)?;
+    let config: MerosConfig = serde_json::from_reader(File::open(config_path)?)?;
     Ok(MerosClient{
+      network: config.network,
       rpc: MerosRpc::new(&config)?,
-      refund: MerosEngine::decode_address(&config.refund)?,
+      refund: MerosEngine::decode_address(config.network, &config.refund)?,
+      payments: MerosEngine::decode_payments(config.network, &config.payments)?,
       key_share: None,
       shared_key: None,
       address: None,
@@ -43,6 +57,30 @@ impl MerosClient {
 
 #[async_trait]
 impl UnscriptedClient for MerosClient {
+  fn serialize_state(&self) -> Vec<u8> {
+    bincode::serialize(
+      &MerosClientState {
+        key_share: self.key_share,
+        shared_key: self.shared_key,
+        address: self.address.clone(),
+        deposited: self.deposited
+      }
+    ).expect("Couldn't serialize the Meros client's state")
+  }
+
+  fn restore_state(&mut self, state: &[u8]) -> anyhow::Result<()> {
+    let state: MerosClientState = bincode::deserialize(state)?;
+    self.key_share = state.key_share;
+    self.shared_key = state.shared_key;
+    self.address = state.address;
+    self.deposited = state.deposited;
+    Ok(())
+  }
+
+  fn settlement_seconds(&self) -> u64 {
+    SETTLEMENT_SECONDS
+  }
+
   fn generate_keys<Verifier: ScriptedVerifier>(&mut self, verifier: &mut Verifier) -> Vec<u8> {
     let (dl_eq, key) = verifier.generate_keys_for_engine::<Ed25519Sha>(PhantomData);
     self.key_share = Some(key);
@@ -63,6 +101,7 @@ impl UnscriptedClient for MerosClient {
 
   fn get_address(&mut self) -> String {
     let address = MerosEngine::get_address(
+      self.network,
       &Ed25519Sha::public_key_to_bytes(
         &self.shared_key.expect("Trying to get the Meros deposit addresss despite not having verified the host's DLEQ proof")
       )
@@ -105,20 +144,24 @@ impl UnscriptedClient for MerosClient {
           We assume path A, and then revert to path B if path A fails
         */
         if let Some(recovered_key) = verifier.claim_refund_or_recover_key().await? {
-          let mut value_sum = 0;
-          for input in utxos.clone() {
-            value_sum += self.rpc.get_transaction_output_value(input.clone()).await?;
+          let mut inputs = vec![];
+          for input in utxos {
+            let value = self.rpc.get_transaction_output_value(input.clone()).await?;
+            inputs.push((input, value));
           }
 
-          let send = MerosEngine::create_send(
+          let sends = MerosEngine::create_sends(
+            self.network,
             Ed25519Sha::little_endian_bytes_to_private_key(recovered_key)?,
             self.key_share.expect("Finishing before generating keys"),
-            utxos,
+            inputs,
+            self.payments,
             self.refund,
-            value_sum,
             self.rpc.get_send_difficulty().await
-          );
-          self.rpc.publish_send(send.serialize()).await?;
+          )?;
+          for send in sends {
+            self.rpc.publish_send(send.serialize()).await?;
+          }
         }
         Ok(())
       }
@@ -139,7 +182,7 @@ impl UnscriptedClient for MerosClient {
   }
   #[cfg(test)]
   fn get_refund_address(&self) -> String {
-    MerosEngine::get_address(&self.refund)
+    MerosEngine::get_address(self.network, &self.refund)
   }
   #[cfg(test)]
   async fn get_if_funded(mut self, address: &str) -> bool {
@@ -9,11 +9,52 @@ use crate::{
   coins::meros::transaction::{Input, Output, Send}
 };
 
+// Transactions are verified within seconds, yet aren't final until they're archived in a block
+pub const SETTLEMENT_SECONDS: u64 = 600;
+// Sends encode their amount of inputs and outputs as a single byte
+pub const MAX_INPUTS: usize = 255;
+pub const MAX_OUTPUTS: usize = 255;
+
+/*
+  Meros has only ever launched a testnet, so it's the only network supported
+  The node doesn't report its network over RPC, so this can't be cross-checked with it
+*/
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum MerosNetwork {
+  Testnet
+}
+
+impl Default for MerosNetwork {
+  fn default() -> MerosNetwork {
+    MerosNetwork::Testnet
+  }
+}
+
+impl MerosNetwork {
+  pub fn hrp(&self) -> &'static str {
+    match self {
+      MerosNetwork::Testnet => "mr"
+    }
+  }
+}
+
+// An output paid by every Send this side creates, before the remainder goes to the destination or refund address
+#[derive(Deserialize, Clone, Debug)]
+pub struct MerosPayment {
+  pub address: String,
+  pub value: u64
+}
+
 #[derive(Deserialize)]
 pub struct MerosConfig {
+  #[serde(default)]
+  pub network: MerosNetwork,
   pub address: SocketAddr,
   pub destination: String,
-  pub refund: String
+  pub refund: String,
+  #[serde(default)]
+  pub payments: Vec<MerosPayment>
 }
 
 pub struct MerosEngine {
@@ -27,8 +68,12 @@ impl MerosEngine {
     }
   }
 
-  pub fn decode_address(address: &str) -> anyhow::Result<Vec<u8>> {
-    let mut result = Vec::<u8>::from_base32(&bech32::decode(address)?.1)?;
+  pub fn decode_address(network: MerosNetwork, address: &str) -> anyhow::Result<Vec<u8>> {
+    let (hrp, data) = bech32::decode(address)?;
+    if hrp != network.hrp() {
+      anyhow::bail!("Meros address isn't for the configured network.");
+    }
+    let mut result = Vec::<u8>::from_base32(&data)?;
     if (result[0] != 0) || (result.len() != 33) {
       anyhow::bail!("Unsupported Meros address type.");
     }
@@ -36,36 +81,76 @@ impl MerosEngine {
     Ok(result)
   }
 
-  pub fn get_address(key: &[u8]) -> String {
+  pub fn get_address(network: MerosNetwork, key: &[u8]) -> String {
     let mut data = vec![0];
     data.extend(key);
-    bech32::encode("mr", data.to_base32()).unwrap()
+    bech32::encode(network.hrp(), data.to_base32()).unwrap()
+  }
+
+  pub fn decode_payments(network: MerosNetwork, payments: &[MerosPayment]) -> anyhow::Result<Vec<Output>> {
+    if payments.len() >= MAX_OUTPUTS {
+      anyhow::bail!("Too many Meros payments were configured, as the change must also fit in the Send.");
+    }
+    payments.iter().map(|payment| {
+      if payment.value == 0 {
+        anyhow::bail!("Meros payment to {} has no value.", payment.address);
+      }
+      Ok(Output {
+        key: Self::decode_address(network, &payment.address)?,
+        value: payment.value
+      })
+    }).collect()
   }
 
-  pub fn create_send(
+  /*
+    Spends every input, paying the outputs and sending the remainder to the change address
+    Sends are limited in how many inputs they can have, so any more are spent by additional Sends
+    The inputs are spent largest first, with the first Send paying the outputs and the rest only having change
+  */
+  pub fn create_sends(
+    network: MerosNetwork,
     key_a: <Ed25519Sha as CryptEngine>::PrivateKey,
     key_b: <Ed25519Sha as CryptEngine>::PrivateKey,
-    utxos: Vec<Input>,
-    destination: Vec<u8>,
-    value: u64,
+    mut inputs: Vec<(Input, u64)>,
+    outputs: Vec<Output>,
+    change: Vec<u8>,
     diff: u32
-  ) -> Send {
-    let mut send = Send::new(
-      utxos,
-      vec![
-        Output {
-          key: destination,
-          value: value
-        }
-      ]
-    );
+  ) -> anyhow::Result<Vec<Send>> {
+    if inputs.len() == 0 {
+      anyhow::bail!("Creating a Meros send without any inputs.");
+    }
+    if outputs.len() >= MAX_OUTPUTS {
+      anyhow::bail!("Creating a Meros send with too many outputs.");
+    }
+
     let total_key = key_a + key_b;
     debug!(
-      "Creating Meros send for shared address {}",
-      Self::get_address((&total_key * &curve25519_dalek::constants::ED25519_BASEPOINT_TABLE).compress().as_bytes())
+      "Creating Meros sends for shared address {}",
+      Self::get_address(network, (&total_key * &curve25519_dalek::constants::ED25519_BASEPOINT_TABLE).compress().as_bytes())
     );
-    send.sign(total_key);
-    send.mine(diff);
-    send
+
+    inputs.sort_by(|a, b| b.1.cmp(&a.1));
+    let mut outputs = Some(outputs);
+    let mut sends = vec![];
+    for chunk in inputs.chunks(MAX_INPUTS) {
+      let value = chunk.iter().map(|(_, value)| value).sum::<u64>();
+      let mut chunk_outputs = outputs.take().unwrap_or_default();
+      let paid = chunk_outputs.iter().map(|output| output.value).sum::<u64>();
+      if paid > value {
+        anyhow::bail!("Meros outputs total {} yet the inputs spent with them only total {}.", paid, value);
+      }
+      if paid < value {
+        chunk_outputs.push(Output {
+          key: change.clone(),
+          value: value - paid
+        });
+      }
+
+      let mut send = Send::new(chunk.iter().map(|(input, _)| input.clone()).collect(), chunk_outputs);
+      send.sign(total_key);
+      send.mine(diff);
+      sends.push(send);
+    }
+    Ok(sends)
   }
 }
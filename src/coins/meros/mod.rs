@@ -1,5 +1,5 @@
-mod transaction;
-mod engine;
+pub(crate) mod transaction;
+pub(crate) mod engine;
 mod rpc;
 
 pub mod client;
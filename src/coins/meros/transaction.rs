@@ -7,7 +7,7 @@ use bigint::uint::U256;
 use blake2::digest::{Update, VariableOutput};
 use argon2::{self, Config, ThreadMode, Variant, Version, hash_raw};
 
-use serde::Deserialize;
+use serde::{Serialize, Deserialize};
 
 use crate::crypt_engines::{CryptEngine, ed25519_engine::Ed25519Sha};
 
@@ -23,12 +23,13 @@ const CONFIG: Config = Config {
   hash_length: 32
 };
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Input {
   pub hash: String,
   pub nonce: u8
 }
 
+#[derive(Clone, PartialEq, Debug)]
 pub struct Output {
   pub key: Vec<u8>,
   pub value: u64
@@ -124,7 +125,12 @@ impl Send {
     self.signature = Some(Ed25519Sha::signature_to_bytes(&Ed25519Sha::sign(&key, &to_sign)));
   }
 
+  // The difficulty scales with the Send's size, where a Send with a single input and output uses the base difficulty
+  pub fn difficulty(&self, diff: u32) -> u32 {
+    diff * (((70 + (self.inputs.len() * 33) + (self.outputs.len() * 40)) / 143) as u32)
+  }
+
   pub fn mine(&mut self, diff: u32) {
-    self.proof = mine(&self.hash, diff * (((70 + (self.inputs.len() * 33) + (self.outputs.len() * 40) / 143)) as u32));
+    self.proof = mine(&self.hash, self.difficulty(diff));
   }
 }
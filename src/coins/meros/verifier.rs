@@ -1,53 +1,94 @@
 use std::{
   marker::PhantomData,
-  io::Write,
   path::Path,
   fs::File
 };
 
 use async_trait::async_trait;
 
+use serde::{Serialize, Deserialize};
+
 use crate::{
   crypt_engines::{CryptEngine, ed25519_engine::Ed25519Sha},
   dl_eq::DlEqProof,
   coins::{
-    UnscriptedVerifier, ScriptedHost,
+    SwapTerms, UnscriptedVerifier, ScriptedHost,
     meros::{
-      transaction::{Input, Transaction},
-      engine::MerosEngine,
+      transaction::{Input, Output, Transaction},
+      engine::{MerosNetwork, MerosConfig, MerosEngine, SETTLEMENT_SECONDS},
       rpc::MerosRpc
     }
   }
 };
 
+#[derive(Serialize, Deserialize)]
+struct MerosVerifierState {
+  terms: Option<SwapTerms>,
+  k: Option<<Ed25519Sha as CryptEngine>::PrivateKey>,
+  shared_key: Option<<Ed25519Sha as CryptEngine>::PublicKey>,
+  utxos: Option<Vec<(Input, u64)>>
+}
+
 pub struct MerosVerifier {
+  network: MerosNetwork,
   engine: MerosEngine,
   rpc: MerosRpc,
   destination_key: Vec<u8>,
+  payments: Vec<Output>,
+  terms: Option<SwapTerms>,
 
   shared_key: Option<<Ed25519Sha as CryptEngine>::PublicKey>,
-  utxos: Option<Vec<Input>>,
-  value_sum: Option<u64>
+  utxos: Option<Vec<(Input, u64)>>
 }
 
 impl MerosVerifier {
   pub fn new(config_path: &Path) -> anyhow::Result<MerosVerifier> {
-    let config = serde_json::from_reader(File::open(config_path)?)?;
+    let config: MerosConfig = serde_json::from_reader(File::open(config_path)?)?;
 
     Ok(MerosVerifier {
+      network: config.network,
       engine: MerosEngine::new(),
       rpc: MerosRpc::new(&config)?,
-      destination_key: MerosEngine::decode_address(&config.destination)?,
+      destination_key: MerosEngine::decode_address(config.network, &config.destination)?,
+      payments: MerosEngine::decode_payments(config.network, &config.payments)?,
+      terms: None,
 
       shared_key: None,
-      utxos: None,
-      value_sum: None
+      utxos: None
     })
   }
 }
 
 #[async_trait]
 impl UnscriptedVerifier for MerosVerifier {
+  fn serialize_state(&self) -> Vec<u8> {
+    bincode::serialize(
+      &MerosVerifierState {
+        terms: self.terms,
+        k: self.engine.k,
+        shared_key: self.shared_key,
+        utxos: self.utxos.clone()
+      }
+    ).expect("Couldn't serialize the Meros verifier's state")
+  }
+
+  fn restore_state(&mut self, state: &[u8]) -> anyhow::Result<()> {
+    let state: MerosVerifierState = bincode::deserialize(state)?;
+    self.terms = state.terms;
+    self.engine.k = state.k;
+    self.shared_key = state.shared_key;
+    self.utxos = state.utxos;
+    Ok(())
+  }
+
+  fn set_terms(&mut self, terms: SwapTerms) {
+    self.terms = Some(terms);
+  }
+
+  fn settlement_seconds(&self) -> u64 {
+    SETTLEMENT_SECONDS
+  }
+
   fn generate_keys_for_engine<OtherCrypt: CryptEngine>(&mut self, _: PhantomData<&OtherCrypt>) -> (Vec<u8>, OtherCrypt::PrivateKey) {
     let (proof, key1, key2) = DlEqProof::<Ed25519Sha, OtherCrypt>::new();
     self.engine.k = Some(key1);
@@ -64,6 +105,7 @@ impl UnscriptedVerifier for MerosVerifier {
 
   async fn verify_and_wait_for_send(&mut self) -> anyhow::Result<()> {
     let address = MerosEngine::get_address(
+      self.network,
       &Ed25519Sha::public_key_to_bytes(
         self.shared_key.as_ref().expect("Waiting for send before verifying the other's DLEQ proof")
       )
@@ -92,8 +134,9 @@ impl UnscriptedVerifier for MerosVerifier {
           done = false;
           break;
         }
-        value_sum += self.rpc.get_transaction_output_value(input.clone()).await?;
-        result.push(input);
+        let value = self.rpc.get_transaction_output_value(input.clone()).await?;
+        value_sum += value;
+        result.push((input, value));
       }
 
       // Don't immediately run the next loop iteration
@@ -102,31 +145,25 @@ impl UnscriptedVerifier for MerosVerifier {
         tokio::time::delay_for(std::time::Duration::from_secs(5)).await;
       }
     }
-    self.value_sum = Some(value_sum);
     self.utxos = Some(result);
 
-    if !cfg!(test) {
-      print!("You will receive {} MR. Continue (yes/no)? ", value_sum);
-      std::io::stdout().flush().expect("Failed to flush stdout");
-      let mut line = String::new();
-      std::io::stdin().read_line(&mut line).expect("Couldn't read from stdin");
-      if !line.to_lowercase().starts_with("y") {
-        anyhow::bail!("User didn't confirm MR amount");
-      }
-    }
+    self.terms.expect("Verifying send before agreeing on terms").verify_unscripted(value_sum as u128)?;
     Ok(())
   }
 
   async fn finish<Host: ScriptedHost>(&mut self, host: &Host) -> anyhow::Result<()> {
-    let send = MerosEngine::create_send(
+    let sends = MerosEngine::create_sends(
+      self.network,
       Ed25519Sha::little_endian_bytes_to_private_key(host.recover_final_key().await?)?,
       self.engine.k.expect("Finishing before generating keys"),
       self.utxos.clone().expect("Finishing before knowing of the UTXOs"),
+      self.payments.clone(),
       self.destination_key.clone(),
-      self.value_sum.expect("Finishing before knowing of the amount"),
       self.rpc.get_send_difficulty().await
-    );
-    self.rpc.publish_send(send.serialize()).await?;
+    )?;
+    for send in sends {
+      self.rpc.publish_send(send.serialize()).await?;
+    }
     Ok(())
   }
 }
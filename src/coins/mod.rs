@@ -1,4 +1,5 @@
 pub mod btc;
+pub mod eth;
 pub mod meros;
 pub mod nano;
 pub mod xmr;
@@ -8,16 +9,166 @@ use std::marker::PhantomData;
 use async_trait::async_trait;
 use enum_dispatch::enum_dispatch;
 
+use serde::{Serialize, Deserialize};
+
 use crate::crypt_engines::CryptEngine;
 
+/*
+  The fee rates, in the scripted coin's smallest unit per virtual byte, its swap transactions may use
+  The host offers its configured range, which the client narrows to the overlap with its own
+*/
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct FeeBounds {
+  pub min: u64,
+  pub max: u64
+}
+
+impl FeeBounds {
+  pub fn unbounded() -> FeeBounds {
+    FeeBounds {
+      min: 0,
+      max: u64::MAX
+    }
+  }
+
+  pub fn intersect(&self, other: &FeeBounds) -> Option<FeeBounds> {
+    let bounds = FeeBounds {
+      min: self.min.max(other.min),
+      max: self.max.min(other.max)
+    };
+    if bounds.min <= bounds.max {
+      Some(bounds)
+    } else {
+      None
+    }
+  }
+
+  pub fn clamp(&self, fee_per_byte: u64) -> u64 {
+    fee_per_byte.max(self.min).min(self.max)
+  }
+
+  pub fn verify(&self, fee_per_byte: u64) -> anyhow::Result<()> {
+    if (fee_per_byte < self.min) || (fee_per_byte > self.max) {
+      anyhow::bail!("Fee rate of {} is outside the agreed range of {}-{}", fee_per_byte, self.min, self.max);
+    }
+    Ok(())
+  }
+}
+
+/*
+  The scripted coin's timelocks, in blocks, which the host offers alongside the amounts
+  The refund can be published t0 blocks after the lock confirms, and claimed by the client t1 blocks after the refund confirms
+  The client won't publish the buy once cutoff blocks have passed since the lock confirmed, leaving t0 - cutoff blocks for it to confirm
+*/
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Timelocks {
+  pub t0: u16,
+  pub t1: u16,
+  pub cutoff: u16
+}
+
+impl Default for Timelocks {
+  fn default() -> Timelocks {
+    Timelocks {
+      t0: 6,
+      t1: 6,
+      cutoff: 4
+    }
+  }
+}
+
+// The fewest blocks a transaction racing a timelock is given to confirm
+pub const MIN_CONFIRMATION_WINDOW: u16 = 2;
+
+impl Timelocks {
+  /*
+    Checks these timelocks leave time for every step which has to happen before they expire
+    block_seconds is the scripted coin's block time
+    settlement_seconds is how long the unscripted coin takes to become final, which has to happen before the cutoff
+  */
+  pub fn verify(&self, block_seconds: u64, settlement_seconds: u64) -> anyhow::Result<()> {
+    if (self.t0 == 0) || (self.t1 == 0) || (self.cutoff == 0) {
+      anyhow::bail!("Timelocks must be nonzero");
+    }
+    if self.t0 < self.cutoff.saturating_add(MIN_CONFIRMATION_WINDOW) {
+      anyhow::bail!("A T0 of {} only leaves {} blocks for the buy to confirm after the cutoff of {}", self.t0, self.t0.saturating_sub(self.cutoff), self.cutoff);
+    }
+    if self.t1 < MIN_CONFIRMATION_WINDOW {
+      anyhow::bail!("A T1 of {} doesn't leave enough time for the refund to be spent", self.t1);
+    }
+    if ((self.cutoff as u64) * block_seconds) < settlement_seconds {
+      anyhow::bail!(
+        "A cutoff of {} blocks is shorter than the {} seconds the unscripted coin takes to become final",
+        self.cutoff, settlement_seconds
+      );
+    }
+    Ok(())
+  }
+}
+
+/*
+  The amounts agreed to before any keys are exchanged
+  Both are in the smallest unit of their coin, with the unscripted amount being a u128 to fit Nano's raw units
+  The scripted amount is the value of the lock, and the unscripted amount is the value sent to the shared address
+  Fees make neither exactly predictable, hence the tolerance, in basis points, applied to both
+*/
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct SwapTerms {
+  pub scripted_amount: u64,
+  pub unscripted_amount: u128,
+  pub tolerance_bps: u16,
+  pub scripted_fee_bounds: FeeBounds,
+  pub timelocks: Timelocks
+}
+
+impl SwapTerms {
+  /*
+    Checks difference * 10000 <= agreed * tolerance without overflowing, as Nano amounts use most of a u128
+    With agreed = 10000q + r, the allowed difference is q * tolerance + (r * tolerance) / 10000, rounded down
+    The second term can't overflow, and saturating the first only occurs when any difference is allowed
+  */
+  fn within_tolerance(&self, agreed: u128, actual: u128) -> bool {
+    let difference = if actual > agreed { actual - agreed } else { agreed - actual };
+    let tolerance = self.tolerance_bps as u128;
+    let allowed = (agreed / 10000).saturating_mul(tolerance).saturating_add(((agreed % 10000) * tolerance) / 10000);
+    difference <= allowed
+  }
+
+  pub fn verify_scripted(&self, actual: u64) -> anyhow::Result<()> {
+    if !self.within_tolerance(self.scripted_amount as u128, actual as u128) {
+      anyhow::bail!("Scripted amount {} doesn't match the agreed amount of {}", actual, self.scripted_amount);
+    }
+    Ok(())
+  }
+
+  pub fn verify_unscripted(&self, actual: u128) -> anyhow::Result<()> {
+    if !self.within_tolerance(self.unscripted_amount, actual) {
+      anyhow::bail!("Unscripted amount {} doesn't match the agreed amount of {}", actual, self.unscripted_amount);
+    }
+    Ok(())
+  }
+}
+
 #[async_trait]
 #[enum_dispatch(AnyScriptedHost)]
 #[allow(non_snake_case)]
 pub trait ScriptedHost: Send + Sync {
+  fn serialize_state(&self) -> Vec<u8>;
+  fn restore_state(&mut self, state: &[u8]) -> anyhow::Result<()>;
+  fn set_terms(&mut self, terms: SwapTerms);
+  // The fee rates this instance is configured to accept
+  fn fee_bounds(&self) -> FeeBounds;
+  // The timelocks this instance is configured to offer, and the average time between blocks
+  fn timelocks(&self) -> Timelocks;
+  fn block_seconds(&self) -> u64;
+
   fn generate_keys<Verifier: UnscriptedVerifier>(&mut self, verifier: &mut Verifier) -> Vec<u8>;
   fn verify_keys<Verifier: UnscriptedVerifier>(&mut self, keys: &[u8], verifier: &mut Verifier) -> anyhow::Result<()>;
 
   fn swap_secret(&self) -> [u8; 32];
+  // Labelled IDs of every transaction created or learned of so far
+  fn txids(&self) -> Vec<(&'static str, String)>;
 
   fn generate_deposit_address(&mut self) -> String;
 
@@ -26,6 +177,8 @@ pub trait ScriptedHost: Send + Sync {
   async fn publish_lock(&mut self) -> anyhow::Result<()>;
 
   async fn prepare_buy_for_client(&mut self) -> anyhow::Result<Vec<u8>>;
+  // Verifies the client's reply to the prepared buy, which is only non-empty when the buy is jointly signed
+  fn verify_buy_signature(&mut self, buy_signature: &[u8]) -> anyhow::Result<()>;
 
   async fn recover_final_key(&self) -> anyhow::Result<[u8; 32]>;
   async fn refund<Verifier: UnscriptedVerifier>(self, verifier: Verifier) -> anyhow::Result<()>;
@@ -45,11 +198,17 @@ pub trait ScriptedHost: Send + Sync {
 #[enum_dispatch]
 pub enum AnyScriptedHost {
   Btc(btc::host::BtcHost),
+  Eth(eth::host::EthHost),
 }
 
 #[async_trait]
 #[enum_dispatch(AnyUnscriptedClient)]
 pub trait UnscriptedClient {
+  fn serialize_state(&self) -> Vec<u8>;
+  fn restore_state(&mut self, state: &[u8]) -> anyhow::Result<()>;
+  // How long, in seconds, a send takes to become final, including any time before it can be spent
+  fn settlement_seconds(&self) -> u64;
+
   fn generate_keys<Verifier: ScriptedVerifier>(&mut self, verifier: &mut Verifier) -> Vec<u8>;
   fn verify_keys<Verifier: ScriptedVerifier>(&mut self, keys: &[u8], verifier: &mut Verifier) -> anyhow::Result<()>;
 
@@ -80,7 +239,17 @@ pub enum AnyUnscriptedClient {
 #[enum_dispatch(AnyScriptedVerifier)]
 #[allow(non_snake_case)]
 pub trait ScriptedVerifier: Send + Sync {
+  fn serialize_state(&self) -> Vec<u8>;
+  fn restore_state(&mut self, state: &[u8]) -> anyhow::Result<()>;
+  fn set_terms(&mut self, terms: SwapTerms);
+  fn fee_bounds(&self) -> FeeBounds;
+  // The timelocks this instance is configured with, and the average time between blocks
+  fn timelocks(&self) -> Timelocks;
+  fn block_seconds(&self) -> u64;
+
   fn destination_script(&self) -> Vec<u8>;
+  // Labelled IDs of every transaction created or learned of so far
+  fn txids(&self) -> Vec<(&'static str, String)>;
 
   // These `PhantomData`s are needed because enum_dispatch doesn't specify method type parameters (probably a bug)
   fn generate_keys_for_engine<OtherCrypt: CryptEngine>(&mut self, phantom: PhantomData<&OtherCrypt>) -> (Vec<u8>, OtherCrypt::PrivateKey);
@@ -89,7 +258,8 @@ pub trait ScriptedVerifier: Send + Sync {
   fn B(&self) -> Vec<u8>;
   fn BR(&self) -> Vec<u8>;
 
-  fn verify_prepared_buy(&mut self, buy_info: &[u8]) -> anyhow::Result<()>;
+  // Returns the reply for the host, which is empty unless the buy is jointly signed
+  fn verify_prepared_buy(&mut self, buy_info: &[u8]) -> anyhow::Result<Vec<u8>>;
 
   async fn verify_and_wait_for_lock(
     &mut self
@@ -108,11 +278,17 @@ pub trait ScriptedVerifier: Send + Sync {
 #[enum_dispatch]
 pub enum AnyScriptedVerifier {
   Btc(btc::verifier::BtcVerifier),
+  Eth(eth::verifier::EthVerifier),
 }
 
 #[async_trait]
 #[enum_dispatch(AnyUnscriptedVerifier)]
 pub trait UnscriptedVerifier: Send + Sync {
+  fn serialize_state(&self) -> Vec<u8>;
+  fn restore_state(&mut self, state: &[u8]) -> anyhow::Result<()>;
+  fn set_terms(&mut self, terms: SwapTerms);
+  fn settlement_seconds(&self) -> u64;
+
   // These `PhantomData`s are needed because enum_dispatch doesn't specify method type parameters (probably a bug)
   fn generate_keys_for_engine<OtherCrypt: CryptEngine>(&mut self, phantom: PhantomData<&OtherCrypt>) -> (Vec<u8>, OtherCrypt::PrivateKey);
   fn verify_dleq_for_engine<OtherCrypt: CryptEngine>(&mut self, dleq: &[u8], phantom: PhantomData<&OtherCrypt>) -> anyhow::Result<OtherCrypt::PublicKey>;
@@ -5,42 +5,75 @@ use std::{
 };
 
 use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
 use nanocurrency_types::{Account, BlockHash};
 
 use crate::{
   crypt_engines::{KeyBundle, CryptEngine, ed25519_engine::Ed25519Blake2b},
   coins::{
     UnscriptedClient, ScriptedVerifier,
-    nano::engine::{NanoConfig, NanoEngine}
+    nano::engine::{NanoConfig, NanoEngine, SETTLEMENT_SECONDS}
   }
 };
 
+#[derive(Serialize, Deserialize)]
+struct NanoClientState {
+  key_share: Option<<Ed25519Blake2b as CryptEngine>::PrivateKey>,
+  shared_key: Option<<Ed25519Blake2b as CryptEngine>::PublicKey>,
+  address: Option<String>,
+  inputs: Vec<([u8; 32], u128)>,
+}
+
 pub struct NanoClient {
   engine: NanoEngine,
   refund: Account,
   key_share: Option<<Ed25519Blake2b as CryptEngine>::PrivateKey>,
   shared_key: Option<<Ed25519Blake2b as CryptEngine>::PublicKey>,
   address: Option<String>,
-  input: Option<(BlockHash, u128)>,
+  inputs: Vec<(BlockHash, u128)>,
 }
 
 impl NanoClient {
-  pub fn new(config_path: &Path) -> anyhow::Result<NanoClient> {
+  pub async fn new(config_path: &Path) -> anyhow::Result<NanoClient> {
     let config: NanoConfig = serde_json::from_reader(File::open(config_path)?)?;
     Ok(NanoClient{
       refund: config.refund.parse()
         .map_err(|e| anyhow::anyhow!("Error parsing Nano address: {}", e))?,
-      engine: NanoEngine::new(config),
+      engine: NanoEngine::new(config).await?,
       key_share: None,
       shared_key: None,
       address: None,
-      input: None,
+      inputs: vec![],
     })
   }
 }
 
 #[async_trait]
 impl UnscriptedClient for NanoClient {
+  fn serialize_state(&self) -> Vec<u8> {
+    bincode::serialize(
+      &NanoClientState {
+        key_share: self.key_share,
+        shared_key: self.shared_key,
+        address: self.address.clone(),
+        inputs: self.inputs.iter().map(|(hash, amount)| (hash.0, *amount)).collect(),
+      }
+    ).expect("Couldn't serialize the Nano client's state")
+  }
+
+  fn restore_state(&mut self, state: &[u8]) -> anyhow::Result<()> {
+    let state: NanoClientState = bincode::deserialize(state)?;
+    self.key_share = state.key_share;
+    self.shared_key = state.shared_key;
+    self.address = state.address;
+    self.inputs = state.inputs.into_iter().map(|(hash, amount)| (BlockHash(hash), amount)).collect();
+    Ok(())
+  }
+
+  fn settlement_seconds(&self) -> u64 {
+    SETTLEMENT_SECONDS
+  }
+
   fn generate_keys<Verifier: ScriptedVerifier>(&mut self, verifier: &mut Verifier) -> Vec<u8> {
     let (dl_eq, key) = verifier.generate_keys_for_engine::<Ed25519Blake2b>(PhantomData);
     self.key_share = Some(key);
@@ -68,17 +101,16 @@ impl UnscriptedClient for NanoClient {
 
   async fn wait_for_deposit(&mut self) -> anyhow::Result<()> {
     let address = self.address.clone().expect("Waiting for deposit despite not knowing the deposit address");
-    while self.input.is_none() {
-      tokio::time::delay_for(std::time::Duration::from_secs(5)).await;
-      let mut inputs = self.engine.get_confirmed_pending(&address).await?;
-      inputs.truncate(1);
-      self.input = inputs.pop();
+    let mut subscription = self.engine.subscribe(&address);
+    while self.inputs.is_empty() {
+      self.engine.wait_for_activity(&mut subscription).await;
+      self.inputs = self.engine.get_confirmed_pending(&address).await?;
     }
     Ok(())
   }
 
   async fn refund<Verifier: ScriptedVerifier + Send + Sync>(mut self, verifier: Verifier) -> anyhow::Result<()> {
-    if let Some((input, amount)) = self.input {
+    if !self.inputs.is_empty() {
       /*
         Once we publish the refund, two paths open up
         A) We can claim the BTC after the second timeout expires
@@ -86,12 +118,11 @@ impl UnscriptedClient for NanoClient {
         We assume path A, and then revert to path B if path A fails
       */
       if let Some(recovered_key) = verifier.claim_refund_or_recover_key().await? {
-        self.engine.send(
+        self.engine.sweep(
           Ed25519Blake2b::little_endian_bytes_to_private_key(recovered_key)?,
           self.key_share.expect("Finishing before generating keys"),
-          input,
+          &self.inputs,
           self.refund,
-          amount,
         ).await?;
       }
     }
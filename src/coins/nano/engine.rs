@@ -1,15 +1,26 @@
 use std::{
   collections::HashMap,
   fmt::Debug,
-  thread,
-  sync::{Arc, atomic::{self, AtomicBool}, mpsc},
+  time::{Duration, Instant},
 };
 
-use log::{debug, info};
+use log::{debug, info, warn};
 use serde::{Serialize, Deserialize, de::DeserializeOwned};
 use nanocurrency_types::{Account, BlockInner, BlockHash, Block, BlockHeader};
 
-use crate::crypt_engines::{CryptEngine, ed25519_engine::Ed25519Blake2b};
+use crate::{
+  crypt_engines::{CryptEngine, ed25519_engine::Ed25519Blake2b},
+  coins::nano::{
+    work::{NanoWorkSource, default_work_sources, NanoWork},
+    websocket::NanoSubscription
+  }
+};
+
+// Blocks are confirmed by representatives' votes within seconds, so this is a generous margin
+pub const SETTLEMENT_SECONDS: u64 = 60;
+// How often the node is polled when there's no WebSocket, and how long a subscription can be silent before polling anyways
+const POLL_SECONDS: u64 = 5;
+const SUBSCRIPTION_POLL_SECONDS: u64 = 30;
 
 /// A workaround for the Nano RPC returning empty strings instead of empty arrays or objects.
 pub mod nano_rpc_maybe_empty {
@@ -44,9 +55,48 @@ pub mod nano_rpc_maybe_empty {
   }
 }
 
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum NanoNetwork {
+  Live,
+  Beta,
+  Test,
+  Dev
+}
+
+impl Default for NanoNetwork {
+  fn default() -> NanoNetwork {
+    NanoNetwork::Live
+  }
+}
+
+impl NanoNetwork {
+  // The name the node reports for this network
+  pub fn name(&self) -> &'static str {
+    match self {
+      NanoNetwork::Live => "live",
+      NanoNetwork::Beta => "beta",
+      NanoNetwork::Test => "test",
+      NanoNetwork::Dev => "dev"
+    }
+  }
+}
+
 #[derive(Deserialize)]
 pub struct NanoConfig {
+  #[serde(default)]
+  pub network: NanoNetwork,
   pub rpc_url: String,
+  // The sources of proof of work, in the order they're tried
+  #[serde(default = "default_work_sources")]
+  pub work: Vec<NanoWorkSource>,
+  // The node's WebSocket, used to be notified of confirmations instead of polling for them
+  #[serde(default)]
+  pub websocket: Option<String>,
+  // The representative the swap's account delegates to, and optionally another for the final sweep's send
+  pub representative: String,
+  #[serde(default)]
+  pub sweep_representative: Option<String>,
   pub destination: String,
   pub refund: String,
   #[cfg(test)]
@@ -55,19 +105,59 @@ pub struct NanoConfig {
   pub wallet_account: String,
 }
 
+impl NanoConfig {
+  // Parses the representatives for the swap's blocks and for the final sweep, which defaults to the former
+  pub fn representatives(&self) -> anyhow::Result<(Account, Account)> {
+    let parse = |representative: &str| representative.parse::<Account>()
+      .map_err(|e| anyhow::anyhow!("Error parsing Nano representative {}: {}", representative, e));
+    let representative = parse(&self.representative)?;
+    let sweep_representative = match &self.sweep_representative {
+      Some(sweep_representative) => parse(sweep_representative)?,
+      None => representative.clone()
+    };
+    Ok((representative, sweep_representative))
+  }
+}
+
 pub struct NanoEngine {
   pub k: Option<<Ed25519Blake2b as CryptEngine>::PrivateKey>,
   pub client: reqwest::Client,
+  work: NanoWork,
+  representative: Account,
+  sweep_representative: Account,
   pub config: NanoConfig,
 }
 
 impl NanoEngine {
-  pub fn new(config: NanoConfig) -> NanoEngine {
-    NanoEngine {
+  pub async fn new(config: NanoConfig) -> anyhow::Result<NanoEngine> {
+    let (representative, sweep_representative) = config.representatives()?;
+    let engine = NanoEngine {
       k: None,
       client: reqwest::Client::new(),
+      work: NanoWork::new(config.work.clone(), config.rpc_url.clone())?,
+      representative,
+      sweep_representative,
       config,
+    };
+    engine.verify_network().await?;
+    Ok(engine)
+  }
+
+  // Nano addresses are the same on every network, so the node's network is all which can be checked
+  async fn verify_network(&self) -> anyhow::Result<()> {
+    #[derive(Serialize, Debug)]
+    struct VersionRequest<'a> {
+      action: &'a str,
+    }
+    #[derive(Deserialize, Debug)]
+    struct VersionResponse {
+      network: String,
     }
+    let version: VersionResponse = self.rpc_call(&VersionRequest { action: "version" }).await?;
+    if version.network != self.config.network.name() {
+      anyhow::bail!("Nano node is on the {} network, yet the {} network is configured", version.network, self.config.network.name());
+    }
+    Ok(())
   }
 
   async fn rpc_call<
@@ -120,36 +210,55 @@ impl NanoEngine {
     Ok(())
   }
 
-  fn compute_work(root: [u8; 32], threshold: u64) -> u64 {
-    info!("Generating Nano proof of work for root {} with threshold 0x{:016x}", hex::encode(&root), threshold);
-    // TODO support calling an external work RPC
-    let (send, recv) = mpsc::channel();
-    let running = Arc::new(AtomicBool::new(true));
-    let thread_count = num_cpus::get();
-    for i in 0..thread_count {
-      let send = send.clone();
-      let running = running.clone();
-      thread::spawn(move || {
-        let mut nonce = (u64::MAX / thread_count as u64) * i as u64;
-        while nanocurrency_types::work_value(&root, nonce) < threshold &&
-          running.load(atomic::Ordering::Relaxed)
-        {
-          nonce += 1;
-        }
-        let _ = send.send(nonce);
-      });
+  // Subscribes to confirmations of blocks to and from the account, if a WebSocket was configured
+  pub fn subscribe(&self, account: &str) -> Option<NanoSubscription> {
+    self.config.websocket.clone().map(|url| NanoSubscription::new(url, account.to_string()))
+  }
+
+  /*
+    Waits until it's worth polling the node again
+    With a subscription, that's once a confirmation arrives, yet the node is still occasionally polled in case one was missed
+  */
+  pub async fn wait_for_activity(&self, subscription: &mut Option<NanoSubscription>) {
+    if let Some(subscription) = subscription {
+      if let Some(hash) = subscription.next(Duration::from_secs(SUBSCRIPTION_POLL_SECONDS)).await {
+        debug!("Nano block {} was confirmed", hash);
+      }
+    } else {
+      tokio::time::delay_for(Duration::from_secs(POLL_SECONDS)).await;
+    }
+  }
+
+  async fn is_confirmed(&self, hash: &BlockHash) -> anyhow::Result<bool> {
+    #[derive(Serialize, Debug)]
+    struct BlockInfoRequest<'a> {
+      action: &'a str,
+      json_block: &'a str,
+      hash: String,
     }
-    let nonce = recv.recv().expect("Work computation threads died");
-    running.store(false, atomic::Ordering::Relaxed);
-    debug!("Generated Nano proof of work nonce {:016x} for root {}", nonce, hex::encode(&root));
-    nonce
+    #[derive(Deserialize, Debug)]
+    struct BlockInfoResponse {
+      confirmed: String,
+    }
+    let request = BlockInfoRequest {
+      action: "block_info",
+      json_block: "true",
+      hash: hex::encode_upper(hash.0),
+    };
+    let response: BlockInfoResponse = self.rpc_call(&request).await?;
+    Ok(response.confirmed == "true")
   }
 
-  fn complete_block(inner: BlockInner, key: <Ed25519Blake2b as CryptEngine>::PrivateKey, work_threshold: u64) -> Block {
+  async fn complete_block(
+    &self,
+    inner: BlockInner,
+    key: <Ed25519Blake2b as CryptEngine>::PrivateKey,
+    work_threshold: u64
+  ) -> anyhow::Result<Block> {
     let hash = inner.get_hash();
     let signature = Ed25519Blake2b::sign(&key, &hash.0);
-    let work = Self::compute_work(inner.root_bytes().clone(), work_threshold);
-    Block {
+    let work = self.work.generate(inner.root_bytes().clone(), work_threshold).await?;
+    Ok(Block {
       inner,
       header: BlockHeader {
         signature: nanocurrency_types::Signature::from_bytes(
@@ -157,7 +266,7 @@ impl NanoEngine {
         ).expect("Generated invalid signature"),
         work,
       }
-    }
+    })
   }
 
   async fn get_work_threshold(&self, is_receive: bool) -> anyhow::Result<u64> {
@@ -180,35 +289,120 @@ impl NanoEngine {
     Ok(threshold)
   }
 
-  pub async fn send(
+  /*
+    The shared account's open block, receiving the first input, followed by a receive block for every other input
+    Each block's balance is the sum of the inputs received so far
+  */
+  fn receive_blocks(&self, account: &Account, inputs: &[(BlockHash, u128)]) -> Vec<BlockInner> {
+    let mut previous = BlockHash::default();
+    let mut balance = 0;
+    let mut blocks = vec![];
+    for (input, amount) in inputs {
+      balance += amount;
+      let block = BlockInner::State {
+        account: account.clone(),
+        previous,
+        representative: self.representative.clone(),
+        balance,
+        link: input.0,
+      };
+      previous = block.get_hash();
+      blocks.push(block);
+    }
+    blocks
+  }
+
+  // Sends the account's entire balance to the destination, changing the representative to the sweep's
+  fn send_block(&self, account: &Account, previous: BlockHash, destination: Account) -> BlockInner {
+    BlockInner::State {
+      account: account.clone(),
+      previous,
+      representative: self.sweep_representative.clone(),
+      balance: 0,
+      link: destination.0,
+    }
+  }
+
+  /*
+    Starts generating the work for the shared account's blocks, which only needs the account and its inputs
+    This way, it's likely done by the time the key needed to sign them is learnt
+  */
+  pub async fn precompute_work(&self, account: &Account, inputs: &[(BlockHash, u128)]) -> anyhow::Result<()> {
+    let receive_threshold = self.get_work_threshold(true).await?;
+    let blocks = self.receive_blocks(account, inputs);
+    for block in &blocks {
+      self.work.precompute(block.root_bytes().clone(), receive_threshold);
+    }
+    // The send's root is the last block it follows
+    if let Some(last) = blocks.last() {
+      self.work.precompute(last.get_hash().0, self.get_work_threshold(false).await?);
+    }
+    Ok(())
+  }
+
+  /*
+    Receives every confirmed pending block to the shared account and sends its entire balance to the destination
+    The inputs already known are received first, in order, as their blocks' work may have been precomputed
+  */
+  pub async fn sweep(
     &self,
     key_a: <Ed25519Blake2b as CryptEngine>::PrivateKey,
     key_b: <Ed25519Blake2b as CryptEngine>::PrivateKey,
-    input: BlockHash,
+    known: &[(BlockHash, u128)],
     destination: Account,
-    value: u128,
   ) -> anyhow::Result<()> {
     let total_key = key_a + key_b;
     let account = Account((&total_key * &curve25519_dalek::constants::ED25519_BASEPOINT_TABLE).compress().to_bytes());
-    debug!("Creating Nano send for shared address {}", account);
-    let open_inner = BlockInner::State {
-      account: account.clone(),
-      previous: BlockHash::default(),
-      representative: Account([0u8; 32]),
-      balance: value,
-      link: input.0,
-    };
-    let open = Self::complete_block(open_inner, total_key, self.get_work_threshold(true).await?);
-    self.publish(&open, "open").await?;
-    let send_inner = BlockInner::State {
-      account,
-      previous: open.get_hash(),
-      representative: Account([0u8; 32]),
-      balance: 0,
-      link: destination.0,
-    };
-    let send = Self::complete_block(send_inner, total_key, self.get_work_threshold(false).await?);
+    debug!("Creating Nano sweep for shared address {}", account);
+
+    let pending = self.get_confirmed_pending(&account.to_string()).await?;
+    let is_known = |hash: &BlockHash| known.iter().any(|(known, _)| known.0 == hash.0);
+    let mut inputs = known.iter()
+      .filter(|(hash, _)| pending.iter().any(|(pending, _)| pending.0 == hash.0))
+      .cloned()
+      .collect::<Vec<_>>();
+    let mut others = pending.into_iter().filter(|(hash, _)| !is_known(hash)).collect::<Vec<_>>();
+    others.sort_by(|a, b| a.0 .0.cmp(&b.0 .0));
+    inputs.extend(others);
+    if inputs.len() == 0 {
+      anyhow::bail!("Nano account {} had nothing to sweep", account);
+    }
+
+    // Subscribed before anything is published so the send's confirmation can't be missed
+    let mut subscription = self.subscribe(&account.to_string());
+    let receive_threshold = self.get_work_threshold(true).await?;
+    let mut previous = BlockHash::default();
+    for (i, block) in self.receive_blocks(&account, &inputs).into_iter().enumerate() {
+      let block = self.complete_block(block, total_key, receive_threshold).await?;
+      self.publish(&block, if i == 0 { "open" } else { "receive" }).await?;
+      previous = block.get_hash();
+    }
+    let send = self.complete_block(
+      self.send_block(&account, previous, destination),
+      total_key,
+      self.get_work_threshold(false).await?
+    ).await?;
     self.publish(&send, "send").await?;
+    info!(
+      "Swept {} raw from {} Nano blocks to the destination",
+      inputs.iter().map(|(_, amount)| amount).sum::<u128>(),
+      inputs.len()
+    );
+
+    /*
+      The send was published, so the swap completing doesn't depend on its confirmation
+      That said, it's waited for so the destination can be told to expect it
+    */
+    let send = send.get_hash();
+    let start = Instant::now();
+    while !self.is_confirmed(&send).await? {
+      if start.elapsed() > Duration::from_secs(SETTLEMENT_SECONDS) {
+        warn!("Nano send {} wasn't confirmed within {} seconds", hex::encode_upper(send.0), SETTLEMENT_SECONDS);
+        return Ok(());
+      }
+      self.wait_for_activity(&mut subscription).await;
+    }
+    info!("Nano send {} was confirmed", hex::encode_upper(send.0));
     Ok(())
   }
 
@@ -2,6 +2,14 @@
 mod engine;
 #[cfg(test)]
 pub mod engine;
+#[cfg(not(test))]
+mod work;
+#[cfg(test)]
+pub mod work;
+#[cfg(not(test))]
+mod websocket;
+#[cfg(test)]
+pub mod websocket;
 
 pub mod client;
 pub mod verifier;
@@ -1,47 +1,85 @@
 use std::{
   marker::PhantomData,
-  io::Write,
   path::Path,
   fs::File,
 };
 
 use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
 use nanocurrency_types::{Account, BlockHash};
 
 use crate::{
   crypt_engines::{CryptEngine, ed25519_engine::Ed25519Blake2b},
   dl_eq::DlEqProof,
   coins::{
-    UnscriptedVerifier, ScriptedHost,
-    nano::engine::{NanoConfig, NanoEngine},
+    SwapTerms, UnscriptedVerifier, ScriptedHost,
+    nano::engine::{NanoConfig, NanoEngine, SETTLEMENT_SECONDS},
   },
 };
 
+#[derive(Serialize, Deserialize)]
+struct NanoVerifierState {
+  terms: Option<SwapTerms>,
+  k: Option<<Ed25519Blake2b as CryptEngine>::PrivateKey>,
+  shared_key: Option<<Ed25519Blake2b as CryptEngine>::PublicKey>,
+  inputs: Vec<([u8; 32], u128)>,
+}
+
 pub struct NanoVerifier {
   engine: NanoEngine,
   destination_key: Account,
+  terms: Option<SwapTerms>,
 
   shared_key: Option<<Ed25519Blake2b as CryptEngine>::PublicKey>,
-  input: Option<(BlockHash, u128)>,
+  inputs: Vec<(BlockHash, u128)>,
 }
 
 impl NanoVerifier {
-  pub fn new(config_path: &Path) -> anyhow::Result<NanoVerifier> {
+  pub async fn new(config_path: &Path) -> anyhow::Result<NanoVerifier> {
     let config: NanoConfig = serde_json::from_reader(File::open(config_path)?)?;
 
     Ok(NanoVerifier {
       destination_key: config.destination.parse()
         .map_err(|e| anyhow::anyhow!("Error parsing Nano address: {}", e))?,
-      engine: NanoEngine::new(config),
+      engine: NanoEngine::new(config).await?,
+      terms: None,
 
       shared_key: None,
-      input: None,
+      inputs: vec![],
     })
   }
 }
 
 #[async_trait]
 impl UnscriptedVerifier for NanoVerifier {
+  fn serialize_state(&self) -> Vec<u8> {
+    bincode::serialize(
+      &NanoVerifierState {
+        terms: self.terms,
+        k: self.engine.k,
+        shared_key: self.shared_key,
+        inputs: self.inputs.iter().map(|(hash, amount)| (hash.0, *amount)).collect(),
+      }
+    ).expect("Couldn't serialize the Nano verifier's state")
+  }
+
+  fn restore_state(&mut self, state: &[u8]) -> anyhow::Result<()> {
+    let state: NanoVerifierState = bincode::deserialize(state)?;
+    self.terms = state.terms;
+    self.engine.k = state.k;
+    self.shared_key = state.shared_key;
+    self.inputs = state.inputs.into_iter().map(|(hash, amount)| (BlockHash(hash), amount)).collect();
+    Ok(())
+  }
+
+  fn set_terms(&mut self, terms: SwapTerms) {
+    self.terms = Some(terms);
+  }
+
+  fn settlement_seconds(&self) -> u64 {
+    SETTLEMENT_SECONDS
+  }
+
   fn generate_keys_for_engine<OtherCrypt: CryptEngine>(&mut self, _: PhantomData<&OtherCrypt>) -> (Vec<u8>, OtherCrypt::PrivateKey) {
     let (proof, key1, key2) = DlEqProof::<Ed25519Blake2b, OtherCrypt>::new();
     self.engine.k = Some(key1);
@@ -66,38 +104,37 @@ impl UnscriptedVerifier for NanoVerifier {
       The node will only track the transaction if it's viable, including having a valid signature
     */
 
-    while self.input.is_none() {
+    /*
+      The amount may be sent in several parts, so every pending block is summed
+      This waits until the total is acceptable or has overshot
+    */
+    let terms = self.terms.expect("Verifying send before agreeing on terms");
+    let mut subscription = self.engine.subscribe(&address);
+    while self.inputs.is_empty() {
       let mut inputs = self.engine.get_confirmed_pending(&address).await?;
-      inputs.truncate(1);
-      self.input = inputs.pop();
-
-      // Don't immediately run the next loop iteration
-      if self.input.is_none() {
-        tokio::time::delay_for(std::time::Duration::from_secs(5)).await;
+      // Sorted so the blocks receiving them are deterministic
+      inputs.sort_by(|a, b| a.0 .0.cmp(&b.0 .0));
+      let total = inputs.iter().map(|(_, amount)| amount).sum::<u128>();
+      if (total != 0) && (terms.verify_unscripted(total).is_ok() || (total > terms.unscripted_amount)) {
+        self.inputs = inputs;
+      } else {
+        // Don't immediately run the next loop iteration
+        self.engine.wait_for_activity(&mut subscription).await;
       }
     }
 
-    if !cfg!(test) {
-      let amount = self.input.as_ref().unwrap().1 as f64 / 1e30;
-      print!("You will receive {:.2} Nano. Continue (yes/no)? ", amount);
-      std::io::stdout().flush().expect("Failed to flush stdout");
-      let mut line = String::new();
-      std::io::stdin().read_line(&mut line).expect("Couldn't read from stdin");
-      if !line.to_lowercase().starts_with("y") {
-        anyhow::bail!("User didn't confirm Nano amount");
-      }
-    }
+    terms.verify_unscripted(self.inputs.iter().map(|(_, amount)| amount).sum::<u128>())?;
+    self.engine.precompute_work(&Account(shared_key.compress().to_bytes()), &self.inputs).await?;
     Ok(())
   }
 
   async fn finish<Host: ScriptedHost>(&mut self, host: &Host) -> anyhow::Result<()> {
-    let input = self.input.clone().expect("Finishing before knowing of the UTXOs");
-    self.engine.send(
+    assert!(!self.inputs.is_empty(), "Finishing before knowing of the inputs");
+    self.engine.sweep(
       Ed25519Blake2b::little_endian_bytes_to_private_key(host.recover_final_key().await?)?,
       self.engine.k.expect("Finishing before generating keys"),
-      input.0,
+      &self.inputs,
       self.destination_key.clone(),
-      input.1,
     ).await?;
     Ok(())
   }
@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use log::{debug, warn};
+use serde::Deserialize;
+use serde_json::json;
+use futures::{SinkExt, StreamExt, future::{abortable, AbortHandle}};
+use tokio::{sync::mpsc, time::{delay_for, timeout}};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+// How long to wait before reconnecting, doubling after every failed attempt
+const RECONNECT_SECONDS: u64 = 1;
+const MAX_RECONNECT_SECONDS: u64 = 60;
+
+#[derive(Deserialize, Debug)]
+struct ConfirmedBlock {
+  hash: String
+}
+
+#[derive(Deserialize, Debug)]
+struct Notification {
+  topic: String,
+  message: ConfirmedBlock
+}
+
+/*
+  A subscription to the node's WebSocket confirmation topic, filtered to an account
+  The node includes blocks sent to the account, not just those of it, so this covers both deposits and the sweep
+  The connection is re-established whenever it drops, for as long as the subscription exists
+*/
+pub struct NanoSubscription {
+  confirmations: mpsc::UnboundedReceiver<String>,
+  connection: AbortHandle
+}
+
+// Forwards confirmations until the connection drops
+async fn forward(url: &str, account: &str, confirmations: &mpsc::UnboundedSender<String>) -> anyhow::Result<()> {
+  let (mut socket, _) = connect_async(url).await?;
+  socket.send(Message::Text(json!({
+    "action": "subscribe",
+    "topic": "confirmation",
+    "options": {
+      "accounts": [account]
+    }
+  }).to_string())).await?;
+  debug!("Subscribed to Nano confirmations for {} via {}", account, url);
+
+  while let Some(message) = socket.next().await {
+    if let Message::Text(text) = message? {
+      // Anything besides a confirmation, such as an acknowledgement, is ignored
+      if let Ok(notification) = serde_json::from_str::<Notification>(&text) {
+        if notification.topic == "confirmation" {
+          let _ = confirmations.send(notification.message.hash);
+        }
+      }
+    }
+  }
+  Ok(())
+}
+
+impl NanoSubscription {
+  pub fn new(url: String, account: String) -> NanoSubscription {
+    let (send, confirmations) = mpsc::unbounded_channel();
+    let (connection, abort) = abortable(async move {
+      let mut backoff = RECONNECT_SECONDS;
+      loop {
+        match forward(&url, &account, &send).await {
+          // The connection was working, so reconnect quickly
+          Ok(()) => backoff = RECONNECT_SECONDS,
+          Err(e) => warn!("Nano WebSocket connection to {} failed: {}", url, e)
+        }
+        delay_for(Duration::from_secs(backoff)).await;
+        backoff = (backoff * 2).min(MAX_RECONNECT_SECONDS);
+      }
+    });
+    tokio::spawn(connection);
+    NanoSubscription {
+      confirmations,
+      connection: abort
+    }
+  }
+
+  // Waits up to the timeout for a confirmation, returning the hash of the block confirmed
+  pub async fn next(&mut self, wait: Duration) -> Option<String> {
+    timeout(wait, self.confirmations.recv()).await.ok().flatten()
+  }
+}
+
+impl Drop for NanoSubscription {
+  fn drop(&mut self) {
+    self.connection.abort();
+  }
+}
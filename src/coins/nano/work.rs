@@ -0,0 +1,156 @@
+use std::{
+  collections::HashMap,
+  thread,
+  sync::{Arc, Mutex, atomic::{self, AtomicBool}, mpsc}
+};
+
+use log::{debug, info, warn};
+use serde::{Serialize, Deserialize};
+use futures::future::{BoxFuture, FutureExt, Shared};
+
+// Where proof of work is generated, which are tried in the configured order until one succeeds
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum NanoWorkSource {
+  // Grinding on every core of this machine
+  Local,
+  // The node's work_generate RPC, which may itself use a work peer or GPU
+  Node,
+  // A work server, such as nano-work-server, at the specified URL, which offers the same RPC
+  Remote(String)
+}
+
+pub fn default_work_sources() -> Vec<NanoWorkSource> {
+  vec![NanoWorkSource::Local]
+}
+
+fn compute_work(root: [u8; 32], threshold: u64) -> u64 {
+  let (send, recv) = mpsc::channel();
+  let running = Arc::new(AtomicBool::new(true));
+  let thread_count = num_cpus::get();
+  for i in 0..thread_count {
+    let send = send.clone();
+    let running = running.clone();
+    thread::spawn(move || {
+      let mut nonce = (u64::MAX / thread_count as u64) * i as u64;
+      while nanocurrency_types::work_value(&root, nonce) < threshold &&
+        running.load(atomic::Ordering::Relaxed)
+      {
+        nonce += 1;
+      }
+      let _ = send.send(nonce);
+    });
+  }
+  let nonce = recv.recv().expect("Work computation threads died");
+  running.store(false, atomic::Ordering::Relaxed);
+  nonce
+}
+
+/*
+  Generates work from the configured sources, falling back to the next whenever one fails
+  Work can be started early via precompute, with generate then waiting on it instead of starting over
+*/
+#[derive(Clone)]
+pub struct NanoWork {
+  sources: Vec<NanoWorkSource>,
+  client: reqwest::Client,
+  rpc_url: String,
+  precomputed: Arc<Mutex<HashMap<[u8; 32], Shared<BoxFuture<'static, Option<u64>>>>>>
+}
+
+impl NanoWork {
+  pub fn new(sources: Vec<NanoWorkSource>, rpc_url: String) -> anyhow::Result<NanoWork> {
+    if sources.len() == 0 {
+      anyhow::bail!("No Nano work sources were configured");
+    }
+    Ok(NanoWork {
+      sources,
+      client: reqwest::Client::new(),
+      rpc_url,
+      precomputed: Arc::new(Mutex::new(HashMap::new()))
+    })
+  }
+
+  async fn work_generate(&self, url: &str, root: [u8; 32], threshold: u64) -> anyhow::Result<u64> {
+    #[derive(Serialize, Debug)]
+    struct WorkRequest<'a> {
+      action: &'a str,
+      hash: String,
+      difficulty: String
+    }
+    #[derive(Deserialize, Debug)]
+    #[serde(untagged)]
+    enum WorkResponse {
+      Error {
+        error: String
+      },
+      Work {
+        work: String
+      }
+    }
+
+    let response: WorkResponse = self.client.post(url).json(&WorkRequest {
+      action: "work_generate",
+      hash: hex::encode_upper(&root),
+      difficulty: format!("{:016x}", threshold)
+    }).send().await?.json().await?;
+    match response {
+      WorkResponse::Error { error } => anyhow::bail!("Work generation failed: {}", error),
+      WorkResponse::Work { work } => Ok(u64::from_str_radix(&work, 16)?)
+    }
+  }
+
+  async fn generate_from(&self, source: &NanoWorkSource, root: [u8; 32], threshold: u64) -> anyhow::Result<u64> {
+    match source {
+      // Grinding blocks, so it's moved off the async executor
+      NanoWorkSource::Local => Ok(tokio::task::spawn_blocking(move || compute_work(root, threshold)).await?),
+      NanoWorkSource::Node => self.work_generate(&self.rpc_url, root, threshold).await,
+      NanoWorkSource::Remote(url) => self.work_generate(url, root, threshold).await
+    }
+  }
+
+  async fn generate_uncached(self, root: [u8; 32], threshold: u64) -> Option<u64> {
+    info!("Generating Nano proof of work for root {} with threshold 0x{:016x}", hex::encode(&root), threshold);
+    for source in &self.sources {
+      match self.generate_from(source, root, threshold).await {
+        // Work from elsewhere is checked, so a faulty server is just skipped
+        Ok(work) if nanocurrency_types::work_value(&root, work) >= threshold => {
+          debug!("Generated Nano proof of work nonce {:016x} for root {} via {:?}", work, hex::encode(&root), source);
+          return Some(work);
+        },
+        Ok(work) => warn!("{:?} returned insufficient Nano proof of work {:016x}", source, work),
+        Err(e) => warn!("Couldn't generate Nano proof of work via {:?}: {}", source, e)
+      }
+    }
+    None
+  }
+
+  // Starts generating work for the root in the background, if it isn't already being generated
+  pub fn precompute(&self, root: [u8; 32], threshold: u64) {
+    let mut precomputed = self.precomputed.lock().unwrap();
+    if !precomputed.contains_key(&root) {
+      precomputed.insert(
+        root,
+        tokio::spawn(self.clone().generate_uncached(root, threshold)).map(|work| work.ok().flatten()).boxed().shared()
+      );
+    }
+  }
+
+  /*
+    Returns work for the root, using precomputed work if it exists
+    If the threshold rose since the work was precomputed, and it no longer suffices, new work is generated
+  */
+  pub async fn generate(&self, root: [u8; 32], threshold: u64) -> anyhow::Result<u64> {
+    let precomputed = self.precomputed.lock().unwrap().get(&root).cloned();
+    if let Some(precomputed) = precomputed {
+      if let Some(work) = precomputed.await {
+        if nanocurrency_types::work_value(&root, work) >= threshold {
+          return Ok(work);
+        }
+      }
+    }
+
+    self.clone().generate_uncached(root, threshold).await
+      .ok_or_else(|| anyhow::anyhow!("Every Nano work source failed to generate work"))
+  }
+}
@@ -0,0 +1,345 @@
+use lazy_static::lazy_static;
+
+use rand::rngs::OsRng;
+
+use curve25519_dalek::{
+  constants::ED25519_BASEPOINT_POINT,
+  traits::VartimeMultiscalarMul,
+  scalar::Scalar,
+  edwards::EdwardsPoint
+};
+
+use crate::coins::xmr::{
+  engine::{C, encode_varint},
+  crypto::{INV_EIGHT, keccak, hash_to_scalar, hash_to_point}
+};
+
+// Bits per value, and the most values one proof may cover
+const N: usize = 64;
+const MAX_M: usize = 16;
+
+lazy_static! {
+  // Derived from H, alternating between H and G generators, as Monero does
+  static ref GENERATORS: (Vec<EdwardsPoint>, Vec<EdwardsPoint>) = {
+    let generator = |i: usize| {
+      let mut to_hash = C.compress().to_bytes().to_vec();
+      to_hash.extend(b"bulletproof_plus");
+      to_hash.extend(encode_varint(i as u64));
+      hash_to_point(&keccak(&to_hash))
+    };
+    let mut g = Vec::with_capacity(N * MAX_M);
+    let mut h = Vec::with_capacity(N * MAX_M);
+    for i in 0 .. (N * MAX_M) {
+      h.push(generator(i * 2));
+      g.push(generator((i * 2) + 1));
+    }
+    (g, h)
+  };
+
+  // The transcript starts as a point, which isn't a valid scalar, so it's kept as bytes
+  static ref TRANSCRIPT: [u8; 32] = hash_to_point(&keccak(b"bulletproof_plus_transcript")).compress().to_bytes();
+}
+
+/*
+  A range proof, in Monero's Bulletproofs+ format, as used by transactions since v15
+  Every point is stored divided by the cofactor
+*/
+#[allow(non_snake_case)]
+pub struct BulletproofPlus {
+  pub V: Vec<EdwardsPoint>,
+  pub A: EdwardsPoint,
+  pub A1: EdwardsPoint,
+  pub B: EdwardsPoint,
+  pub r1: Scalar,
+  pub s1: Scalar,
+  pub d1: Scalar,
+  pub L: Vec<EdwardsPoint>,
+  pub R: Vec<EdwardsPoint>
+}
+
+// The transcript hashes itself, followed by the new elements, with the result becoming the new transcript
+fn transcript_update(transcript: &mut [u8; 32], elements: &[[u8; 32]]) -> Scalar {
+  let mut to_hash = transcript.to_vec();
+  for element in elements {
+    to_hash.extend(element);
+  }
+  let result = hash_to_scalar(&to_hash);
+  *transcript = result.to_bytes();
+  result
+}
+
+#[allow(non_snake_case)]
+fn initial_transcript(V: &[EdwardsPoint]) -> [u8; 32] {
+  let mut transcript = *TRANSCRIPT;
+  let V = V.iter().flat_map(|V| V.compress().to_bytes().to_vec()).collect::<Vec<_>>();
+  transcript_update(&mut transcript, &[hash_to_scalar(&V).to_bytes()]);
+  transcript
+}
+
+fn powers(x: &Scalar, len: usize) -> Vec<Scalar> {
+  let mut result = Vec::with_capacity(len);
+  let mut current = Scalar::one();
+  for _ in 0 .. len {
+    result.push(current);
+    current *= x;
+  }
+  result
+}
+
+// The inner product, with the i-th term weighted by y^(i + 1)
+fn weighted_inner_product(a: &[Scalar], b: &[Scalar], y_powers: &[Scalar]) -> Scalar {
+  a.iter().zip(b).zip(&y_powers[1 ..]).map(|((a, b), y)| a * b * y).sum()
+}
+
+fn multiexp(scalars: &[Scalar], points: &[EdwardsPoint]) -> EdwardsPoint {
+  EdwardsPoint::vartime_multiscalar_mul(scalars, points)
+}
+
+#[allow(non_snake_case)]
+fn proof_size(values: usize) -> (usize, usize) {
+  let mut M = 1;
+  let mut logM = 0;
+  while M < values {
+    M *= 2;
+    logM += 1;
+  }
+  (M, logM)
+}
+
+// The 2^i terms, each weighted by z to the power of twice one more than the index of the value they're for
+#[allow(non_snake_case)]
+fn d_terms(z: &Scalar, M: usize) -> Vec<Scalar> {
+  let z_powers = powers(z, (2 * M) + 1);
+  let two_powers = powers(&Scalar::from(2u8), N);
+  (0 .. (M * N)).map(|i| z_powers[2 * ((i / N) + 1)] * two_powers[i % N]).collect()
+}
+
+impl BulletproofPlus {
+  #[allow(non_snake_case)]
+  pub fn prove(values: &[u64], masks: &[Scalar]) -> BulletproofPlus {
+    assert_eq!(values.len(), masks.len());
+    assert!((values.len() != 0) && (values.len() <= MAX_M), "Invalid amount of values to prove");
+
+    let (M, _) = proof_size(values.len());
+    let MN = M * N;
+    let (Gi, Hi) = (&GENERATORS.0[.. MN], &GENERATORS.1[.. MN]);
+    let G = ED25519_BASEPOINT_POINT;
+    let H = *C;
+
+    let V = values.iter().zip(masks).map(
+      |(value, mask)| ((mask * *INV_EIGHT) * G) + ((Scalar::from(*value) * *INV_EIGHT) * H)
+    ).collect::<Vec<_>>();
+
+    let mut aL = vec![Scalar::zero(); MN];
+    let mut aR = vec![-Scalar::one(); MN];
+    for (j, value) in values.iter().enumerate() {
+      for i in 0 .. N {
+        if ((value >> i) & 1) == 1 {
+          aL[(j * N) + i] = Scalar::one();
+          aR[(j * N) + i] = Scalar::zero();
+        }
+      }
+    }
+
+    loop {
+      let mut transcript = initial_transcript(&V);
+
+      let alpha = Scalar::random(&mut OsRng);
+      let A = (multiexp(&aL, Gi) + multiexp(&aR, Hi) + (alpha * G)) * *INV_EIGHT;
+
+      let y = transcript_update(&mut transcript, &[A.compress().to_bytes()]);
+      if y == Scalar::zero() {
+        continue;
+      }
+      let z = hash_to_scalar(&y.to_bytes());
+      transcript = z.to_bytes();
+      if z == Scalar::zero() {
+        continue;
+      }
+
+      let y_powers = powers(&y, MN + 2);
+      let z_powers = powers(&z, (2 * M) + 1);
+      let d = d_terms(&z, M);
+
+      // The weighted inner product argument's witness, which A is offset to commit to
+      let mut a_prime = aL.iter().map(|aL| aL - z).collect::<Vec<_>>();
+      let mut b_prime = (0 .. MN).map(|i| aR[i] + z + (d[i] * y_powers[MN - i])).collect::<Vec<_>>();
+      let mut alpha1 = alpha;
+      for (j, mask) in masks.iter().enumerate() {
+        alpha1 += z_powers[2 * (j + 1)] * y_powers[MN + 1] * mask;
+      }
+
+      let mut G_prime = Gi.to_vec();
+      let mut H_prime = Hi.to_vec();
+
+      let mut L = vec![];
+      let mut R = vec![];
+      let mut valid = true;
+      let mut n_prime = MN;
+      while n_prime > 1 {
+        n_prime /= 2;
+        let y_n_prime = y_powers[n_prime];
+        let y_inv_n_prime = y_n_prime.invert();
+
+        let a_R_scaled = a_prime[n_prime ..].iter().map(|a| a * y_n_prime).collect::<Vec<_>>();
+        let cL = weighted_inner_product(&a_prime[.. n_prime], &b_prime[n_prime ..], &y_powers);
+        let cR = weighted_inner_product(&a_R_scaled, &b_prime[.. n_prime], &y_powers);
+        let dL = Scalar::random(&mut OsRng);
+        let dR = Scalar::random(&mut OsRng);
+
+        let mut scalars = a_prime[.. n_prime].iter().map(|a| a * y_inv_n_prime).collect::<Vec<_>>();
+        scalars.extend(&b_prime[n_prime ..]);
+        scalars.extend(&[cL, dL]);
+        let mut points = G_prime[n_prime ..].to_vec();
+        points.extend(&H_prime[.. n_prime]);
+        points.extend(&[H, G]);
+        L.push(multiexp(&scalars, &points) * *INV_EIGHT);
+
+        let mut scalars = a_R_scaled;
+        scalars.extend(&b_prime[.. n_prime]);
+        scalars.extend(&[cR, dR]);
+        let mut points = G_prime[.. n_prime].to_vec();
+        points.extend(&H_prime[n_prime ..]);
+        points.extend(&[H, G]);
+        R.push(multiexp(&scalars, &points) * *INV_EIGHT);
+
+        let e = transcript_update(
+          &mut transcript,
+          &[L[L.len() - 1].compress().to_bytes(), R[R.len() - 1].compress().to_bytes()]
+        );
+        if e == Scalar::zero() {
+          valid = false;
+          break;
+        }
+        let e_inv = e.invert();
+
+        G_prime = (0 .. n_prime).map(|i| (G_prime[i] * e_inv) + (G_prime[n_prime + i] * (e * y_inv_n_prime))).collect();
+        H_prime = (0 .. n_prime).map(|i| (H_prime[i] * e) + (H_prime[n_prime + i] * e_inv)).collect();
+        a_prime = (0 .. n_prime).map(|i| (a_prime[i] * e) + (a_prime[n_prime + i] * e_inv * y_n_prime)).collect();
+        b_prime = (0 .. n_prime).map(|i| (b_prime[i] * e_inv) + (b_prime[n_prime + i] * e)).collect();
+        alpha1 += (dL * e * e) + (dR * e_inv * e_inv);
+      }
+      if !valid {
+        continue;
+      }
+
+      let r = Scalar::random(&mut OsRng);
+      let s = Scalar::random(&mut OsRng);
+      let d = Scalar::random(&mut OsRng);
+      let eta = Scalar::random(&mut OsRng);
+      let A1 = multiexp(
+        &[r, s, d, (r * y * b_prime[0]) + (s * y * a_prime[0])],
+        &[G_prime[0], H_prime[0], G, H]
+      ) * *INV_EIGHT;
+      let B = ((eta * G) + ((r * y * s) * H)) * *INV_EIGHT;
+
+      let e = transcript_update(&mut transcript, &[A1.compress().to_bytes(), B.compress().to_bytes()]);
+      if e == Scalar::zero() {
+        continue;
+      }
+
+      return BulletproofPlus {
+        V,
+        A,
+        A1,
+        B,
+        r1: r + (a_prime[0] * e),
+        s1: s + (b_prime[0] * e),
+        d1: eta + (d * e) + (alpha1 * e * e),
+        L,
+        R
+      };
+    }
+  }
+
+  // The proof's elements, whose hash is part of the message transactions sign
+  pub fn signature_data(&self) -> Vec<u8> {
+    let mut data = vec![];
+    for point in &[self.A, self.A1, self.B] {
+      data.extend(point.compress().as_bytes());
+    }
+    data.extend(self.r1.as_bytes());
+    data.extend(self.s1.as_bytes());
+    data.extend(self.d1.as_bytes());
+    for point in self.L.iter().chain(&self.R) {
+      data.extend(point.compress().as_bytes());
+    }
+    data
+  }
+
+  pub fn serialize(&self) -> Vec<u8> {
+    let mut data = vec![];
+    for point in &[self.A, self.A1, self.B] {
+      data.extend(point.compress().as_bytes());
+    }
+    data.extend(self.r1.as_bytes());
+    data.extend(self.s1.as_bytes());
+    data.extend(self.d1.as_bytes());
+    for points in &[&self.L, &self.R] {
+      data.extend(encode_varint(points.len() as u64));
+      for point in points.iter() {
+        data.extend(point.compress().as_bytes());
+      }
+    }
+    data
+  }
+
+  // A direct, unbatched, verification, only used to test proofs against
+  #[cfg(test)]
+  #[allow(non_snake_case)]
+  pub fn verify(&self) -> bool {
+    let (M, logM) = proof_size(self.V.len());
+    let MN = M * N;
+    if (self.V.len() > MAX_M) || (self.L.len() != (logM + 6)) || (self.R.len() != self.L.len()) {
+      return false;
+    }
+    let (Gi, Hi) = (&GENERATORS.0[.. MN], &GENERATORS.1[.. MN]);
+    let G = ED25519_BASEPOINT_POINT;
+    let H = *C;
+
+    let mut transcript = initial_transcript(&self.V);
+    let y = transcript_update(&mut transcript, &[self.A.compress().to_bytes()]);
+    let z = hash_to_scalar(&y.to_bytes());
+    transcript = z.to_bytes();
+    let e = self.L.iter().zip(&self.R).map(
+      |(L, R)| transcript_update(&mut transcript, &[L.compress().to_bytes(), R.compress().to_bytes()])
+    ).collect::<Vec<_>>();
+    let e_final = transcript_update(&mut transcript, &[self.A1.compress().to_bytes(), self.B.compress().to_bytes()]);
+
+    let y_powers = powers(&y, MN + 2);
+    let z_powers = powers(&z, (2 * M) + 1);
+    let d = d_terms(&z, M);
+
+    // The commitment to the weighted inner product argument's witness, derived from A and the value commitments
+    let sum_y: Scalar = y_powers[1 ..= MN].iter().sum();
+    let sum_d: Scalar = d.iter().sum();
+    let mut P = self.A.mul_by_cofactor() + ((((z - (z * z)) * sum_y) - (z * y_powers[MN + 1] * sum_d)) * H);
+    for i in 0 .. MN {
+      P -= z * Gi[i];
+      P += (z + (d[i] * y_powers[MN - i])) * Hi[i];
+    }
+    for (j, V) in self.V.iter().enumerate() {
+      P += (z_powers[2 * (j + 1)] * y_powers[MN + 1]) * V.mul_by_cofactor();
+    }
+
+    let mut G_prime = Gi.to_vec();
+    let mut H_prime = Hi.to_vec();
+    let mut n_prime = MN;
+    for (round, e) in e.iter().enumerate() {
+      n_prime /= 2;
+      let e_inv = e.invert();
+      let y_inv_n_prime = y_powers[n_prime].invert();
+      P += ((e * e) * self.L[round].mul_by_cofactor()) + ((e_inv * e_inv) * self.R[round].mul_by_cofactor());
+      G_prime = (0 .. n_prime).map(|i| (G_prime[i] * e_inv) + (G_prime[n_prime + i] * (e * y_inv_n_prime))).collect();
+      H_prime = (0 .. n_prime).map(|i| (H_prime[i] * e) + (H_prime[n_prime + i] * e_inv)).collect();
+    }
+
+    let e = e_final;
+    (((e * e) * P) + (e * self.A1.mul_by_cofactor()) + self.B.mul_by_cofactor()) == (
+      ((self.r1 * e) * G_prime[0]) +
+      ((self.s1 * e) * H_prime[0]) +
+      ((self.r1 * y * self.s1) * H) +
+      (self.d1 * G)
+    )
+  }
+}
@@ -8,11 +8,10 @@ use std::{
 
 use async_trait::async_trait;
 
+use serde::{Serialize, Deserialize};
+
 #[allow(unused_imports)]
-use monero::util::{
-  key::{PrivateKey, PublicKey, ViewPair},
-  address::Address
-};
+use monero::util::key::{PrivateKey, PublicKey, ViewPair};
 
 use crate::{
   crypt_engines::{KeyBundle, CryptEngine, ed25519_engine::Ed25519Sha},
@@ -22,6 +21,13 @@ use crate::{
   }
 };
 
+#[derive(Serialize, Deserialize)]
+struct XmrClientState {
+  engine: XmrEngineState,
+  address: Option<String>,
+  deposited: bool
+}
+
 pub struct XmrClient {
   engine: XmrEngine,
   #[cfg(test)]
@@ -31,10 +37,11 @@ pub struct XmrClient {
 }
 
 impl XmrClient {
-  pub async fn new(config_path: &Path) -> anyhow::Result<XmrClient> {
+  pub async fn new(config_path: &Path, chain: XmrChain) -> anyhow::Result<XmrClient> {
     Ok(XmrClient {
       engine: XmrEngine::new(
-        serde_json::from_reader(File::open(config_path)?)?
+        serde_json::from_reader(File::open(config_path)?)?,
+        chain
       ).await?,
       #[cfg(test)]
       refund_pair: None,
@@ -46,6 +53,28 @@ impl XmrClient {
 
 #[async_trait]
 impl UnscriptedClient for XmrClient {
+  fn serialize_state(&self) -> Vec<u8> {
+    bincode::serialize(
+      &XmrClientState {
+        engine: self.engine.state(),
+        address: self.address.clone(),
+        deposited: self.deposited
+      }
+    ).expect("Couldn't serialize the Monero client's state")
+  }
+
+  fn restore_state(&mut self, state: &[u8]) -> anyhow::Result<()> {
+    let state: XmrClientState = bincode::deserialize(state)?;
+    self.engine.restore_state(state.engine);
+    self.address = state.address;
+    self.deposited = state.deposited;
+    Ok(())
+  }
+
+  fn settlement_seconds(&self) -> u64 {
+    self.engine.params.chain.settlement_seconds()
+  }
+
   fn generate_keys<Verifier: ScriptedVerifier>(&mut self, verifier: &mut Verifier) -> Vec<u8> {
     let (dl_eq, key) = verifier.generate_keys_for_engine::<Ed25519Sha>(PhantomData);
     self.engine.k = Some(key);
@@ -75,13 +104,13 @@ impl UnscriptedClient for XmrClient {
   }
 
   fn get_address(&mut self) -> String {
-    let address = Address::from_viewpair(NETWORK, &self.engine.get_view_pair()).to_string();
+    let address = self.engine.params.address_from_view_pair(&self.engine.get_view_pair());
     self.address = Some(address.clone());
     address
   }
 
   async fn wait_for_deposit(&mut self) -> anyhow::Result<()> {
-    self.engine.get_deposit(&self.engine.get_view_pair(), true).await?.unwrap();
+    self.engine.wait_for_deposits(&self.engine.get_view_pair(), |_| true).await?;
     self.deposited = true;
     Ok(())
   }
@@ -110,7 +139,7 @@ impl UnscriptedClient for XmrClient {
         point: Ed25519Sha::to_public_key(&Ed25519Sha::new_private_key()).compress()
       }
     });
-    self.engine.config.refund = Address::from_viewpair(NETWORK, self.refund_pair.as_ref().unwrap()).to_string();
+    self.engine.config.refund = self.engine.params.address_from_view_pair(self.refund_pair.as_ref().unwrap());
   }
 
   #[cfg(test)]
@@ -139,6 +168,6 @@ impl UnscriptedClient for XmrClient {
         point: Ed25519Sha::bytes_to_public_key(pair[32..].try_into().unwrap()).unwrap().compress()
       },
     };
-    self.engine.get_deposit(&pair, false).await.expect("Couldn't get if a Transaction to a ViewPair exists").is_some()
+    !self.engine.get_deposits(&pair).await.expect("Couldn't get if a Transaction to a ViewPair exists").is_empty()
   }
 }
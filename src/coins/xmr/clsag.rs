@@ -0,0 +1,169 @@
+use rand::rngs::OsRng;
+
+use curve25519_dalek::{
+  constants::{ED25519_BASEPOINT_TABLE, ED25519_BASEPOINT_POINT},
+  traits::VartimeMultiscalarMul,
+  scalar::Scalar,
+  edwards::EdwardsPoint
+};
+
+use crate::coins::xmr::crypto::{INV_EIGHT, hash_to_scalar, hash_to_point};
+
+// A CLSAG ring signature, as used by every input of a transaction, with the key image kept in the prefix
+#[allow(non_snake_case)]
+pub struct Clsag {
+  pub s: Vec<Scalar>,
+  pub c1: Scalar,
+  // Stored divided by the cofactor
+  pub D: EdwardsPoint
+}
+
+// Domain separators are zero padded to the length of a key
+fn domain(tag: &[u8]) -> Vec<u8> {
+  let mut result = tag.to_vec();
+  result.resize(32, 0);
+  result
+}
+
+/*
+  The ring is a list of (one-time key, commitment) pairs
+  Commitments are offset by the pseudo output, which is what the signature proves the difference of
+*/
+#[allow(non_snake_case)]
+fn aggregation_coefficients(
+  ring: &[[EdwardsPoint; 2]],
+  I: &EdwardsPoint,
+  D: &EdwardsPoint,
+  pseudo_out: &EdwardsPoint
+) -> (Scalar, Scalar) {
+  let mut to_hash = domain(b"CLSAG_agg_0");
+  for i in 0 .. 2 {
+    for member in ring {
+      to_hash.extend(member[i].compress().as_bytes());
+    }
+  }
+  for point in &[I, D, pseudo_out] {
+    to_hash.extend(point.compress().as_bytes());
+  }
+  let mu_P = hash_to_scalar(&to_hash);
+  to_hash[.. 32].copy_from_slice(&domain(b"CLSAG_agg_1"));
+  (mu_P, hash_to_scalar(&to_hash))
+}
+
+// Everything hashed for each round's challenge besides that round's L and R
+fn round_prefix(ring: &[[EdwardsPoint; 2]], pseudo_out: &EdwardsPoint, message: &[u8; 32]) -> Vec<u8> {
+  let mut result = domain(b"CLSAG_round");
+  for i in 0 .. 2 {
+    for member in ring {
+      result.extend(member[i].compress().as_bytes());
+    }
+  }
+  result.extend(pseudo_out.compress().as_bytes());
+  result.extend(message);
+  result
+}
+
+#[allow(non_snake_case)]
+fn challenge(prefix: &[u8], L: &EdwardsPoint, R: &EdwardsPoint) -> Scalar {
+  let mut to_hash = prefix.to_vec();
+  to_hash.extend(L.compress().as_bytes());
+  to_hash.extend(R.compress().as_bytes());
+  hash_to_scalar(&to_hash)
+}
+
+/*
+  Advances the ring by one member, returning the next challenge
+  mu_P and mu_C are passed already multiplied by the current challenge
+*/
+#[allow(non_snake_case)]
+fn round(
+  member: &[EdwardsPoint; 2],
+  pseudo_out: &EdwardsPoint,
+  I: &EdwardsPoint,
+  D: &EdwardsPoint,
+  s: &Scalar,
+  c_mu: [Scalar; 2],
+  prefix: &[u8]
+) -> Scalar {
+  let scalars = [*s, c_mu[0], c_mu[1]];
+  let L = EdwardsPoint::vartime_multiscalar_mul(&scalars, &[ED25519_BASEPOINT_POINT, member[0], member[1] - pseudo_out]);
+  let R = EdwardsPoint::vartime_multiscalar_mul(&scalars, &[hash_to_point(member[0].compress().as_bytes()), *I, *D]);
+  challenge(prefix, &L, &R)
+}
+
+/*
+  Signs for the ring member at the specified index
+  key is the one-time private key, and mask and pseudo_mask are the blinding factors of its commitment and the pseudo output
+*/
+#[allow(non_snake_case)]
+pub fn sign(
+  message: &[u8; 32],
+  ring: &[[EdwardsPoint; 2]],
+  index: usize,
+  key: &Scalar,
+  mask: &Scalar,
+  pseudo_out: &EdwardsPoint,
+  pseudo_mask: &Scalar
+) -> Clsag {
+  assert!(index < ring.len(), "Signing for a ring member which doesn't exist");
+  let n = ring.len();
+  let H = hash_to_point(ring[index][0].compress().as_bytes());
+  let I = key * H;
+  let z = mask - pseudo_mask;
+  let D = z * H;
+
+  let D_stored = D * *INV_EIGHT;
+  let (mu_P, mu_C) = aggregation_coefficients(ring, &I, &D_stored, pseudo_out);
+  let prefix = round_prefix(ring, pseudo_out, message);
+
+  let a = Scalar::random(&mut OsRng);
+  let mut c = challenge(&prefix, &(&a * &ED25519_BASEPOINT_TABLE), &(a * H));
+
+  let mut s = (0 .. n).map(|_| Scalar::random(&mut OsRng)).collect::<Vec<_>>();
+  // c1 is the challenge used by the first member, which may be this initial challenge
+  let mut c1 = c;
+  let mut i = (index + 1) % n;
+  while i != index {
+    c = round(&ring[i], pseudo_out, &I, &D, &s[i], [c * mu_P, c * mu_C], &prefix);
+    i = (i + 1) % n;
+    if i == 0 {
+      c1 = c;
+    }
+  }
+  s[index] = a - (c * ((mu_P * key) + (mu_C * z)));
+
+  Clsag {
+    s,
+    c1,
+    D: D_stored
+  }
+}
+
+impl Clsag {
+  pub fn serialize(&self) -> Vec<u8> {
+    let mut result = Vec::with_capacity((self.s.len() + 2) * 32);
+    for s in &self.s {
+      result.extend(s.as_bytes());
+    }
+    result.extend(self.c1.as_bytes());
+    result.extend(self.D.compress().as_bytes());
+    result
+  }
+
+  #[cfg(test)]
+  #[allow(non_snake_case)]
+  pub fn verify(&self, message: &[u8; 32], ring: &[[EdwardsPoint; 2]], I: &EdwardsPoint, pseudo_out: &EdwardsPoint) -> bool {
+    if self.s.len() != ring.len() {
+      return false;
+    }
+    let (mu_P, mu_C) = aggregation_coefficients(ring, I, &self.D, pseudo_out);
+    let prefix = round_prefix(ring, pseudo_out, message);
+    let D = self.D.mul_by_cofactor();
+
+    let mut c = self.c1;
+    for (member, s) in ring.iter().zip(&self.s) {
+      c = round(member, pseudo_out, I, &D, s, [c * mu_P, c * mu_C], &prefix);
+    }
+    c == self.c1
+  }
+}
@@ -0,0 +1,313 @@
+use lazy_static::lazy_static;
+
+use curve25519_dalek::{
+  constants::ED25519_BASEPOINT_TABLE,
+  scalar::Scalar,
+  edwards::{EdwardsPoint, CompressedEdwardsY}
+};
+
+use monero::cryptonote::hash::Hash;
+
+use crate::coins::xmr::engine::{C, encode_varint};
+
+/*
+  Monero's hash to point isn't Elligator 2 as curve25519-dalek offers, yet its own map
+  That needs field arithmetic dalek doesn't expose, so a minimal, variable time, implementation is here
+  Everything it's used on is public, so being variable time is fine
+*/
+#[derive(Clone, Copy, Debug)]
+struct FieldElement([u64; 4]);
+
+const P: [u64; 4] = [0xffffffffffffffed, 0xffffffffffffffff, 0xffffffffffffffff, 0x7fffffffffffffff];
+
+impl FieldElement {
+  fn from_u64(value: u64) -> FieldElement {
+    FieldElement([value, 0, 0, 0])
+  }
+
+  // Unlike most encodings, the top bit isn't ignored, with the full 256-bit value being reduced, as Monero does
+  fn from_bytes(bytes: &[u8; 32]) -> FieldElement {
+    let mut limbs = [0; 4];
+    for (i, limb) in limbs.iter_mut().enumerate() {
+      let mut le = [0; 8];
+      le.copy_from_slice(&bytes[(i * 8) .. ((i + 1) * 8)]);
+      *limb = u64::from_le_bytes(le);
+    }
+    FieldElement(limbs)
+  }
+
+  fn to_bytes(self) -> [u8; 32] {
+    let mut bytes = [0; 32];
+    for (i, limb) in self.reduce().0.iter().enumerate() {
+      bytes[(i * 8) .. ((i + 1) * 8)].copy_from_slice(&limb.to_le_bytes());
+    }
+    bytes
+  }
+
+  // Limbs are kept below 2^256, yet may be above the modulus until this is called
+  fn reduce(self) -> FieldElement {
+    let mut value = self.0;
+    loop {
+      let mut less = false;
+      for i in (0 .. 4).rev() {
+        if value[i] != P[i] {
+          less = value[i] < P[i];
+          break;
+        }
+      }
+      if less {
+        return FieldElement(value);
+      }
+
+      let mut borrow = 0;
+      for i in 0 .. 4 {
+        let (diff, borrow1) = value[i].overflowing_sub(P[i]);
+        let (diff, borrow2) = diff.overflowing_sub(borrow);
+        value[i] = diff;
+        borrow = (borrow1 || borrow2) as u64;
+      }
+    }
+  }
+
+  // 2^256 is 38 modulo the field's modulus, so any carry out of the top limb is folded back in as such
+  fn fold(mut limbs: [u64; 4], mut carry: u64) -> FieldElement {
+    while carry != 0 {
+      let mut acc = (carry as u128) * 38;
+      for limb in limbs.iter_mut() {
+        acc += *limb as u128;
+        *limb = acc as u64;
+        acc >>= 64;
+      }
+      carry = acc as u64;
+    }
+    FieldElement(limbs)
+  }
+
+  fn add(self, other: FieldElement) -> FieldElement {
+    let mut limbs = [0; 4];
+    let mut acc = 0u128;
+    for i in 0 .. 4 {
+      acc += (self.0[i] as u128) + (other.0[i] as u128);
+      limbs[i] = acc as u64;
+      acc >>= 64;
+    }
+    FieldElement::fold(limbs, acc as u64)
+  }
+
+  fn neg(self) -> FieldElement {
+    let value = self.reduce().0;
+    let mut limbs = [0; 4];
+    let mut borrow = 0;
+    for i in 0 .. 4 {
+      let (diff, borrow1) = P[i].overflowing_sub(value[i]);
+      let (diff, borrow2) = diff.overflowing_sub(borrow);
+      limbs[i] = diff;
+      borrow = (borrow1 || borrow2) as u64;
+    }
+    FieldElement(limbs)
+  }
+
+  fn sub(self, other: FieldElement) -> FieldElement {
+    self.add(other.neg())
+  }
+
+  fn mul(self, other: FieldElement) -> FieldElement {
+    let mut wide = [0u64; 8];
+    for i in 0 .. 4 {
+      let mut carry = 0u128;
+      for j in 0 .. 4 {
+        let acc = (wide[i + j] as u128) + ((self.0[i] as u128) * (other.0[j] as u128)) + carry;
+        wide[i + j] = acc as u64;
+        carry = acc >> 64;
+      }
+      wide[i + 4] = carry as u64;
+    }
+
+    let mut limbs = [0; 4];
+    let mut acc = 0u128;
+    for i in 0 .. 4 {
+      acc += (wide[i] as u128) + ((wide[i + 4] as u128) * 38);
+      limbs[i] = acc as u64;
+      acc >>= 64;
+    }
+    FieldElement::fold(limbs, acc as u64)
+  }
+
+  fn square(self) -> FieldElement {
+    self.mul(self)
+  }
+
+  // Exponentiation by a little endian exponent
+  fn pow(self, exponent: [u64; 4]) -> FieldElement {
+    let mut result = FieldElement::from_u64(1);
+    for limb in exponent.iter().rev() {
+      for bit in (0 .. 64).rev() {
+        result = result.square();
+        if ((limb >> bit) & 1) == 1 {
+          result = result.mul(self);
+        }
+      }
+    }
+    result
+  }
+
+  fn is_zero(self) -> bool {
+    self.reduce().0 == [0; 4]
+  }
+
+  fn is_negative(self) -> bool {
+    (self.reduce().0[0] & 1) == 1
+  }
+
+  fn invert(self) -> FieldElement {
+    // p - 2
+    self.pow([0xffffffffffffffeb, 0xffffffffffffffff, 0xffffffffffffffff, 0x7fffffffffffffff])
+  }
+
+  // (u / v)^((p + 3) / 8), computed as u * v^3 * (u * v^7)^((p - 5) / 8)
+  fn div_pow_m1(u: FieldElement, v: FieldElement) -> FieldElement {
+    let v3 = v.square().mul(v);
+    let uv7 = u.mul(v3.square().mul(v));
+    u.mul(v3).mul(uv7.pow([0xfffffffffffffffd, 0xffffffffffffffff, 0xffffffffffffffff, 0x0fffffffffffffff]))
+  }
+
+  // Which of the two roots is returned doesn't matter, as the hash to point fixes the sign of its result
+  fn sqrt(self) -> FieldElement {
+    let candidate = FieldElement::div_pow_m1(self, FieldElement::from_u64(1));
+    if candidate.square().sub(self).is_zero() {
+      candidate
+    } else {
+      let candidate = candidate.mul(*SQRT_M1);
+      assert!(candidate.square().sub(self).is_zero(), "Square root of a non-square");
+      candidate
+    }
+  }
+}
+
+lazy_static! {
+  // 2^((p - 1) / 4)
+  static ref SQRT_M1: FieldElement = FieldElement::from_u64(2).pow(
+    [0xfffffffffffffffb, 0xffffffffffffffff, 0xffffffffffffffff, 0x1fffffffffffffff]
+  );
+  static ref A: FieldElement = FieldElement::from_u64(486662);
+  static ref MA: FieldElement = A.neg();
+  static ref MA2: FieldElement = A.square().neg();
+  // A * (A + 2)
+  static ref A_A2: FieldElement = A.mul(A.add(FieldElement::from_u64(2)));
+  static ref FFFB1: FieldElement = A_A2.add(*A_A2).neg().sqrt();
+  static ref FFFB2: FieldElement = A_A2.add(*A_A2).sqrt();
+  static ref FFFB3: FieldElement = SQRT_M1.neg().mul(*A_A2).sqrt();
+  static ref FFFB4: FieldElement = SQRT_M1.mul(*A_A2).sqrt();
+
+  // Used to store points divided by the cofactor, as Monero does to ensure they're in the prime order subgroup
+  pub static ref INV_EIGHT: Scalar = Scalar::from(8u8).invert();
+}
+
+// ge_fromfe_frombytes_vartime, returning a point which has yet to be multiplied by the cofactor
+fn map_to_point(bytes: &[u8; 32]) -> EdwardsPoint {
+  let one = FieldElement::from_u64(1);
+  let u = FieldElement::from_bytes(bytes);
+  let v = u.square().add(u.square());
+  let w = v.add(one);
+  let mut x = w.square().add(MA2.mul(v));
+  let mut r_x = FieldElement::div_pow_m1(w, x);
+  x = r_x.square().mul(x);
+
+  let mut z = *MA;
+  let sign;
+  if w.sub(x).is_zero() {
+    r_x = r_x.mul(*FFFB2).mul(u);
+    z = z.mul(v);
+    sign = false;
+  } else if w.add(x).is_zero() {
+    r_x = r_x.mul(*FFFB1).mul(u);
+    z = z.mul(v);
+    sign = false;
+  } else {
+    x = x.mul(*SQRT_M1);
+    if w.sub(x).is_zero() {
+      r_x = r_x.mul(*FFFB4);
+    } else {
+      r_x = r_x.mul(*FFFB3);
+    }
+    sign = true;
+  }
+
+  if r_x.is_negative() != sign {
+    r_x = r_x.neg();
+  }
+  let r_z = z.add(w);
+  let r_y = z.sub(w);
+  r_x = r_x.mul(r_z);
+
+  // Convert from projective coordinates to a compressed point for dalek
+  let z_inv = r_z.invert();
+  let mut compressed = r_y.mul(z_inv).to_bytes();
+  if r_x.mul(z_inv).is_negative() {
+    compressed[31] |= 0x80;
+  }
+  CompressedEdwardsY(compressed).decompress().expect("Monero's hash to point produced an invalid point")
+}
+
+pub fn keccak(data: &[u8]) -> [u8; 32] {
+  Hash::hash(data).to_fixed_bytes()
+}
+
+pub fn hash_to_scalar(data: &[u8]) -> Scalar {
+  Scalar::from_bytes_mod_order(keccak(data))
+}
+
+// hash_to_p3, which hashes the data before mapping it and multiplying by the cofactor
+pub fn hash_to_point(data: &[u8]) -> EdwardsPoint {
+  map_to_point(&keccak(data)).mul_by_cofactor()
+}
+
+pub fn key_image(key: &Scalar) -> EdwardsPoint {
+  key * hash_to_point((key * &ED25519_BASEPOINT_TABLE).compress().as_bytes())
+}
+
+pub fn commit(mask: &Scalar, amount: u64) -> EdwardsPoint {
+  (mask * &ED25519_BASEPOINT_TABLE) + (*C * Scalar::from(amount))
+}
+
+// The scalar derived from the ECDH of a transaction key and a view key, for the output with the specified index
+pub fn shared_key(ecdh: &EdwardsPoint, index: u64) -> Scalar {
+  let mut to_hash = ecdh.mul_by_cofactor().compress().to_bytes().to_vec();
+  to_hash.extend(encode_varint(index));
+  hash_to_scalar(&to_hash)
+}
+
+// The first byte of a hash of the ECDH, which is published so recipients can skip outputs without deriving their keys
+pub fn view_tag(ecdh: &EdwardsPoint, index: u64) -> u8 {
+  let mut to_hash = b"view_tag".to_vec();
+  to_hash.extend(ecdh.mul_by_cofactor().compress().as_bytes());
+  to_hash.extend(encode_varint(index));
+  keccak(&to_hash)[0]
+}
+
+pub fn commitment_mask(shared_key: &Scalar) -> Scalar {
+  let mut to_hash = b"commitment_mask".to_vec();
+  to_hash.extend(shared_key.as_bytes());
+  hash_to_scalar(&to_hash)
+}
+
+// Amounts are XORed with a hash of the shared key, making this both the encryption and the decryption
+pub fn xor_amount(shared_key: &Scalar, amount: u64) -> u64 {
+  let mut to_hash = b"amount".to_vec();
+  to_hash.extend(shared_key.as_bytes());
+  let mut key = [0; 8];
+  key.copy_from_slice(&keccak(&to_hash)[.. 8]);
+  u64::from_le_bytes(key) ^ amount
+}
+
+// Payment IDs are XORed with a hash of the ECDH, which isn't scaled to a scalar first unlike the shared key
+pub fn xor_payment_id(ecdh: &EdwardsPoint, payment_id: [u8; 8]) -> [u8; 8] {
+  let mut to_hash = ecdh.mul_by_cofactor().compress().to_bytes().to_vec();
+  to_hash.push(0x8d);
+  let key = keccak(&to_hash);
+  let mut result = payment_id;
+  for (byte, key) in result.iter_mut().zip(key.iter()) {
+    *byte ^= key;
+  }
+  result
+}
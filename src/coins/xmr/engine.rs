@@ -1,31 +1,37 @@
-use std::fmt::Debug;
+use std::{
+  fmt::Debug,
+  convert::TryInto,
+  collections::BTreeMap
+};
 
-use log::debug;
+use log::{debug, info, warn};
 
 use lazy_static::lazy_static;
 use hex_literal::hex;
-use rand::{rngs::OsRng, RngCore};
+use rand::{rngs::OsRng, Rng, RngCore};
 
 use serde::{Serialize, Deserialize, de::DeserializeOwned};
 use serde_json::json;
 
 use reqwest;
+#[cfg(test)]
 use digest_auth::AuthContext;
 
-use monero::{
-  util::{
-    key::{PrivateKey, PublicKey, ViewPair},
-    address::Address
-  },
-  blockdata::{
-    transaction::Transaction,
-    block::Block
-  },
-  consensus::encode::deserialize,
-  network::Network
+use curve25519_dalek::{
+  constants::ED25519_BASEPOINT_TABLE,
+  edwards::{EdwardsPoint, CompressedEdwardsY}
 };
 
-use crate::crypt_engines::{CryptEngine, ed25519_engine::Ed25519Sha};
+use monero::util::key::{PrivateKey, PublicKey, ViewPair};
+
+use crate::{
+  crypt_engines::{CryptEngine, ed25519_engine::Ed25519Sha},
+  coins::xmr::{
+    crypto::commit,
+    transaction::{self, Transaction, OwnedOutput, Input, Payment},
+    tracker::{TrackerEvent, XmrTracker}
+  }
+};
 
 #[cfg(not(feature = "no_confs"))]
 pub const CONFIRMATIONS: isize = 3;
@@ -35,14 +41,11 @@ pub const CONFIRMATIONS: isize = 3;
 #[cfg(feature = "no_confs")]
 pub const CONFIRMATIONS: isize = 1;
 
-// This should NOT be used with the mainnet. It is a Proof of Concept
-// It only uses the mainnet network byte because it's shared with regtest
-pub const NETWORK: Network = Network::Mainnet;
-
 lazy_static! {
   pub static ref C: <Ed25519Sha as CryptEngine>::PublicKey = Ed25519Sha::bytes_to_public_key(&hex!("8b655970153799af2aeadc9ff1add0ea6c7251d54154cfa92c173a0dd39c1f94")).unwrap();
 }
 
+#[cfg(test)]
 #[derive(Deserialize, Debug)]
 struct EmptyResponse {}
 #[derive(Deserialize, Debug)]
@@ -50,11 +53,228 @@ struct JsonRpcResponse<T> {
   result: T
 }
 
+// This should NOT be used with the mainnet. It is a Proof of Concept
+#[derive(Clone, Copy, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum XmrNetwork {
+  Mainnet,
+  Testnet,
+  Stagenet,
+  Regtest
+}
+
+impl Default for XmrNetwork {
+  fn default() -> XmrNetwork {
+    XmrNetwork::Mainnet
+  }
+}
+
+impl XmrNetwork {
+  // The nettype the daemon reports for this network
+  fn nettype(&self) -> &'static str {
+    match self {
+      XmrNetwork::Mainnet => "mainnet",
+      XmrNetwork::Testnet => "testnet",
+      XmrNetwork::Stagenet => "stagenet",
+      XmrNetwork::Regtest => "fakechain"
+    }
+  }
+}
+
+/*
+  The chains supported as the unscripted coin which share Monero's cryptography and transaction format
+  They only differ in their address prefixes, block times, and how long outputs take to unlock
+  Supporting another fork should only require another variant
+*/
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum XmrChain {
+  Monero,
+  Wownero
+}
+
+impl XmrChain {
+  pub fn name(&self) -> &'static str {
+    match self {
+      XmrChain::Monero => "Monero",
+      XmrChain::Wownero => "Wownero"
+    }
+  }
+
+  fn block_seconds(&self) -> u64 {
+    match self {
+      XmrChain::Monero => 120,
+      XmrChain::Wownero => 300
+    }
+  }
+
+  // How many outputs each input's ring has, which is fixed by consensus
+  fn ring_size(&self) -> usize {
+    match self {
+      XmrChain::Monero => 16,
+      XmrChain::Wownero => 22
+    }
+  }
+
+  // How many blocks old an output has to be before it can be spent
+  pub fn spendable_age(&self) -> isize {
+    match self {
+      XmrChain::Monero => 10,
+      XmrChain::Wownero => 4
+    }
+  }
+
+  // Outputs can't be spent until they're old enough, which outlasts the confirmations we wait for
+  pub fn settlement_seconds(&self) -> u64 {
+    (self.spendable_age() as u64) * self.block_seconds()
+  }
+
+  // One whole coin, in atomic units
+  #[cfg(test)]
+  pub fn coin(&self) -> u64 {
+    match self {
+      XmrChain::Monero => 1_000_000_000_000,
+      XmrChain::Wownero => 100_000_000_000
+    }
+  }
+
+  // How many blocks old a miner's reward has to be before it can be spent
+  #[cfg(test)]
+  pub fn coinbase_maturity(&self) -> isize {
+    match self {
+      XmrChain::Monero => 60,
+      XmrChain::Wownero => 288
+    }
+  }
+}
+
+pub fn encode_varint(mut value: u64) -> Vec<u8> {
+  let mut result = Vec::new();
+  while value >= 0x80 {
+    result.push(((value & 0x7f) as u8) | 0x80);
+    value >>= 7;
+  }
+  result.push(value as u8);
+  result
+}
+
+// Returns the value and how many bytes it took
+pub fn decode_varint(bytes: &[u8]) -> Option<(u64, usize)> {
+  let mut value = 0;
+  for (i, byte) in bytes.iter().enumerate().take(9) {
+    value |= ((byte & 0x7f) as u64) << (7 * i);
+    if byte & 0x80 == 0 {
+      return Some((value, i + 1));
+    }
+  }
+  None
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum XmrAddressKind {
+  Standard,
+  // Addresses with an 8-byte payment ID, which is encrypted into the transaction sent to them
+  Integrated([u8; 8]),
+  // Addresses derived from a wallet's keys, which are sent to with a transaction key using their spend key as the base
+  Subaddress
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct XmrAddress {
+  pub spend: EdwardsPoint,
+  pub view: EdwardsPoint,
+  pub kind: XmrAddressKind
+}
+
+// A chain and the network of it in use, which decide how addresses are encoded
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct XmrParams {
+  pub chain: XmrChain,
+  pub network: XmrNetwork
+}
+
+impl XmrParams {
+  // The standard, integrated, and subaddress prefixes, where regtest shares the mainnet's
+  fn prefixes(&self) -> [u64; 3] {
+    match (self.chain, self.network) {
+      (XmrChain::Monero, XmrNetwork::Mainnet) | (XmrChain::Monero, XmrNetwork::Regtest) => [18, 19, 42],
+      (XmrChain::Wownero, XmrNetwork::Mainnet) | (XmrChain::Wownero, XmrNetwork::Regtest) => [4146, 6810, 12208],
+      // Wownero kept Monero's prefixes for its test networks
+      (_, XmrNetwork::Testnet) => [53, 54, 63],
+      (_, XmrNetwork::Stagenet) => [24, 25, 36]
+    }
+  }
+
+  pub fn encode_address(&self, address: &XmrAddress) -> String {
+    let (prefix, payment_id) = match address.kind {
+      XmrAddressKind::Standard => (self.prefixes()[0], None),
+      XmrAddressKind::Integrated(payment_id) => (self.prefixes()[1], Some(payment_id)),
+      XmrAddressKind::Subaddress => (self.prefixes()[2], None)
+    };
+    let mut bytes = encode_varint(prefix);
+    bytes.extend(address.spend.compress().as_bytes());
+    bytes.extend(address.view.compress().as_bytes());
+    if let Some(payment_id) = payment_id {
+      bytes.extend(&payment_id);
+    }
+    base58_monero::encode_check(&bytes).expect("Couldn't encode an address")
+  }
+
+  pub fn address_from_view_pair(&self, pair: &ViewPair) -> String {
+    self.encode_address(&XmrAddress {
+      spend: pair.spend.point.decompress().expect("View pair had an invalid spend key"),
+      view: &pair.view.scalar * &ED25519_BASEPOINT_TABLE,
+      kind: XmrAddressKind::Standard
+    })
+  }
+
+  pub fn decode_address(&self, address: &str) -> anyhow::Result<XmrAddress> {
+    let invalid = || anyhow::anyhow!("{} isn't a valid {} address", address, self.chain.name());
+    let bytes = base58_monero::decode_check(address).map_err(|_| invalid())?;
+    let (prefix, prefix_len) = decode_varint(&bytes).ok_or_else(invalid)?;
+
+    let kind = match self.prefixes().iter().position(|candidate| *candidate == prefix) {
+      Some(kind) => kind,
+      None => {
+        // Name the network the address is for, as using one for the wrong network is the most likely mistake
+        for network in &[XmrNetwork::Mainnet, XmrNetwork::Testnet, XmrNetwork::Stagenet] {
+          if (XmrParams { chain: self.chain, network: *network }).prefixes().contains(&prefix) {
+            anyhow::bail!("{} is a {:?} address, yet the {:?} network is configured", address, network, self.network);
+          }
+        }
+        return Err(invalid());
+      }
+    };
+
+    // Integrated addresses append their payment ID to the keys
+    let data = &bytes[prefix_len ..];
+    if data.len() != (if kind == 1 { 72 } else { 64 }) {
+      return Err(invalid());
+    }
+    let kind = match kind {
+      0 => XmrAddressKind::Standard,
+      1 => XmrAddressKind::Integrated(data[64 ..].try_into().unwrap()),
+      _ => XmrAddressKind::Subaddress
+    };
+
+    Ok(XmrAddress {
+      spend: CompressedEdwardsY::from_slice(&data[.. 32]).decompress().ok_or_else(invalid)?,
+      view: CompressedEdwardsY::from_slice(&data[32 .. 64]).decompress().ok_or_else(invalid)?,
+      kind
+    })
+  }
+}
+
 #[derive(Clone, Deserialize)]
 pub struct XmrConfig {
+  #[serde(default)]
+  pub network: XmrNetwork,
   daemon: String,
+  // Only used by tests, to fund swaps
+  #[cfg(test)]
   wallet: String,
+  #[cfg(test)]
   wallet_user: String,
+  #[cfg(test)]
   wallet_pass: String,
   pub destination: String,
   pub refund: String
@@ -66,37 +286,163 @@ pub struct XmrKeys {
   pub view_share: [u8; 32]
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct XmrEngineState {
+  k: Option<<Ed25519Sha as CryptEngine>::PrivateKey>,
+  view: <Ed25519Sha as CryptEngine>::PrivateKey,
+  spend: Option<<Ed25519Sha as CryptEngine>::PublicKey>,
+
+  height_at_start: isize
+}
+
+/*
+  A sample from a gamma distribution, via Marsaglia and Tsang's method
+  rand 0.7's distributions are deprecated, and only this one is needed
+*/
+fn gamma(shape: f64, scale: f64) -> f64 {
+  let d = shape - (1.0 / 3.0);
+  let c = 1.0 / (9.0 * d).sqrt();
+  loop {
+    // A standard normal sample, via the Box-Muller transform
+    let normal = (-2.0 * (1.0 - OsRng.gen::<f64>()).ln()).sqrt() * (2.0 * std::f64::consts::PI * OsRng.gen::<f64>()).cos();
+    let v = (1.0 + (c * normal)).powi(3);
+    if v <= 0.0 {
+      continue;
+    }
+    if (1.0 - OsRng.gen::<f64>()).ln() < (((normal * normal) / 2.0) + d - (d * v) + (d * v.ln())) {
+      return d * v * scale;
+    }
+  }
+}
+
+/*
+  Selects a decoy's global index from the cumulative amount of outputs as of each block
+  Ages are gamma distributed, as wallet2 selects them, so the real output doesn't stand out
+  Returns None when the sampled age is older than the chain, in which case another sample should be taken
+*/
+fn select_decoy(distribution: &[u64], block_seconds: u64) -> Option<u64> {
+  let total = *distribution.last()?;
+  // The average time between outputs over the last year
+  let blocks = distribution.len().min((365 * 24 * 60 * 60 / block_seconds) as usize);
+  let outputs = total - if blocks < distribution.len() { distribution[distribution.len() - blocks - 1] } else { 0 };
+  if outputs == 0 {
+    return None;
+  }
+  let output_seconds = ((blocks as u64) * block_seconds) as f64 / (outputs as f64);
+
+  let age = (gamma(19.28, 1.0 / 1.61).exp() / output_seconds) as u64;
+  if age >= total {
+    return None;
+  }
+  let index = total - 1 - age;
+
+  // Find the block with this output, and select a random output from it
+  let (mut low, mut high) = (0, distribution.len() - 1);
+  while low < high {
+    let middle = (low + high) / 2;
+    if distribution[middle] > index {
+      high = middle;
+    } else {
+      low = middle + 1;
+    }
+  }
+  let first = if low == 0 { 0 } else { distribution[low - 1] };
+  Some(first + (OsRng.next_u64() % (distribution[low] - first)))
+}
+
+pub struct XmrBlock {
+  pub hash: String,
+  pub prev_hash: String,
+  pub tx_hashes: Vec<String>
+}
+
+// A transaction which sent to us, and the outputs it sent
+pub struct Deposit {
+  pub hash: String,
+  pub height: isize,
+  pub unlock_time: u64,
+  pub outputs: Vec<OwnedOutput>
+}
+
 pub struct XmrEngine {
   pub config: XmrConfig,
+  pub params: XmrParams,
 
   pub k: Option<<Ed25519Sha as CryptEngine>::PrivateKey>,
   pub view: <Ed25519Sha as CryptEngine>::PrivateKey,
   spend: Option<<Ed25519Sha as CryptEngine>::PublicKey>,
 
-  height_at_start: isize,
-  deposit: Option<String>,
+  pub height_at_start: isize,
   #[cfg(test)]
   wallet_address: Option<String>
 }
 
 impl XmrEngine {
-  pub async fn new(config: XmrConfig) -> anyhow::Result<XmrEngine> {
+  pub async fn new(config: XmrConfig, chain: XmrChain) -> anyhow::Result<XmrEngine> {
+    let params = XmrParams {
+      chain,
+      network: config.network
+    };
+    for address in &[&config.destination, &config.refund] {
+      params.decode_address(address)?;
+    }
+
     let mut result = XmrEngine {
       config,
+      params,
 
       k: None,
       view: Ed25519Sha::new_private_key(),
       spend: None,
 
       height_at_start: -1,
-      deposit: None,
       #[cfg(test)]
       wallet_address: None
     };
+    result.verify_network().await?;
     result.height_at_start = result.get_height().await;
     Ok(result)
   }
 
+  async fn verify_network(&self) -> anyhow::Result<()> {
+    #[derive(Deserialize, Debug)]
+    struct InfoResponse {
+      nettype: String
+    }
+    let info: JsonRpcResponse<InfoResponse> = self.rpc_call("json_rpc", Some(json!({
+      "jsonrpc": "2.0",
+      "id": (),
+      "method": "get_info"
+    }))).await?;
+    if info.result.nettype != self.params.network.nettype() {
+      anyhow::bail!(
+        "{} daemon is on {}, yet the {:?} network is configured",
+        self.params.chain.name(),
+        info.result.nettype,
+        self.params.network
+      );
+    }
+    Ok(())
+  }
+
+  pub fn state(&self) -> XmrEngineState {
+    XmrEngineState {
+      k: self.k,
+      view: self.view,
+      spend: self.spend,
+
+      height_at_start: self.height_at_start
+    }
+  }
+
+  pub fn restore_state(&mut self, state: XmrEngineState) {
+    self.k = state.k;
+    self.view = state.view;
+    self.spend = state.spend;
+
+    self.height_at_start = state.height_at_start;
+  }
+
   pub fn set_spend(&mut self, other: <Ed25519Sha as CryptEngine>::PublicKey) {
     self.spend = Some(Ed25519Sha::to_public_key(&self.k.expect("Verifying keys before generating")) + other);
   }
@@ -131,6 +477,7 @@ impl XmrEngine {
     )
   }
 
+  #[cfg(test)]
   async fn wallet_call<
     Params: Serialize + Debug,
     Response: DeserializeOwned + Debug
@@ -190,6 +537,7 @@ impl XmrEngine {
     }
     #[derive(Deserialize, Debug)]
     struct TransactionsResponse {
+      #[serde(default)]
       txs: Vec<TransactionResponse>
     };
 
@@ -202,9 +550,9 @@ impl XmrEngine {
       } else {
         Some(
           (
-            deserialize(
-              &hex::decode(&txs.txs[0].as_hex).expect("Monero RPC returned a non-hex transaction")
-            ).expect("Monero returned a transaction we couldn't deserialize"),
+            Transaction::parse(
+              &hex::decode(&txs.txs[0].as_hex).map_err(|_| anyhow::anyhow!("RPC returned a non-hex transaction"))?
+            )?,
             txs.txs[0].block_height
           )
         )
@@ -212,139 +560,357 @@ impl XmrEngine {
     )
   }
 
-  pub async fn get_deposit(&mut self, pair: &ViewPair, wait: bool) -> anyhow::Result<Option<Transaction>> {
+  // The global indexes of a confirmed transaction's outputs, which is how rings refer to them
+  async fn get_output_indexes(&self, hash_hex: &str) -> anyhow::Result<Vec<u64>> {
+    #[derive(Deserialize, Debug)]
+    struct TransactionResponse {
+      #[serde(default)]
+      output_indices: Vec<u64>
+    }
+    #[derive(Deserialize, Debug)]
+    struct TransactionsResponse {
+      #[serde(default)]
+      txs: Vec<TransactionResponse>
+    };
+
+    let txs: TransactionsResponse = self.rpc_call("get_transactions", Some(json!({
+      "txs_hashes": [hash_hex]
+    }))).await?;
+    txs.txs.into_iter().next().map(|tx| tx.output_indices).filter(|indexes| indexes.len() != 0)
+      .ok_or_else(|| anyhow::anyhow!("Couldn't get the output indexes of {}", hash_hex))
+  }
+
+  /*
+    The daemon's list of the block's transactions is used instead of deserializing the block
+    Forks, such as Wownero, add fields to the block header which Monero's library can't parse
+    Their transactions, which are all that's needed, share Monero's format
+  */
+  pub async fn get_block(&self, height: isize) -> anyhow::Result<XmrBlock> {
+    #[derive(Deserialize, Debug)]
+    struct BlockHeader {
+      hash: String,
+      prev_hash: String
+    }
     #[derive(Deserialize, Debug)]
     struct BlockResponse {
-      blob: String
+      block_header: BlockHeader,
+      #[serde(default)]
+      tx_hashes: Vec<String>
+    }
+
+    let block = self.rpc_call::<_, JsonRpcResponse<BlockResponse>>("json_rpc", Some(json!({
+      "jsonrpc": "2.0",
+      "id": (),
+      "method": "get_block",
+      "params": {
+        "height": height
+      }
+    }))).await?.result;
+    Ok(XmrBlock {
+      hash: block.block_header.hash,
+      prev_hash: block.block_header.prev_hash,
+      tx_hashes: block.tx_hashes
+    })
+  }
+
+  // Every transaction in the daemon's mempool, by hash
+  pub async fn get_mempool(&self) -> anyhow::Result<Vec<(String, Transaction)>> {
+    #[derive(Deserialize, Debug)]
+    struct PoolTransaction {
+      id_hash: String,
+      tx_blob: String
     }
     #[derive(Deserialize, Debug)]
-    struct JsonRpcResponse {
-      result: BlockResponse
-    }
-
-    let mut block = self.height_at_start - 1;
-    let mut tx_hash;
-    let mut result;
-    'outer: loop {
-      while self.get_height().await > block {
-        for hash in deserialize::<Block>(
-          &hex::decode(
-            &(
-              self.rpc_call::<_, JsonRpcResponse>(
-                "json_rpc",
-                Some(json!({
-                  "jsonrpc": "2.0",
-                  "id": (),
-                  "method": "get_block",
-                  "params": {
-                    "height": block
-                  }
-                }))
-              ).await?
-            ).result.blob
-          ).expect("Monero returned a non-hex blob")
-        ).expect("Monero returned a block we couldn't deserialize").tx_hashes {
-          tx_hash = hex::encode(hash.as_bytes());
-          result = self.get_transaction(&tx_hash)
-            .await?
-            .expect("Couldn't get transaction included in block");
-
-          let outputs = result.0.prefix.check_outputs(pair, 0..1, 0..1);
-          if outputs.is_err() || (outputs.unwrap().len() == 0) {
-            continue;
-          }
+    struct PoolResponse {
+      #[serde(default)]
+      transactions: Vec<PoolTransaction>
+    }
 
-          if block != result.1 {
-            anyhow::bail!("Transaction's confirmation height changed");
-          }
+    let pool: PoolResponse = self.rpc_call::<Option<()>, _>("get_transaction_pool", None).await?;
+    let mut result = vec![];
+    for tx in pool.transactions {
+      result.push((
+        tx.id_hash,
+        Transaction::parse(&hex::decode(&tx.tx_blob).map_err(|_| anyhow::anyhow!("RPC returned a non-hex transaction"))?)?
+      ));
+    }
+    Ok(result)
+  }
+
+  // Finds every transaction which sent to the view pair since the swap started, with what it sent
+  pub async fn get_deposits(&self, pair: &ViewPair) -> anyhow::Result<Vec<Deposit>> {
+    let mut tracker = XmrTracker::new(self, pair);
+    tracker.update().await?;
+    Ok(tracker.into_deposits())
+  }
 
-          break 'outer;
+  /*
+    Waits until done accepts the total of every deposit with enough confirmations, returning that total
+    Deposits can be split over several outputs and transactions, yet none can have an unlock time
+    The total is recalculated from the tracked chain every pass, so a reorganization only extends the wait
+  */
+  pub async fn wait_for_deposits<F: Fn(u128) -> bool>(&self, pair: &ViewPair, done: F) -> anyhow::Result<u128> {
+    let mut tracker = XmrTracker::new(self, pair);
+    loop {
+      for event in tracker.update().await? {
+        match event {
+          TrackerEvent::Mempool(hash) => info!("Saw deposit {} in the mempool, waiting for it to confirm", hash),
+          TrackerEvent::Included(hash, height) => info!("Deposit {} was included in block {}", hash, height),
+          TrackerEvent::Reorganized(height) => warn!("Blocks from {} were reorganized, waiting for deposits again", height)
         }
-        block += 1;
       }
 
-      if !wait {
-        return Ok(None);
+      let mut total = 0;
+      for deposit in tracker.deposits() {
+        if deposit.unlock_time != 0 {
+          anyhow::bail!("Deposit {} has an unlock time", deposit.hash);
+        }
+        if (tracker.height() - deposit.height) >= CONFIRMATIONS {
+          total += deposit.outputs.iter().map(|output| output.amount as u128).sum::<u128>();
+        }
+      }
+      if (total != 0) && done(total) {
+        return Ok(total);
       }
       tokio::time::delay_for(std::time::Duration::from_secs(10)).await;
     }
-    if !wait {
-      return Ok(Some(result.0));
+  }
+
+  // The cumulative amount of RingCT outputs as of each block, excluding blocks whose outputs can't be spent yet
+  async fn get_output_distribution(&self) -> anyhow::Result<Vec<u64>> {
+    #[derive(Deserialize, Debug)]
+    struct Distribution {
+      distribution: Vec<u64>
+    }
+    #[derive(Deserialize, Debug)]
+    struct DistributionResponse {
+      distributions: Vec<Distribution>
     }
 
-    let mut confirmation_height = result.1;
-    while self.get_height().await - confirmation_height < CONFIRMATIONS {
-      tokio::time::delay_for(std::time::Duration::from_secs(10)).await;
-      confirmation_height = self.get_transaction(&tx_hash).await?.unwrap().1;
-      if confirmation_height != block {
-        anyhow::bail!("Transaction's confirmation height changed");
+    let mut distribution = self.rpc_call::<_, JsonRpcResponse<DistributionResponse>>("json_rpc", Some(json!({
+      "jsonrpc": "2.0",
+      "id": (),
+      "method": "get_output_distribution",
+      "params": {
+        "amounts": [0],
+        "cumulative": true,
+        "binary": false
       }
+    }))).await?.result.distributions.pop().ok_or_else(|| anyhow::anyhow!("Daemon didn't return an output distribution"))?.distribution;
+    let spendable = distribution.len().saturating_sub(self.params.chain.spendable_age() as usize);
+    distribution.truncate(spendable);
+    Ok(distribution)
+  }
+
+  // The one-time key and commitment of each output, or None for outputs which are still locked
+  async fn get_outputs(&self, indexes: &[u64]) -> anyhow::Result<Vec<Option<[EdwardsPoint; 2]>>> {
+    #[derive(Deserialize, Debug)]
+    struct Output {
+      key: String,
+      mask: String,
+      unlocked: bool
+    }
+    #[derive(Deserialize, Debug)]
+    struct OutputsResponse {
+      #[serde(default)]
+      outs: Vec<Output>
     }
 
-    self.deposit = Some(tx_hash);
-    Ok(Some(result.0))
+    let outputs: OutputsResponse = self.rpc_call("get_outs", Some(json!({
+      "outputs": indexes.iter().map(|index| json!({
+        "amount": 0,
+        "index": index
+      })).collect::<Vec<_>>()
+    }))).await?;
+    if outputs.outs.len() != indexes.len() {
+      anyhow::bail!("Daemon didn't return every requested output");
+    }
+
+    let point = |hex_point: &str| hex::decode(hex_point).ok()
+      .filter(|bytes| bytes.len() == 32)
+      .and_then(|bytes| CompressedEdwardsY::from_slice(&bytes).decompress())
+      .ok_or_else(|| anyhow::anyhow!("Daemon returned an invalid output"));
+    outputs.outs.iter().map(|output| Ok(
+      if output.unlocked {
+        Some([point(&output.key)?, point(&output.mask)?])
+      } else {
+        None
+      }
+    )).collect()
   }
 
-  pub async fn claim(
+  /*
+    Selects decoys for the output with the specified global index, returning the ring's indexes, members, and the real output's position
+    Only unlocked outputs are used, and every member is unique
+  */
+  async fn select_ring(
     &self,
-    spend_key: <Ed25519Sha as CryptEngine>::PrivateKey,
-    destination: &str
-  ) -> anyhow::Result<()> {
+    distribution: &[u64],
+    real: u64,
+    member: [EdwardsPoint; 2]
+  ) -> anyhow::Result<(Vec<u64>, Vec<[EdwardsPoint; 2]>, usize)> {
+    let ring_size = self.params.chain.ring_size();
+    if (*distribution.last().unwrap_or(&0) as usize) <= ring_size {
+      anyhow::bail!("Not enough spendable outputs exist to form a ring");
+    }
+
+    let mut ring = BTreeMap::new();
+    ring.insert(real, member);
+    let mut attempts = 0;
+    while ring.len() < ring_size {
+      let mut candidates = vec![];
+      while (ring.len() + candidates.len()) < ring_size {
+        attempts += 1;
+        if attempts > 10000 {
+          anyhow::bail!("Couldn't select enough decoys");
+        }
+        if let Some(index) = select_decoy(distribution, self.params.chain.block_seconds()) {
+          if !ring.contains_key(&index) && !candidates.contains(&index) {
+            candidates.push(index);
+          }
+        }
+      }
+
+      for (index, output) in candidates.iter().zip(self.get_outputs(&candidates).await?) {
+        if let Some(output) = output {
+          ring.insert(*index, output);
+        }
+      }
+    }
+
+    let position = ring.keys().position(|index| *index == real).unwrap();
+    Ok((ring.keys().cloned().collect(), ring.values().cloned().collect(), position))
+  }
+
+  // The fee per byte, and the value fees are rounded up to a multiple of
+  async fn get_fee_rate(&self) -> anyhow::Result<(u64, u64)> {
     #[derive(Deserialize, Debug)]
-    struct WalletResponse {
-      address: String
+    struct FeeResponse {
+      fee: u64,
+      #[serde(default)]
+      quantization_mask: u64
     }
+
+    let fee: JsonRpcResponse<FeeResponse> = self.rpc_call("json_rpc", Some(json!({
+      "jsonrpc": "2.0",
+      "id": (),
+      "method": "get_fee_estimate"
+    }))).await?;
+    Ok((fee.result.fee, fee.result.quantization_mask.max(1)))
+  }
+
+  async fn publish(&self, tx: &[u8]) -> anyhow::Result<()> {
     #[derive(Deserialize, Debug)]
-    struct SweepResponse {
-      tx_hash_list: Vec<String>
+    struct SendResponse {
+      status: String,
+      #[serde(default)]
+      reason: String
     }
 
+    let res: SendResponse = self.rpc_call("send_raw_transaction", Some(json!({
+      "tx_as_hex": hex::encode(tx),
+      "do_not_relay": false
+    }))).await?;
+    if res.status != "OK" {
+      anyhow::bail!("{} daemon rejected the transaction: {}", self.params.chain.name(), res.reason);
+    }
+    Ok(())
+  }
+
+  /*
+    Sweeps the deposits to the destination with a transaction built and signed here, so only the daemon is needed
+    Their outputs are spent with CLSAG signatures over rings of decoys, with a Bulletproofs+ range proof for the outputs
+  */
+  pub async fn claim(
+    &self,
+    spend_key: <Ed25519Sha as CryptEngine>::PrivateKey,
+    destination: &str
+  ) -> anyhow::Result<()> {
     let spend_key = spend_key + self.k.expect("Claiming funds before generating a k");
-    let address = Address::standard(
-      NETWORK,
-      PublicKey {
-        point: Ed25519Sha::to_public_key(&spend_key).compress()
-      },
-      PublicKey {
-        point: Ed25519Sha::to_public_key(&self.view).compress()
+    let destination = self.params.decode_address(destination)?;
+
+    // Wait for every deposit to unlock, skipping any with an unlock time which the verifier would've refused
+    let pair = self.get_view_pair();
+    let mut tracker = XmrTracker::new(self, &pair);
+    loop {
+      tracker.update().await?;
+      let height = tracker.height();
+      if tracker.deposits()
+        .filter(|deposit| deposit.unlock_time == 0)
+        .all(|deposit| (height - deposit.height) >= self.params.chain.spendable_age()) {
+        break;
       }
-    ).to_string();
 
-    let mut name = [0; 32];
-    OsRng.fill_bytes(&mut name);
-
-    let res: WalletResponse = self.wallet_call("generate_from_keys", json!({
-        "restore_height": self.height_at_start,
-        "filename": hex::encode(&name),
-        "address": address,
-        "spendkey": hex::encode(&Ed25519Sha::private_key_to_bytes(&spend_key)),
-        "viewkey": hex::encode(&Ed25519Sha::private_key_to_bytes(&self.view)),
-        "password": ""
-      })
-    ).await.expect("Couldn't create the wallet").result;
-    if res.address != address {
-      anyhow::bail!("Generated a different wallet");
-    }
-
-    // Wait ten blocks for the transaction to unlock
-    while self.get_height().await - self.get_transaction(
-      self.deposit.as_ref().expect("Claiming Monero before knowing of its deposit")
-    ).await?.unwrap().1 < 10 {
       #[cfg(test)]
       self.mine_block().await?;
 
       tokio::time::delay_for(std::time::Duration::from_secs(10)).await;
     }
 
-    // Trigger a rescan
-    let _: EmptyResponse = self.wallet_call("rescan_blockchain", json!({})).await?.result;
-
-    // Use sweep to forward the funds
-    let _: SweepResponse = self.wallet_call("sweep_all", json!({
-      "address": destination,
-    })).await.expect("Couldn't transfer the Monero").result;
+    let distribution = self.get_output_distribution().await?;
+    let mut inputs = vec![];
+    for deposit in tracker.deposits().filter(|deposit| deposit.unlock_time == 0) {
+      let indexes = self.get_output_indexes(&deposit.hash).await?;
+      for output in &deposit.outputs {
+        let key = spend_key + output.key_offset;
+        let (offsets, ring, index) = self.select_ring(
+          &distribution,
+          indexes[output.index],
+          [Ed25519Sha::to_public_key(&key), commit(&output.mask, output.amount)]
+        ).await?;
+        inputs.push(Input {
+          offsets,
+          ring,
+          index,
+          key,
+          mask: output.mask,
+          amount: output.amount
+        });
+      }
+    }
+    if inputs.len() == 0 {
+      anyhow::bail!("No deposits were found to claim");
+    }
+    let total = inputs.iter().map(|input| input.amount).sum::<u64>();
+
+    // Transactions need at least two outputs, so the second is a zero value output to a random address
+    let dummy = Payment {
+      address: XmrAddress {
+        spend: Ed25519Sha::to_public_key(&Ed25519Sha::new_private_key()),
+        view: Ed25519Sha::to_public_key(&Ed25519Sha::new_private_key()),
+        kind: XmrAddressKind::Standard
+      },
+      amount: 0
+    };
 
-    Ok(())
+    // Signing decides the size, which decides the fee, so sign until the fee covers the size
+    let (fee_per_byte, quantization) = self.get_fee_rate().await?;
+    let mut fee = 0;
+    loop {
+      if fee >= total {
+        anyhow::bail!("Claimed funds don't cover the fee");
+      }
+      let (signed, hash) = transaction::sign(
+        &inputs,
+        &[
+          Payment {
+            address: destination,
+            amount: total - fee
+          },
+          dummy.clone()
+        ],
+        fee
+      );
+
+      // With two outputs, a transaction's weight is its size
+      let needed = ((((signed.len() as u64) * fee_per_byte) + quantization - 1) / quantization) * quantization;
+      if fee >= needed {
+        self.publish(&signed).await?;
+        info!("Claimed {} atomic units with a fee of {} in {}", total - fee, fee, hex::encode(hash));
+        return Ok(());
+      }
+      fee = needed;
+    }
   }
 
   #[cfg(test)]
@@ -371,19 +937,19 @@ impl XmrEngine {
     })).await.expect("Couldn't get the address").result;
     self.wallet_address = Some(res.address);
 
-    // Mine 70 blocks to it (coin maturity happens at 60)
-    for _ in 0 .. 7 {
+    // Mine enough blocks to it for the first to mature, ten at a time
+    for _ in 0 ..= (self.params.chain.coinbase_maturity() / 10) {
       self.mine_block().await?;
     }
     let _: EmptyResponse = self.wallet_call("rescan_blockchain", json!({})).await?.result;
 
-    // Send 1 XMR to our address
+    // Send 1 coin to our address
     let _: TransactionResponse = self.wallet_call("transfer", json!({
       "destinations": [{
-        "address": Address::from_viewpair(NETWORK, &self.get_view_pair()).to_string(),
-        "amount": (1000000000000 as u64)
+        "address": self.params.address_from_view_pair(&self.get_view_pair()),
+        "amount": self.params.chain.coin()
       }]
-    })).await.expect("Couldn't transfer the Monero for testing purposes").result;
+    })).await.expect("Couldn't transfer the funds for testing purposes").result;
     self.mine_block().await?;
 
     Ok(())
@@ -397,11 +963,14 @@ impl XmrEngine {
       "method": "generateblocks",
       "params": {
         "wallet_address": if self.wallet_address.is_some() {
-          self.wallet_address.as_ref().unwrap()
+          self.wallet_address.clone().unwrap()
         } else {
           // Fallback for when the recreated client advances the consensus one last time
-          // Random address from one of the swap runs
-          "456BaF31m8NF1DQ9HRA7ZJ7JJZMVLw1ZY5Uzev5q7QBxFPqkyy1vNRN2Yvn43sE6LLYtQtLH16HKjHDhn1W7a2Wy1aWbp3U"
+          self.params.encode_address(&XmrAddress {
+            spend: Ed25519Sha::to_public_key(&Ed25519Sha::new_private_key()),
+            view: Ed25519Sha::to_public_key(&Ed25519Sha::new_private_key()),
+            kind: XmrAddressKind::Standard
+          })
         },
         "amount_of_blocks": 10
       }
@@ -1,4 +1,30 @@
+#[cfg(not(test))]
 mod engine;
+#[cfg(test)]
+pub mod engine;
+
+pub use engine::XmrChain;
+
+#[cfg(not(test))]
+mod crypto;
+#[cfg(test)]
+pub mod crypto;
+#[cfg(not(test))]
+mod bulletproofs_plus;
+#[cfg(test)]
+pub mod bulletproofs_plus;
+#[cfg(not(test))]
+mod clsag;
+#[cfg(test)]
+pub mod clsag;
+#[cfg(not(test))]
+mod transaction;
+#[cfg(test)]
+pub mod transaction;
+#[cfg(not(test))]
+mod tracker;
+#[cfg(test)]
+pub mod tracker;
 
 pub mod client;
 pub mod verifier;
@@ -0,0 +1,133 @@
+use std::collections::HashSet;
+
+use curve25519_dalek::{scalar::Scalar, edwards::EdwardsPoint};
+
+use monero::util::key::ViewPair;
+
+use crate::coins::xmr::engine::{XmrEngine, Deposit};
+
+#[derive(PartialEq, Debug)]
+pub enum TrackerEvent {
+  // A deposit which was seen in the mempool, which is only an early notice as it may never confirm
+  Mempool(String),
+  // A deposit which was included in the block with the specified height
+  Included(String, isize),
+  // Blocks from the specified height on were replaced, dropping any deposits in them until they're included again
+  Reorganized(isize)
+}
+
+struct TrackedBlock {
+  hash: String,
+  deposits: Vec<Deposit>
+}
+
+/*
+  Follows the chain's tip from the block before the swap started, scanning every block for deposits to the view pair
+  Each block is checked to build on the last one tracked, and if it doesn't, the last one is dropped and rescanned
+  As a block's hash commits to its parent, this unwinds reorganizations of any depth back to where the chains meet
+*/
+pub struct XmrTracker<'a> {
+  engine: &'a XmrEngine,
+  view: Scalar,
+  spend: EdwardsPoint,
+  start: isize,
+  blocks: Vec<TrackedBlock>,
+  // Every transaction in the mempool as of the last update, so each is only scanned once
+  mempool: HashSet<String>
+}
+
+impl<'a> XmrTracker<'a> {
+  pub fn new(engine: &'a XmrEngine, pair: &ViewPair) -> XmrTracker<'a> {
+    XmrTracker {
+      engine,
+      view: pair.view.scalar,
+      spend: pair.spend.point.decompress().expect("View pair had an invalid spend key"),
+      start: engine.height_at_start - 1,
+      blocks: vec![],
+      mempool: HashSet::new()
+    }
+  }
+
+  // The height of the tracked chain, which is the amount of blocks in it
+  pub fn height(&self) -> isize {
+    self.start + (self.blocks.len() as isize)
+  }
+
+  // Every deposit included in the tracked chain
+  pub fn deposits(&self) -> impl Iterator<Item = &Deposit> {
+    self.blocks.iter().flat_map(|block| block.deposits.iter())
+  }
+
+  pub fn into_deposits(self) -> Vec<Deposit> {
+    self.blocks.into_iter().flat_map(|block| block.deposits).collect()
+  }
+
+  // Drops the last tracked block, noting the lowest height reorganized
+  fn pop(&mut self, reorganized: &mut Option<isize>) {
+    self.blocks.pop().expect("Popping a block when none are tracked");
+    let height = self.height();
+    *reorganized = Some(reorganized.map_or(height, |lowest| lowest.min(height)));
+  }
+
+  pub async fn update(&mut self) -> anyhow::Result<Vec<TrackerEvent>> {
+    let mut events = vec![];
+
+    // Unwind any blocks which are no longer part of the chain, which is detected here even if the chain didn't grow
+    let mut reorganized = None;
+    while let Some(last) = self.blocks.last() {
+      if self.engine.get_block(self.height() - 1).await?.hash == last.hash {
+        break;
+      }
+      self.pop(&mut reorganized);
+    }
+
+    while self.height() < self.engine.get_height().await {
+      let block = self.engine.get_block(self.height()).await?;
+      if let Some(last) = self.blocks.last() {
+        // The chain was reorganized while it was being scanned
+        if block.prev_hash != last.hash {
+          self.pop(&mut reorganized);
+          continue;
+        }
+      }
+
+      let height = self.height();
+      let mut deposits = vec![];
+      for hash in block.tx_hashes {
+        let (tx, _) = self.engine.get_transaction(&hash)
+          .await?
+          .ok_or_else(|| anyhow::anyhow!("Couldn't get transaction included in block"))?;
+        let outputs = tx.scan(&self.view, &self.spend);
+        if outputs.len() != 0 {
+          events.push(TrackerEvent::Included(hash.clone(), height));
+          deposits.push(Deposit {
+            hash,
+            height,
+            unlock_time: tx.unlock_time,
+            outputs
+          });
+        }
+      }
+      self.blocks.push(TrackedBlock {
+        hash: block.hash,
+        deposits
+      });
+    }
+
+    // Reported before the inclusions, which may be of the same deposits in the new blocks
+    if let Some(height) = reorganized {
+      events.insert(0, TrackerEvent::Reorganized(height));
+    }
+
+    let mut mempool = HashSet::new();
+    for (hash, tx) in self.engine.get_mempool().await? {
+      if !self.mempool.contains(&hash) && (tx.scan(&self.view, &self.spend).len() != 0) {
+        events.push(TrackerEvent::Mempool(hash.clone()));
+      }
+      mempool.insert(hash);
+    }
+    self.mempool = mempool;
+
+    Ok(events)
+  }
+}
@@ -0,0 +1,420 @@
+use std::convert::TryInto;
+
+use rand::{rngs::OsRng, RngCore, seq::SliceRandom};
+
+use curve25519_dalek::{
+  constants::ED25519_BASEPOINT_TABLE,
+  scalar::Scalar,
+  edwards::{EdwardsPoint, CompressedEdwardsY}
+};
+
+use crate::coins::xmr::{
+  engine::{encode_varint, decode_varint, XmrAddress, XmrAddressKind},
+  crypto::{keccak, key_image, commit, shared_key, view_tag, commitment_mask, xor_amount, xor_payment_id},
+  bulletproofs_plus::BulletproofPlus,
+  clsag
+};
+
+// Transactions with Bulletproofs or Bulletproofs+, signed by either MLSAG or CLSAG, which share the same RingCT base
+const RCT_TYPE_BULLETPROOF2: u8 = 4;
+const RCT_TYPE_CLSAG: u8 = 5;
+const RCT_TYPE_BULLETPROOF_PLUS: u8 = 6;
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+  position: usize
+}
+
+impl<'a> Reader<'a> {
+  fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+    if (self.bytes.len() - self.position) < len {
+      anyhow::bail!("Transaction ended unexpectedly");
+    }
+    self.position += len;
+    Ok(&self.bytes[(self.position - len) .. self.position])
+  }
+
+  fn byte(&mut self) -> anyhow::Result<u8> {
+    Ok(self.take(1)?[0])
+  }
+
+  fn varint(&mut self) -> anyhow::Result<u64> {
+    let (value, len) = decode_varint(&self.bytes[self.position ..])
+      .ok_or_else(|| anyhow::anyhow!("Transaction had an invalid VarInt"))?;
+    self.position += len;
+    Ok(value)
+  }
+
+  fn key(&mut self) -> anyhow::Result<[u8; 32]> {
+    Ok(self.take(32)?.try_into().unwrap())
+  }
+
+  // Lengths are bounded by the remaining data so a malicious length can't trigger a massive allocation
+  fn len(&mut self, element_size: usize) -> anyhow::Result<usize> {
+    let len = self.varint()? as usize;
+    if len > ((self.bytes.len() - self.position) / element_size) {
+      anyhow::bail!("Transaction had an invalid length");
+    }
+    Ok(len)
+  }
+}
+
+pub struct Output {
+  pub key: [u8; 32],
+  // Only set for outputs created since v15, which let recipients skip most outputs with a single hash
+  pub view_tag: Option<u8>,
+  pub encrypted_amount: [u8; 8],
+  pub commitment: [u8; 32]
+}
+
+// An output sent to us, with everything needed to spend it
+pub struct OwnedOutput {
+  pub index: usize,
+  // The scalar added to the spend key to get the output's one-time key
+  pub key_offset: Scalar,
+  pub amount: u64,
+  pub mask: Scalar
+}
+
+/*
+  The parts of a transaction needed to scan it, as the prunable signatures are never parsed
+  Monero's library isn't used as it can't parse the CLSAG and Bulletproofs+ transactions the network, and our claims, use
+  Transactions which can't be sent to us, or whose format isn't known, are still parsed, just without any outputs
+  Any input or output type may follow an unknown one, so the rest of the transaction can't be read
+*/
+pub struct Transaction {
+  pub unlock_time: u64,
+  pub outputs: Vec<Output>,
+  pub extra: Vec<u8>
+}
+
+impl Transaction {
+  pub fn parse(bytes: &[u8]) -> anyhow::Result<Transaction> {
+    let mut reader = Reader {
+      bytes,
+      position: 0
+    };
+
+    let version = reader.varint()?;
+    let unlock_time = reader.varint()?;
+    let unscannable = |extra| Ok(Transaction {
+      unlock_time,
+      outputs: vec![],
+      extra
+    });
+    if version != 2 {
+      return unscannable(vec![]);
+    }
+
+    for _ in 0 .. reader.len(1)? {
+      match reader.byte()? {
+        // Miner transactions, which can only be the first transaction of a block
+        0xff => {
+          reader.varint()?;
+        },
+        0x02 => {
+          reader.varint()?;
+          for _ in 0 .. reader.len(1)? {
+            reader.varint()?;
+          }
+          reader.key()?;
+        },
+        _ => return unscannable(vec![])
+      }
+    }
+
+    let mut keys = vec![];
+    for _ in 0 .. reader.len(1)? {
+      reader.varint()?;
+      match reader.byte()? {
+        0x02 => keys.push((reader.key()?, None)),
+        // Tagged keys, which are followed by their view tag
+        0x03 => keys.push((reader.key()?, Some(reader.byte()?))),
+        _ => return unscannable(vec![])
+      }
+    }
+
+    let extra_len = reader.len(1)?;
+    let extra = reader.take(extra_len)?.to_vec();
+
+    // Miner transactions have no RingCT data, and older types encrypt amounts differently, yet none can be sent to us
+    let rct_type = reader.byte()?;
+    if ![RCT_TYPE_BULLETPROOF2, RCT_TYPE_CLSAG, RCT_TYPE_BULLETPROOF_PLUS].contains(&rct_type) {
+      return unscannable(extra);
+    }
+    // Fee
+    reader.varint()?;
+    let mut amounts = vec![];
+    for _ in 0 .. keys.len() {
+      amounts.push(reader.take(8)?.try_into().unwrap());
+    }
+    let mut outputs = vec![];
+    for ((key, view_tag), encrypted_amount) in keys.into_iter().zip(amounts) {
+      outputs.push(Output {
+        key,
+        view_tag,
+        encrypted_amount,
+        commitment: reader.key()?
+      });
+    }
+
+    Ok(Transaction {
+      unlock_time,
+      outputs,
+      extra
+    })
+  }
+
+  /*
+    The transaction key, any additional keys which are used when sending to multiple subaddresses, and the nonce
+    The nonce is where payment IDs are placed
+  */
+  fn parse_extra(&self) -> (Option<EdwardsPoint>, Vec<EdwardsPoint>, Option<Vec<u8>>) {
+    // Returns whether parsing should continue
+    fn field(
+      reader: &mut Reader,
+      key: &mut Option<EdwardsPoint>,
+      additional: &mut Vec<EdwardsPoint>,
+      nonce: &mut Option<Vec<u8>>
+    ) -> anyhow::Result<bool> {
+      match reader.byte()? {
+        0x01 => {
+          let parsed = CompressedEdwardsY(reader.key()?).decompress();
+          if key.is_none() {
+            *key = parsed;
+          }
+        },
+        0x02 => {
+          let len = reader.len(1)?;
+          let parsed = reader.take(len)?.to_vec();
+          if nonce.is_none() {
+            *nonce = Some(parsed);
+          }
+        },
+        0x03 | 0xde => {
+          let len = reader.len(1)?;
+          reader.take(len)?;
+        },
+        0x04 => {
+          for _ in 0 .. reader.len(32)? {
+            additional.push(
+              CompressedEdwardsY(reader.key()?).decompress().ok_or_else(|| anyhow::anyhow!("Invalid additional key"))?
+            );
+          }
+        },
+        _ => return Ok(false)
+      }
+      Ok(true)
+    }
+
+    let mut reader = Reader {
+      bytes: &self.extra,
+      position: 0
+    };
+    let mut key = None;
+    let mut additional = vec![];
+    let mut nonce = None;
+    // Extra isn't validated by consensus, so parsing stops at the first field which can't be handled
+    while reader.position < reader.bytes.len() {
+      match field(&mut reader, &mut key, &mut additional, &mut nonce) {
+        Ok(true) => (),
+        _ => break
+      }
+    }
+    (key, additional, nonce)
+  }
+
+  // Decrypts the payment ID, which is only meaningful to the recipient of an integrated address
+  #[cfg(test)]
+  pub fn payment_id(&self, view: &Scalar) -> Option<[u8; 8]> {
+    let (key, _, nonce) = self.parse_extra();
+    let nonce = nonce?;
+    if (nonce.len() != 9) || (nonce[0] != 0x01) {
+      return None;
+    }
+    Some(xor_payment_id(&(view * key?), nonce[1 ..].try_into().unwrap()))
+  }
+
+  // Finds every output sent to the specified keys, skipping any whose amount doesn't match its commitment
+  pub fn scan(&self, view: &Scalar, spend: &EdwardsPoint) -> Vec<OwnedOutput> {
+    let (key, additional, _) = self.parse_extra();
+    let mut result = vec![];
+    for (i, output) in self.outputs.iter().enumerate() {
+      for tx_key in key.iter().chain(additional.get(i)) {
+        let ecdh = view * tx_key;
+        if output.view_tag.map(|tag| tag != view_tag(&ecdh, i as u64)).unwrap_or(false) {
+          continue;
+        }
+        let key_offset = shared_key(&ecdh, i as u64);
+        if ((&key_offset * &ED25519_BASEPOINT_TABLE) + spend).compress().to_bytes() != output.key {
+          continue;
+        }
+
+        let amount = xor_amount(&key_offset, u64::from_le_bytes(output.encrypted_amount));
+        let mask = commitment_mask(&key_offset);
+        if commit(&mask, amount).compress().to_bytes() == output.commitment {
+          result.push(OwnedOutput {
+            index: i,
+            key_offset,
+            amount,
+            mask
+          });
+        }
+        break;
+      }
+    }
+    result
+  }
+}
+
+// An output being spent, alongside the decoys it's hidden among
+pub struct Input {
+  // Global indexes of the ring members, in ascending order
+  pub offsets: Vec<u64>,
+  // The one-time key and commitment of each ring member
+  pub ring: Vec<[EdwardsPoint; 2]>,
+  // Which ring member is actually being spent
+  pub index: usize,
+  pub key: Scalar,
+  pub mask: Scalar,
+  pub amount: u64
+}
+
+#[derive(Clone)]
+pub struct Payment {
+  pub address: XmrAddress,
+  pub amount: u64
+}
+
+// Builds and signs a CLSAG transaction with a Bulletproofs+ range proof, returning its serialization and hash
+pub fn sign(inputs: &[Input], payments: &[Payment], fee: u64) -> (Vec<u8>, [u8; 32]) {
+  assert_eq!(
+    inputs.iter().map(|input| input.amount as u128).sum::<u128>(),
+    payments.iter().map(|payment| payment.amount as u128).sum::<u128>() + (fee as u128),
+    "Transaction's inputs didn't equal its outputs"
+  );
+
+  // Key images must be in descending order
+  let mut inputs = inputs.iter().map(|input| (key_image(&input.key), input)).collect::<Vec<_>>();
+  inputs.sort_by(|a, b| b.0.compress().to_bytes().cmp(&a.0.compress().to_bytes()));
+
+  // Shuffle the payments so which is the actual destination isn't revealed by its position
+  let mut payments = payments.to_vec();
+  payments.shuffle(&mut OsRng);
+
+  /*
+    Sending to a subaddress uses its spend key as the base of the transaction key, so its view key finds the output
+    Any other outputs are still derived from the transaction key as normal, as only the subaddress's owner scans for it
+  */
+  let tx_key = Scalar::random(&mut OsRng);
+  let mut subaddresses = payments.iter().filter(|payment| payment.address.kind == XmrAddressKind::Subaddress);
+  let tx_key_public = match subaddresses.next() {
+    Some(payment) => tx_key * payment.address.spend,
+    None => &tx_key * &ED25519_BASEPOINT_TABLE
+  };
+  assert!(subaddresses.next().is_none(), "Transaction sent to multiple subaddresses");
+
+  /*
+    Two output transactions always have an encrypted payment ID, so ones sent to integrated addresses don't stand out
+    Without an integrated address, random bytes are used, which are indistinguishable from an encrypted ID
+  */
+  let mut payment_ids = payments.iter().filter_map(|payment| match payment.address.kind {
+    XmrAddressKind::Integrated(payment_id) => Some(xor_payment_id(&(tx_key * payment.address.view), payment_id)),
+    _ => None
+  });
+  let payment_id = payment_ids.next().unwrap_or_else(|| {
+    let mut payment_id = [0; 8];
+    OsRng.fill_bytes(&mut payment_id);
+    payment_id
+  });
+  assert!(payment_ids.next().is_none(), "Transaction sent to multiple integrated addresses");
+
+  let mut keys = vec![];
+  let mut view_tags = vec![];
+  let mut encrypted_amounts = vec![];
+  let mut masks = vec![];
+  let mut commitments = vec![];
+  for (i, payment) in payments.iter().enumerate() {
+    let ecdh = tx_key * payment.address.view;
+    let key_offset = shared_key(&ecdh, i as u64);
+    keys.push((&key_offset * &ED25519_BASEPOINT_TABLE) + payment.address.spend);
+    view_tags.push(view_tag(&ecdh, i as u64));
+    encrypted_amounts.push(xor_amount(&key_offset, payment.amount));
+    masks.push(commitment_mask(&key_offset));
+    commitments.push(commit(&masks[i], payment.amount));
+  }
+  let bulletproof = BulletproofPlus::prove(&payments.iter().map(|payment| payment.amount).collect::<Vec<_>>(), &masks);
+
+  // The pseudo outputs' masks have to sum to the outputs' masks for the commitments to balance
+  let mut pseudo_masks = (1 .. inputs.len()).map(|_| Scalar::random(&mut OsRng)).collect::<Vec<_>>();
+  pseudo_masks.push(masks.iter().sum::<Scalar>() - pseudo_masks.iter().sum::<Scalar>());
+  let pseudo_outs = inputs.iter().zip(&pseudo_masks).map(|(input, mask)| commit(mask, input.1.amount)).collect::<Vec<_>>();
+
+  let mut prefix = encode_varint(2);
+  // Unlock time
+  prefix.extend(encode_varint(0));
+  prefix.extend(encode_varint(inputs.len() as u64));
+  for (image, input) in &inputs {
+    prefix.push(0x02);
+    // Amount, which is hidden by RingCT
+    prefix.extend(encode_varint(0));
+    prefix.extend(encode_varint(input.offsets.len() as u64));
+    let mut last = 0;
+    for offset in &input.offsets {
+      prefix.extend(encode_varint(offset - last));
+      last = *offset;
+    }
+    prefix.extend(image.compress().as_bytes());
+  }
+  prefix.extend(encode_varint(keys.len() as u64));
+  // Tagged keys, as untagged outputs have been rejected since v15
+  for (key, view_tag) in keys.iter().zip(&view_tags) {
+    prefix.extend(encode_varint(0));
+    prefix.push(0x03);
+    prefix.extend(key.compress().as_bytes());
+    prefix.push(*view_tag);
+  }
+  // The transaction key, followed by a nonce of the encrypted payment ID, as wallet2 orders them
+  prefix.extend(encode_varint(44));
+  prefix.push(0x01);
+  prefix.extend(tx_key_public.compress().as_bytes());
+  prefix.push(0x02);
+  prefix.extend(encode_varint(9));
+  prefix.push(0x01);
+  prefix.extend(&payment_id);
+
+  let mut base = vec![RCT_TYPE_BULLETPROOF_PLUS];
+  base.extend(encode_varint(fee));
+  for amount in &encrypted_amounts {
+    base.extend(&amount.to_le_bytes());
+  }
+  for commitment in &commitments {
+    base.extend(commitment.compress().as_bytes());
+  }
+
+  let mut message = keccak(&prefix).to_vec();
+  message.extend(&keccak(&base));
+  message.extend(&keccak(&bulletproof.signature_data()));
+  let message = keccak(&message);
+
+  let mut prunable = encode_varint(1);
+  prunable.extend(bulletproof.serialize());
+  for ((_, input), (pseudo_out, pseudo_mask)) in inputs.iter().zip(pseudo_outs.iter().zip(&pseudo_masks)) {
+    prunable.extend(
+      clsag::sign(&message, &input.ring, input.index, &input.key, &input.mask, pseudo_out, pseudo_mask).serialize()
+    );
+  }
+  for pseudo_out in &pseudo_outs {
+    prunable.extend(pseudo_out.compress().as_bytes());
+  }
+
+  let mut hash = keccak(&prefix).to_vec();
+  hash.extend(&keccak(&base));
+  hash.extend(&keccak(&prunable));
+  let hash = keccak(&hash);
+
+  let mut serialized = prefix;
+  serialized.extend(base);
+  serialized.extend(prunable);
+  (serialized, hash)
+}
@@ -0,0 +1,323 @@
+use std::{
+  sync::{Arc, Mutex},
+  collections::HashMap,
+  convert::Infallible,
+  net::SocketAddr
+};
+
+use log::{error, info};
+
+use futures::future::{Abortable, AbortHandle};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use hyper::{
+  Body, Request, Response, Server,
+  service::{make_service_fn, service_fn}
+};
+use tokio::{sync::oneshot, net::TcpListener};
+
+use crate::{
+  coins::{FeeBounds, Timelocks, SwapTerms},
+  cli::{HostOrClient, CoinPair, Cli},
+  channel::Identity,
+  db::{SwapDb, SwapStep, ClientStep},
+  SwapContext, SwapControls, host_swap, client_swap
+};
+
+// A swap started via this API which hasn't completed yet
+struct ActiveSwap {
+  abort: AbortHandle,
+  // Stops a host still waiting for its client, as the swap itself can only be aborted once it's started
+  accept: Option<AbortHandle>,
+  confirmation: Option<oneshot::Sender<bool>>
+}
+
+struct ControlState {
+  opts: Cli,
+  identity: Identity,
+  swaps: Mutex<HashMap<String, ActiveSwap>>
+}
+
+// Removes a swap from the active swaps once its task ends, even if it panicked
+struct ActiveSwapGuard {
+  state: Arc<ControlState>,
+  id: String
+}
+
+impl Drop for ActiveSwapGuard {
+  fn drop(&mut self) {
+    // If the mutex was poisoned, the map is still usable, so take it regardless
+    let mut swaps = match self.state.swaps.lock() {
+      Ok(swaps) => swaps,
+      Err(poisoned) => poisoned.into_inner()
+    };
+    swaps.remove(&self.id);
+  }
+}
+
+/*
+  A local JSON-RPC 2.0 server for driving swaps from other programs
+  Params are always passed by name, and amounts are passed as strings as Nano's don't fit in a double
+  This offers no authentication, so it's only bound to a loopback address unless explicitly allowed
+*/
+pub async fn serve(address: SocketAddr, opts: Cli) {
+  assert!(
+    address.ip().is_loopback() || opts.rpc_allow_remote,
+    "Refusing to serve the unauthenticated control API on {}; pass --rpc-allow-remote to do so anyway",
+    address
+  );
+
+  let identity = Identity::load_or_generate(&opts.identity).expect("Failed to load the identity key");
+  println!("Identity key: {}", hex::encode(&identity.public));
+
+  let state = Arc::new(ControlState {
+    opts,
+    identity,
+    swaps: Mutex::new(HashMap::new())
+  });
+  let make_service = make_service_fn(move |_| {
+    let state = state.clone();
+    async move {
+      Ok::<_, Infallible>(service_fn(move |request| handle(state.clone(), request)))
+    }
+  });
+
+  info!("Serving the control API on {}", address);
+  Server::bind(&address).serve(make_service).await.expect("Control API server failed");
+}
+
+async fn handle(state: Arc<ControlState>, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+  #[derive(Deserialize)]
+  struct RpcRequest {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value
+  }
+
+  let error = |id: Value, code: i64, message: String| json!({
+    "jsonrpc": "2.0",
+    "id": id,
+    "error": {
+      "code": code,
+      "message": message
+    }
+  });
+
+  let body = hyper::body::to_bytes(request.into_body()).await;
+  let request: RpcRequest = match body.ok().and_then(|body| serde_json::from_slice(&body).ok()) {
+    Some(request) => request,
+    None => return Ok(Response::new(Body::from(error(Value::Null, -32700, "Couldn't parse the request".to_string()).to_string())))
+  };
+
+  let result = match request.method.as_ref() {
+    "start_swap" => start_swap(&state, request.params).await,
+    "list_swaps" => list_swaps(&state),
+    "get_swap_status" => get_swap_status(&state, request.params),
+    "confirm_amount" => confirm_amount(&state, request.params),
+    "cancel" => cancel(&state, request.params),
+    _ => return Ok(Response::new(Body::from(error(request.id, -32601, format!("Unknown method {}", request.method)).to_string())))
+  };
+
+  let response = match result {
+    Ok(result) => json!({
+      "jsonrpc": "2.0",
+      "id": request.id,
+      "result": result
+    }),
+    Err(err) => error(request.id, -32000, format!("{:#}", err))
+  };
+  Ok(Response::new(Body::from(response.to_string())))
+}
+
+fn parse_amount<T: std::str::FromStr>(amount: Option<String>, name: &str) -> anyhow::Result<Option<T>> {
+  amount.map(
+    |amount| amount.parse().map_err(|_| anyhow::anyhow!("Invalid {}", name))
+  ).transpose()
+}
+
+async fn start_swap(state: &Arc<ControlState>, params: Value) -> anyhow::Result<Value> {
+  #[derive(Deserialize)]
+  struct StartSwapParams {
+    role: String,
+    address: SocketAddr,
+    pair: String,
+    scripted_amount: Option<String>,
+    unscripted_amount: Option<String>,
+    tolerance: Option<u16>,
+    // Have the client wait for confirm_amount instead of expecting specific amounts
+    #[serde(default)]
+    confirm: bool
+  }
+
+  let params: StartSwapParams = serde_json::from_value(params)?;
+  let role: HostOrClient = params.role.parse().map_err(|_| anyhow::anyhow!("Role must be host or client"))?;
+  let pair: CoinPair = params.pair.parse().map_err(|e| anyhow::anyhow!("{}", e))?;
+  let scripted_amount = parse_amount(params.scripted_amount, "scripted amount")?;
+  let unscripted_amount = parse_amount(params.unscripted_amount, "unscripted amount")?;
+
+  let terms = match (scripted_amount, unscripted_amount) {
+    (Some(scripted_amount), Some(unscripted_amount)) => Some(
+      SwapTerms {
+        scripted_amount,
+        unscripted_amount,
+        tolerance_bps: params.tolerance.unwrap_or(state.opts.tolerance),
+        scripted_fee_bounds: FeeBounds::unbounded(),
+        timelocks: Timelocks::default()
+      }
+    ),
+    _ => None
+  };
+  let confirm = match role {
+    HostOrClient::Host => {
+      anyhow::ensure!(!params.confirm, "Only the client confirms amounts");
+      anyhow::ensure!(terms.is_some(), "The host must specify both amounts");
+      false
+    },
+    HostOrClient::Client => {
+      anyhow::ensure!(params.confirm || terms.is_some(), "The client must either specify both amounts or confirm them");
+      params.confirm
+    },
+    HostOrClient::HostAndClient => anyhow::bail!("A swap is either hosted or joined")
+  };
+
+  let context = SwapContext::new(&state.opts, pair, terms, state.identity.clone());
+  let (mut controls, abort) = SwapControls::new();
+  let id = controls.id.clone();
+  let mut active = ActiveSwap {
+    abort,
+    accept: None,
+    confirmation: None
+  };
+  if confirm {
+    let (sender, receiver) = oneshot::channel();
+    active.confirmation = Some(sender);
+    controls.confirmation = Some(receiver);
+  }
+
+  // Bind now so an unusable address is reported to the caller
+  let listener = if role.is_host() {
+    let (accept, registration) = AbortHandle::new_pair();
+    active.accept = Some(accept);
+    Some((TcpListener::bind(params.address).await?, registration))
+  } else {
+    None
+  };
+  state.swaps.lock().unwrap().insert(id.clone(), active);
+
+  let guard = ActiveSwapGuard {
+    state: state.clone(),
+    id: id.clone()
+  };
+  let address = params.address;
+  tokio::spawn(async move {
+    if let Some((mut listener, registration)) = listener {
+      match Abortable::new(listener.accept(), registration).await {
+        Ok(Ok((stream, addr))) => host_swap(context, stream, addr, controls).await,
+        Ok(Err(err)) => error!("Failed to accept incoming TCP connection for swap {}: {:?}", guard.id, err),
+        Err(_) => info!("Host swap {} was cancelled before a client connected", guard.id)
+      }
+    } else {
+      client_swap(context, address, controls).await;
+    }
+    drop(guard);
+  });
+
+  Ok(json!({ "id": id }))
+}
+
+fn list_swaps(state: &Arc<ControlState>) -> anyhow::Result<Value> {
+  let active = state.swaps.lock().unwrap();
+  let mut ids = SwapDb::list(&state.opts.data_dir)?;
+  // Hosts don't create their record until a client connects
+  for id in active.keys() {
+    if !ids.contains(id) {
+      ids.push(id.clone());
+    }
+  }
+  ids.sort();
+
+  Ok(Value::Array(ids.into_iter().map(|id| json!({ "active": active.contains_key(&id), "id": id })).collect()))
+}
+
+#[derive(Deserialize)]
+struct IdParams {
+  id: String
+}
+
+fn get_swap_status(state: &Arc<ControlState>, params: Value) -> anyhow::Result<Value> {
+  let params: IdParams = serde_json::from_value(params)?;
+  let active = state.swaps.lock().unwrap().contains_key(&params.id);
+  let db = match SwapDb::open(&state.opts.data_dir, &params.id) {
+    Ok(db) => db,
+    Err(_) if active => return Ok(json!({
+      "id": params.id,
+      "step": "AwaitingConnection",
+      "active": true,
+      "done": false
+    })),
+    Err(err) => return Err(err)
+  };
+
+  let record = db.record;
+  let mut txids = serde_json::Map::new();
+  for (label, txid) in record.txids {
+    txids.insert(label, Value::String(txid));
+  }
+  Ok(json!({
+    "id": params.id,
+    "pair": record.pair,
+    "step": format!("{:?}", record.step),
+    "active": active,
+    "done": record.step.is_done(),
+    "terms": record.terms.map(|terms| json!({
+      "scripted_amount": terms.scripted_amount.to_string(),
+      "unscripted_amount": terms.unscripted_amount.to_string(),
+      "tolerance": terms.tolerance_bps,
+      "min_fee_per_byte": terms.scripted_fee_bounds.min.to_string(),
+      "max_fee_per_byte": terms.scripted_fee_bounds.max.to_string(),
+      "t0": terms.timelocks.t0,
+      "t1": terms.timelocks.t1,
+      "cutoff": terms.timelocks.cutoff
+    })),
+    "deposit_address": record.deposit_address,
+    "txids": txids
+  }))
+}
+
+fn confirm_amount(state: &Arc<ControlState>, params: Value) -> anyhow::Result<Value> {
+  #[derive(Deserialize)]
+  struct ConfirmParams {
+    id: String,
+    accept: bool
+  }
+
+  let params: ConfirmParams = serde_json::from_value(params)?;
+  // Don't let a confirmation race the offer, as that would accept amounts never shown
+  let db = SwapDb::open(&state.opts.data_dir, &params.id)?;
+  anyhow::ensure!(
+    db.record.step == SwapStep::Client(ClientStep::AwaitingConfirmation),
+    "Swap {} isn't awaiting confirmation",
+    params.id
+  );
+
+  let confirmation = state.swaps.lock().unwrap().get_mut(&params.id).and_then(|swap| swap.confirmation.take())
+    .ok_or_else(|| anyhow::anyhow!("Swap {} isn't awaiting confirmation", params.id))?;
+  confirmation.send(params.accept).map_err(|_| anyhow::anyhow!("Swap {} is no longer running", params.id))?;
+  Ok(Value::Bool(true))
+}
+
+// Stops the swap, refunding whatever has been sent so far
+fn cancel(state: &Arc<ControlState>, params: Value) -> anyhow::Result<Value> {
+  let params: IdParams = serde_json::from_value(params)?;
+  let swaps = state.swaps.lock().unwrap();
+  let swap = swaps.get(&params.id).ok_or_else(|| anyhow::anyhow!("Swap {} isn't running", params.id))?;
+  if let Some(accept) = swap.accept.as_ref() {
+    accept.abort();
+  }
+  swap.abort.abort();
+  Ok(Value::Bool(true))
+}
@@ -334,3 +334,222 @@ impl CryptEngine for Secp256k1Engine {
     }
   }
 }
+
+/*
+  BIP340 Schnorr adaptor signatures, for a Taproot lock
+  Unlike the ECDSA variant above, the nonce commitment is linear, so no DLEq proof is needed
+  The encrypted signature commits to R = R_offset + Y, and is negated alongside it if R doesn't have an even y coordinate, as BIP340 requires
+  R_offset may also be an aggregate of several signers' nonces, as when MuSig creates the encrypted signature for the Taproot lock
+*/
+pub mod schnorr {
+  use super::*;
+
+  #[derive(Clone, Serialize, Deserialize, PartialEq)]
+  #[allow(non_snake_case)]
+  pub struct SchnorrSignature {
+    pub R_x: [u8; 32],
+    pub s: Scalar::<Public, Zero>,
+  }
+
+  #[derive(Clone, Serialize, Deserialize, PartialEq)]
+  #[allow(non_snake_case)]
+  pub struct SchnorrEncryptedSignature {
+    pub R_offset: Point,
+    pub s_offset: Scalar::<Public, Zero>,
+  }
+
+  pub fn tagged_hash(tag: &str) -> Sha256 {
+    let tag = Sha256::digest(tag.as_bytes());
+    Sha256::new().chain(&tag).chain(&tag)
+  }
+
+  pub fn has_even_y(point: &Point) -> bool {
+    (point.coordinates().1[31] & 1) == 0
+  }
+
+  // The point with the specified x coordinate and an even y coordinate
+  pub fn lift_x(x: [u8; 32]) -> anyhow::Result<Point> {
+    let mut bytes = [2; 33];
+    bytes[1 ..].copy_from_slice(&x);
+    Point::from_bytes(bytes).ok_or_else(|| anyhow::anyhow!("X coordinate isn't on the curve"))
+  }
+
+  #[allow(non_snake_case)]
+  pub fn schnorr_challenge(R_x: &[u8; 32], key_x: &[u8; 32], message: &[u8; 32]) -> Scalar::<Public, Zero> {
+    let challenge: [u8; 32] = tagged_hash("BIP0340/challenge")
+      .chain(R_x)
+      .chain(key_x)
+      .chain(message)
+      .finalize()
+      .into();
+    Scalar::from_bytes_mod_order(challenge).mark::<Public>()
+  }
+
+  pub fn schnorr_message(message: &[u8]) -> anyhow::Result<[u8; 32]> {
+    if message.len() != 32 {
+      anyhow::bail!("Expected message for a Schnorr signature to be 32 byte hash");
+    }
+    let mut message_bytes = [0u8; 32];
+    message_bytes.copy_from_slice(message);
+    Ok(message_bytes)
+  }
+
+  // 1, or -1 if the nonce needs to be negated to have an even y coordinate
+  #[allow(non_snake_case)]
+  fn nonce_sign(R: &Point) -> Scalar {
+    let mut sign = Scalar::one();
+    sign.conditional_negate(!has_even_y(R));
+    sign
+  }
+
+  impl Secp256k1Engine {
+    pub fn x_only(key: &Point) -> [u8; 32] {
+      key.coordinates().0
+    }
+
+    pub fn schnorr_signature_to_bytes(sig: &SchnorrSignature) -> Vec<u8> {
+      let mut result = sig.R_x.to_vec();
+      result.extend(&sig.s.to_bytes());
+      result
+    }
+
+    #[allow(non_snake_case)]
+    pub fn bytes_to_schnorr_signature(bytes: &[u8]) -> anyhow::Result<SchnorrSignature> {
+      if bytes.len() != 64 {
+        anyhow::bail!("Expected Schnorr signature to be 64 bytes long");
+      }
+      let mut R_x = [0; 32];
+      let mut s = [0; 32];
+      R_x.copy_from_slice(&bytes[.. 32]);
+      s.copy_from_slice(&bytes[32 ..]);
+      Ok(SchnorrSignature {
+        R_x,
+        s: Scalar::from_bytes(s).ok_or_else(|| anyhow::anyhow!("Invalid s scalar"))?.mark::<Public>(),
+      })
+    }
+
+    #[allow(non_snake_case)]
+    pub fn schnorr_sign(signing_key: &Scalar, message: &[u8]) -> anyhow::Result<SchnorrSignature> {
+      let message = schnorr_message(message)?;
+      // BIP340 keys are x-only, so the key used is the one with an even y coordinate
+      let mut signing_key = signing_key.clone();
+      let public = g!(signing_key * G).mark::<Normal>();
+      signing_key.conditional_negate(!has_even_y(&public));
+
+      let mut r = Scalar::random(&mut OsRng);
+      let R = g!(r * G).mark::<Normal>();
+      r.conditional_negate(!has_even_y(&R));
+      let R_x = Self::x_only(&R);
+      let challenge = schnorr_challenge(&R_x, &Self::x_only(&public), &message);
+      Ok(SchnorrSignature {
+        R_x,
+        s: s!(r + challenge * signing_key).mark::<Public>(),
+      })
+    }
+
+    #[allow(non_snake_case)]
+    pub fn schnorr_encrypted_sign(
+      signing_key: &Scalar,
+      encryption_key: &Point,
+      message: &[u8]
+    ) -> anyhow::Result<SchnorrEncryptedSignature> {
+      let message = schnorr_message(message)?;
+      // BIP340 keys are x-only, so the key used is the one with an even y coordinate
+      let mut signing_key = signing_key.clone();
+      let public = g!(signing_key * G).mark::<Normal>();
+      signing_key.conditional_negate(!has_even_y(&public));
+      let public_x = Self::x_only(&public);
+
+      let r = Scalar::random(&mut OsRng);
+      let R_offset = g!(r * G).mark::<Normal>();
+      let R = g!(R_offset + encryption_key).mark::<Normal>().mark::<NonZero>()
+        .ok_or_else(|| anyhow::anyhow!("Encrypted Schnorr signature had a zero nonce"))?;
+      let sign = nonce_sign(&R);
+      let challenge = schnorr_challenge(&Self::x_only(&R), &public_x, &message);
+      let sig = SchnorrEncryptedSignature {
+        R_offset,
+        s_offset: s!(sign * r + challenge * signing_key).mark::<Public>(),
+      };
+      trace!(
+        "Generated Schnorr encrypted signature for signing key {}, encryption key {}, and message {}",
+        hex::encode(public_x),
+        hex::encode(encryption_key.to_bytes()),
+        hex::encode(message)
+      );
+      Ok(sig)
+    }
+
+    // Verifies the encrypted signature is for the signing key, which is x-only, once decrypted by the encryption key's discrete log
+    #[allow(non_snake_case)]
+    pub fn schnorr_encrypted_verify(
+      signing_key: &Point,
+      encryption_key: &Point,
+      ciphertext: &SchnorrEncryptedSignature,
+      message: &[u8]
+    ) -> anyhow::Result<()> {
+      let message = schnorr_message(message)?;
+      let SchnorrEncryptedSignature { R_offset, s_offset } = ciphertext;
+      let R = g!(R_offset + encryption_key).mark::<Normal>().mark::<NonZero>()
+        .ok_or_else(|| anyhow::anyhow!("Encrypted Schnorr signature had a zero nonce"))?;
+      let sign = nonce_sign(&R);
+      let public_x = Self::x_only(signing_key);
+      let public = lift_x(public_x)?;
+      let challenge = schnorr_challenge(&Self::x_only(&R), &public_x, &message);
+      if g!(s_offset * G) != g!(sign * R_offset + challenge * public) {
+        anyhow::bail!("Invalid encrypted Schnorr signature");
+      }
+      trace!(
+        "Verified Schnorr encrypted signature for signing key {}, encryption key {}, and message {}",
+        hex::encode(public_x),
+        hex::encode(encryption_key.to_bytes()),
+        hex::encode(message)
+      );
+      Ok(())
+    }
+
+    #[allow(non_snake_case)]
+    pub fn schnorr_decrypt_signature(ciphertext: &SchnorrEncryptedSignature, key: &Scalar) -> anyhow::Result<SchnorrSignature> {
+      let SchnorrEncryptedSignature { R_offset, s_offset } = ciphertext;
+      let R = g!(R_offset + key * G).mark::<Normal>().mark::<NonZero>()
+        .ok_or_else(|| anyhow::anyhow!("Decrypted Schnorr signature had a zero nonce"))?;
+      let sign = nonce_sign(&R);
+      Ok(SchnorrSignature {
+        R_x: Self::x_only(&R),
+        s: s!(s_offset + sign * key).mark::<Public>(),
+      })
+    }
+
+    #[allow(non_snake_case)]
+    pub fn schnorr_recover_key(
+      encryption_key: &Point,
+      ciphertext: &SchnorrEncryptedSignature,
+      sig: &SchnorrSignature
+    ) -> anyhow::Result<Scalar> {
+      let SchnorrEncryptedSignature { R_offset, s_offset } = ciphertext;
+      let R = g!(R_offset + encryption_key).mark::<Normal>().mark::<NonZero>()
+        .ok_or_else(|| anyhow::anyhow!("Encrypted Schnorr signature had a zero nonce"))?;
+      let sign = nonce_sign(&R);
+      let s = &sig.s;
+      let key = s!(sign * (s - s_offset)).mark::<Secret>().mark::<NonZero>()
+        .ok_or_else(|| anyhow::anyhow!("Recovered a zero Schnorr encryption key"))?;
+      if &g!(key * G) != encryption_key {
+        anyhow::bail!("Failed to recover Schnorr encryption key");
+      }
+      Ok(key)
+    }
+
+    #[allow(non_snake_case)]
+    pub fn schnorr_verify(key_x: &[u8; 32], sig: &SchnorrSignature, message: &[u8]) -> anyhow::Result<()> {
+      let message = schnorr_message(message)?;
+      let public = lift_x(*key_x)?;
+      let challenge = schnorr_challenge(&sig.R_x, key_x, &message);
+      let s = &sig.s;
+      let R = g!(s * G - challenge * public).mark::<Normal>().mark::<NonZero>()
+        .ok_or_else(|| anyhow::anyhow!("Schnorr signature had a zero nonce"))?;
+      if (!has_even_y(&R)) || (Self::x_only(&R) != sig.R_x) {
+        anyhow::bail!("Invalid Schnorr signature");
+      }
+      Ok(())
+    }
+  }
+}
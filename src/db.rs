@@ -0,0 +1,261 @@
+use std::{
+  fs::{self, File, OpenOptions, DirBuilder},
+  io::{self, Write},
+  path::{Path, PathBuf}
+};
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, DirBuilderExt, PermissionsExt};
+
+use rand::{rngs::OsRng, RngCore};
+use serde::{Serialize, Deserialize};
+
+use crate::coins::{SwapTerms, ScriptedHost, ScriptedVerifier, UnscriptedClient, UnscriptedVerifier};
+
+/*
+  Swap records and the identity key contain private keys, so they're only accessible to their owner
+  Modes are only applied on Unix; elsewhere, the platform's defaults are used
+*/
+pub fn create_private_dir(dir: &Path) -> io::Result<()> {
+  let mut builder = DirBuilder::new();
+  builder.recursive(true);
+  #[cfg(unix)]
+  builder.mode(0o700);
+  builder.create(dir)
+}
+
+pub fn create_private_file(path: &Path) -> io::Result<File> {
+  let mut options = OpenOptions::new();
+  options.write(true).create(true).truncate(true);
+  #[cfg(unix)]
+  options.mode(0o600);
+  let file = options.open(path)?;
+  // The mode is only used when creating the file, so a pre-existing file is restricted as well
+  #[cfg(unix)]
+  file.set_permissions(fs::Permissions::from_mode(0o600))?;
+  Ok(file)
+}
+
+/// The last step of the swap the host completed.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+pub enum HostStep {
+  Started,
+  KeysVerified,
+  AwaitingDeposit,
+  LockCreated,
+  RefundVerified,
+  LockPublished,
+  BuyPrepared,
+  BuySignatureVerified,
+  SendVerified,
+  SecretSent,
+  Finished,
+  Refunded
+}
+
+/// The last step of the swap the client completed.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+pub enum ClientStep {
+  Started,
+  // Only used when the amounts must be confirmed via the control API
+  AwaitingConfirmation,
+  KeysVerified,
+  RefundSigned,
+  BuyVerified,
+  LockVerified,
+  AwaitingDeposit,
+  Deposited,
+  SecretReceived,
+  Finished,
+  Refunded
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum SwapStep {
+  Host(HostStep),
+  Client(ClientStep)
+}
+
+impl SwapStep {
+  pub fn is_done(self) -> bool {
+    match self {
+      SwapStep::Host(HostStep::Finished) | SwapStep::Host(HostStep::Refunded) => true,
+      SwapStep::Client(ClientStep::Finished) | SwapStep::Client(ClientStep::Refunded) => true,
+      _ => false
+    }
+  }
+
+  // Before keys are exchanged, nothing has been locked or deposited, and no state has been saved
+  pub fn exchanged_keys(self) -> bool {
+    match self {
+      SwapStep::Host(HostStep::Started) => false,
+      SwapStep::Client(ClientStep::Started) | SwapStep::Client(ClientStep::AwaitingConfirmation) => false,
+      _ => true
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SwapRecord {
+  pub pair: String,
+  pub scripted_config: PathBuf,
+  pub unscripted_config: PathBuf,
+  pub step: SwapStep,
+  pub terms: Option<SwapTerms>,
+  pub deposit_address: Option<String>,
+  // Labelled IDs of the scripted transactions known so far
+  pub txids: Vec<(String, String)>,
+  // Opaque states produced by the scripted and unscripted implementations
+  pub scripted: Vec<u8>,
+  pub unscripted: Vec<u8>,
+  // Only set for the client, once the host has sent it
+  pub swap_secret: Option<[u8; 32]>
+}
+
+/*
+  Every swap is stored as its own file, rewritten after each protocol step
+  The write is done to a temporary file which is then renamed over the old record
+  This means a crash mid-write leaves the previous checkpoint intact
+*/
+pub struct SwapDb {
+  pub id: String,
+  path: PathBuf,
+  pub record: SwapRecord
+}
+
+impl SwapDb {
+  fn record_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}.swap", id))
+  }
+
+  pub fn new_id() -> String {
+    let mut id = [0; 8];
+    OsRng.fill_bytes(&mut id);
+    hex::encode(id)
+  }
+
+  pub fn create(
+    dir: &Path,
+    id: String,
+    pair: String,
+    scripted_config: PathBuf,
+    unscripted_config: PathBuf,
+    step: SwapStep
+  ) -> anyhow::Result<SwapDb> {
+    create_private_dir(dir)?;
+    // The directory may predate this instance, in which case it's restricted as well
+    #[cfg(unix)]
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+
+    let db = SwapDb {
+      path: Self::record_path(dir, &id),
+      id,
+      record: SwapRecord {
+        pair,
+        scripted_config,
+        unscripted_config,
+        step,
+        terms: None,
+        deposit_address: None,
+        txids: Vec::new(),
+        scripted: Vec::new(),
+        unscripted: Vec::new(),
+        swap_secret: None
+      }
+    };
+    db.save()?;
+    Ok(db)
+  }
+
+  pub fn open(dir: &Path, id: &str) -> anyhow::Result<SwapDb> {
+    let path = Self::record_path(dir, id);
+    let record = bincode::deserialize(
+      &fs::read(&path).map_err(|e| anyhow::anyhow!("Couldn't read swap {}: {}", id, e))?
+    )?;
+    Ok(SwapDb {
+      id: id.to_string(),
+      path,
+      record
+    })
+  }
+
+  // Every swap in the directory, in no particular order
+  pub fn list(dir: &Path) -> anyhow::Result<Vec<String>> {
+    let mut ids = Vec::new();
+    if !dir.exists() {
+      return Ok(ids);
+    }
+    for entry in fs::read_dir(dir)? {
+      let path = entry?.path();
+      if path.extension().map(|extension| extension == "swap").unwrap_or(false) {
+        if let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) {
+          ids.push(id.to_string());
+        }
+      }
+    }
+    Ok(ids)
+  }
+
+  fn save(&self) -> anyhow::Result<()> {
+    let tmp = self.path.with_extension("swap.tmp");
+    {
+      let mut file = create_private_file(&tmp)?;
+      file.write_all(&bincode::serialize(&self.record).expect("Couldn't serialize the swap record"))?;
+      file.sync_all()?;
+    }
+    fs::rename(&tmp, &self.path)?;
+    Ok(())
+  }
+
+  pub fn checkpoint_host<Host: ScriptedHost, Verifier: UnscriptedVerifier>(
+    &mut self,
+    step: HostStep,
+    host: &Host,
+    verifier: &Verifier
+  ) -> anyhow::Result<()> {
+    self.record.step = SwapStep::Host(step);
+    self.record.txids = Self::label_txids(host.txids());
+    self.record.scripted = host.serialize_state();
+    self.record.unscripted = verifier.serialize_state();
+    self.save()
+  }
+
+  pub fn checkpoint_client<Client: UnscriptedClient, Verifier: ScriptedVerifier>(
+    &mut self,
+    step: ClientStep,
+    client: &Client,
+    verifier: &Verifier
+  ) -> anyhow::Result<()> {
+    self.record.step = SwapStep::Client(step);
+    self.record.txids = Self::label_txids(verifier.txids());
+    self.record.scripted = verifier.serialize_state();
+    self.record.unscripted = client.serialize_state();
+    self.save()
+  }
+
+  fn label_txids(txids: Vec<(&'static str, String)>) -> Vec<(String, String)> {
+    txids.into_iter().map(|(label, txid)| (label.to_string(), txid)).collect()
+  }
+
+  pub fn set_swap_secret(&mut self, swap_secret: [u8; 32]) -> anyhow::Result<()> {
+    self.record.swap_secret = Some(swap_secret);
+    self.save()
+  }
+
+  // Used once the implementations have been consumed, such as after a refund
+  pub fn set_step(&mut self, step: SwapStep) -> anyhow::Result<()> {
+    self.record.step = step;
+    self.save()
+  }
+
+  // Marks a swap which never exchanged keys as refunded, as there's nothing to refund, returning whether it did
+  pub fn abandon_before_keys(&mut self) -> anyhow::Result<bool> {
+    if self.record.step.exchanged_keys() {
+      return Ok(false);
+    }
+    self.set_step(match self.record.step {
+      SwapStep::Host(_) => SwapStep::Host(HostStep::Refunded),
+      SwapStep::Client(_) => SwapStep::Client(ClientStep::Refunded)
+    })?;
+    Ok(true)
+  }
+}
@@ -4,19 +4,29 @@ mod crypt_engines;
 mod coins;
 mod cli;
 mod dl_eq;
+mod channel;
+#[macro_use]
+mod protocol;
+mod db;
+mod control;
 
 #[cfg(test)]
 mod tests;
 
-use std::{panic, time::Duration};
+use std::{
+  panic,
+  path::{Path, PathBuf},
+  net::SocketAddr,
+  time::Duration
+};
 
 use anyhow::Context;
 use log::{error, info};
 use structopt::StructOpt;
 
-use futures::prelude::*;
+use futures::{prelude::*, future::{Abortable, AbortHandle, AbortRegistration}};
 use tokio::{
-  prelude::*,
+  sync::oneshot,
   time::timeout,
   net::{TcpStream, TcpListener}
 };
@@ -24,17 +34,18 @@ use tokio::{
 use crate::{
   coins::{
     *,
-    btc::{host::BtcHost, verifier::BtcVerifier},
+    btc::{BtcChain, host::BtcHost, verifier::BtcVerifier},
+    eth::{host::EthHost, verifier::EthVerifier},
     meros::{client::MerosClient, verifier::MerosVerifier},
     nano::{client::NanoClient, verifier::NanoVerifier},
-    xmr::{client::XmrClient, verifier::XmrVerifier}
+    xmr::{XmrChain, client::XmrClient, verifier::XmrVerifier}
   },
-  cli::{ScriptedCoin, UnscriptedCoin, Cli}
+  cli::{ScriptedCoin, UnscriptedCoin, CoinPair, Cli},
+  channel::{Identity, Channel},
+  protocol::Message,
+  db::{SwapDb, SwapStep, HostStep, ClientStep}
 };
 
-const MAGIC: &[u8] = b"ASMR";
-const MAGIC_RESPONSE: &[u8] = b"ConfirmASMR";
-const MAX_ITEM_LENGTH: u32 = 256 * 1024; // 256 KB. The largest transmitted data is the DL EQ Proof which is still less than this
 const TIMEOUT: Duration = Duration::from_secs(60 * 60); // 1 hour
 
 #[tokio::main]
@@ -42,100 +53,66 @@ async fn main() {
   env_logger::init();
 
   let opts = Cli::from_args();
-  let scripted_config = opts.scripted_config.clone()
-    .unwrap_or_else(|| format!("config/{:?}.json", opts.pair.scripted).to_lowercase().into());
-  let unscripted_config = opts.unscripted_config.clone()
-    .unwrap_or_else(|| format!("config/{:?}.json", opts.pair.unscripted).to_lowercase().into());
+  if let Some(id) = opts.resume.clone() {
+    resume(&opts.data_dir, &id).await;
+    return;
+  }
+  if let Some(address) = opts.rpc {
+    control::serve(address, opts).await;
+    return;
+  }
 
-  let mut listen_handle = None;
-  if opts.host_or_client.is_host() {
-    let mut scripted_host: AnyScriptedHost = match opts.pair.scripted {
-      ScriptedCoin::Bitcoin => BtcHost::new(&scripted_config).map(Into::into),
-    }.expect("Failed to create scripted host");
-    let mut unscripted_verifier: AnyUnscriptedVerifier = match opts.pair.unscripted {
-      UnscriptedCoin::Meros => MerosVerifier::new(&unscripted_config).map(Into::into),
-      UnscriptedCoin::Nano => NanoVerifier::new(&unscripted_config).map(Into::into),
-      UnscriptedCoin::Monero => XmrVerifier::new(&unscripted_config).await.map(Into::into)
-    }.expect("Failed to create unscripted verifier");
+  let host_or_client = opts.host_or_client.expect("Neither resuming a swap nor specifying host or client");
+  let tcp_address = opts.tcp_address.expect("Neither resuming a swap nor specifying a TCP address");
+  let pair = opts.pair.clone().expect("Neither resuming a swap nor specifying a pair");
+  let terms = SwapTerms {
+    scripted_amount: opts.scripted_amount.expect("Neither resuming a swap nor specifying the scripted amount"),
+    unscripted_amount: opts.unscripted_amount.expect("Neither resuming a swap nor specifying the unscripted amount"),
+    tolerance_bps: opts.tolerance,
+    // Narrowed to the configured fee rates once the coins are created
+    scripted_fee_bounds: FeeBounds::unbounded(),
+    // Replaced with the host's configured timelocks once the coins are created
+    timelocks: Timelocks::default()
+  };
+
+  let identity = Identity::load_or_generate(&opts.identity).expect("Failed to load the identity key");
+  println!("Identity key: {}", hex::encode(&identity.public));
 
+  let context = SwapContext::new(&opts, pair, Some(terms), identity);
+
+  let mut listen_handle = None;
+  if host_or_client.is_host() {
     // Have the host also host the server socket
     // As this is a proof of concept, this is a valid simplification
     // It simply removes the need to add another config flag/switch
-    let opts = opts.clone();
+    let context = context.clone();
+    let daemon = opts.daemon;
     listen_handle = Some(tokio::spawn(async move {
-      let mut listener = TcpListener::bind(opts.tcp_address).await
+      let mut listener = TcpListener::bind(tcp_address).await
         .expect("Failed to create TCP listener");
-      info!("Listening as host on {}", opts.tcp_address);
-      let (stream, addr) = listener.accept().await
-        .expect("Failed to accept incoming TCP connection");
-      info!("Got connection from {}", addr);
-
-      let swap_fut = panic::AssertUnwindSafe(host(
-        opts,
-        stream,
-        &mut scripted_host,
-        &mut unscripted_verifier
-      )).catch_unwind();
-      let swap_res = timeout(TIMEOUT, swap_fut).await;
-      let attempt_refund = match swap_res {
-        // Timeout
-        Err(_) => {
-          error!("Host swap timed out");
-          true
+      info!("Listening as host on {}", tcp_address);
+
+      if !daemon {
+        let (stream, addr) = listener.accept().await
+          .expect("Failed to accept incoming TCP connection");
+        host_swap(context, stream, addr, SwapControls::new().0).await;
+        return;
+      }
+
+      // Every swap runs as its own task, so one failing, or even panicking, doesn't affect the others
+      loop {
+        match listener.accept().await {
+          Ok((stream, addr)) => {
+            tokio::spawn(host_swap(context.clone(), stream, addr, SwapControls::new().0));
+          },
+          Err(err) => error!("Failed to accept incoming TCP connection: {:?}", err)
         }
-        // Panic occurred
-        Ok(Err(_)) => true,
-        // Normal error
-        Ok(Ok(Err(err))) => {
-          error!("Error attempting host swap: {:?}", err);
-          true
-        },
-        // Success
-        Ok(Ok(Ok(()))) => false,
-      };
-      if attempt_refund {
-        scripted_host.refund(unscripted_verifier).await.expect("Couldn't call refund");
       }
     }));
   }
 
-  if opts.host_or_client.is_client() {
-    let mut unscripted_client: AnyUnscriptedClient = match opts.pair.unscripted {
-      UnscriptedCoin::Meros => MerosClient::new(&unscripted_config).map(Into::into),
-      UnscriptedCoin::Nano => NanoClient::new(&unscripted_config).map(Into::into),
-      UnscriptedCoin::Monero => XmrClient::new(&unscripted_config).await.map(Into::into)
-    }.expect("Failed to create unscripted client");
-    let mut scripted_verifier: AnyScriptedVerifier = match opts.pair.scripted {
-      ScriptedCoin::Bitcoin => BtcVerifier::new(&scripted_config).map(Into::into),
-    }.expect("Failed to create scripted verifier");
-
-    let stream = TcpStream::connect(opts.tcp_address).await.expect("Failed to connect to host");
-    let swap_fut = panic::AssertUnwindSafe(client(
-      opts,
-      stream,
-      &mut unscripted_client,
-      &mut scripted_verifier
-    )).catch_unwind();
-    let swap_res = timeout(TIMEOUT, swap_fut).await;
-    let attempt_refund = match swap_res {
-      // Timeout
-      Err(_) => {
-        error!("Client swap timed out");
-        true
-      }
-      // Panic occurred
-      Ok(Err(_)) => true,
-      // Normal error
-      Ok(Ok(Err(err))) => {
-        error!("Error attempting client swap: {:?}", err);
-        true
-      },
-      // Success
-      Ok(Ok(Ok(()))) => false,
-    };
-    if attempt_refund {
-      unscripted_client.refund(scripted_verifier).await.expect("Couldn't call refund");
-    }
+  if host_or_client.is_client() {
+    client_swap(context, tcp_address, SwapControls::new().0).await;
   }
 
   if let Some(listen_handle) = listen_handle {
@@ -143,45 +120,357 @@ async fn main() {
   }
 }
 
-async fn write(stream: &mut TcpStream, value: &[u8]) -> anyhow::Result<()> {
-  let len = value.len() as u32;
-  assert!(len <= MAX_ITEM_LENGTH);
-  let len = len.to_le_bytes();
-  stream.write_all(&len).await?;
-  stream.write_all(value).await?;
-  Ok(())
+/*
+  Everything a swap needs which isn't specific to it
+  The host clones this for every connection, creating fresh coin implementations and a fresh database from it
+*/
+#[derive(Clone)]
+struct SwapContext {
+  pair: CoinPair,
+  // Only optional for a client which confirms the host's offer via the control API
+  terms: Option<SwapTerms>,
+  scripted_config: PathBuf,
+  unscripted_config: PathBuf,
+  data_dir: PathBuf,
+  identity: Identity,
+  peer_key: Option<Vec<u8>>
 }
 
-async fn read(stream: &mut TcpStream) -> anyhow::Result<Vec<u8>> {
-  let mut len_buf = [0u8; 4];
-  stream.read_exact(&mut len_buf).await?;
-  let len = u32::from_le_bytes(len_buf);
-  if len > MAX_ITEM_LENGTH {
-    panic!("Attempted to read {} byte item, longer than maximum", len);
+impl SwapContext {
+  fn new(opts: &Cli, pair: CoinPair, terms: Option<SwapTerms>, identity: Identity) -> SwapContext {
+    SwapContext {
+      scripted_config: opts.scripted_config.clone()
+        .unwrap_or_else(|| format!("config/{:?}.json", pair.scripted).to_lowercase().into()),
+      unscripted_config: opts.unscripted_config.clone()
+        .unwrap_or_else(|| format!("config/{:?}.json", pair.unscripted).to_lowercase().into()),
+      pair,
+      terms,
+      data_dir: opts.data_dir.clone(),
+      identity,
+      peer_key: opts.peer_key.clone()
+    }
   }
-  let mut buf = vec![0u8; len as usize];
-  stream.read_exact(&mut buf).await?;
-  Ok(buf)
 }
 
-async fn host(opts: Cli, mut stream: TcpStream, host: &mut AnyScriptedHost, verifier: &mut AnyUnscriptedVerifier) -> anyhow::Result<()> {
-  // Verify the protocol using magic bytes
-  stream.write_all(MAGIC).await.context("Failed to write magic bytes")?;
-  write(&mut stream, opts.pair.to_string().as_bytes()).await.context("Failed to write pair name to socket")?;
-  let mut magic = [0u8; MAGIC_RESPONSE.len()];
-  stream.read_exact(&mut magic).await.context("Failed to read magic bytes")?;
-  anyhow::ensure!(magic == MAGIC_RESPONSE, "Bad magic bytes - is the client an ASMR instance?");
+// What the control API uses to interact with a running swap
+struct SwapControls {
+  id: String,
+  abort: AbortRegistration,
+  confirmation: Option<oneshot::Receiver<bool>>
+}
+
+impl SwapControls {
+  fn new() -> (SwapControls, AbortHandle) {
+    let (handle, abort) = AbortHandle::new_pair();
+    (
+      SwapControls {
+        id: SwapDb::new_id(),
+        abort,
+        confirmation: None
+      },
+      handle
+    )
+  }
+}
+
+/*
+  How the client decides whether to accept the host's offer
+  Either it must match the amounts it was started with, or it's explicitly confirmed via the control API
+*/
+enum TermsApproval {
+  Expect(SwapTerms),
+  Confirm(oneshot::Receiver<bool>)
+}
+
+async fn host_swap(context: SwapContext, stream: TcpStream, addr: SocketAddr, controls: SwapControls) {
+  info!("Got connection from {}", addr);
+
+  let mut scripted_host = create_scripted_host(&context.pair.scripted, &context.scripted_config).await
+    .expect("Failed to create scripted host");
+  let mut unscripted_verifier = create_unscripted_verifier(&context.pair.unscripted, &context.unscripted_config).await
+    .expect("Failed to create unscripted verifier");
+  let id = controls.id;
+  let mut db = SwapDb::create(
+    &context.data_dir,
+    id.clone(),
+    context.pair.to_string().to_lowercase(),
+    context.scripted_config.clone(),
+    context.unscripted_config.clone(),
+    SwapStep::Host(HostStep::Started)
+  ).expect("Failed to create the swap database");
+  println!("Host swap ID for {}: {}. Pass this to --resume if this process is interrupted.", addr, id);
+
+  /*
+    The handshake is part of the swap future so a stalled peer is subject to the same timeout and cancellation
+    Nothing has been exchanged if it fails, so the refund is a no-op
+  */
+  let swap_fut = Abortable::new(
+    panic::AssertUnwindSafe(async {
+      let channel = Channel::respond(stream, &context.identity, context.peer_key.as_deref()).await
+        .with_context(|| format!("Failed to establish an encrypted channel with {}", addr))?;
+      host(
+        &context.pair,
+        context.terms.expect("Hosting a swap without terms"),
+        channel,
+        &mut scripted_host,
+        &mut unscripted_verifier,
+        &mut db
+      ).await
+    }).catch_unwind(),
+    controls.abort
+  );
+  let swap_res = timeout(TIMEOUT, swap_fut).await;
+  let attempt_refund = match swap_res {
+    // Timeout
+    Err(_) => {
+      error!("Host swap {} timed out", id);
+      true
+    }
+    // Cancelled
+    Ok(Err(_)) => {
+      info!("Host swap {} was cancelled", id);
+      true
+    },
+    // Panic occurred
+    Ok(Ok(Err(_))) => true,
+    // Normal error
+    Ok(Ok(Ok(Err(err)))) => {
+      error!("Error attempting host swap {}: {:?}", id, err);
+      true
+    },
+    // Success
+    Ok(Ok(Ok(Ok(())))) => false,
+  };
+  if attempt_refund {
+    scripted_host.refund(unscripted_verifier).await.expect("Couldn't call refund");
+    db.set_step(SwapStep::Host(HostStep::Refunded)).expect("Couldn't save the swap as refunded");
+    info!("Host swap {} refunded", id);
+  } else {
+    info!("Host swap {} finished", id);
+  }
+}
+
+async fn client_swap(context: SwapContext, tcp_address: SocketAddr, controls: SwapControls) {
+  let mut unscripted_client = create_unscripted_client(&context.pair.unscripted, &context.unscripted_config).await
+    .expect("Failed to create unscripted client");
+  let mut scripted_verifier = create_scripted_verifier(&context.pair.scripted, &context.scripted_config).await
+    .expect("Failed to create scripted verifier");
+  let id = controls.id;
+  let mut db = SwapDb::create(
+    &context.data_dir,
+    id.clone(),
+    context.pair.to_string().to_lowercase(),
+    context.scripted_config.clone(),
+    context.unscripted_config.clone(),
+    SwapStep::Client(ClientStep::Started)
+  ).expect("Failed to create the swap database");
+  println!("Client swap ID: {}. Pass this to --resume if this process is interrupted.", id);
+
+  let approval = match controls.confirmation {
+    Some(confirmation) => TermsApproval::Confirm(confirmation),
+    None => TermsApproval::Expect(context.terms.expect("Neither expecting terms nor confirming them"))
+  };
+
+  // As with the host, connecting and the handshake are subject to the swap's timeout and cancellation
+  let swap_fut = Abortable::new(
+    panic::AssertUnwindSafe(async {
+      let stream = TcpStream::connect(tcp_address).await.context("Failed to connect to the host")?;
+      let channel = Channel::initiate(stream, &context.identity, context.peer_key.as_deref()).await
+        .context("Failed to establish an encrypted channel with the host")?;
+      client(
+        &context.pair,
+        approval,
+        channel,
+        &mut unscripted_client,
+        &mut scripted_verifier,
+        &mut db
+      ).await
+    }).catch_unwind(),
+    controls.abort
+  );
+  let swap_res = timeout(TIMEOUT, swap_fut).await;
+  let attempt_refund = match swap_res {
+    // Timeout
+    Err(_) => {
+      error!("Client swap {} timed out", id);
+      true
+    }
+    // Cancelled
+    Ok(Err(_)) => {
+      info!("Client swap {} was cancelled", id);
+      true
+    },
+    // Panic occurred
+    Ok(Ok(Err(_))) => true,
+    // Normal error
+    Ok(Ok(Ok(Err(err)))) => {
+      error!("Error attempting client swap {}: {:?}", id, err);
+      true
+    },
+    // Success
+    Ok(Ok(Ok(Ok(())))) => false,
+  };
+  if attempt_refund {
+    unscripted_client.refund(scripted_verifier).await.expect("Couldn't call refund");
+    db.set_step(SwapStep::Client(ClientStep::Refunded)).expect("Couldn't save the swap as refunded");
+  }
+}
+
+async fn create_scripted_host(coin: &ScriptedCoin, config: &Path) -> anyhow::Result<AnyScriptedHost> {
+  match coin {
+    ScriptedCoin::Bitcoin => BtcHost::new(config, BtcChain::Bitcoin).await.map(Into::into),
+    ScriptedCoin::Litecoin => BtcHost::new(config, BtcChain::Litecoin).await.map(Into::into),
+    ScriptedCoin::Ethereum => EthHost::new(config).await.map(Into::into),
+  }
+}
+
+async fn create_scripted_verifier(coin: &ScriptedCoin, config: &Path) -> anyhow::Result<AnyScriptedVerifier> {
+  match coin {
+    ScriptedCoin::Bitcoin => BtcVerifier::new(config, BtcChain::Bitcoin).await.map(Into::into),
+    ScriptedCoin::Litecoin => BtcVerifier::new(config, BtcChain::Litecoin).await.map(Into::into),
+    ScriptedCoin::Ethereum => EthVerifier::new(config).await.map(Into::into),
+  }
+}
+
+async fn create_unscripted_verifier(coin: &UnscriptedCoin, config: &Path) -> anyhow::Result<AnyUnscriptedVerifier> {
+  match coin {
+    UnscriptedCoin::Meros => MerosVerifier::new(config).map(Into::into),
+    UnscriptedCoin::Nano => NanoVerifier::new(config).await.map(Into::into),
+    UnscriptedCoin::Monero => XmrVerifier::new(config, XmrChain::Monero).await.map(Into::into),
+    UnscriptedCoin::Wownero => XmrVerifier::new(config, XmrChain::Wownero).await.map(Into::into)
+  }
+}
+
+async fn create_unscripted_client(coin: &UnscriptedCoin, config: &Path) -> anyhow::Result<AnyUnscriptedClient> {
+  match coin {
+    UnscriptedCoin::Meros => MerosClient::new(config).map(Into::into),
+    UnscriptedCoin::Nano => NanoClient::new(config).await.map(Into::into),
+    UnscriptedCoin::Monero => XmrClient::new(config, XmrChain::Monero).await.map(Into::into),
+    UnscriptedCoin::Wownero => XmrClient::new(config, XmrChain::Wownero).await.map(Into::into)
+  }
+}
+
+/*
+  Resuming never reconnects to the counterparty
+  Their side has almost certainly timed out by now, or will shortly, and the protocol doesn't support re-establishing a session
+  Instead, we continue with whatever can still be done without them, which is either finishing the swap or refunding
+*/
+async fn resume(data_dir: &Path, id: &str) {
+  let mut db = SwapDb::open(data_dir, id).expect("Couldn't open the swap to resume");
+  if db.record.step.is_done() {
+    println!("Swap {} already completed ({:?}).", id, db.record.step);
+    return;
+  }
+  // The implementations' states are only saved once keys are exchanged, so there's nothing to restore before then
+  if db.abandon_before_keys().expect("Couldn't save the swap as refunded") {
+    println!("Swap {} never exchanged keys, so there was nothing to refund.", id);
+    return;
+  }
+  info!("Resuming swap {} from {:?}", id, db.record.step);
+
+  let pair: CoinPair = db.record.pair.parse().expect("Swap database contained an invalid pair");
+  let scripted_config = db.record.scripted_config.clone();
+  let unscripted_config = db.record.unscripted_config.clone();
+
+  match db.record.step {
+    SwapStep::Host(step) => {
+      let mut host = create_scripted_host(&pair.scripted, &scripted_config).await
+        .expect("Failed to create scripted host");
+      let mut verifier = create_unscripted_verifier(&pair.unscripted, &unscripted_config).await
+        .expect("Failed to create unscripted verifier");
+      host.restore_state(&db.record.scripted).expect("Couldn't restore the scripted host's state");
+      verifier.restore_state(&db.record.unscripted).expect("Couldn't restore the unscripted verifier's state");
+
+      // Once the swap secret is sent, the client can buy from the lock, so try to claim the unscripted coin first
+      // If they never do, refund will still succeed, or will detect the buy and finish instead
+      if step == HostStep::SecretSent {
+        match timeout(TIMEOUT, verifier.finish(&host)).await {
+          Ok(Ok(())) => {
+            db.set_step(SwapStep::Host(HostStep::Finished)).expect("Couldn't save the swap as finished");
+            return;
+          },
+          Ok(Err(err)) => error!("Error finishing resumed host swap: {:?}", err),
+          Err(_) => error!("Resumed host swap timed out")
+        }
+      }
+
+      host.refund(verifier).await.expect("Couldn't call refund");
+      db.set_step(SwapStep::Host(HostStep::Refunded)).expect("Couldn't save the swap as refunded");
+    },
+
+    SwapStep::Client(step) => {
+      let mut client = create_unscripted_client(&pair.unscripted, &unscripted_config).await
+        .expect("Failed to create unscripted client");
+      let mut verifier = create_scripted_verifier(&pair.scripted, &scripted_config).await
+        .expect("Failed to create scripted verifier");
+      verifier.restore_state(&db.record.scripted).expect("Couldn't restore the scripted verifier's state");
+      client.restore_state(&db.record.unscripted).expect("Couldn't restore the unscripted client's state");
+
+      if step == ClientStep::SecretReceived {
+        let swap_secret = db.record.swap_secret.expect("Swap database had received the secret without storing it");
+        match timeout(TIMEOUT, verifier.finish(&swap_secret)).await {
+          Ok(Ok(())) => {
+            db.set_step(SwapStep::Client(ClientStep::Finished)).expect("Couldn't save the swap as finished");
+            return;
+          },
+          Ok(Err(err)) => error!("Error finishing resumed client swap: {:?}", err),
+          Err(_) => error!("Resumed client swap timed out")
+        }
+      } else if step == ClientStep::AwaitingDeposit {
+        // A deposit may have been made while we weren't running, in which case it must be tracked to be refunded
+        println!("Send to {} and this will automatically proceed when funds are confirmed.", client.get_address());
+        if timeout(TIMEOUT, client.wait_for_deposit()).await.is_err() {
+          error!("Resumed client swap timed out waiting for a deposit");
+        }
+      }
+
+      client.refund(verifier).await.expect("Couldn't call refund");
+      db.set_step(SwapStep::Client(ClientStep::Refunded)).expect("Couldn't save the swap as refunded");
+    }
+  }
+}
+
+async fn host(
+  pair: &CoinPair,
+  terms: SwapTerms,
+  mut channel: Channel,
+  host: &mut AnyScriptedHost,
+  verifier: &mut AnyUnscriptedVerifier,
+  db: &mut SwapDb
+) -> anyhow::Result<()> {
+  // Agree on the pair and protocol version
+  let version = protocol::host_hello(&mut channel, &pair.to_string()).await.context("Failed to agree on the swap with the client")?;
+  info!("Using protocol version {}", version);
+
+  // Fix the amounts, fee rates, and timelocks before anything is exchanged, so every later step can be verified against them
+  let timelocks = host.timelocks();
+  timelocks.verify(host.block_seconds(), verifier.settlement_seconds()).context("The configured timelocks are unsafe for this pair")?;
+  let terms = SwapTerms {
+    scripted_fee_bounds: terms.scripted_fee_bounds.intersect(&host.fee_bounds())
+      .ok_or_else(|| anyhow::anyhow!("Specified fee rates don't overlap with the configured fee rates"))?,
+    timelocks,
+    ..terms
+  };
+  let terms = protocol::host_offer(&mut channel, terms).await.context("The client didn't accept our terms")?;
+  host.set_terms(terms);
+  verifier.set_terms(terms);
+  db.record.terms = Some(terms);
 
   // Send over our keys
   // Namely the DL EQ proof, scripted lock/refund keys, and scripted destination key
-  write(&mut stream, &host.generate_keys(verifier)).await?;
+  protocol::send(&mut channel, &Message::Keys(host.generate_keys(verifier))).await?;
 
   // Read and verify the keys
-  host.verify_keys(&read(&mut stream).await?, verifier).context("Couldn't verify client DlEq proof")?;
+  host.verify_keys(&receive!(&mut channel, Keys), verifier).context("Couldn't verify client DlEq proof")?;
+  db.checkpoint_host(HostStep::KeysVerified, host, verifier)?;
 
   // Have funds enter the system
   // We use our own intermediate address to ensure the transaction isn't malleable, a problem with BTC solved via SegWit
-  println!("Send to {} and this will automatically proceed when funds are confirmed.", host.generate_deposit_address());
+  // The deposit address's key is checkpointed before it's shown so funds sent to it are never unrecoverable
+  let deposit_address = host.generate_deposit_address();
+  db.record.deposit_address = Some(deposit_address.clone());
+  db.checkpoint_host(HostStep::AwaitingDeposit, host, verifier)?;
+  println!("Swap {}: send to {} and this will automatically proceed when funds are confirmed.", db.id, deposit_address);
 
   /*
     Now that we've exchanged the relevant keys, it's time to start on the transactions
@@ -208,57 +497,99 @@ async fn host(opts: Cli, mut stream: TcpStream, host: &mut AnyScriptedHost, veri
     Their only meaningful info is the keys used for them, which was included in the above key transmission
     That said, the refund signature does still need to be transmitted
   */
-  write(&mut stream, &host.create_lock_and_prepare_refund().await.context("Couldn't create the BTC lock")?).await?;
+  let lock_and_refund = host.create_lock_and_prepare_refund().await.context("Couldn't create the BTC lock")?;
+  db.checkpoint_host(HostStep::LockCreated, host, verifier)?;
+  protocol::send(&mut channel, &Message::LockAndRefund(lock_and_refund)).await?;
 
   // Next, we have to receive the client's signature for the refund
   // As well as the client's encrypted signature for our claim of the refund
-  host.verify_refund_and_spend(&read(&mut stream).await?)?;
+  host.verify_refund_and_spend(&receive!(&mut channel, RefundAndSpendSignatures))?;
+  db.checkpoint_host(HostStep::RefundVerified, host, verifier)?;
 
   // Once we have our failure path secured, we publish the lock and move on
   host.publish_lock().await.context("Couldn't publish the lock")?;
+  db.checkpoint_host(HostStep::LockPublished, host, verifier)?;
 
   // In order for the client to be able to now purchase from our lock, we need to prepare buy transaction for them
   // This is sent over with an encrypted signature so when they publish the decrypted version, we learn their key
-  write(&mut stream, &host.prepare_buy_for_client().await.context("Couldn't prepare the buy")?).await?;
+  let buy = host.prepare_buy_for_client().await.context("Couldn't prepare the buy")?;
+  db.checkpoint_host(HostStep::BuyPrepared, host, verifier)?;
+  protocol::send(&mut channel, &Message::Buy(buy)).await?;
+
+  // If the buy is signed by both of us, the client replies with their half of its signature, which we verify before continuing
+  host.verify_buy_signature(&receive!(&mut channel, BuySignature)).context("Couldn't verify the client's signature for the buy")?;
+  db.checkpoint_host(HostStep::BuySignatureVerified, host, verifier)?;
 
   // Now, we wait for the unscripted send to appear
   verifier.verify_and_wait_for_send().await.context("Couldn't verify and wait for the unscripted send")?;
+  db.checkpoint_host(HostStep::SendVerified, host, verifier)?;
 
   // Now that we've verified both transactions are on their networks and confirmed, we transmit the swap secret
-  write(&mut stream, &host.swap_secret()).await?;
+  protocol::send(&mut channel, &Message::SwapSecret(host.swap_secret())).await?;
+  db.checkpoint_host(HostStep::SecretSent, host, verifier)?;
 
   // Finally, we watch for the client to buy from the lock
   // Then we can recover the key and claim the other coin
   verifier.finish(host).await.context("Couldn't finish buying the unscripted coin")?;
+  db.checkpoint_host(HostStep::Finished, host, verifier)?;
   Ok(())
 }
 
-async fn client(opts: Cli, mut stream: TcpStream, client: &mut AnyUnscriptedClient, verifier: &mut AnyScriptedVerifier) -> anyhow::Result<()> {
+async fn client(
+  pair: &CoinPair,
+  approval: TermsApproval,
+  mut channel: Channel,
+  client: &mut AnyUnscriptedClient,
+  verifier: &mut AnyScriptedVerifier,
+  db: &mut SwapDb
+) -> anyhow::Result<()> {
   // The majority of comments explaining this protocol and this implementation is in the host function
   // The comments here are meant to explain the client-specific side of things
 
-  let mut magic = [0u8; MAGIC.len()];
-  stream.read_exact(&mut magic).await.context("Failed to read magic bytes")?;
-  anyhow::ensure!(magic == MAGIC, "Bad magic bytes - is the host an ASMR instance?");
-  let remote_pair = read(&mut stream).await?;
-  if remote_pair != opts.pair.to_string().as_bytes() {
-    anyhow::bail!("The host is attempting to exchange a different pair");
-  }
-  stream.write_all(MAGIC_RESPONSE).await.context("Failed to write magic bytes")?;
+  let version = protocol::client_hello(&mut channel, &pair.to_string()).await.context("Failed to agree on the swap with the host")?;
+  info!("Using protocol version {}", version);
+
+  let offer = protocol::receive_offer(&mut channel).await?;
+  let narrowed = protocol::narrow_offer(&offer, &verifier.fee_bounds()).and_then(
+    |terms| protocol::check_timelocks(&terms, &verifier.timelocks(), verifier.block_seconds(), client.settlement_seconds()).map(|_| terms)
+  );
+  let decision = match (narrowed, approval) {
+    (Err(reason), _) => Err(reason),
+    (Ok(terms), TermsApproval::Expect(expected)) => protocol::check_offer(&terms, &expected).map(|_| terms),
+    (Ok(terms), TermsApproval::Confirm(confirmation)) => {
+      // Expose the offer so it can be confirmed
+      db.record.terms = Some(terms);
+      db.set_step(SwapStep::Client(ClientStep::AwaitingConfirmation))?;
+      info!("Swap {}: awaiting confirmation of {:?}", db.id, terms);
+      if confirmation.await.unwrap_or(false) {
+        Ok(terms)
+      } else {
+        Err("The offered amounts were declined".to_string())
+      }
+    }
+  };
+  let terms = protocol::respond_to_offer(&mut channel, decision).await.context("Couldn't accept the host's terms")?;
+  verifier.set_terms(terms);
+  db.record.terms = Some(terms);
 
-  write(&mut stream, &client.generate_keys(verifier)).await?;
-  client.verify_keys(&read(&mut stream).await?, verifier).context("Couldn't verify host DlEq proof")?;
+  protocol::send(&mut channel, &Message::Keys(client.generate_keys(verifier))).await?;
+  client.verify_keys(&receive!(&mut channel, Keys), verifier).context("Couldn't verify host DlEq proof")?;
+  db.checkpoint_client(ClientStep::KeysVerified, client, verifier)?;
 
   // Receive the host's refund signature and send back our own
   // Also offer them a way to claim the refund transaction if our side cancels/errors
   let refund_and_spend_signatures = verifier.complete_refund_and_prepare_spend(
-    &read(&mut stream).await?
+    &receive!(&mut channel, LockAndRefund)
   ).await.context("Couldn't complete the refund transaction")?;
-  write(&mut stream, &refund_and_spend_signatures).await?;
+  db.checkpoint_client(ClientStep::RefundSigned, client, verifier)?;
+  protocol::send(&mut channel, &Message::RefundAndSpendSignatures(refund_and_spend_signatures)).await?;
 
   // Receive info about the buy transaction we will end up publishing
   // Namely, the host's signature, which we'll use to verify the buy and make sure we should continue
-  verifier.verify_prepared_buy(&read(&mut stream).await?)?;
+  // If the buy is signed by both of us, we reply with our half of its signature
+  let buy_signature = verifier.verify_prepared_buy(&receive!(&mut channel, Buy))?;
+  db.checkpoint_client(ClientStep::BuyVerified, client, verifier)?;
+  protocol::send(&mut channel, &Message::BuySignature(buy_signature)).await?;
 
   /*
     We now need to finally verify the lock, as well as start tracking it
@@ -268,13 +599,23 @@ async fn client(opts: Cli, mut stream: TcpStream, client: &mut AnyUnscriptedClie
     The first is preferred due it lowering the amount of data transferred between the two parties
   */
   verifier.verify_and_wait_for_lock().await.context("Couldn't verify the lock")?;
+  db.checkpoint_client(ClientStep::LockVerified, client, verifier)?;
 
   // Now that the lock is on chain and we have everything we need to buy its funds, we need to publish our transaction
-  println!("Send to {} and this will automatically proceed when funds are confirmed.", client.get_address());
+  let deposit_address = client.get_address();
+  db.record.deposit_address = Some(deposit_address.clone());
+  db.checkpoint_client(ClientStep::AwaitingDeposit, client, verifier)?;
+  println!("Send to {} and this will automatically proceed when funds are confirmed.", deposit_address);
   client.wait_for_deposit().await?;
+  db.checkpoint_client(ClientStep::Deposited, client, verifier)?;
 
   // Now, receive the swap secret and finish buying the funds locked by the host
-  verifier.finish(&read(&mut stream).await?).await.context("Couldn't finishing buying the scripted coin")?;
+  // It's saved before being used so a crash while buying doesn't lose the only way to claim the lock
+  let swap_secret = receive!(&mut channel, SwapSecret);
+  db.set_swap_secret(swap_secret)?;
+  db.checkpoint_client(ClientStep::SecretReceived, client, verifier)?;
+  verifier.finish(&swap_secret).await.context("Couldn't finishing buying the scripted coin")?;
+  db.checkpoint_client(ClientStep::Finished, client, verifier)?;
 
   Ok(())
 }
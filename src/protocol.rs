@@ -0,0 +1,254 @@
+use serde::{Serialize, Deserialize};
+
+use crate::{channel::Channel, coins::{FeeBounds, Timelocks, SwapTerms}};
+
+/*
+  The version of the messages below, and the order they're exchanged in
+  Increment this whenever either changes after a release, updating MIN_PROTOCOL_VERSION if the old behavior is no longer supported
+*/
+pub const PROTOCOL_VERSION: u16 = 1;
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+
+/*
+  Every message exchanged between the host and client after the handshake
+  The coin-specific payloads are still opaque, as only the relevant coin implementation can decode them
+  Tagging them still means a message sent out of order is detected before any coin code interprets it
+*/
+#[derive(Serialize, Deserialize)]
+pub enum Message {
+  // Sent by the host
+  Hello {
+    version: u16,
+    min_version: u16,
+    pair: String
+  },
+  // Sent by the client with the version both sides will use
+  HelloAck {
+    version: u16
+  },
+  // Sent by either side when refusing to continue
+  Reject {
+    reason: String
+  },
+
+  // Sent by the host, with the client replying with Accept, containing the final terms, or Reject
+  Offer(SwapTerms),
+  Accept(SwapTerms),
+
+  Keys(Vec<u8>),
+  LockAndRefund(Vec<u8>),
+  RefundAndSpendSignatures(Vec<u8>),
+  Buy(Vec<u8>),
+  // Sent by the client, with its half of the buy's signature when the host and client both sign it
+  BuySignature(Vec<u8>),
+  SwapSecret([u8; 32])
+}
+
+impl Message {
+  pub fn name(&self) -> &'static str {
+    match self {
+      Message::Hello { .. } => "Hello",
+      Message::HelloAck { .. } => "HelloAck",
+      Message::Reject { .. } => "Reject",
+      Message::Offer(_) => "Offer",
+      Message::Accept(_) => "Accept",
+      Message::Keys(_) => "Keys",
+      Message::LockAndRefund(_) => "LockAndRefund",
+      Message::RefundAndSpendSignatures(_) => "RefundAndSpendSignatures",
+      Message::Buy(_) => "Buy",
+      Message::BuySignature(_) => "BuySignature",
+      Message::SwapSecret(_) => "SwapSecret"
+    }
+  }
+
+  pub fn unexpected(&self, expected: &str) -> anyhow::Error {
+    anyhow::anyhow!("Expected a {} message yet the peer sent {}, violating the protocol", expected, self.name())
+  }
+}
+
+pub async fn send(channel: &mut Channel, message: &Message) -> anyhow::Result<()> {
+  channel.write(&bincode::serialize(message).expect("Couldn't serialize a protocol message")).await
+}
+
+pub async fn receive(channel: &mut Channel) -> anyhow::Result<Message> {
+  let message: Message = bincode::deserialize(&channel.read().await?).map_err(
+    |_| anyhow::anyhow!("Couldn't decode the peer's message; they're likely running an incompatible version of ASMR")
+  )?;
+  if let Message::Reject { reason } = message {
+    anyhow::bail!("Peer refused to continue: {}", reason);
+  }
+  Ok(message)
+}
+
+// Receives the specified message, erroring if the peer sent any other
+macro_rules! receive {
+  ($channel: expr, $variant: ident) => {
+    match $crate::protocol::receive($channel).await? {
+      $crate::protocol::Message::$variant(value) => value,
+      other => return Err(other.unexpected(stringify!($variant)))
+    }
+  }
+}
+
+// Returns the highest version within both inclusive ranges, or the reason there isn't one
+pub fn negotiate_between(ours: (u16, u16), theirs: (u16, u16)) -> Result<u16, String> {
+  let negotiated = theirs.1.min(ours.1);
+  if negotiated < theirs.0.max(ours.0) {
+    Err(
+      format!(
+        "Incompatible protocol versions (ours are {}-{}, theirs are {}-{})",
+        ours.0, ours.1, theirs.0, theirs.1
+      )
+    )
+  } else {
+    Ok(negotiated)
+  }
+}
+
+// Returns the version to use, or the reason the peer's versions aren't compatible with ours
+pub fn negotiate_version(version: u16, min_version: u16) -> Result<u16, String> {
+  negotiate_between((MIN_PROTOCOL_VERSION, PROTOCOL_VERSION), (min_version, version))
+}
+
+/*
+  Verifies the client is trading the same pair and a compatible protocol version
+  Any refusal is sent to the peer before erroring, so they see why instead of just a closed connection
+*/
+pub async fn host_hello(channel: &mut Channel, pair: &str) -> anyhow::Result<u16> {
+  send(channel, &Message::Hello {
+    version: PROTOCOL_VERSION,
+    min_version: MIN_PROTOCOL_VERSION,
+    pair: pair.to_string()
+  }).await?;
+
+  match receive(channel).await? {
+    Message::HelloAck { version } => {
+      if (version < MIN_PROTOCOL_VERSION) || (version > PROTOCOL_VERSION) {
+        let reason = format!("Client selected protocol version {}, which we don't support", version);
+        send(channel, &Message::Reject { reason: reason.clone() }).await?;
+        anyhow::bail!(reason);
+      }
+      Ok(version)
+    },
+    other => Err(other.unexpected("HelloAck"))
+  }
+}
+
+pub async fn client_hello(channel: &mut Channel, pair: &str) -> anyhow::Result<u16> {
+  let (version, min_version, remote_pair) = match receive(channel).await? {
+    Message::Hello { version, min_version, pair } => (version, min_version, pair),
+    other => return Err(other.unexpected("Hello"))
+  };
+
+  let result = if remote_pair != pair {
+    Err(format!("The host is attempting to exchange a different pair ({})", remote_pair))
+  } else {
+    negotiate_version(version, min_version)
+  };
+
+  match result {
+    Ok(version) => {
+      send(channel, &Message::HelloAck { version }).await?;
+      Ok(version)
+    },
+    Err(reason) => {
+      send(channel, &Message::Reject { reason: reason.clone() }).await?;
+      anyhow::bail!(reason);
+    }
+  }
+}
+
+// Returns the terms as accepted by the client, which may only have narrowed the fee bounds
+pub async fn host_offer(channel: &mut Channel, terms: SwapTerms) -> anyhow::Result<SwapTerms> {
+  send(channel, &Message::Offer(terms)).await?;
+  let accepted = receive!(channel, Accept);
+  let narrowed = accepted.scripted_fee_bounds.intersect(&terms.scripted_fee_bounds) == Some(accepted.scripted_fee_bounds);
+  if (SwapTerms { scripted_fee_bounds: terms.scripted_fee_bounds, ..accepted } != terms) || !narrowed {
+    let reason = format!("Client accepted different terms ({:?}) than offered", accepted);
+    send(channel, &Message::Reject { reason: reason.clone() }).await?;
+    anyhow::bail!(reason);
+  }
+  Ok(accepted)
+}
+
+pub async fn receive_offer(channel: &mut Channel) -> anyhow::Result<SwapTerms> {
+  Ok(receive!(channel, Offer))
+}
+
+/*
+  When not confirmed by the user, the client only accepts the exact amounts it was configured with
+  A tolerance tighter than our own is fine, as it only limits what the host can get away with
+*/
+pub fn check_offer(offer: &SwapTerms, expected: &SwapTerms) -> Result<(), String> {
+  if (offer.scripted_amount != expected.scripted_amount) || (offer.unscripted_amount != expected.unscripted_amount) {
+    Err(
+      format!(
+        "Offered amounts ({}, {}) don't match the expected amounts ({}, {})",
+        offer.scripted_amount, offer.unscripted_amount, expected.scripted_amount, expected.unscripted_amount
+      )
+    )
+  } else if offer.tolerance_bps > expected.tolerance_bps {
+    Err(format!("Offered tolerance of {} bps exceeds the maximum of {} bps", offer.tolerance_bps, expected.tolerance_bps))
+  } else {
+    Ok(())
+  }
+}
+
+// Narrows the offered fee bounds to those we also accept
+pub fn narrow_offer(offer: &SwapTerms, fee_bounds: &FeeBounds) -> Result<SwapTerms, String> {
+  match offer.scripted_fee_bounds.intersect(fee_bounds) {
+    Some(scripted_fee_bounds) => Ok(SwapTerms { scripted_fee_bounds, ..*offer }),
+    None => Err(
+      format!(
+        "Offered fee rates ({}-{}) don't overlap with ours ({}-{})",
+        offer.scripted_fee_bounds.min, offer.scripted_fee_bounds.max, fee_bounds.min, fee_bounds.max
+      )
+    )
+  }
+}
+
+/*
+  Refuses timelocks which don't leave time for the swap to safely complete
+  The buy must also be given at least as many blocks to confirm as our own configuration gives it
+*/
+pub fn check_timelocks(
+  offer: &SwapTerms,
+  ours: &Timelocks,
+  block_seconds: u64,
+  settlement_seconds: u64
+) -> Result<(), String> {
+  let offered = offer.timelocks;
+  offered.verify(block_seconds, settlement_seconds).map_err(|e| e.to_string())?;
+  if (offered.t0 - offered.cutoff) < ours.t0.saturating_sub(ours.cutoff) {
+    Err(
+      format!(
+        "Offered timelocks only leave {} blocks for the buy to confirm, when we require {}",
+        offered.t0 - offered.cutoff, ours.t0.saturating_sub(ours.cutoff)
+      )
+    )
+  } else {
+    Ok(())
+  }
+}
+
+// Any refusal is sent to the host before erroring
+pub async fn respond_to_offer(channel: &mut Channel, decision: Result<SwapTerms, String>) -> anyhow::Result<SwapTerms> {
+  match decision {
+    Ok(terms) => {
+      send(channel, &Message::Accept(terms)).await?;
+      Ok(terms)
+    },
+    Err(reason) => {
+      send(channel, &Message::Reject { reason: reason.clone() }).await?;
+      anyhow::bail!(reason);
+    }
+  }
+}
+
+// The expected terms' fee bounds are used as our own
+#[cfg(test)]
+pub async fn client_accept(channel: &mut Channel, expected: SwapTerms) -> anyhow::Result<SwapTerms> {
+  let offer = receive_offer(channel).await?;
+  let decision = check_offer(&offer, &expected).and_then(|_| narrow_offer(&offer, &expected.scripted_fee_bounds));
+  respond_to_offer(channel, decision).await
+}
@@ -6,7 +6,7 @@ use std::{
 use crate::{
   coins::{
     *,
-    btc::{host::BtcHost, verifier::BtcVerifier},
+    btc::{BtcChain, host::BtcHost, verifier::BtcVerifier},
     meros::{client::MerosClient, verifier::MerosVerifier}
   },
   tests::swap::{
@@ -28,26 +28,38 @@ use crate::{
   }
 };
 
-pub async fn run_test<F, Fut>(host_test: bool, test: F)
+pub async fn run_test<F, Fut>(chain: BtcChain, host_test: bool, test: F)
   where F: FnOnce(AnyScriptedHost, AnyUnscriptedVerifier, AnyUnscriptedClient, AnyScriptedVerifier) -> Fut,
     Fut: Future<Output = anyhow::Result<bool>>
 {
-  let scripted: PathBuf = "config/bitcoin.json".to_string().into();
+  let scripted: PathBuf = format!("config/{:?}.json", chain).to_lowercase().into();
   let unscripted: PathBuf = "config/meros.json".to_string().into();
 
-  let mut host: AnyScriptedHost = BtcHost::new(&scripted).expect("Failed to create BTC host").into();
+  let mut host: AnyScriptedHost = BtcHost::new(&scripted, chain).await.expect("Failed to create the scripted host").into();
   host.override_refund_with_random_address();
   let host_refund = host.get_refund_address();
-  let hosts_verifier: AnyUnscriptedVerifier = MerosVerifier::new(&unscripted).expect("Failed to create BTC verifier").into();
+  let mut hosts_verifier: AnyUnscriptedVerifier = MerosVerifier::new(&unscripted).expect("Failed to create BTC verifier").into();
 
   let mut client: AnyUnscriptedClient = MerosClient::new(&unscripted).expect("Failed to create Meros client").into();
   client.override_refund_with_random_address();
   let client_refund = client.get_refund_address();
-  let clients_verifier: AnyScriptedVerifier = BtcVerifier::new(&scripted).expect("Failed to create Meros verifier").into();
+  let mut clients_verifier: AnyScriptedVerifier = BtcVerifier::new(&scripted, chain).await.expect("Failed to create Meros verifier").into();
+
+  // Electrum sends 0.01 BTC and the node sends 1 Meri, with the full tolerance absorbing fees
+  let terms = SwapTerms {
+    scripted_amount: 1_000_000,
+    unscripted_amount: 1,
+    tolerance_bps: 10000,
+    scripted_fee_bounds: FeeBounds::unbounded(),
+    timelocks: Timelocks::default()
+  };
+  host.set_terms(terms);
+  hosts_verifier.set_terms(terms);
+  clients_verifier.set_terms(terms);
 
   let should_have_funds = test(host, hosts_verifier, client, clients_verifier).await.unwrap();
   if host_test {
-    let host = BtcHost::new(&scripted).unwrap();
+    let host = BtcHost::new(&scripted, chain).await.unwrap();
     host.advance_consensus().await.unwrap();
     assert_eq!(should_have_funds, host.get_if_funded(&host_refund).await);
   } else {
@@ -57,20 +69,32 @@ pub async fn run_test<F, Fut>(host_test: bool, test: F)
   }
 }
 
+async fn test_all(chain: BtcChain) {
+  run_test(chain, true, test_success).await;
+
+  run_test(chain, true, test_no_host_address).await;
+  run_test(chain, true, test_never_funded_address).await;
+  run_test(chain, true, test_funded_address_no_lock).await;
+  run_test(chain, true, test_funded_address_created_lock).await;
+  run_test(chain, true, test_published_lock).await;
+  run_test(chain, true, test_attempted_refund_yet_success).await;
+
+  run_test(chain, false, test_no_client_address).await;
+  run_test(chain, false, test_generated_address).await;
+  run_test(chain, false, test_funded_get_unscripted).await;
+  run_test(chain, false, test_funded_get_scripted).await;
+}
+
+#[cfg(feature = "test_bitcoin_node")]
 #[tokio::test]
 pub async fn test_btc_and_meros() {
   let _ = env_logger::builder().is_test(true).try_init();
-  run_test(true, test_success).await;
-
-  run_test(true, test_no_host_address).await;
-  run_test(true, test_never_funded_address).await;
-  run_test(true, test_funded_address_no_lock).await;
-  run_test(true, test_funded_address_created_lock).await;
-  run_test(true, test_published_lock).await;
-  run_test(true, test_attempted_refund_yet_success).await;
+  test_all(BtcChain::Bitcoin).await;
+}
 
-  run_test(false, test_no_client_address).await;
-  run_test(false, test_generated_address).await;
-  run_test(false, test_funded_get_unscripted).await;
-  run_test(false, test_funded_get_scripted).await;
+#[cfg(feature = "test_litecoin_node")]
+#[tokio::test]
+pub async fn test_ltc_and_meros() {
+  let _ = env_logger::builder().is_test(true).try_init();
+  test_all(BtcChain::Litecoin).await;
 }
@@ -6,8 +6,8 @@ use std::{
 use crate::{
   coins::{
     *,
-    btc::{host::BtcHost, verifier::BtcVerifier},
-    xmr::{client::XmrClient, verifier::XmrVerifier}
+    btc::{BtcChain, host::BtcHost, verifier::BtcVerifier},
+    xmr::{XmrChain, client::XmrClient, verifier::XmrVerifier}
   },
   tests::swap::{
     success::test_success,
@@ -28,49 +28,80 @@ use crate::{
   }
 };
 
-pub async fn run_test<F, Fut>(host_test: bool, test: F)
+pub async fn run_test<F, Fut>(chain: BtcChain, xmr_chain: XmrChain, host_test: bool, test: F)
   where F: FnOnce(AnyScriptedHost, AnyUnscriptedVerifier, AnyUnscriptedClient, AnyScriptedVerifier) -> Fut,
     Fut: Future<Output = anyhow::Result<bool>>
 {
-  let scripted: PathBuf = "config/bitcoin.json".to_string().into();
-  let unscripted: PathBuf = "config/monero.json".to_string().into();
+  let scripted: PathBuf = format!("config/{:?}.json", chain).to_lowercase().into();
+  let unscripted: PathBuf = format!("config/{:?}.json", xmr_chain).to_lowercase().into();
 
-  let mut host: AnyScriptedHost = BtcHost::new(&scripted).expect("Failed to create BTC host").into();
+  let mut host: AnyScriptedHost = BtcHost::new(&scripted, chain).await.expect("Failed to create the scripted host").into();
   host.override_refund_with_random_address();
   let host_refund = host.get_refund_address();
-  let hosts_verifier: AnyUnscriptedVerifier = XmrVerifier::new(&unscripted).await.expect("Failed to create BTC verifier").into();
+  let mut hosts_verifier: AnyUnscriptedVerifier = XmrVerifier::new(&unscripted, xmr_chain).await.expect("Failed to create BTC verifier").into();
 
-  let mut client: AnyUnscriptedClient = XmrClient::new(&unscripted).await.expect("Failed to create Monero client").into();
+  let mut client: AnyUnscriptedClient = XmrClient::new(&unscripted, xmr_chain).await.expect("Failed to create Monero client").into();
   client.override_refund_with_random_address();
   let client_refund = client.get_refund_address();
-  let clients_verifier: AnyScriptedVerifier = BtcVerifier::new(&scripted).expect("Failed to create Monero verifier").into();
+  let mut clients_verifier: AnyScriptedVerifier = BtcVerifier::new(&scripted, chain).await.expect("Failed to create Monero verifier").into();
+
+  // Electrum sends 0.01 BTC and the wallet sends 1 XMR, or WOW, with the full tolerance absorbing fees
+  let terms = SwapTerms {
+    scripted_amount: 1_000_000,
+    unscripted_amount: xmr_chain.coin() as u128,
+    tolerance_bps: 10000,
+    scripted_fee_bounds: FeeBounds::unbounded(),
+    timelocks: Timelocks::default()
+  };
+  host.set_terms(terms);
+  hosts_verifier.set_terms(terms);
+  clients_verifier.set_terms(terms);
 
   let should_have_funds = test(host, hosts_verifier, client, clients_verifier).await.unwrap();
   if host_test {
-    let host = BtcHost::new(&scripted).unwrap();
+    let host = BtcHost::new(&scripted, chain).await.unwrap();
     host.advance_consensus().await.unwrap();
     assert_eq!(should_have_funds, host.get_if_funded(&host_refund).await);
   } else {
-    let client = XmrClient::new(&unscripted).await.unwrap();
+    let client = XmrClient::new(&unscripted, xmr_chain).await.unwrap();
     client.advance_consensus().await.unwrap();
     assert_eq!(should_have_funds, client.get_if_funded(&client_refund).await);
   }
 }
 
+async fn test_all(chain: BtcChain, xmr_chain: XmrChain) {
+  run_test(chain, xmr_chain, true, test_success).await;
+
+  run_test(chain, xmr_chain, true, test_no_host_address).await;
+  run_test(chain, xmr_chain, true, test_never_funded_address).await;
+  run_test(chain, xmr_chain, true, test_funded_address_no_lock).await;
+  run_test(chain, xmr_chain, true, test_funded_address_created_lock).await;
+  run_test(chain, xmr_chain, true, test_published_lock).await;
+  run_test(chain, xmr_chain, true, test_attempted_refund_yet_success).await;
+
+  run_test(chain, xmr_chain, false, test_no_client_address).await;
+  run_test(chain, xmr_chain, false, test_generated_address).await;
+  run_test(chain, xmr_chain, false, test_funded_get_unscripted).await;
+  run_test(chain, xmr_chain, false, test_funded_get_scripted).await;
+}
+
+#[cfg(all(feature = "test_bitcoin_node", feature = "test_monero_node"))]
 #[tokio::test]
 pub async fn test_btc_and_xmr() {
   let _ = env_logger::builder().is_test(true).try_init();
-  run_test(true, test_success).await;
+  test_all(BtcChain::Bitcoin, XmrChain::Monero).await;
+}
 
-  run_test(true, test_no_host_address).await;
-  run_test(true, test_never_funded_address).await;
-  run_test(true, test_funded_address_no_lock).await;
-  run_test(true, test_funded_address_created_lock).await;
-  run_test(true, test_published_lock).await;
-  run_test(true, test_attempted_refund_yet_success).await;
+#[cfg(all(feature = "test_litecoin_node", feature = "test_monero_node"))]
+#[tokio::test]
+pub async fn test_ltc_and_xmr() {
+  let _ = env_logger::builder().is_test(true).try_init();
+  test_all(BtcChain::Litecoin, XmrChain::Monero).await;
+}
 
-  run_test(false, test_no_client_address).await;
-  run_test(false, test_generated_address).await;
-  run_test(false, test_funded_get_unscripted).await;
-  run_test(false, test_funded_get_scripted).await;
+#[cfg(all(feature = "test_bitcoin_node", feature = "test_wownero_node"))]
+#[tokio::test]
+pub async fn test_btc_and_wow() {
+  let _ = env_logger::builder().is_test(true).try_init();
+  test_all(BtcChain::Bitcoin, XmrChain::Wownero).await;
 }
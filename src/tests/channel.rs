@@ -0,0 +1,47 @@
+use tokio::net::{TcpStream, TcpListener};
+
+use crate::channel::{Identity, Channel};
+
+pub async fn connect(
+  host: &Identity,
+  client: &Identity,
+  host_pin: Option<Vec<u8>>,
+  client_pin: Option<Vec<u8>>
+) -> (anyhow::Result<Channel>, anyhow::Result<Channel>) {
+  let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let address = listener.local_addr().unwrap();
+  let host = host.clone();
+  let accept = tokio::spawn(async move {
+    let (stream, _) = listener.accept().await.unwrap();
+    Channel::respond(stream, &host, host_pin.as_deref()).await
+  });
+  let client = Channel::initiate(TcpStream::connect(address).await.unwrap(), client, client_pin.as_deref()).await;
+  (accept.await.unwrap(), client)
+}
+
+#[tokio::test]
+async fn encrypted_channel() {
+  let _ = env_logger::builder().is_test(true).try_init();
+  let dir = std::env::temp_dir().join("asmr-channel-test");
+  let _ = std::fs::remove_dir_all(&dir);
+  let host = Identity::load_or_generate(&dir.join("host.key")).unwrap();
+  let client = Identity::load_or_generate(&dir.join("client.key")).unwrap();
+  // Loading an existing key must produce the same identity
+  assert_eq!(Identity::load_or_generate(&dir.join("host.key")).unwrap().public, host.public);
+
+  let (host_channel, client_channel) = connect(&host, &client, Some(client.public.clone()), Some(host.public.clone())).await;
+  let (mut host_channel, mut client_channel) = (host_channel.unwrap(), client_channel.unwrap());
+
+  // Larger than a single Noise message
+  let large: Vec<u8> = (0 .. (200 * 1024)).map(|i| i as u8).collect();
+  host_channel.write(&large).await.unwrap();
+  host_channel.write(b"").await.unwrap();
+  assert_eq!(client_channel.read().await.unwrap(), large);
+  assert_eq!(client_channel.read().await.unwrap(), b"");
+  client_channel.write(b"ASMR").await.unwrap();
+  assert_eq!(host_channel.read().await.unwrap(), b"ASMR");
+
+  // Pinning the wrong key must fail the handshake
+  let (_, client_channel) = connect(&host, &client, None, Some(client.public.clone())).await;
+  assert!(client_channel.is_err());
+}
@@ -0,0 +1,125 @@
+use std::{
+  convert::Infallible,
+  sync::{Arc, atomic::{AtomicUsize, Ordering}}
+};
+
+use serde_json::{json, Value};
+
+use hyper::{
+  Body, Request, Response, Server,
+  service::{make_service_fn, service_fn}
+};
+
+use bitcoin::{
+  blockdata::{script::Script, transaction::{OutPoint, TxIn, TxOut, Transaction}},
+  consensus::serialize
+};
+
+use crate::coins::btc::{engine::BtcConfig, rpc::{BtcBackend, bitcoind::BitcoindBackend}};
+
+const ADDRESS: &str = "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080";
+const OTHER: &str = "bcrt1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qzf5egd";
+
+fn transaction(previous_output: OutPoint, outputs: usize) -> Transaction {
+  Transaction {
+    version: 2,
+    lock_time: 0,
+    input: vec![TxIn {
+      previous_output,
+      script_sig: Script::new(),
+      sequence: 0xFFFFFFFF,
+      witness: Vec::new()
+    }],
+    output: (0 .. outputs).map(|i| TxOut {
+      value: 5000 + (i as u64),
+      script_pubkey: Script::new()
+    }).collect()
+  }
+}
+
+async fn respond(
+  request: Request<Body>,
+  listed: Value,
+  transactions: Vec<Transaction>,
+  fetched: Arc<AtomicUsize>
+) -> Result<Response<Body>, Infallible> {
+  let body: Value = serde_json::from_slice(&hyper::body::to_bytes(request.into_body()).await.unwrap()).unwrap();
+  if body["method"] == "gettransaction" {
+    fetched.fetch_add(1, Ordering::SeqCst);
+  }
+  let result = match body["method"].as_str().unwrap() {
+    "getdescriptorinfo" => json!({ "descriptor": format!("addr({})#checksum", ADDRESS) }),
+    "importdescriptors" => json!([{ "success": true }]),
+    "listsinceblock" => listed,
+    "gettransaction" => match transactions.iter().find(|tx| Value::String(tx.txid().to_string()) == body["params"][0]) {
+      Some(tx) => json!({ "hex": hex::encode(serialize(tx)) }),
+      None => return Ok(Response::new(Body::from(
+        json!({ "result": null, "error": { "code": -5, "message": "Invalid or non-wallet transaction id" } }).to_string()
+      )))
+    },
+    _ => return Ok(Response::new(Body::from(
+      json!({ "result": null, "error": { "code": -32601, "message": "Method not found" } }).to_string()
+    )))
+  };
+  Ok(Response::new(Body::from(json!({ "result": result, "error": null }).to_string())))
+}
+
+#[tokio::test]
+async fn bitcoind_history_includes_spends() {
+  // Pays the address with its first output and another address with its second
+  let funding = transaction(OutPoint::default(), 2);
+  // Spends the address's output
+  let spend = transaction(OutPoint { txid: funding.txid(), vout: 0 }, 1);
+  // Spends the other address's output, so it's unrelated
+  let unrelated = transaction(OutPoint { txid: funding.txid(), vout: 1 }, 1);
+  // Paid the address, yet was double spent
+  let conflicted = transaction(OutPoint { txid: unrelated.txid(), vout: 0 }, 1);
+  // Never fetched, as coinbases can't spend the address's outputs
+  let coinbase = transaction(OutPoint::null(), 1);
+
+  let entry = |tx: &Transaction, category: &str, address: &str, vout: u32, confirmations: isize, blockheight: Option<isize>| json!({
+    "address": address,
+    "category": category,
+    "vout": vout,
+    "txid": tx.txid().to_string(),
+    "confirmations": confirmations,
+    "blockheight": blockheight
+  });
+  // Unordered, as listsinceblock's output is
+  let listed = json!({
+    "transactions": [
+      entry(&spend, "send", OTHER, 0, 0, None),
+      entry(&coinbase, "generate", OTHER, 0, 5, Some(106)),
+      entry(&unrelated, "send", OTHER, 0, 2, Some(109)),
+      entry(&conflicted, "receive", ADDRESS, 0, -1, None),
+      entry(&funding, "receive", OTHER, 1, 3, Some(108)),
+      entry(&funding, "receive", ADDRESS, 0, 3, Some(108))
+    ]
+  });
+
+  let transactions = vec![funding.clone(), spend.clone(), unrelated, conflicted];
+  let fetched = Arc::new(AtomicUsize::new(0));
+  let service_fetched = fetched.clone();
+  let make_service = make_service_fn(move |_| {
+    let (listed, transactions, fetched) = (listed.clone(), transactions.clone(), service_fetched.clone());
+    async move {
+      Ok::<_, Infallible>(service_fn(move |request| respond(request, listed.clone(), transactions.clone(), fetched.clone())))
+    }
+  });
+  let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+  let config: BtcConfig = serde_json::from_value(json!({
+    "backend": "bitcoind",
+    "url": format!("http://{}/", server.local_addr()),
+    "destination": ADDRESS,
+    "refund": ADDRESS
+  })).unwrap();
+  let bitcoind = BitcoindBackend::new(&config);
+  tokio::spawn(server);
+
+  let history = vec![(funding.txid().to_string(), 108), (spend.txid().to_string(), 0)];
+  assert_eq!(bitcoind.get_address_history(ADDRESS).await.unwrap(), history);
+  // Only the spend and the unrelated transaction are fetched, and only once, despite polling again
+  assert_eq!(fetched.load(Ordering::SeqCst), 2);
+  assert_eq!(bitcoind.get_address_history(ADDRESS).await.unwrap(), history);
+  assert_eq!(fetched.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,39 @@
+use hex_literal::hex;
+
+use bitcoin::blockdata::script::Script;
+
+use crate::coins::btc::engine::{BtcChain, BtcNetwork, BtcParams};
+
+#[test]
+fn btc_addresses() {
+  let bitcoin = BtcParams::new(BtcChain::Bitcoin, BtcNetwork::Regtest).unwrap();
+  let litecoin = BtcParams::new(BtcChain::Litecoin, BtcNetwork::Regtest).unwrap();
+  assert!(BtcParams::new(BtcChain::Litecoin, BtcNetwork::Signet).is_err());
+
+  // The BIP173 test vector, as encoded for regtest
+  let p2wpkh = Script::from(hex!("0014751e76e8199196d454941c45d1b3a323f1433bd6").to_vec());
+  assert_eq!(bitcoin.encode_address(&p2wpkh), "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080");
+  assert_eq!(bitcoin.decode_address("bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080").unwrap(), p2wpkh);
+
+  let p2wsh = Script::from(hex!("00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262").to_vec());
+  let p2pkh = Script::from(hex!("76a914751e76e8199196d454941c45d1b3a323f1433bd688ac").to_vec());
+  let p2sh = Script::from(hex!("a914751e76e8199196d454941c45d1b3a323f1433bd687").to_vec());
+  for script in &[p2wpkh, p2wsh, p2pkh, p2sh] {
+    for params in &[bitcoin, litecoin] {
+      assert_eq!(&params.decode_address(&params.encode_address(script)).unwrap(), script);
+    }
+  }
+
+  // The BIP350 test vector, as encoded for regtest
+  let p2tr = Script::from(hex!("512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798").to_vec());
+  assert_eq!(bitcoin.encode_address(&p2tr), "bcrt1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqc8gma6");
+  assert_eq!(litecoin.encode_address(&p2tr), "rltc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqsyzyjh");
+
+  let ltc_address = litecoin.encode_address(&Script::from(hex!("0014751e76e8199196d454941c45d1b3a323f1433bd6").to_vec()));
+  assert!(ltc_address.starts_with("rltc1"));
+  assert!(bitcoin.decode_address(&ltc_address).is_err());
+  assert!(litecoin.decode_address("bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080").is_err());
+  // Litecoin's P2SH prefix differs from Bitcoin's, even though their P2PKH prefixes are shared on test networks
+  let p2sh_address = bitcoin.encode_address(&Script::from(hex!("a914751e76e8199196d454941c45d1b3a323f1433bd687").to_vec()));
+  assert!(litecoin.decode_address(&p2sh_address).is_err());
+}
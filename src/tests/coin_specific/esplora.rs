@@ -0,0 +1,107 @@
+use std::convert::Infallible;
+
+use hyper::{
+  Body, Request, Response, Server, Method, StatusCode,
+  service::{make_service_fn, service_fn}
+};
+
+use bitcoin::{
+  blockdata::{script::Script, transaction::{OutPoint, TxIn, TxOut, Transaction}},
+  consensus::serialize
+};
+
+use crate::coins::btc::{engine::{BtcChain, BtcNetwork, BtcParams}, rpc::{BtcBackend, esplora::EsploraBackend}};
+
+const ADDRESS: &str = "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080";
+
+fn params(chain: BtcChain, network: BtcNetwork) -> BtcParams {
+  BtcParams::new(chain, network).unwrap()
+}
+
+async fn respond(request: Request<Body>, tx: String, txid: String) -> Result<Response<Body>, Infallible> {
+  let (method, path) = (request.method().clone(), request.uri().path().to_string());
+  let body = match (&method, path.as_ref()) {
+    (&Method::GET, "/blocks/tip/height") => "110".to_string(),
+    (&Method::GET, "/block-height/0") => params(BtcChain::Bitcoin, BtcNetwork::Regtest).genesis_hash().to_string(),
+    (&Method::GET, path) if path == format!("/address/{}/utxo", ADDRESS) => format!(
+      r#"[{{"txid": "{}", "vout": 0, "status": {{"confirmed": true, "block_height": 105}}, "value": 5000}}]"#,
+      txid
+    ),
+    // Newest first, with the unconfirmed transaction leading
+    (&Method::GET, path) if path == format!("/address/{}/txs", ADDRESS) => format!(
+      r#"[
+        {{"txid": "{0}", "status": {{"confirmed": false}}}},
+        {{"txid": "{0}", "status": {{"confirmed": true, "block_height": 108}}}},
+        {{"txid": "{0}", "status": {{"confirmed": true, "block_height": 105}}}}
+      ]"#,
+      txid
+    ),
+    (&Method::GET, path) if path == format!("/tx/{}/hex", txid) => tx,
+    (&Method::GET, "/fee-estimates") => r#"{"1": 20.5, "3": 10.2, "6": 5.0, "144": 1.0}"#.to_string(),
+    (&Method::POST, "/tx") => {
+      let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+      if body == tx.as_bytes() {
+        txid
+      } else {
+        return Ok(Response::builder().status(StatusCode::BAD_REQUEST).body(Body::from("bad-txns")).unwrap());
+      }
+    },
+    _ => return Ok(Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty()).unwrap())
+  };
+  Ok(Response::new(Body::from(body)))
+}
+
+#[tokio::test]
+async fn esplora_backend() {
+  let tx = Transaction {
+    version: 2,
+    lock_time: 0,
+    input: vec![TxIn {
+      previous_output: OutPoint::default(),
+      script_sig: Script::new(),
+      sequence: 0xFFFFFFFF,
+      witness: Vec::new()
+    }],
+    output: vec![TxOut {
+      value: 5000,
+      script_pubkey: Script::new()
+    }]
+  };
+  let tx_hex = hex::encode(serialize(&tx));
+  let txid = tx.txid().to_string();
+
+  let (server_tx, server_txid) = (tx_hex.clone(), txid.clone());
+  let make_service = make_service_fn(move |_| {
+    let (tx, txid) = (server_tx.clone(), server_txid.clone());
+    async move {
+      Ok::<_, Infallible>(service_fn(move |request| respond(request, tx.clone(), txid.clone())))
+    }
+  });
+  let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+  let esplora = EsploraBackend::new(&format!("http://{}/", server.local_addr()));
+  tokio::spawn(server);
+
+  assert!(esplora.verify_network(params(BtcChain::Bitcoin, BtcNetwork::Regtest)).await.is_ok());
+  assert!(esplora.verify_network(params(BtcChain::Bitcoin, BtcNetwork::Mainnet)).await.is_err());
+  assert!(esplora.verify_network(params(BtcChain::Litecoin, BtcNetwork::Regtest)).await.is_err());
+
+  assert_eq!(esplora.get_height().await.unwrap(), 110);
+
+  let utxos = esplora.get_spendable(ADDRESS).await.unwrap();
+  assert_eq!(utxos.len(), 1);
+  assert_eq!(utxos[0].tx_hash, txid);
+  assert_eq!(utxos[0].tx_pos, 0);
+  assert_eq!(utxos[0].height, 105);
+  assert_eq!(utxos[0].value, 5000);
+
+  let heights: Vec<isize> = esplora.get_address_history(ADDRESS).await.unwrap().into_iter().map(|tx| tx.1).collect();
+  assert_eq!(heights, vec![105, 108, 0]);
+
+  assert_eq!(esplora.get_transaction(&txid).await.unwrap(), tx);
+  assert_eq!(esplora.publish(&serialize(&tx)).await.unwrap(), txid);
+  assert!(esplora.publish(&[0]).await.is_err());
+
+  assert_eq!(esplora.estimate_fee_per_byte(2).await.unwrap(), Some(21));
+  assert_eq!(esplora.estimate_fee_per_byte(6).await.unwrap(), Some(5));
+  assert_eq!(esplora.estimate_fee_per_byte(0).await.unwrap(), None);
+}
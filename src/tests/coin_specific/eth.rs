@@ -0,0 +1,82 @@
+use hex_literal::hex;
+
+use secp256k1::SecretKey;
+
+use crate::{
+  crypt_engines::{CryptEngine, secp256k1_engine::Secp256k1Engine},
+  coins::eth::engine::*
+};
+
+#[test]
+fn eth_encoding() {
+  // EIP-55's test vectors
+  for address in &["0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed", "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359"] {
+    assert_eq!(&encode_address(&decode_address(address).unwrap()), address);
+    assert!(decode_address(&address.to_lowercase()).is_ok());
+  }
+  assert!(decode_address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD").is_err());
+  assert!(decode_address("5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").is_err());
+
+  assert_eq!(selector("transfer(address,uint256)"), hex!("a9059cbb"));
+  assert_eq!(decode_quantity("0x1234").unwrap(), 0x1234);
+  assert_eq!(decode_quantity("0x").unwrap(), 0);
+
+  // EIP-155's example transaction
+  let key = SecretKey::from_slice(&[0x46; 32]).unwrap();
+  assert_eq!(
+    address_from_public_key(&secp256k1::PublicKey::from_secret_key(&SECP, &key)),
+    hex!("9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f")
+  );
+  let (tx, _) = EthTransaction {
+    nonce: 9,
+    gas_price: 20_000_000_000,
+    gas: 21000,
+    to: [0x35; 20],
+    value: 1_000_000_000_000_000_000,
+    data: Vec::new()
+  }.sign(1, &key);
+  assert_eq!(
+    tx,
+    hex!("f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83").to_vec()
+  );
+}
+
+#[test]
+fn eth_adaptor_signature() {
+  let signing_key = Secp256k1Engine::new_private_key();
+  let signer = address_from_key_bytes(&Secp256k1Engine::public_key_to_bytes(&Secp256k1Engine::to_public_key(&signing_key))).unwrap();
+  let encryption_key = Secp256k1Engine::new_private_key();
+
+  let params = SwapParams {
+    token: [0; 20],
+    value: 1,
+    hash: [0xff; 32],
+    host: signer,
+    client_refund: [1; 20],
+    host_destination: [2; 20],
+    client_destination: [3; 20],
+    t0: 6,
+    t1: 6
+  };
+  let message = params.message(1337, &[4; 20], BUY);
+  assert_ne!(message, params.message(1337, &[4; 20], SPEND));
+
+  let encrypted = Secp256k1Engine::encrypted_sign(&signing_key, &Secp256k1Engine::to_public_key(&encryption_key), &message).unwrap();
+  let decrypted = Secp256k1Engine::decrypt_signature(&encrypted, &encryption_key).unwrap();
+  let signature_bytes = Secp256k1Engine::signature_to_bytes(&decrypted);
+
+  // The recovery ID is found by recovering the signer, as ecrecover will
+  let signature = EthSignature::from_compact(&signature_bytes, &message, &signer).unwrap();
+  assert!((signature.v == 27) || (signature.v == 28));
+  assert!(EthSignature::from_compact(&signature_bytes, &message, &[1; 20]).is_err());
+
+  // And once it's logged on chain, the encryption key can be recovered from it
+  let logged = [signature.r, signature.s].concat();
+  assert!(
+    Secp256k1Engine::recover_key(
+      &Secp256k1Engine::to_public_key(&encryption_key),
+      &encrypted,
+      &EthSignature::from_log(&logged).unwrap()
+    ).unwrap() == encryption_key
+  );
+}
@@ -0,0 +1,100 @@
+use crate::{
+  crypt_engines::{CryptEngine, ed25519_engine::Ed25519Sha},
+  coins::meros::{
+    transaction::{Input, Output, Transaction, Send},
+    engine::{MerosNetwork, MerosPayment, MerosEngine, MAX_INPUTS}
+  }
+};
+
+fn input(i: usize) -> Input {
+  Input {
+    hash: hex::encode(&[i as u8; 32]),
+    nonce: (i / 256) as u8
+  }
+}
+
+fn output(value: u64) -> Output {
+  Output {
+    key: vec![0; 32],
+    value
+  }
+}
+
+#[test]
+fn meros_send_difficulty() {
+  // A Send with a single input and output, or just under 143 bytes of them, uses the base difficulty
+  // Only dividing the outputs' size by 143 would've made this 10300
+  assert_eq!(Send::new(vec![input(0)], vec![output(1)]).difficulty(100), 100);
+  assert_eq!(Send::new(vec![input(0)], vec![output(1), output(2)]).difficulty(100), 100);
+  // (70 + (2 * 33) + (2 * 40)) / 143
+  assert_eq!(Send::new(vec![input(0), input(1)], vec![output(1), output(2)]).difficulty(100), 100);
+  // (70 + (255 * 33) + (2 * 40)) / 143
+  assert_eq!(Send::new((0 .. 255).map(input).collect(), vec![output(1), output(2)]).difficulty(100), 5900);
+  // (70 + (33) + (255 * 40)) / 143
+  assert_eq!(Send::new(vec![input(0)], (1 ..= 255).map(output).collect()).difficulty(100), 7200);
+  // (70 + (255 * 33) + (255 * 40)) / 143
+  assert_eq!(Send::new((0 .. 255).map(input).collect(), (1 ..= 255).map(output).collect()).difficulty(100), 13000);
+}
+
+#[test]
+fn meros_sends() {
+  let key_a = Ed25519Sha::new_private_key();
+  let key_b = Ed25519Sha::new_private_key();
+  let change = vec![1; 32];
+  let fee = Output {
+    key: vec![2; 32],
+    value: 10
+  };
+
+  // More inputs than fit in a single Send
+  let inputs = (1 ..= 300).map(|i| (input(i), i as u64)).collect::<Vec<_>>();
+  let sends = MerosEngine::create_sends(
+    MerosNetwork::Testnet,
+    key_a,
+    key_b,
+    inputs.clone(),
+    vec![fee.clone()],
+    change.clone(),
+    1
+  ).unwrap();
+  assert_eq!(sends.len(), 2);
+
+  // The first spends the largest inputs, paying the fee alongside the change
+  assert_eq!(sends[0].inputs().len(), MAX_INPUTS);
+  assert!(sends[0].inputs().iter().all(|input| inputs[45 ..].iter().any(|(other, _)| (other.hash == input.hash) && (other.nonce == input.nonce))));
+  assert_eq!(sends[0].outputs(), &vec![fee.clone(), Output { key: change.clone(), value: (46 ..= 300).sum::<u64>() - 10 }]);
+  // The second only sends the rest to the change address
+  assert_eq!(sends[1].inputs().len(), 45);
+  assert_eq!(sends[1].outputs(), &vec![Output { key: change.clone(), value: (1 ..= 45).sum() }]);
+
+  // Without any change, only the outputs are created
+  let sends = MerosEngine::create_sends(
+    MerosNetwork::Testnet,
+    key_a,
+    key_b,
+    vec![(input(0), 10)],
+    vec![fee.clone()],
+    change.clone(),
+    1
+  ).unwrap();
+  assert_eq!(sends[0].outputs(), &vec![fee.clone()]);
+
+  // Outputs exceeding the inputs, or no inputs at all, are rejected
+  assert!(
+    MerosEngine::create_sends(MerosNetwork::Testnet, key_a, key_b, vec![(input(0), 9)], vec![fee.clone()], change.clone(), 1).is_err()
+  );
+  assert!(MerosEngine::create_sends(MerosNetwork::Testnet, key_a, key_b, vec![], vec![fee], change, 1).is_err());
+}
+
+#[test]
+fn meros_payments() {
+  let address = "mr1qz3286zv8gfwac2hej686lkvxdqlsw7jt006m4qcktkjlhwxxp9ggs83vms";
+  let payments = MerosEngine::decode_payments(
+    MerosNetwork::Testnet,
+    &[MerosPayment { address: address.to_string(), value: 5 }]
+  ).unwrap();
+  assert_eq!(payments, vec![Output { key: MerosEngine::decode_address(MerosNetwork::Testnet, address).unwrap(), value: 5 }]);
+
+  assert!(MerosEngine::decode_payments(MerosNetwork::Testnet, &[MerosPayment { address: address.to_string(), value: 0 }]).is_err());
+  assert!(MerosEngine::decode_payments(MerosNetwork::Testnet, &[MerosPayment { address: "mr1invalid".to_string(), value: 5 }]).is_err());
+}
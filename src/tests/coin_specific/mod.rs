@@ -1 +1,10 @@
 mod nano;
+mod esplora;
+mod bitcoind;
+mod taproot;
+mod btc_addresses;
+mod xmr_addresses;
+mod eth;
+mod xmr_ringct;
+mod monerod;
+mod meros;
@@ -0,0 +1,350 @@
+use std::{
+  convert::Infallible,
+  marker::PhantomData,
+  fs::File,
+  collections::HashMap,
+  sync::{Arc, Mutex}
+};
+
+use serde_json::{json, Value};
+
+use hyper::{
+  Body, Request, Response, Server,
+  service::{make_service_fn, service_fn}
+};
+
+use curve25519_dalek::constants::ED25519_BASEPOINT_TABLE;
+
+use crate::{
+  crypt_engines::{CryptEngine, ed25519_engine::Ed25519Sha, secp256k1_engine::Secp256k1Engine},
+  dl_eq::DlEqProof,
+  coins::{
+    SwapTerms, FeeBounds, Timelocks, UnscriptedVerifier,
+    xmr::{
+      XmrChain,
+      engine::{CONFIRMATIONS, XmrEngine, XmrKeys, XmrAddress, XmrAddressKind},
+      crypto::keccak,
+      tracker::{XmrTracker, TrackerEvent},
+      verifier::XmrVerifier
+    }
+  }
+};
+
+use super::xmr_ringct::sign_to;
+
+const ADDRESS: &str = "42L9GkQeerChpA4rz4MTagL5mBGbEnvPzWLRL5vfJTr3bd8Diz6okcpd9vkxerLXHADdPMbTW9Xk8JcWj8WbeGEmD3aKdsi";
+
+#[derive(Default)]
+struct Chain {
+  // The hash and transaction hashes of each block
+  blocks: Vec<(String, Vec<String>)>,
+  transactions: HashMap<String, Vec<u8>>,
+  mempool: Vec<String>,
+  // How many blocks were ever mined, so blocks replacing others at the same height have distinct hashes
+  mined: u64
+}
+
+fn hash(data: &[u8]) -> String {
+  hex::encode(keccak(data))
+}
+
+impl Chain {
+  fn respond(&self, path: &str, body: &Value) -> Value {
+    match path {
+      "/json_rpc" => json!({
+        "result": match body["method"].as_str().unwrap() {
+          "get_info" => json!({ "nettype": "fakechain" }),
+          "get_block" => {
+            let height = body["params"]["height"].as_u64().unwrap() as usize;
+            json!({
+              "block_header": {
+                "hash": self.blocks[height].0,
+                "prev_hash": if height == 0 { "00".repeat(32) } else { self.blocks[height - 1].0.clone() }
+              },
+              "tx_hashes": self.blocks[height].1
+            })
+          },
+          method => panic!("Unexpected json_rpc method {}", method)
+        }
+      }),
+      "/get_height" => json!({ "height": self.blocks.len() }),
+      "/get_transactions" => {
+        let hash = body["txs_hashes"][0].as_str().unwrap();
+        match self.transactions.get(hash) {
+          Some(tx) => json!({
+            "txs": [{
+              "as_hex": hex::encode(tx),
+              "block_height": self.blocks.iter().position(|block| block.1.iter().any(|included| included == hash)).unwrap_or(0)
+            }]
+          }),
+          None => json!({})
+        }
+      },
+      "/get_transaction_pool" => json!({
+        "transactions": self.mempool.iter().map(|hash| json!({
+          "id_hash": hash,
+          "tx_blob": hex::encode(&self.transactions[hash])
+        })).collect::<Vec<_>>()
+      }),
+      _ => panic!("Unexpected RPC call to {}", path)
+    }
+  }
+}
+
+async fn respond(request: Request<Body>, chain: Arc<Mutex<Chain>>) -> Result<Response<Body>, Infallible> {
+  let path = request.uri().path().to_string();
+  let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+  let body = if body.len() == 0 { Value::Null } else { serde_json::from_slice(&body).unwrap() };
+  Ok(Response::new(Body::from(chain.lock().unwrap().respond(&path, &body).to_string())))
+}
+
+// A monerod serving a chain the test builds, and reorganizes, itself
+struct Monerod {
+  chain: Arc<Mutex<Chain>>,
+  url: String
+}
+
+impl Monerod {
+  // Starts with a few blocks, as deposits are tracked from the block before the swap started
+  fn new() -> Monerod {
+    let chain = Arc::new(Mutex::new(Chain::default()));
+    let service_chain = chain.clone();
+    let make_service = make_service_fn(move |_| {
+      let chain = service_chain.clone();
+      async move {
+        Ok::<_, Infallible>(service_fn(move |request| respond(request, chain.clone())))
+      }
+    });
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+    let url = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+
+    let monerod = Monerod {
+      chain,
+      url
+    };
+    for _ in 0 .. 5 {
+      monerod.mine(vec![]);
+    }
+    monerod
+  }
+
+  fn config(&self) -> Value {
+    json!({
+      "network": "regtest",
+      "daemon": self.url,
+      "wallet": self.url,
+      "wallet_user": "",
+      "wallet_pass": "",
+      "destination": ADDRESS,
+      "refund": ADDRESS
+    })
+  }
+
+  // An engine with a random view pair, as if the swap's keys were exchanged
+  async fn engine(&self) -> XmrEngine {
+    let mut engine = XmrEngine::new(serde_json::from_value(self.config()).unwrap(), XmrChain::Monero).await.unwrap();
+    engine.k = Some(Ed25519Sha::new_private_key());
+    engine.set_spend(Ed25519Sha::to_public_key(&Ed25519Sha::new_private_key()));
+    engine
+  }
+
+  // Includes the transactions in a new block, removing them from the mempool, and returns their hashes
+  fn mine(&self, transactions: Vec<Vec<u8>>) -> Vec<String> {
+    let mut chain = self.chain.lock().unwrap();
+    let hashes = transactions.iter().map(|tx| hash(tx)).collect::<Vec<_>>();
+    for (hash, tx) in hashes.iter().zip(transactions) {
+      chain.transactions.insert(hash.clone(), tx);
+    }
+    chain.mempool.retain(|hash| !hashes.contains(hash));
+    chain.mined += 1;
+    let block = hash(&chain.mined.to_le_bytes());
+    chain.blocks.push((block, hashes.clone()));
+    hashes
+  }
+
+  fn add_to_mempool(&self, tx: Vec<u8>) -> String {
+    let mut chain = self.chain.lock().unwrap();
+    let hash = hash(&tx);
+    chain.transactions.insert(hash.clone(), tx);
+    chain.mempool.push(hash.clone());
+    hash
+  }
+
+  // Drops every block from the height on, so the blocks mined next replace them
+  fn fork(&self, height: usize) {
+    self.chain.lock().unwrap().blocks.truncate(height);
+  }
+
+  // Mines enough blocks for every deposit already included to be counted
+  fn confirm(&self) {
+    for _ in 0 .. CONFIRMATIONS {
+      self.mine(vec![]);
+    }
+  }
+}
+
+fn address(engine: &XmrEngine) -> XmrAddress {
+  let pair = engine.get_view_pair();
+  XmrAddress {
+    spend: pair.spend.point.decompress().unwrap(),
+    view: &pair.view.scalar * &ED25519_BASEPOINT_TABLE,
+    kind: XmrAddressKind::Standard
+  }
+}
+
+fn unrelated() -> Vec<u8> {
+  sign_to(XmrAddress {
+    spend: Ed25519Sha::to_public_key(&Ed25519Sha::new_private_key()),
+    view: Ed25519Sha::to_public_key(&Ed25519Sha::new_private_key()),
+    kind: XmrAddressKind::Standard
+  }, &[5000])
+}
+
+// Each deposit's hash, height, and output amounts, which are sorted as transactions shuffle their outputs
+fn deposits(tracker: &XmrTracker) -> Vec<(String, isize, Vec<u64>)> {
+  tracker.deposits().map(|deposit| {
+    let mut amounts = deposit.outputs.iter().map(|output| output.amount).collect::<Vec<_>>();
+    amounts.sort();
+    (deposit.hash.clone(), deposit.height, amounts)
+  }).collect()
+}
+
+#[tokio::test]
+async fn tracker_follows_reorganizations() {
+  let monerod = Monerod::new();
+  let engine = monerod.engine().await;
+  let pair = engine.get_view_pair();
+  let address = address(&engine);
+
+  let mut tracker = XmrTracker::new(&engine, &pair);
+  assert_eq!(tracker.update().await.unwrap(), vec![]);
+  assert_eq!(tracker.height(), 5);
+
+  let confirmed = sign_to(address, &[2900]);
+  let pending = sign_to(address, &[1000, 500]);
+  let confirmed_hash = monerod.mine(vec![unrelated(), confirmed.clone()]).pop().unwrap();
+  let pending_hash = monerod.add_to_mempool(pending.clone());
+  monerod.add_to_mempool(unrelated());
+  assert_eq!(
+    tracker.update().await.unwrap(),
+    vec![TrackerEvent::Included(confirmed_hash.clone(), 5), TrackerEvent::Mempool(pending_hash.clone())]
+  );
+  assert_eq!(deposits(&tracker), vec![(confirmed_hash.clone(), 5, vec![2900])]);
+  // Transactions still in the mempool are only reported once
+  assert_eq!(tracker.update().await.unwrap(), vec![]);
+
+  // A fork which replaces the deposit's block, and includes both deposits a block later
+  monerod.fork(5);
+  monerod.mine(vec![]);
+  monerod.mine(vec![confirmed, pending]);
+  assert_eq!(
+    tracker.update().await.unwrap(),
+    vec![
+      TrackerEvent::Reorganized(5),
+      TrackerEvent::Included(confirmed_hash.clone(), 6),
+      TrackerEvent::Included(pending_hash.clone(), 6)
+    ]
+  );
+  assert_eq!(
+    deposits(&tracker),
+    vec![(confirmed_hash, 6, vec![2900]), (pending_hash, 6, vec![500, 1000])]
+  );
+  assert_eq!(tracker.height(), 7);
+
+  // A fork of the same length, without either deposit, is still noticed
+  monerod.fork(5);
+  monerod.mine(vec![]);
+  monerod.mine(vec![]);
+  assert_eq!(tracker.update().await.unwrap(), vec![TrackerEvent::Reorganized(5)]);
+  assert_eq!(deposits(&tracker), vec![]);
+  assert_eq!(tracker.height(), 7);
+}
+
+#[tokio::test]
+async fn deposits_are_summed() {
+  let monerod = Monerod::new();
+  let engine = monerod.engine().await;
+  let address = address(&engine);
+
+  // Several outputs, over several transactions and blocks
+  monerod.mine(vec![sign_to(address, &[1000, 1900]), unrelated()]);
+  monerod.mine(vec![sign_to(address, &[500])]);
+  monerod.confirm();
+  // Neither unconfirmed deposits nor unrelated transactions are counted
+  monerod.add_to_mempool(sign_to(address, &[700]));
+  monerod.mine(vec![unrelated()]);
+  assert_eq!(engine.wait_for_deposits(&engine.get_view_pair(), |_| true).await.unwrap(), 3400);
+}
+
+#[tokio::test]
+async fn deposits_with_unlock_times_are_refused() {
+  let monerod = Monerod::new();
+  let engine = monerod.engine().await;
+  let address = address(&engine);
+
+  monerod.mine(vec![sign_to(address, &[1000])]);
+  // The unlock time is the varint after the version, so this locks the deposit until block 10
+  let mut locked = sign_to(address, &[1000]);
+  assert_eq!(locked[1], 0);
+  locked[1] = 10;
+  monerod.mine(vec![locked]);
+  monerod.confirm();
+  assert!(engine.wait_for_deposits(&engine.get_view_pair(), |_| true).await.is_err());
+}
+
+// A verifier whose keys were exchanged with a host, returning it alongside the address it expects the deposit at
+async fn verifier(monerod: &Monerod, unscripted_amount: u128) -> (XmrVerifier, XmrAddress) {
+  let config = std::env::temp_dir().join(format!("asmr-monerod-{}.json", monerod.url.rsplit(':').next().unwrap()));
+  serde_json::to_writer(File::create(&config).unwrap(), &monerod.config()).unwrap();
+  let mut verifier = XmrVerifier::new(&config, XmrChain::Monero).await.unwrap();
+  verifier.set_terms(SwapTerms {
+    scripted_amount: 1_000_000,
+    unscripted_amount,
+    tolerance_bps: 100,
+    scripted_fee_bounds: FeeBounds::unbounded(),
+    timelocks: Timelocks::default()
+  });
+
+  let (keys, _) = verifier.generate_keys_for_engine::<Secp256k1Engine>(PhantomData);
+  let keys: XmrKeys = bincode::deserialize(&keys).unwrap();
+  let (verifier_spend, _) = DlEqProof::<Ed25519Sha, Secp256k1Engine>::deserialize(&keys.dl_eq).unwrap().verify().unwrap();
+
+  let (proof, _, host_spend) = DlEqProof::<Secp256k1Engine, Ed25519Sha>::new();
+  let host_view = Ed25519Sha::new_private_key();
+  verifier.verify_dleq_for_engine::<Secp256k1Engine>(
+    &bincode::serialize(&XmrKeys {
+      dl_eq: proof.serialize(),
+      view_share: Ed25519Sha::private_key_to_bytes(&host_view)
+    }).unwrap(),
+    PhantomData
+  ).unwrap();
+
+  let view = Ed25519Sha::bytes_to_private_key(keys.view_share).unwrap() + host_view;
+  (
+    verifier,
+    XmrAddress {
+      spend: verifier_spend + Ed25519Sha::to_public_key(&host_spend),
+      view: Ed25519Sha::to_public_key(&view),
+      kind: XmrAddressKind::Standard
+    }
+  )
+}
+
+#[tokio::test]
+async fn verifier_refuses_overshot_deposits() {
+  // Deposits within the tolerance are accepted once they reach the agreed amount
+  let monerod = Monerod::new();
+  let (mut verifier, address) = verifier(&monerod, 2000).await;
+  monerod.mine(vec![sign_to(address, &[1000]), sign_to(address, &[1010])]);
+  monerod.confirm();
+  verifier.verify_and_wait_for_send().await.unwrap();
+
+  // Deposits which pass the agreed amount by more than the tolerance end the wait, yet are refused
+  let monerod = Monerod::new();
+  let (mut verifier, address) = verifier(&monerod, 2000).await;
+  monerod.mine(vec![sign_to(address, &[1000])]);
+  monerod.mine(vec![sign_to(address, &[1500])]);
+  monerod.confirm();
+  assert!(verifier.verify_and_wait_for_send().await.is_err());
+}
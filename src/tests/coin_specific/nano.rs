@@ -1,6 +1,15 @@
+use std::time::Duration;
+
 use serde::Deserialize;
+use futures::{SinkExt, StreamExt};
+use tokio::net::TcpListener;
+use tokio_tungstenite::{accept_async, tungstenite::Message};
 
-use crate::coins::nano::engine::nano_rpc_maybe_empty;
+use crate::coins::nano::{
+    engine::{nano_rpc_maybe_empty, NanoConfig},
+    work::{NanoWorkSource, NanoWork},
+    websocket::NanoSubscription,
+};
 
 #[test]
 fn empty_string_response() {
@@ -22,3 +31,113 @@ fn empty_string_response() {
         assert_eq!(&result.blocks, expected);
     }
 }
+
+#[test]
+fn representatives() {
+    const REPRESENTATIVE: &str = "nano_3caknohn7t71qxw4zr7pj3ng36nqahhb7cfnd5rm3u3ppiiqq6zyxhikb388";
+    const SWEEP_REPRESENTATIVE: &str = "nano_1oo84wq1g8s1ejwb46s48qk5sbs76aea5b9r6nm586cb9usz1xuegomdsriw";
+    let config = |representatives: serde_json::Value| {
+        let mut config = serde_json::json!({
+            "rpc_url": "http://[::1]:55000/",
+            "destination": REPRESENTATIVE,
+            "refund": REPRESENTATIVE,
+            "wallet": "",
+            "wallet_account": ""
+        });
+        config.as_object_mut().unwrap().extend(representatives.as_object().unwrap().clone());
+        serde_json::from_value::<NanoConfig>(config).unwrap()
+    };
+
+    // The sweep defaults to the same representative
+    let (representative, sweep_representative) = config(serde_json::json!({
+        "representative": REPRESENTATIVE
+    })).representatives().unwrap();
+    assert_eq!(representative.to_string(), REPRESENTATIVE);
+    assert_eq!(sweep_representative.to_string(), REPRESENTATIVE);
+
+    let (representative, sweep_representative) = config(serde_json::json!({
+        "representative": REPRESENTATIVE,
+        "sweep_representative": SWEEP_REPRESENTATIVE
+    })).representatives().unwrap();
+    assert_eq!(representative.to_string(), REPRESENTATIVE);
+    assert_eq!(sweep_representative.to_string(), SWEEP_REPRESENTATIVE);
+
+    // Invalid accounts, including one with a bad checksum, are rejected
+    let mut corrupted = REPRESENTATIVE.to_string();
+    corrupted.pop();
+    corrupted.push('9');
+    assert!(config(serde_json::json!({ "representative": "nano_invalid" })).representatives().is_err());
+    assert!(config(serde_json::json!({ "representative": corrupted })).representatives().is_err());
+    assert!(config(serde_json::json!({
+        "representative": REPRESENTATIVE,
+        "sweep_representative": "nano_invalid"
+    })).representatives().is_err());
+}
+
+#[test]
+fn work_sources() {
+    let sources: Vec<NanoWorkSource> = serde_json::from_str(r#"["node", {"remote": "http://[::1]:7076"}, "local"]"#).unwrap();
+    assert_eq!(sources, vec![
+        NanoWorkSource::Node,
+        NanoWorkSource::Remote("http://[::1]:7076".to_string()),
+        NanoWorkSource::Local,
+    ]);
+    assert!(NanoWork::new(vec![], "http://[::1]:55000".to_string()).is_err());
+}
+
+#[tokio::test]
+async fn work_fallback_and_precompute() {
+    // Nothing listens on this port, so the remote fails and local work is used
+    let work = NanoWork::new(
+        vec![NanoWorkSource::Remote("http://127.0.0.1:1".to_string()), NanoWorkSource::Local],
+        "http://127.0.0.1:1".to_string(),
+    ).unwrap();
+    let threshold = 0xff00000000000000;
+
+    let root = [1; 32];
+    let nonce = work.generate(root, threshold).await.unwrap();
+    assert!(nanocurrency_types::work_value(&root, nonce) >= threshold);
+
+    let root = [2; 32];
+    work.precompute(root, threshold);
+    let nonce = work.generate(root, threshold).await.unwrap();
+    assert!(nanocurrency_types::work_value(&root, nonce) >= threshold);
+    // The precomputed work is reused
+    assert_eq!(work.generate(root, threshold).await.unwrap(), nonce);
+}
+
+#[tokio::test]
+async fn websocket_confirmations() {
+    const ACCOUNT: &str = "nano_3caknohn7t71qxw4zr7pj3ng36nqahhb7cfnd5rm3u3ppiiqq6zyxhikb388";
+
+    let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let mut subscription = NanoSubscription::new(url, ACCOUNT.to_string());
+
+    // Each connection is checked to subscribe to the account, sent a confirmation, and then dropped
+    for hash in &["AA", "BB"] {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut socket = accept_async(stream).await.unwrap();
+        let subscribe: serde_json::Value = match socket.next().await.unwrap().unwrap() {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            message => panic!("Expected a subscription, got {:?}", message),
+        };
+        assert_eq!(subscribe["action"], "subscribe");
+        assert_eq!(subscribe["topic"], "confirmation");
+        assert_eq!(subscribe["options"]["accounts"], serde_json::json!([ACCOUNT]));
+
+        socket.send(Message::Text(r#"{"ack": "subscribe"}"#.to_string())).await.unwrap();
+        socket.send(Message::Text(serde_json::json!({
+            "topic": "confirmation",
+            "message": {
+                "account": ACCOUNT,
+                "amount": "1",
+                "hash": hash
+            }
+        }).to_string())).await.unwrap();
+        assert_eq!(subscription.next(Duration::from_secs(5)).await.unwrap(), *hash);
+    }
+
+    // Without anything confirmed, waiting times out
+    assert!(subscription.next(Duration::from_millis(100)).await.is_none());
+}
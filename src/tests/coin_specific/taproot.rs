@@ -0,0 +1,141 @@
+use bitcoin::blockdata::{script::Script, transaction::{OutPoint, TxIn, TxOut, Transaction}};
+
+use crate::{
+  crypt_engines::{CryptEngine, secp256k1_engine::Secp256k1Engine},
+  coins::{Timelocks, btc::{engine::BtcEngine, taproot::*}}
+};
+
+fn public_key_bytes(key: &<Secp256k1Engine as CryptEngine>::PrivateKey) -> Vec<u8> {
+  Secp256k1Engine::public_key_to_bytes(&Secp256k1Engine::to_public_key(key))
+}
+
+// Creates the host's and client's engines, with both creating the same lock
+fn create_locks(t0: u16) -> (BtcEngine, BtcEngine) {
+  let mut host = BtcEngine::new();
+  let mut client = BtcEngine::new();
+  host.create_taproot_lock(true, &public_key_bytes(&client.b), &public_key_bytes(&client.br), t0).unwrap();
+  client.create_taproot_lock(false, &public_key_bytes(&host.b), &public_key_bytes(&host.br), t0).unwrap();
+  assert_eq!(host.lock_script_pubkey(), client.lock_script_pubkey());
+  (host, client)
+}
+
+#[test]
+fn taproot_lock() {
+  let (host, _) = create_locks(144);
+  let lock = host.taproot.as_ref().unwrap();
+  let script = lock.script_pubkey();
+  assert_eq!(script.len(), 34);
+  assert_eq!(&script[2 ..], &Secp256k1Engine::x_only(&lock.output_key));
+  assert_eq!(lock.control_block().len(), 33);
+  // 144 is pushed as 2 bytes, as its top bit would otherwise be read as a sign
+  assert_eq!(&lock.refund_leaf[.. 4], &[2, 144, 0, 0xb2]);
+  // The host's key is always first
+  assert!(lock.keys[0] == Secp256k1Engine::to_public_key(&host.b));
+}
+
+#[test]
+fn taproot_musig_buy() {
+  let (host, client) = create_locks(6);
+  let lock = host.taproot.clone().unwrap();
+  let buy = Transaction {
+    version: 2,
+    lock_time: 0,
+    input: vec![TxIn {
+      previous_output: OutPoint::default(),
+      script_sig: Script::new(),
+      sequence: 0xFFFFFFFF,
+      witness: Vec::new()
+    }],
+    output: vec![TxOut {
+      value: 9000,
+      script_pubkey: lock.script_pubkey().into()
+    }]
+  };
+  let message = host.buy_message(&buy, 10000);
+  assert_eq!(message, client.buy_message(&buy, 10000));
+  let mut message_bytes = [0; 32];
+  message_bytes.copy_from_slice(&message);
+
+  // The client's decryption key, which publishing the buy reveals
+  let decryption_key = Secp256k1Engine::new_private_key();
+  let encryption_key = Secp256k1Engine::to_public_key(&decryption_key);
+
+  // Each party only ever has their own secret nonces
+  let host_nonces = SecretNonces::new();
+  let client_nonces = SecretNonces::new();
+  let public_nonces = vec![
+    bytes_to_nonces(&nonces_to_bytes(&host_nonces.public())).unwrap(),
+    bytes_to_nonces(&nonces_to_bytes(&client_nonces.public())).unwrap()
+  ];
+
+  let client_session = MusigSession::new(client.taproot.as_ref().unwrap(), &public_nonces, &encryption_key, &message_bytes).unwrap();
+  let client_partial = client_session.partial_sign(
+    &client.taproot.as_ref().unwrap().tweak_key_share(&client.b, false).unwrap(),
+    client_nonces
+  );
+  let client_partial = bytes_to_partial_signature(&client_partial.to_bytes()).unwrap();
+
+  let host_session = MusigSession::new(&lock, &public_nonces, &encryption_key, &message_bytes).unwrap();
+  let host_partial = host_session.partial_sign(&lock.tweak_key_share(&host.b, true).unwrap(), host_nonces);
+
+  // Either party's session produces the same encrypted signature
+  let encrypted_signature = host_session.encrypted_signature(&[host_partial.clone(), client_partial.clone()]);
+  assert!(encrypted_signature == client_session.encrypted_signature(&[host_partial.clone(), client_partial.clone()]));
+  Secp256k1Engine::schnorr_encrypted_verify(&lock.output_key, &encryption_key, &encrypted_signature, &message).unwrap();
+  assert!(
+    Secp256k1Engine::schnorr_encrypted_verify(
+      &lock.output_key,
+      &encryption_key,
+      &host_session.encrypted_signature(&[host_partial.clone(), host_partial]),
+      &message
+    ).is_err()
+  );
+
+  let signature = Secp256k1Engine::schnorr_decrypt_signature(&encrypted_signature, &decryption_key).unwrap();
+  let signature_bytes = Secp256k1Engine::schnorr_signature_to_bytes(&signature);
+  assert_eq!(signature_bytes.len(), 64);
+  Secp256k1Engine::schnorr_verify(
+    &Secp256k1Engine::x_only(&lock.output_key),
+    &Secp256k1Engine::bytes_to_schnorr_signature(&signature_bytes).unwrap(),
+    &message
+  ).unwrap();
+  assert_eq!(
+    Secp256k1Engine::private_key_to_bytes(&Secp256k1Engine::schnorr_recover_key(&encryption_key, &encrypted_signature, &signature).unwrap()),
+    Secp256k1Engine::private_key_to_bytes(&decryption_key)
+  );
+}
+
+#[test]
+fn taproot_refund() {
+  let (mut host, mut client) = create_locks(6);
+  let timelocks = Timelocks::default();
+  let lock_id = OutPoint::default().txid;
+  let (_, host_refund, host_message, host_signature) = host.prepare_and_sign_refund(
+    lock_id,
+    true,
+    &public_key_bytes(&client.br),
+    &public_key_bytes(&client.b),
+    10000,
+    2,
+    &timelocks
+  ).unwrap();
+  let (_, client_refund, client_message, client_signature) = client.prepare_and_sign_refund(
+    lock_id,
+    false,
+    &public_key_bytes(&host.br),
+    &public_key_bytes(&client.b),
+    10000,
+    2,
+    &timelocks
+  ).unwrap();
+  assert_eq!(host_refund.txid(), client_refund.txid());
+  assert_eq!(host_message, client_message);
+
+  client.verify_refund_signature(&client_message, &host_signature, &public_key_bytes(&host.br)).unwrap();
+  host.verify_refund_signature(&host_message, &client_signature, &public_key_bytes(&client.br)).unwrap();
+  assert!(host.verify_refund_signature(&host_message, &client_signature, &public_key_bytes(&host.br)).is_err());
+
+  let witness = host.refund_witness_with_signatures(host_signature.clone(), client_signature.clone());
+  assert_eq!(witness, client.refund_witness_with_signatures(host_signature, client_signature));
+  assert_eq!(witness.iter().map(|item| item.len()).collect::<Vec<_>>(), host.refund_witness());
+}
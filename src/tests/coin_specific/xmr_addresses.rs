@@ -0,0 +1,79 @@
+use std::str::FromStr;
+
+use monero::util::address::Address;
+
+use crate::coins::xmr::engine::{XmrChain, XmrNetwork, XmrParams, XmrAddress, XmrAddressKind};
+
+#[test]
+fn xmr_addresses() {
+  let monero = XmrParams { chain: XmrChain::Monero, network: XmrNetwork::Regtest };
+  let wownero = XmrParams { chain: XmrChain::Wownero, network: XmrNetwork::Regtest };
+
+  let xmr_address = "42L9GkQeerChpA4rz4MTagL5mBGbEnvPzWLRL5vfJTr3bd8Diz6okcpd9vkxerLXHADdPMbTW9Xk8JcWj8WbeGEmD3aKdsi";
+  // The same keys under Wownero's mainnet prefix
+  let wow_address = "Wo3E9m57wSZ9McGbYDPnm5iiwF1KqFHMFNh7FWcUwdihKCacWoEHXugQ4GEqW7v6JzDG4324anxjFbMkLAdpJg3C12Cke5Usy";
+
+  let parsed = Address::from_str(xmr_address).unwrap();
+  let standard = XmrAddress {
+    spend: parsed.public_spend.point.decompress().unwrap(),
+    view: parsed.public_view.point.decompress().unwrap(),
+    kind: XmrAddressKind::Standard
+  };
+  assert_eq!(monero.encode_address(&standard), xmr_address);
+  assert_eq!(wownero.encode_address(&standard), wow_address);
+
+  assert_eq!(monero.decode_address(xmr_address).unwrap(), standard);
+  assert_eq!(wownero.decode_address(wow_address).unwrap(), standard);
+  assert!(monero.decode_address(wow_address).is_err());
+  assert!(wownero.decode_address(xmr_address).is_err());
+
+  // Both chains share their testnet prefixes
+  let testnet = XmrParams { chain: XmrChain::Wownero, network: XmrNetwork::Testnet };
+  let testnet_address = testnet.encode_address(&standard);
+  assert!(testnet_address.starts_with('9'));
+  XmrParams { chain: XmrChain::Monero, network: XmrNetwork::Testnet }.decode_address(&testnet_address).unwrap();
+  assert!(wownero.decode_address(&testnet_address).is_err());
+
+  // Addresses for another network are rejected, naming the network they're for
+  let stagenet_address = "52YBMbKcJTJhpA4rz4MTagL5mBGbEnvPzWLRL5vfJTr3bd8Diz6okcpd9vkxerLXHADdPMbTW9Xk8JcWj8WbeGEmCzcV4uV";
+  assert!(monero.decode_address(stagenet_address).unwrap_err().to_string().contains("Stagenet"));
+  assert_eq!(
+    XmrParams { chain: XmrChain::Monero, network: XmrNetwork::Stagenet }.decode_address(stagenet_address).unwrap(),
+    standard
+  );
+
+  // A corrupted checksum
+  let mut corrupted = xmr_address.to_string();
+  corrupted.pop();
+  corrupted.push('j');
+  assert!(monero.decode_address(&corrupted).is_err());
+}
+
+#[test]
+fn xmr_subaddresses_and_integrated_addresses() {
+  let monero = XmrParams { chain: XmrChain::Monero, network: XmrNetwork::Mainnet };
+  let parsed = Address::from_str(
+    "42L9GkQeerChpA4rz4MTagL5mBGbEnvPzWLRL5vfJTr3bd8Diz6okcpd9vkxerLXHADdPMbTW9Xk8JcWj8WbeGEmD3aKdsi"
+  ).unwrap();
+  let spend = parsed.public_spend.point.decompress().unwrap();
+  let view = parsed.public_view.point.decompress().unwrap();
+
+  // The same keys as a subaddress and as an integrated address
+  let subaddress = "83AHc84VFGchpA4rz4MTagL5mBGbEnvPzWLRL5vfJTr3bd8Diz6okcpd9vkxerLXHADdPMbTW9Xk8JcWj8WbeGEmD1N94WT";
+  let integrated = "4C2pHZE9G7ihpA4rz4MTagL5mBGbEnvPzWLRL5vfJTr3bd8Diz6okcpd9vkxerLXHADdPMbTW9Xk8JcWj8WbeGEmJjyxg9LNw66GT5fj7M";
+
+  let decoded = monero.decode_address(subaddress).unwrap();
+  assert_eq!(decoded, XmrAddress { spend, view, kind: XmrAddressKind::Subaddress });
+  assert_eq!(monero.encode_address(&decoded), subaddress);
+
+  let decoded = monero.decode_address(integrated).unwrap();
+  assert_eq!(
+    decoded,
+    XmrAddress { spend, view, kind: XmrAddressKind::Integrated([0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88]) }
+  );
+  assert_eq!(monero.encode_address(&decoded), integrated);
+
+  // Wownero's mainnet uses its own prefixes for both
+  assert!(XmrParams { chain: XmrChain::Wownero, network: XmrNetwork::Mainnet }.decode_address(subaddress).is_err());
+  assert!(XmrParams { chain: XmrChain::Wownero, network: XmrNetwork::Mainnet }.decode_address(integrated).is_err());
+}
@@ -0,0 +1,222 @@
+use hex_literal::hex;
+
+use rand::rngs::OsRng;
+
+use curve25519_dalek::{
+  constants::ED25519_BASEPOINT_TABLE,
+  scalar::Scalar,
+  edwards::EdwardsPoint
+};
+
+use crate::coins::xmr::{
+  engine::{encode_varint, XmrAddress, XmrAddressKind},
+  crypto::{
+    INV_EIGHT,
+    hash_to_scalar, hash_to_point, key_image, commit, shared_key, view_tag, commitment_mask, xor_amount
+  },
+  bulletproofs_plus::BulletproofPlus,
+  clsag,
+  transaction::{self, Transaction, Input, Payment}
+};
+
+fn random_point() -> EdwardsPoint {
+  &Scalar::random(&mut OsRng) * &ED25519_BASEPOINT_TABLE
+}
+
+#[test]
+fn hash_to_point_vectors() {
+  assert_eq!(
+    hash_to_point(b"test").compress().to_bytes(),
+    hex!("95b245c28403d8c25c9923e7c3a862f5723ef1f4c855dfe3776e1aa8167db604")
+  );
+  // The hash of this has its top bit set, which Monero doesn't ignore
+  assert_eq!(
+    hash_to_point(&[1; 32]).compress().to_bytes(),
+    hex!("cc077a353803f70173260ced184179a87fed92c89d4d38e6b43ffd4f099baf22")
+  );
+}
+
+#[test]
+fn bulletproofs_plus() {
+  let masks = (0 .. 3).map(|_| Scalar::random(&mut OsRng)).collect::<Vec<_>>();
+  assert!(BulletproofPlus::prove(&[0, 1, u64::max_value()], &masks).verify());
+  assert!(BulletproofPlus::prove(&[5], &masks[.. 1]).verify());
+
+  let mut proof = BulletproofPlus::prove(&[5, 6], &masks[.. 2]);
+  assert!(proof.verify());
+  proof.s1 += Scalar::one();
+  assert!(!proof.verify());
+
+  // A commitment to a different value than was proven
+  let mut proof = BulletproofPlus::prove(&[5], &masks[.. 1]);
+  proof.V[0] = commit(&masks[0], 6) * *INV_EIGHT;
+  assert!(!proof.verify());
+}
+
+#[test]
+fn clsag() {
+  let key = Scalar::random(&mut OsRng);
+  let mask = Scalar::random(&mut OsRng);
+  let pseudo_mask = Scalar::random(&mut OsRng);
+  let mut ring = (0 .. 16).map(|_| [random_point(), random_point()]).collect::<Vec<_>>();
+  ring[3] = [&key * &ED25519_BASEPOINT_TABLE, commit(&mask, 100)];
+  let pseudo_out = commit(&pseudo_mask, 100);
+
+  let message = [7; 32];
+  let signature = clsag::sign(&message, &ring, 3, &key, &mask, &pseudo_out, &pseudo_mask);
+  assert!(signature.verify(&message, &ring, &key_image(&key), &pseudo_out));
+  assert!(!signature.verify(&[8; 32], &ring, &key_image(&key), &pseudo_out));
+  // A pseudo output for a different amount
+  assert!(!signature.verify(&message, &ring, &key_image(&key), &commit(&pseudo_mask, 101)));
+}
+
+fn inputs(amounts: &[u64]) -> Vec<Input> {
+  let mut inputs = vec![];
+  for amount in amounts {
+    let key = Scalar::random(&mut OsRng);
+    let mask = Scalar::random(&mut OsRng);
+    let mut ring = (0 .. 16).map(|_| [random_point(), random_point()]).collect::<Vec<_>>();
+    ring[0] = [&key * &ED25519_BASEPOINT_TABLE, commit(&mask, *amount)];
+    inputs.push(Input {
+      offsets: (0 .. 16).map(|i| 3 + (i * 5)).collect(),
+      ring,
+      index: 0,
+      key,
+      mask,
+      amount: *amount
+    });
+  }
+  inputs
+}
+
+// Signs a transaction sending each amount to the address, alongside a zero value output to a random address
+pub fn sign_to(address: XmrAddress, amounts: &[u64]) -> Vec<u8> {
+  let mut payments = amounts.iter().map(|amount| Payment {
+    address,
+    amount: *amount
+  }).collect::<Vec<_>>();
+  payments.push(Payment {
+    address: XmrAddress {
+      spend: random_point(),
+      view: random_point(),
+      kind: XmrAddressKind::Standard
+    },
+    amount: 0
+  });
+  let total = amounts.iter().sum::<u64>() + 100;
+  transaction::sign(&inputs(&[total / 2, total - (total / 2)]), &payments, 100).0
+}
+
+fn send(address: XmrAddress) -> Transaction {
+  Transaction::parse(&sign_to(address, &[2900])).unwrap()
+}
+
+#[test]
+fn transaction() {
+  let view = Scalar::random(&mut OsRng);
+  let spend = random_point();
+  let parsed = send(XmrAddress {
+    spend,
+    view: &view * &ED25519_BASEPOINT_TABLE,
+    kind: XmrAddressKind::Standard
+  });
+  assert_eq!(parsed.unlock_time, 0);
+  assert_eq!(parsed.outputs.len(), 2);
+  assert!(parsed.outputs.iter().all(|output| output.view_tag.is_some()));
+  let outputs = parsed.scan(&view, &spend);
+  assert_eq!(outputs.len(), 1);
+  assert_eq!(outputs[0].amount, 2900);
+  assert_eq!(parsed.scan(&Scalar::random(&mut OsRng), &spend).len(), 0);
+}
+
+#[test]
+fn subaddress_transaction() {
+  // Derive the subaddress with index (0, 1), as wallets do
+  let view = Scalar::random(&mut OsRng);
+  let mut to_hash = b"SubAddr\0".to_vec();
+  to_hash.extend(view.as_bytes());
+  to_hash.extend(&0u32.to_le_bytes());
+  to_hash.extend(&1u32.to_le_bytes());
+  let spend = random_point() + (&hash_to_scalar(&to_hash) * &ED25519_BASEPOINT_TABLE);
+
+  // Wallets scan for subaddresses with their view key and the subaddress's spend key
+  let outputs = send(XmrAddress {
+    spend,
+    view: view * spend,
+    kind: XmrAddressKind::Subaddress
+  }).scan(&view, &spend);
+  assert_eq!(outputs.len(), 1);
+  assert_eq!(outputs[0].amount, 2900);
+}
+
+#[test]
+fn integrated_transaction() {
+  let view = Scalar::random(&mut OsRng);
+  let spend = random_point();
+  let parsed = send(XmrAddress {
+    spend,
+    view: &view * &ED25519_BASEPOINT_TABLE,
+    kind: XmrAddressKind::Integrated([1, 2, 3, 4, 5, 6, 7, 8])
+  });
+  assert_eq!(parsed.scan(&view, &spend).len(), 1);
+  assert_eq!(parsed.payment_id(&view), Some([1, 2, 3, 4, 5, 6, 7, 8]));
+}
+
+// A transaction with a single output, built by hand to cover formats our own transactions don't use
+fn raw_transaction(view: &Scalar, spend: &EdwardsPoint, output_type: u8, rct_type: u8) -> Vec<u8> {
+  let tx_key = Scalar::random(&mut OsRng);
+  let ecdh = tx_key * (view * &ED25519_BASEPOINT_TABLE);
+  let key_offset = shared_key(&ecdh, 0);
+
+  let mut tx = encode_varint(2);
+  tx.extend(encode_varint(0));
+  // A single input with a single ring member, as inputs are never checked when parsing
+  tx.extend(encode_varint(1));
+  tx.push(0x02);
+  tx.extend(encode_varint(0));
+  tx.extend(encode_varint(1));
+  tx.extend(encode_varint(5));
+  tx.extend(random_point().compress().as_bytes());
+  tx.extend(encode_varint(1));
+  tx.extend(encode_varint(0));
+  tx.push(output_type);
+  tx.extend(((&key_offset * &ED25519_BASEPOINT_TABLE) + spend).compress().as_bytes());
+  if output_type == 0x03 {
+    tx.push(view_tag(&ecdh, 0));
+  }
+  tx.extend(encode_varint(33));
+  tx.push(0x01);
+  tx.extend((&tx_key * &ED25519_BASEPOINT_TABLE).compress().as_bytes());
+  tx.push(rct_type);
+  tx.extend(encode_varint(100));
+  tx.extend(&xor_amount(&key_offset, 2900).to_le_bytes());
+  tx.extend(commit(&commitment_mask(&key_offset), 2900).compress().as_bytes());
+  tx
+}
+
+#[test]
+fn parse_formats() {
+  let view = Scalar::random(&mut OsRng);
+  let spend = random_point();
+  for output_type in &[0x02, 0x03] {
+    for rct_type in &[4, 5, 6] {
+      let parsed = Transaction::parse(&raw_transaction(&view, &spend, *output_type, *rct_type)).unwrap();
+      assert_eq!(parsed.outputs[0].view_tag.is_some(), *output_type == 0x03);
+      let outputs = parsed.scan(&view, &spend);
+      assert_eq!(outputs.len(), 1);
+      assert_eq!(outputs[0].amount, 2900);
+    }
+  }
+
+  // Outputs with another view tag are skipped
+  let mut parsed = Transaction::parse(&raw_transaction(&view, &spend, 0x03, 6)).unwrap();
+  parsed.outputs[0].view_tag = Some(parsed.outputs[0].view_tag.unwrap() ^ 1);
+  assert_eq!(parsed.scan(&view, &spend).len(), 0);
+
+  // Unknown output types, RingCT types, and transaction versions are parsed without any outputs
+  assert_eq!(Transaction::parse(&raw_transaction(&view, &spend, 0x04, 6)).unwrap().outputs.len(), 0);
+  assert_eq!(Transaction::parse(&raw_transaction(&view, &spend, 0x03, 7)).unwrap().outputs.len(), 0);
+  let mut v1 = raw_transaction(&view, &spend, 0x02, 5);
+  v1[0] = 1;
+  assert_eq!(Transaction::parse(&v1).unwrap().outputs.len(), 0);
+}
@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+
+use crate::db::{SwapDb, SwapStep, HostStep, ClientStep};
+
+#[test]
+fn save_and_open() {
+  let _ = env_logger::builder().is_test(true).try_init();
+  let dir = std::env::temp_dir().join("asmr-db-test");
+
+  let mut db = SwapDb::create(
+    &dir,
+    SwapDb::new_id(),
+    "bitcoin-monero".to_string(),
+    PathBuf::from("config/bitcoin.json"),
+    PathBuf::from("config/monero.json"),
+    SwapStep::Client(ClientStep::Started)
+  ).unwrap();
+  db.record.scripted = vec![1, 2, 3];
+  db.set_swap_secret([7; 32]).unwrap();
+  db.set_step(SwapStep::Client(ClientStep::SecretReceived)).unwrap();
+
+  let opened = SwapDb::open(&dir, &db.id).unwrap();
+  assert_eq!(opened.record.pair, "bitcoin-monero");
+  assert_eq!(opened.record.scripted_config, PathBuf::from("config/bitcoin.json"));
+  assert_eq!(opened.record.step, SwapStep::Client(ClientStep::SecretReceived));
+  assert_eq!(opened.record.scripted, vec![1, 2, 3]);
+  assert_eq!(opened.record.swap_secret, Some([7; 32]));
+  assert!(!opened.record.step.is_done());
+  assert!(SwapDb::list(&dir).unwrap().contains(&db.id));
+
+  assert!(SwapStep::Host(HostStep::Refunded).is_done());
+  assert!(SwapDb::open(&dir, "nonexistent").is_err());
+}
+
+#[test]
+fn abandon_before_keys() {
+  let _ = env_logger::builder().is_test(true).try_init();
+  let dir = std::env::temp_dir().join("asmr-db-test");
+  let create = |step| SwapDb::create(
+    &dir,
+    SwapDb::new_id(),
+    "bitcoin-monero".to_string(),
+    PathBuf::from("config/bitcoin.json"),
+    PathBuf::from("config/monero.json"),
+    step
+  ).unwrap();
+
+  // Records which never exchanged keys still have empty states, so they're marked refunded instead of resumed
+  for (step, refunded) in &[
+    (SwapStep::Host(HostStep::Started), SwapStep::Host(HostStep::Refunded)),
+    (SwapStep::Client(ClientStep::Started), SwapStep::Client(ClientStep::Refunded)),
+    (SwapStep::Client(ClientStep::AwaitingConfirmation), SwapStep::Client(ClientStep::Refunded))
+  ] {
+    let mut db = create(*step);
+    assert!(db.record.scripted.is_empty());
+    assert!(db.abandon_before_keys().unwrap());
+    let opened = SwapDb::open(&dir, &db.id).unwrap();
+    assert_eq!(opened.record.step, *refunded);
+    assert!(opened.record.step.is_done());
+  }
+
+  for step in &[SwapStep::Host(HostStep::KeysVerified), SwapStep::Client(ClientStep::KeysVerified)] {
+    let mut db = create(*step);
+    assert!(!db.abandon_before_keys().unwrap());
+    assert_eq!(SwapDb::open(&dir, &db.id).unwrap().record.step, *step);
+  }
+}
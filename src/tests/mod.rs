@@ -2,13 +2,18 @@ mod dl_eq;
 mod secp_dl_eq;
 mod ves;
 mod serialization;
+mod db;
+mod channel;
+mod protocol;
 mod coin_specific;
 
-#[cfg_attr(not(feature = "test_bitcoin_node"), allow(dead_code))]
+#[cfg_attr(not(any(feature = "test_bitcoin_node", feature = "test_litecoin_node", feature = "test_ethereum_node")), allow(dead_code))]
 mod swap;
-#[cfg(all(feature = "test_bitcoin_node", feature = "test_meros_node"))]
+#[cfg(all(any(feature = "test_bitcoin_node", feature = "test_litecoin_node"), feature = "test_meros_node"))]
 mod btc_and_meros;
-#[cfg(all(feature = "test_bitcoin_node", feature = "test_nano_node"))]
+#[cfg(all(any(feature = "test_bitcoin_node", feature = "test_litecoin_node"), feature = "test_nano_node"))]
 mod btc_and_nano;
-#[cfg(all(feature = "test_bitcoin_node", feature = "test_monero_node"))]
+#[cfg(all(any(feature = "test_bitcoin_node", feature = "test_litecoin_node"), any(feature = "test_monero_node", feature = "test_wownero_node")))]
 mod btc_and_xmr;
+#[cfg(all(feature = "test_ethereum_node", feature = "test_nano_node"))]
+mod eth_and_nano;
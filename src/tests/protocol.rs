@@ -0,0 +1,186 @@
+use crate::{
+  coins::{FeeBounds, Timelocks, SwapTerms},
+  channel::{Identity, Channel},
+  protocol::{self, Message, PROTOCOL_VERSION, MIN_PROTOCOL_VERSION, negotiate_version, negotiate_between},
+  tests::channel::connect
+};
+
+async fn channels() -> (Channel, Channel) {
+  let dir = std::env::temp_dir().join("asmr-protocol-test");
+  let host = Identity::load_or_generate(&dir.join("host.key")).unwrap();
+  let client = Identity::load_or_generate(&dir.join("client.key")).unwrap();
+  let (host, client) = connect(&host, &client, None, None).await;
+  (host.unwrap(), client.unwrap())
+}
+
+#[test]
+fn version_negotiation() {
+  assert_eq!(negotiate_version(PROTOCOL_VERSION, MIN_PROTOCOL_VERSION), Ok(PROTOCOL_VERSION));
+  // A newer peer which still supports our version
+  assert_eq!(negotiate_version(PROTOCOL_VERSION + 1, MIN_PROTOCOL_VERSION), Ok(PROTOCOL_VERSION));
+  // A newer peer which no longer does
+  assert!(negotiate_version(PROTOCOL_VERSION + 2, PROTOCOL_VERSION + 1).is_err());
+  // An older peer we no longer support
+  assert!(negotiate_version(MIN_PROTOCOL_VERSION - 1, 0).is_err());
+}
+
+// Once there have been several releases, each side may support a range of versions
+#[test]
+fn version_range_negotiation() {
+  let ours = (2, 4);
+  assert_eq!(negotiate_between(ours, ours), Ok(4));
+  // Overlapping ranges use the highest version both support
+  assert_eq!(negotiate_between(ours, (1, 3)), Ok(3));
+  assert_eq!(negotiate_between(ours, (3, 6)), Ok(4));
+  assert_eq!(negotiate_between(ours, (3, 3)), Ok(3));
+  // Ranges which only share an endpoint
+  assert_eq!(negotiate_between(ours, (1, 2)), Ok(2));
+  assert_eq!(negotiate_between(ours, (4, 5)), Ok(4));
+  // Ranges which don't overlap
+  assert!(negotiate_between(ours, (5, 6)).is_err());
+  assert!(negotiate_between(ours, (0, 1)).is_err());
+  // Negotiation is symmetric
+  assert_eq!(negotiate_between((1, 3), ours), Ok(3));
+  assert!(negotiate_between((5, 6), ours).is_err());
+}
+
+#[tokio::test]
+async fn hello() {
+  let _ = env_logger::builder().is_test(true).try_init();
+
+  let (mut host, mut client) = channels().await;
+  let host = tokio::spawn(async move { protocol::host_hello(&mut host, "bitcoin-monero").await });
+  assert_eq!(protocol::client_hello(&mut client, "bitcoin-monero").await.unwrap(), PROTOCOL_VERSION);
+  assert_eq!(host.await.unwrap().unwrap(), PROTOCOL_VERSION);
+
+  // The host should learn why the client refused
+  let (mut host, mut client) = channels().await;
+  let host = tokio::spawn(async move { protocol::host_hello(&mut host, "bitcoin-nano").await });
+  assert!(protocol::client_hello(&mut client, "bitcoin-monero").await.is_err());
+  let err = host.await.unwrap().unwrap_err().to_string();
+  assert!(err.contains("different pair"));
+}
+
+#[tokio::test]
+async fn out_of_order() {
+  let _ = env_logger::builder().is_test(true).try_init();
+
+  let (mut host, mut client) = channels().await;
+  protocol::send(&mut host, &Message::SwapSecret([0; 32])).await.unwrap();
+  let received = protocol::receive(&mut client).await.unwrap();
+  assert!(received.unexpected("Keys").to_string().contains("SwapSecret"));
+
+  // Data which isn't a message, as sent by an incompatible version
+  host.write(b"ASMR").await.unwrap();
+  assert!(protocol::receive(&mut client).await.unwrap_err().to_string().contains("incompatible"));
+}
+
+#[tokio::test]
+async fn terms() {
+  let _ = env_logger::builder().is_test(true).try_init();
+  let terms = SwapTerms {
+    scripted_amount: 1_000_000,
+    unscripted_amount: 1_000_000_000_000,
+    tolerance_bps: 100,
+    scripted_fee_bounds: FeeBounds { min: 1, max: 100 },
+    timelocks: Timelocks::default()
+  };
+
+  let (mut host, mut client) = channels().await;
+  let host = tokio::spawn(async move { protocol::host_offer(&mut host, terms).await });
+  assert_eq!(protocol::client_accept(&mut client, terms).await.unwrap(), terms);
+  assert_eq!(host.await.unwrap().unwrap(), terms);
+
+  // The client narrows the fee rates to the overlap with its own, which the host then uses
+  let (mut host, mut client) = channels().await;
+  let host = tokio::spawn(async move { protocol::host_offer(&mut host, terms).await });
+  let narrowed = SwapTerms { scripted_fee_bounds: FeeBounds { min: 5, max: 100 }, ..terms };
+  let accepted = protocol::client_accept(&mut client, SwapTerms { scripted_fee_bounds: FeeBounds { min: 5, max: 200 }, ..terms });
+  assert_eq!(accepted.await.unwrap(), narrowed);
+  assert_eq!(host.await.unwrap().unwrap(), narrowed);
+
+  // Yet refuses when there's no overlap
+  let (mut host, mut client) = channels().await;
+  let host = tokio::spawn(async move { protocol::host_offer(&mut host, terms).await });
+  let accepted = protocol::client_accept(&mut client, SwapTerms { scripted_fee_bounds: FeeBounds { min: 101, max: 200 }, ..terms });
+  assert!(accepted.await.is_err());
+  assert!(host.await.unwrap().unwrap_err().to_string().contains("overlap"));
+
+  // The client must refuse a different amount, and the host must learn why
+  let (mut host, mut client) = channels().await;
+  let host = tokio::spawn(
+    async move {
+      protocol::host_offer(&mut host, SwapTerms { scripted_amount: 900_000, ..terms }).await
+    }
+  );
+  assert!(protocol::client_accept(&mut client, terms).await.is_err());
+  assert!(host.await.unwrap().unwrap_err().to_string().contains("don't match"));
+
+  // As well as a looser tolerance
+  let (mut host, mut client) = channels().await;
+  let host = tokio::spawn(async move { protocol::host_offer(&mut host, SwapTerms { tolerance_bps: 200, ..terms }).await });
+  assert!(protocol::client_accept(&mut client, terms).await.is_err());
+  assert!(host.await.unwrap().is_err());
+}
+
+#[test]
+fn tolerance() {
+  let terms = SwapTerms {
+    scripted_amount: 1_000_000,
+    unscripted_amount: 1_000_000_000_000_000_000_000_000_000_000,
+    tolerance_bps: 100,
+    scripted_fee_bounds: FeeBounds::unbounded(),
+    timelocks: Timelocks::default()
+  };
+  assert!(terms.verify_scripted(1_000_000).is_ok());
+  assert!(terms.verify_scripted(990_000).is_ok());
+  assert!(terms.verify_scripted(1_010_000).is_ok());
+  assert!(terms.verify_scripted(989_999).is_err());
+  assert!(terms.verify_scripted(1_010_001).is_err());
+  // Nano's raw units must not overflow
+  assert!(terms.verify_unscripted(990_000_000_000_000_000_000_000_000_000).is_ok());
+  assert!(terms.verify_unscripted(0).is_err());
+  assert!(SwapTerms { tolerance_bps: 0, ..terms }.verify_scripted(999_999).is_err());
+  // Even at the extremes of a u128
+  let extreme = SwapTerms { unscripted_amount: u128::MAX, tolerance_bps: u16::MAX, ..terms };
+  assert!(extreme.verify_unscripted(0).is_ok());
+  assert!(SwapTerms { unscripted_amount: u128::MAX, ..terms }.verify_unscripted(u128::MAX - (u128::MAX / 100)).is_ok());
+  assert!(SwapTerms { unscripted_amount: u128::MAX, ..terms }.verify_unscripted(u128::MAX / 2).is_err());
+  assert!(SwapTerms { unscripted_amount: 1, ..terms }.verify_unscripted(u128::MAX).is_err());
+}
+
+#[test]
+fn fee_bounds() {
+  let bounds = FeeBounds { min: 5, max: 50 };
+  assert_eq!(bounds.intersect(&FeeBounds::unbounded()), Some(bounds));
+  assert_eq!(bounds.intersect(&FeeBounds { min: 10, max: 100 }), Some(FeeBounds { min: 10, max: 50 }));
+  assert_eq!(bounds.intersect(&FeeBounds { min: 51, max: 100 }), None);
+  assert_eq!(bounds.clamp(1), 5);
+  assert_eq!(bounds.clamp(20), 20);
+  assert_eq!(bounds.clamp(100), 50);
+  assert!(bounds.verify(5).is_ok());
+  assert!(bounds.verify(4).is_err());
+  assert!(bounds.verify(51).is_err());
+}
+
+#[test]
+fn timelocks() {
+  let timelocks = Timelocks::default();
+  assert!(timelocks.verify(600, 1200).is_ok());
+  // The unscripted coin must be final before the cutoff
+  assert!(timelocks.verify(600, 2401).is_err());
+  // The buy must have time to confirm before T0
+  assert!(Timelocks { t0: 5, ..timelocks }.verify(600, 0).is_err());
+  assert!(Timelocks { t1: 1, ..timelocks }.verify(600, 0).is_err());
+  assert!(Timelocks { cutoff: 0, ..timelocks }.verify(600, 0).is_err());
+
+  let terms = SwapTerms {
+    scripted_amount: 1_000_000,
+    unscripted_amount: 1_000_000,
+    tolerance_bps: 100,
+    scripted_fee_bounds: FeeBounds::unbounded(),
+    timelocks: Timelocks { t0: 144, t1: 72, cutoff: 100 }
+  };
+  assert!(protocol::check_timelocks(&terms, &timelocks, 600, 1200).is_ok());
+  assert!(protocol::check_timelocks(&terms, &Timelocks { t0: 50, ..timelocks }, 600, 1200).is_err());
+}
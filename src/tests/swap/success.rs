@@ -24,7 +24,9 @@ pub async fn test_success(
   host.publish_lock().await.context("Couldn't publish the lock")?;
   host.advance_consensus().await?;
 
-  clients_verifier.verify_prepared_buy(&host.prepare_buy_for_client().await.context("Couldn't prepare the buy")?)?;
+  host.verify_buy_signature(
+    &clients_verifier.verify_prepared_buy(&host.prepare_buy_for_client().await.context("Couldn't prepare the buy")?)?
+  )?;
   clients_verifier.verify_and_wait_for_lock().await.context("Couldn't verify the lock")?;
   let _ = client.get_address();
   client.send_from_node().await?;
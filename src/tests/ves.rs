@@ -59,3 +59,31 @@ fn secp256k1() {
     Ok(())
   });
 }
+
+#[test]
+fn secp256k1_schnorr() {
+  let _ = env_logger::builder().is_test(true).try_init();
+  let signing_key = Secp256k1Engine::new_private_key();
+  let pub_signing_key = Secp256k1Engine::to_public_key(&signing_key);
+  let encryption_key = Secp256k1Engine::new_private_key();
+  let pub_encryption_key = Secp256k1Engine::to_public_key(&encryption_key);
+  let message: [u8; 32] = sha2::Sha256::digest(b"hello world").into();
+
+  let enc_sig = Secp256k1Engine::schnorr_encrypted_sign(&signing_key, &pub_encryption_key, &message)
+    .expect("Failed to create encrypted Schnorr signature");
+  Secp256k1Engine::schnorr_encrypted_verify(&pub_signing_key, &pub_encryption_key, &enc_sig, &message)
+    .expect("Failed to verify encrypted Schnorr signature");
+  assert!(
+    Secp256k1Engine::schnorr_encrypted_verify(&pub_encryption_key, &pub_encryption_key, &enc_sig, &message).is_err()
+  );
+
+  let dec_sig = Secp256k1Engine::schnorr_decrypt_signature(&enc_sig, &encryption_key)
+    .expect("Failed to decrypt Schnorr signature");
+  assert_eq!(Secp256k1Engine::schnorr_signature_to_bytes(&dec_sig).len(), 64);
+  Secp256k1Engine::schnorr_verify(&Secp256k1Engine::x_only(&pub_signing_key), &dec_sig, &message)
+    .expect("Failed to verify decrypted Schnorr signature");
+
+  let recreated_encryption_key = Secp256k1Engine::schnorr_recover_key(&pub_encryption_key, &enc_sig, &dec_sig)
+    .expect("Failed to recover Schnorr signature encryption key");
+  assert_eq!(encryption_key, recreated_encryption_key);
+}